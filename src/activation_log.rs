@@ -0,0 +1,208 @@
+use crate::{
+    config::Config,
+    launcher::{AppEntry, EntryType},
+    log, modes,
+};
+use gtk4::glib;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// The log is rolled over to `activations.log.1` once it grows past this, so
+// at most twice this much is kept.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const TOP_COUNT: usize = 10;
+
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Activation {
+    pub at: u64,
+    pub entry_type: String,
+    pub key: String,
+    pub query: String,
+    pub mode: String,
+}
+
+impl Activation {
+    pub fn new(app: &AppEntry, query: &str) -> Self {
+        let key: &str = match app.entry_type {
+            EntryType::Application => &app.id,
+            EntryType::Link | EntryType::Query | EntryType::Value => &app.exec,
+            EntryType::File | EntryType::Divider => &app.path,
+            // Addresses change with every window, classes don't.
+            EntryType::Window => &app.startup_wm_class,
+        };
+        Self {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            entry_type: format!("{:?}", app.entry_type).to_lowercase(),
+            key: key.to_string(),
+            query: query.to_string(),
+            mode: modes::current()
+                .map_or("default", modes::Mode::name)
+                .to_string(),
+        }
+    }
+}
+
+// Appends a launch to the log when `debug.activation_log` is enabled. Blocks
+// on file IO, so it has to run off the UI thread.
+pub fn record(activation: &Activation) {
+    if !Config::load().debug.activation_log {
+        return;
+    }
+    if let Err(e) = append(activation) {
+        log!("Failed to write activation log: {}", e);
+    }
+}
+
+fn append(activation: &Activation) -> Result<(), std::io::Error> {
+    let _guard = LOG_LOCK.lock().unwrap();
+    let path = log_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    if fs::metadata(&path).is_ok_and(|metadata| metadata.len() >= MAX_LOG_SIZE) {
+        log!(
+            "Activation log exceeds {} bytes, rolling it over",
+            MAX_LOG_SIZE
+        );
+        fs::rename(&path, rolled_path())?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(activation)?)?;
+    file.sync_data()
+}
+
+fn log_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(|| glib::home_dir().join(".local/state"))
+        .join("hyprlauncher/activations.log")
+}
+
+fn rolled_path() -> PathBuf {
+    log_path().with_extension("log.1")
+}
+
+// Oldest first. Lines that don't parse, such as a partially written last
+// line, are skipped.
+fn load() -> Vec<Activation> {
+    [rolled_path(), log_path()]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Parses durations like `7d`, `12h`, `2w` or `30m` into seconds.
+fn parse_since(value: &str) -> Option<u64> {
+    let unit = match value.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = value[..value.len() - 1].parse().ok()?;
+    Some(count * unit)
+}
+
+fn top<'a>(values: impl Iterator<Item = &'a str>) -> Vec<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts.truncate(TOP_COUNT);
+    counts
+}
+
+// `hyprlauncher stats [--since <duration>]`
+pub fn run(args: &[String]) -> i32 {
+    let since = match args {
+        [] => None,
+        [flag, value] if flag == "--since" => match parse_since(value) {
+            Some(seconds) => Some(seconds),
+            None => {
+                eprintln!("Invalid duration {:?}, expected e.g. 7d, 12h or 2w", value);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("Usage: hyprlauncher stats [--since <duration>]");
+            return 2;
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let cutoff = since.map_or(0, |since| now.saturating_sub(since));
+    let activations: Vec<_> = load()
+        .into_iter()
+        .filter(|activation| activation.at >= cutoff)
+        .collect();
+
+    if activations.is_empty() {
+        println!("No launches recorded");
+        if !Config::load().debug.activation_log {
+            println!("Set debug.activation_log to true to record launches");
+        }
+        return 0;
+    }
+
+    println!("Launches: {}", activations.len());
+
+    println!("Top entries:");
+    for (key, count) in top(activations.iter().map(|activation| activation.key.as_str())) {
+        println!("  {:>5}  {}", count, key);
+    }
+
+    println!("Launches per day:");
+    let mut days: Vec<(String, usize)> = Vec::new();
+    for activation in &activations {
+        let day = glib::DateTime::from_unix_local(activation.at as i64)
+            .and_then(|time| time.format("%Y-%m-%d"))
+            .map(String::from)
+            .unwrap_or_default();
+        match days.last_mut() {
+            Some((last, count)) if *last == day => *count += 1,
+            _ => days.push((day, 1)),
+        }
+    }
+    for (day, count) in days {
+        println!("  {}  {:>5}", day, count);
+    }
+
+    println!("Top queries:");
+    for (query, count) in top(activations
+        .iter()
+        .map(|activation| activation.query.as_str())
+        .filter(|query| !query.is_empty()))
+    {
+        println!("  {:>5}  {}", count, query);
+    }
+
+    0
+}
@@ -1,5 +1,13 @@
-use crate::{config::Config, log, ui::LauncherWindow};
+use crate::{
+    clipboard,
+    config::Config,
+    control, dmenu,
+    launcher::{self, LaunchPlan},
+    log, modes, open_with,
+    ui::LauncherWindow,
+};
 use gtk4::{
+    gio,
     glib::{self, ControlFlow},
     prelude::*,
     Application, ApplicationWindow,
@@ -8,16 +16,34 @@ use std::{
     env,
     fs::{self, File},
     io::Write,
-    path::PathBuf,
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
     process,
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
     time::{self, Duration, Instant},
 };
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime};
+
+pub const APPLICATION_ID: &str = "hyprutils.hyprlauncher";
+
+const SIGTERM: i32 = 15;
+
+const APPLICATION_FLAGS: gio::ApplicationFlags =
+    gio::ApplicationFlags::ALLOW_REPLACEMENT.union(gio::ApplicationFlags::HANDLES_OPEN);
+
+const SET_THEME_ARGUMENT: &str = "--set-theme";
+const PERSIST_ARGUMENT: &str = "--persist";
+
+const STARTUP_ATTEMPTS_FILE: &str = "startup-attempts";
+const MAX_CRASHED_STARTUPS: u32 = 3;
 
 pub struct App {
     app: Application,
     rt: Runtime,
+    load_cancelled: Arc<AtomicBool>,
 }
 
 impl App {
@@ -25,24 +51,59 @@ impl App {
         log!("Initializing application runtime...");
         let rt = Runtime::new().expect("Failed to create Tokio runtime");
 
-        if !Self::can_create_instance() {
+        let load_cancelled = Arc::new(AtomicBool::new(false));
+        if dmenu::is_requested(&Self::arguments()) {
+            return Self::new_dmenu(rt, load_cancelled);
+        }
+        if !Self::can_create_instance(load_cancelled.clone()) {
             log!("Another instance is already running, exiting");
             let app = Application::builder()
-                .application_id("hyprutils.hyprlauncher")
-                .flags(gtk4::gio::ApplicationFlags::ALLOW_REPLACEMENT)
+                .application_id(APPLICATION_ID)
+                .flags(APPLICATION_FLAGS)
                 .build();
 
             app.register(None::<&gtk4::gio::Cancellable>)
                 .expect("Failed to register application");
 
-            app.activate();
-            process::exit(0);
+            // Switching themes leaves the window as it is.
+            if let Some((name, persist)) = Self::requested_theme() {
+                if let Err(e) = Config::load_theme(&name) {
+                    eprintln!("Unknown theme {:?}: {}", name, e);
+                    process::exit(1);
+                }
+                app.activate_action("set-theme", Some(&(name, persist).to_variant()));
+                Self::flush_and_exit(&app, 0);
+            }
+
+            // Always forward the mode so a launch without --mode leaves the
+            // mode a previous launch entered.
+            let args = Self::arguments();
+            let mode = modes::requested_mode(&args).unwrap_or_default();
+            app.activate_action("set-mode", Some(&mode.to_variant()));
+
+            let files = Self::launch_files();
+            if files.is_empty() {
+                app.activate();
+            } else {
+                app.open(&files, "");
+            }
+            Self::flush_and_exit(&app, 0);
+        }
+
+        Self::track_startup_attempt();
+        if let Some(mode) = modes::requested_mode(&Self::arguments()) {
+            modes::set(mode);
+        }
+        if let Some((name, persist)) = Self::requested_theme() {
+            if let Err(e) = Config::set_session_theme(&name, persist) {
+                log!("Not switching theme: {}", e);
+            }
         }
 
         log!("Creating new application instance");
         let app = Application::builder()
-            .application_id("hyprutils.hyprlauncher")
-            .flags(gtk4::gio::ApplicationFlags::ALLOW_REPLACEMENT)
+            .application_id(APPLICATION_ID)
+            .flags(APPLICATION_FLAGS)
             .build();
 
         app.register(None::<&gtk4::gio::Cancellable>)
@@ -56,20 +117,40 @@ impl App {
         let app_clone = app.clone();
         let mut last_config = Config::load();
         let mut last_update = Instant::now();
+        let rt_handle = rt.handle().clone();
+        let cancelled = load_cancelled.clone();
 
         glib::timeout_add_local(Duration::from_millis(100), move || {
             if rx.try_recv().is_ok() {
+                crate::capabilities::probe();
                 let now = Instant::now();
                 if now.duration_since(last_update).as_millis() > 250 {
                     if let Some(window) = app_clone.windows().first() {
                         log!("Loading new config for comparison");
                         let new_config = Config::load();
-                        if new_config != last_config {
+                        let changes = last_config.diff(&new_config);
+                        if changes.any() {
                             if let Some(launcher_window) =
                                 window.downcast_ref::<ApplicationWindow>()
                             {
                                 log!("Config changed, updating window");
-                                LauncherWindow::update_window_config(launcher_window, &new_config);
+                                if changes.applications {
+                                    log!("Launcher options changed, reloading applications");
+                                    crate::launcher::rewatch_desktop_dirs();
+                                    let cancelled = cancelled.clone();
+                                    rt_handle.spawn(async move {
+                                        if let Err(e) =
+                                            crate::launcher::load_applications(cancelled).await
+                                        {
+                                            log!("Failed to reload applications: {}", e);
+                                        }
+                                    });
+                                }
+                                LauncherWindow::update_window_config(
+                                    launcher_window,
+                                    &new_config,
+                                    changes,
+                                );
                                 last_config = new_config;
                                 last_update = now;
                             }
@@ -83,33 +164,105 @@ impl App {
         });
 
         if !app.is_remote() {
-            let load_start = Instant::now();
-            rt.block_on(async {
-                crate::launcher::load_applications().await.unwrap();
+            let rt_handle = rt.handle().clone();
+            let cancelled = load_cancelled.clone();
+            crate::overrides::watch_overrides(Config::config_dir().clone(), move || {
+                let cancelled = cancelled.clone();
+                rt_handle.spawn(async move {
+                    if let Err(e) = crate::launcher::load_applications(cancelled).await {
+                        log!("Failed to reload applications: {}", e);
+                    }
+                });
+            });
+
+            crate::launcher::watch_desktop_dirs(rt.handle().clone());
+            Self::watch_workspaces(&app, rt.handle());
+            if let Some(runtime_dir) = Self::get_runtime_dir() {
+                control::serve(runtime_dir.join(control::CONTROL_SOCKET), rt.handle());
+            }
+
+            let cancelled = load_cancelled.clone();
+            rt.spawn(async move {
+                let load_start = Instant::now();
+                if let Err(e) = crate::launcher::load_applications(cancelled).await {
+                    log!("Failed to load applications: {}", e);
+                    return;
+                }
+                log!(
+                    "Loading applications ({:.3}ms)",
+                    load_start.elapsed().as_secs_f64() * 1000.0
+                );
+
+                crate::files::load_documents(Config::load().files.document_dirs).await;
             });
-            log!(
-                "Loading applications ({:.3}ms)",
-                load_start.elapsed().as_secs_f64() * 1000.0
-            );
         }
 
-        Self { app, rt }
+        Self {
+            app,
+            rt,
+            load_cancelled,
+        }
+    }
+
+    // A dmenu instance runs on its own, next to any running launcher, and
+    // lists the lines of standard input instead of the applications.
+    fn new_dmenu(rt: Runtime, load_cancelled: Arc<AtomicBool>) -> Self {
+        dmenu::start(dmenu::read_lines(std::io::stdin().lock()));
+
+        log!("Creating dmenu instance");
+        let app = Application::builder()
+            .application_id(APPLICATION_ID)
+            .flags(gio::ApplicationFlags::NON_UNIQUE)
+            .build();
+
+        Self {
+            app,
+            rt,
+            load_cancelled,
+        }
     }
 
     pub fn run(&self) -> i32 {
         let rt_handle = self.rt.handle().clone();
 
+        if !self.app.is_remote() {
+            Self::setup_actions(&self.app);
+        }
+
+        let load_cancelled = self.load_cancelled.clone();
+        self.app.connect_name_lost(move |app| {
+            log!("Replaced by another instance, shutting down");
+            load_cancelled.store(true, Ordering::Relaxed);
+            let app = app.clone();
+            clipboard::when_settled(move || app.quit());
+            true
+        });
+
+        let load_cancelled = self.load_cancelled.clone();
+        let app_for_signal = self.app.clone();
+        glib::unix_signal_add_local(SIGTERM, move || {
+            log!("Received SIGTERM, shutting down");
+            load_cancelled.store(true, Ordering::Relaxed);
+            app_for_signal.quit();
+            ControlFlow::Break
+        });
+
+        let rt_handle_for_open = rt_handle.clone();
         self.app.connect_activate(move |app| {
-            let windows = app.windows();
-            if let Some(window) = windows.first() {
-                window.present();
-            } else {
-                let window = LauncherWindow::new(app, rt_handle.clone());
-                window.present();
+            Self::present_window(app, &rt_handle);
+        });
+
+        self.app.connect_open(move |app, files, _| {
+            open_with::set_targets(files);
+            if let Some(window) = app.windows().first() {
+                LauncherWindow::refresh_results(window);
             }
+            Self::present_window(app, &rt_handle_for_open);
         });
 
-        let status = self.app.run();
+        let mut args = vec![env::args().next().unwrap_or_default()];
+        args.extend(Self::launch_arguments());
+        let status = self.app.run_with_args(&args);
 
         if !self.app.is_remote() {
             self.app.quit();
@@ -117,62 +270,258 @@ impl App {
             if let Some(instance_file) = Self::get_instance_file() {
                 let _ = fs::remove_file(instance_file);
             }
+            if let Some(runtime_dir) = Self::get_runtime_dir().filter(|_| !dmenu::is_active()) {
+                let _ = fs::remove_file(runtime_dir.join(control::CONTROL_SOCKET));
+            }
         }
 
+        if dmenu::is_active() {
+            return dmenu::exit_status();
+        }
         status.into()
     }
 
-    fn get_runtime_dir() -> PathBuf {
-        let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR").unwrap_or(String::from("/tmp"));
-        PathBuf::from(format!("{}/hyprlauncher", xdg_runtime_dir))
+    fn present_window(app: &Application, rt_handle: &Handle) {
+        let windows = app.windows();
+        if let Some(window) = windows.first() {
+            LauncherWindow::apply_mode(window);
+            window.present();
+        } else {
+            let window = LauncherWindow::new(app, rt_handle.clone());
+            window.present();
+            Self::clear_startup_attempts();
+        }
+    }
+
+    // Follows the active Hyprland workspace for `ranking.workspace_affinity`
+    // and hides the launcher when it changes.
+    fn watch_workspaces(app: &Application, rt: &Handle) {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        rt.spawn(crate::hyprland::watch_events(
+            &["workspace", "focusedmon"],
+            tx,
+        ));
+        rt.spawn_blocking(crate::hyprland::active_workspace);
+
+        let app = app.clone();
+        glib::MainContext::default().spawn_local(async move {
+            while let Some((event, data)) = rx.recv().await {
+                // `focusedmon>>MONITOR,WORKSPACE`
+                let workspace = match data.split_once(',') {
+                    Some((_, workspace)) if event == "focusedmon" => workspace,
+                    _ => &data,
+                };
+                crate::hyprland::set_current_workspace(Some(workspace.to_string()));
+
+                if !Config::load().window.hide_on_workspace_switch {
+                    continue;
+                }
+                if let Some(window) = app.windows().first() {
+                    log!("Hiding on Hyprland {} event", event);
+                    LauncherWindow::dismiss(window);
+                }
+            }
+        });
+    }
+
+    fn arguments() -> Vec<String> {
+        env::args().skip(1).collect()
+    }
+
+    fn launch_arguments() -> Vec<String> {
+        let mut args = Self::arguments().into_iter();
+        let mut launch_arguments = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == modes::MODE_ARGUMENT || arg == SET_THEME_ARGUMENT {
+                args.next();
+            } else if !arg.starts_with("--") {
+                launch_arguments.push(arg);
+            }
+        }
+        launch_arguments
+    }
+
+    // `--set-theme <name> [--persist]`
+    // Calls to the running instance are queued on the bus connection, exiting
+    // before they are written would drop them.
+    fn flush_and_exit(app: &Application, code: i32) -> ! {
+        if let Some(connection) = app.dbus_connection() {
+            if let Err(e) = connection.flush_sync(None::<&gio::Cancellable>) {
+                log!("Failed to flush the D-Bus connection: {}", e);
+            }
+        }
+        process::exit(code)
+    }
+
+    fn requested_theme() -> Option<(String, bool)> {
+        let args = Self::arguments();
+        let index = args.iter().position(|arg| arg == SET_THEME_ARGUMENT)?;
+        let name = args.get(index + 1)?.clone();
+        Some((name, args.iter().any(|arg| arg == PERSIST_ARGUMENT)))
+    }
+
+    fn launch_files() -> Vec<gio::File> {
+        Self::launch_arguments()
+            .iter()
+            .map(gio::File::for_commandline_arg)
+            .collect()
+    }
+
+    fn setup_actions(app: &Application) {
+        let plan_type = LaunchPlan::variant_type();
+
+        let retry = gio::SimpleAction::new("retry", Some(&plan_type));
+        retry.connect_activate(|_, parameter| {
+            if let Some(plan) = parameter.and_then(LaunchPlan::from_variant) {
+                log!("Retrying launch: {}", plan.name);
+                if let Err(e) = plan.launch(false) {
+                    log!("Retry failed: {}: {}", plan.exec, e);
+                }
+            }
+        });
+        app.add_action(&retry);
+
+        let copy_command = gio::SimpleAction::new("copy-command", Some(&plan_type));
+        copy_command.connect_activate(|_, parameter| {
+            if let Some(plan) = parameter.and_then(LaunchPlan::from_variant) {
+                launcher::copy_to_clipboard(&plan.exec);
+            }
+        });
+        app.add_action(&copy_command);
+
+        let open_desktop_file = gio::SimpleAction::new("open-desktop-file", Some(&plan_type));
+        open_desktop_file.connect_activate(|_, parameter| {
+            if let Some(plan) = parameter.and_then(LaunchPlan::from_variant) {
+                log!("Opening desktop file: {}", plan.path);
+                crate::launcher::open_uri(&plan.path);
+            }
+        });
+        app.add_action(&open_desktop_file);
+
+        let set_mode = gio::SimpleAction::new("set-mode", Some(glib::VariantTy::STRING));
+        set_mode.connect_activate(|_, parameter| {
+            if let Some(mode) = parameter.and_then(|parameter| parameter.get::<String>()) {
+                modes::set(&mode);
+            }
+        });
+        app.add_action(&set_mode);
+
+        // Takes the preset name and whether to save it as `theme_name`.
+        let set_theme =
+            gio::SimpleAction::new("set-theme", Some(&<(String, bool)>::static_variant_type()));
+        set_theme.connect_activate(|_, parameter| {
+            let Some((name, persist)) =
+                parameter.and_then(|parameter| parameter.get::<(String, bool)>())
+            else {
+                return;
+            };
+            if let Err(e) = switch_theme(&name, persist) {
+                log!("Warning: not switching theme: {}", e);
+            }
+        });
+        app.add_action(&set_theme);
+    }
+
+    fn get_runtime_dir() -> Option<PathBuf> {
+        let uid = current_uid();
+        let runtime_dir = match env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("hyprlauncher"),
+            _ => PathBuf::from(format!("/tmp/hyprlauncher-{}", uid)),
+        };
+
+        match prepare_private_dir(&runtime_dir, uid) {
+            Ok(()) => Some(runtime_dir),
+            Err(e) => {
+                log!("Not using runtime directory {:?}: {}", runtime_dir, e);
+                None
+            }
+        }
+    }
+
+    fn track_startup_attempt() {
+        let Some(attempts_file) =
+            Self::get_runtime_dir().map(|dir| dir.join(STARTUP_ATTEMPTS_FILE))
+        else {
+            return;
+        };
+        let crashed = fs::read_to_string(&attempts_file)
+            .ok()
+            .and_then(|contents| contents.trim().parse::<u32>().ok())
+            .unwrap_or(0);
+
+        if crashed >= MAX_CRASHED_STARTUPS {
+            log!(
+                "{} startups crashed before showing the window, entering safe mode",
+                crashed
+            );
+            let _ = crate::config::SAFE_MODE.set(format!(
+                "the last {} startups crashed before the window was shown",
+                crashed
+            ));
+        }
+
+        let _ = fs::write(&attempts_file, (crashed + 1).to_string());
+    }
+
+    fn clear_startup_attempts() {
+        if let Some(runtime_dir) = Self::get_runtime_dir() {
+            let _ = fs::remove_file(runtime_dir.join(STARTUP_ATTEMPTS_FILE));
+        }
     }
 
     fn get_instance_file() -> Option<PathBuf> {
-        let runtime_dir = Self::get_runtime_dir();
-        let pid = process::id();
-        Some(runtime_dir.join(format!("instance-{}", pid)))
+        Self::get_runtime_dir().map(|dir| dir.join(format!("instance-{}", process::id())))
     }
 
-    fn can_create_instance() -> bool {
-        let runtime_dir = Self::get_runtime_dir();
-        fs::create_dir_all(&runtime_dir)
-            .unwrap_or_else(|_| panic!("Failed to create runtime directory"));
+    fn can_create_instance(load_cancelled: Arc<AtomicBool>) -> bool {
+        let instance_file = Self::get_instance_file();
 
-        Self::cleanup_stale_instances(&runtime_dir);
+        if let Some(runtime_dir) = instance_file.as_deref().and_then(|file| file.parent()) {
+            Self::cleanup_stale_instances(runtime_dir);
 
-        let instances: Vec<_> = fs::read_dir(&runtime_dir)
-            .unwrap_or_else(|_| panic!("Failed to read runtime directory"))
-            .filter_map(Result::ok)
-            .filter(|entry| entry.file_name().to_string_lossy().starts_with("instance-"))
-            .collect();
+            let instances = fs::read_dir(runtime_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .filter(|entry| {
+                            entry.file_name().to_string_lossy().starts_with("instance-")
+                        })
+                        .count()
+                })
+                .unwrap_or(0);
 
-        if instances.len() >= 2 {
-            return false;
+            if instances >= 2 {
+                return false;
+            }
         }
 
-        let pid = process::id();
-        let instance_file = runtime_dir.join(format!("instance-{}", pid));
-        let mut file = File::create(&instance_file).unwrap();
-        let _ = writeln!(
-            file,
-            "{}",
-            time::SystemTime::now()
+        if let Some(instance_file) = &instance_file {
+            let timestamp = time::SystemTime::now()
                 .duration_since(time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        );
+                .unwrap_or_default()
+                .as_secs();
+            if let Err(e) =
+                File::create(instance_file).and_then(|mut file| writeln!(file, "{}", timestamp))
+            {
+                log!("Failed to write instance file {:?}: {}", instance_file, e);
+            }
+        }
 
-        let instance_file_clone = instance_file.clone();
-        ctrlc::set_handler(move || {
-            let _ = fs::remove_file(&instance_file_clone);
+        let result = ctrlc::set_handler(move || {
+            load_cancelled.store(true, Ordering::Relaxed);
+            if let Some(instance_file) = &instance_file {
+                let _ = fs::remove_file(instance_file);
+            }
             process::exit(0);
-        })
-        .expect("Error setting Ctrl-C handler");
+        });
+        if let Err(e) = result {
+            log!("Failed to set Ctrl-C handler: {}", e);
+        }
 
         true
     }
 
-    fn cleanup_stale_instances(runtime_dir: &PathBuf) {
+    fn cleanup_stale_instances(runtime_dir: &Path) {
         if let Ok(entries) = fs::read_dir(runtime_dir) {
             for entry in entries.filter_map(Result::ok) {
                 let path = entry.path();
@@ -190,7 +539,137 @@ impl App {
     }
 }
 
+// Switches to the theme preset `name` for the session, see
+// Config::set_session_theme, and restyles the window through the theme diff.
+// Runs on the main thread, for the `set-theme` action and control socket.
+pub fn switch_theme(name: &str, persist: bool) -> Result<(), String> {
+    let old_config = Config::load();
+    Config::set_session_theme(name, persist)?;
+    log!("Switched to theme {}", name);
+
+    let new_config = Config::load();
+    let changes = old_config.diff(&new_config);
+    if let Some(window) = gio::Application::default()
+        .and_downcast::<Application>()
+        .and_then(|app| app.windows().first().cloned())
+        .and_downcast::<ApplicationWindow>()
+        .filter(|_| changes.any())
+    {
+        LauncherWindow::update_window_config(&window, &new_config, changes);
+    }
+    Ok(())
+}
+
+fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+fn prepare_private_dir(dir: &Path, uid: u32) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+
+    if let Err(e) = fs::DirBuilder::new().mode(0o700).create(dir) {
+        if e.kind() != ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+
+    let meta = fs::symlink_metadata(dir)?;
+    if !meta.is_dir() {
+        return Err(Error::other("not a directory"));
+    }
+    if meta.uid() != uid {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("owned by uid {} instead of {}", meta.uid(), uid),
+        ));
+    }
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(())
+}
+
 #[cfg(target_os = "linux")]
 fn process_exists(pid: u32) -> bool {
     std::path::Path::new(&format!("/proc/{}", pid)).exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::io::ErrorKind;
+
+    fn mode(path: &Path) -> u32 {
+        fs::symlink_metadata(path).unwrap().mode() & 0o777
+    }
+
+    #[test]
+    fn private_dir_is_created_for_the_owner_only() {
+        let parent = temp_dir("private-dir-created");
+        let dir = parent.join("hyprlauncher");
+        let uid = current_uid();
+
+        prepare_private_dir(&dir, uid).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(fs::metadata(&dir).unwrap().uid(), uid);
+        // Preparing it again leaves it alone.
+        prepare_private_dir(&dir, uid).unwrap();
+        let _ = fs::remove_dir_all(&parent);
+    }
+
+    #[test]
+    fn loose_permissions_are_tightened() {
+        let parent = temp_dir("private-dir-loose");
+        let dir = parent.join("hyprlauncher");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+        prepare_private_dir(&dir, current_uid()).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        let _ = fs::remove_dir_all(&parent);
+    }
+
+    #[test]
+    fn directories_of_another_user_are_refused() {
+        let parent = temp_dir("private-dir-owner");
+        let dir = parent.join("hyprlauncher");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let other = current_uid() + 1;
+        let e = prepare_private_dir(&dir, other).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        // Nothing is changed on a directory that isn't ours.
+        assert_eq!(mode(&dir), 0o755);
+        let _ = fs::remove_dir_all(&parent);
+    }
+
+    #[test]
+    fn symlinks_and_files_are_refused() {
+        let parent = temp_dir("private-dir-symlink");
+        let target = parent.join("elsewhere");
+        fs::create_dir(&target).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+        let link = parent.join("hyprlauncher");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let file = parent.join("file");
+        fs::write(&file, "").unwrap();
+        let uid = current_uid();
+
+        assert!(prepare_private_dir(&link, uid).is_err());
+        assert_eq!(mode(&target), 0o755);
+        assert!(prepare_private_dir(&file, uid).is_err());
+        let _ = fs::remove_dir_all(&parent);
+    }
+
+    #[test]
+    fn missing_parents_are_an_error() {
+        let parent = temp_dir("private-dir-missing");
+        let dir = parent.join("missing").join("hyprlauncher");
+        let e = prepare_private_dir(&dir, current_uid()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+        let _ = fs::remove_dir_all(&parent);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    launcher::{AppEntry, EntryType},
+    log,
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use once_cell::sync::Lazy;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+pub const BOOKMARK_QUERY_PREFIX: &str = "bookmarks:";
+
+static BOOKMARKS_PATH: &str = "~/.local/share/hyprlauncher/bookmarks.json";
+
+// Paths are kept as bookmarked, with `~` expanded, oldest first.
+static BOOKMARKS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(load()));
+
+fn bookmarks_path() -> PathBuf {
+    PathBuf::from(shellexpand::tilde(BOOKMARKS_PATH).as_ref())
+}
+
+fn load() -> Vec<String> {
+    load_from(&bookmarks_path())
+}
+
+fn load_from(path: &Path) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log!("Ignoring malformed bookmarks in {}: {}", path.display(), e);
+        Vec::new()
+    })
+}
+
+fn save_to(path: &Path, bookmarks: &[String]) -> Result<(), std::io::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(serde_json::to_string_pretty(bookmarks)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+// Changes a copy of the bookmarks, which replaces them once it is saved.
+fn update<R>(change: impl FnOnce(&mut Vec<String>) -> R) -> Result<R, std::io::Error> {
+    let mut bookmarks = BOOKMARKS.write().unwrap();
+    let mut updated = bookmarks.clone();
+    let result = change(&mut updated);
+    save_to(&bookmarks_path(), &updated)?;
+    *bookmarks = updated;
+    Ok(result)
+}
+
+pub fn contains(path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    BOOKMARKS.read().unwrap().iter().any(|known| known == path)
+}
+
+// Bookmarks the folder, or removes its bookmark. Returns whether it is
+// bookmarked now.
+pub fn toggle(path: &str) -> Result<bool, std::io::Error> {
+    update(|bookmarks| toggled(bookmarks, path))
+}
+
+pub fn remove(path: &str) -> Result<(), std::io::Error> {
+    let path = path.trim_end_matches('/');
+    update(|bookmarks| bookmarks.retain(|known| known != path))
+}
+
+fn toggled(bookmarks: &mut Vec<String>, path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    match bookmarks.iter().position(|known| known == path) {
+        Some(index) => {
+            bookmarks.remove(index);
+            false
+        }
+        None => {
+            bookmarks.push(path.to_string());
+            true
+        }
+    }
+}
+
+pub fn is_listing(query: &str) -> bool {
+    query.starts_with(BOOKMARK_QUERY_PREFIX)
+}
+
+// The `bookmarks:` listing: every bookmark whose path matches `filter`, in
+// any case.
+pub fn listing(filter: &str) -> Vec<AppEntry> {
+    listing_of(&BOOKMARKS.read().unwrap(), filter)
+}
+
+fn listing_of(bookmarks: &[String], filter: &str) -> Vec<AppEntry> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    bookmarks
+        .iter()
+        .filter(|path| filter.is_empty() || matcher.fuzzy_match(path, filter).is_some())
+        .map(|path| entry(path))
+        .collect()
+}
+
+pub fn entry(path: &str) -> AppEntry {
+    let name = Path::new(path).file_name().map_or_else(
+        || path.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    AppEntry {
+        id: path.to_string(),
+        name,
+        description: path.to_string(),
+        icon_name: "folder".into(),
+        path: path.to_string(),
+        entry_type: EntryType::File,
+        ..Default::default()
+    }
+}
+
+// Bookmarks whose folder name or path matches the query, with the best of
+// the two scores. Folders that no longer exist are left out but stay
+// bookmarked, e.g. on an unmounted drive.
+pub fn matching_entries(query: &str, matcher: &SkimMatcherV2) -> Vec<(AppEntry, i64)> {
+    BOOKMARKS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|path| Path::new(path).is_dir())
+        .filter_map(|path| {
+            let entry = entry(path);
+            let score = matcher
+                .fuzzy_match(&entry.name.to_lowercase(), query)
+                .max(matcher.fuzzy_match(&path.to_lowercase(), query))?;
+            Some((entry, score))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn temp_file(name: &str) -> PathBuf {
+        temp_dir(&format!("bookmarks-{}", name)).join("bookmarks.json")
+    }
+
+    #[test]
+    fn bookmarks_round_trip_through_their_file() {
+        let path = temp_file("round-trip");
+        let mut bookmarks = Vec::new();
+        assert!(toggled(&mut bookmarks, "/home/user/src/"));
+        assert!(toggled(&mut bookmarks, "/mnt/photos"));
+        save_to(&path, &bookmarks).unwrap();
+
+        assert_eq!(load_from(&path), ["/home/user/src", "/mnt/photos"]);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        assert!(!toggled(&mut bookmarks, "/home/user/src"));
+        save_to(&path, &bookmarks).unwrap();
+        assert_eq!(load_from(&path), ["/mnt/photos"]);
+    }
+
+    #[test]
+    fn unreadable_files_have_no_bookmarks() {
+        let path = temp_file("unreadable");
+        assert!(load_from(&path).is_empty());
+
+        fs::write(&path, "{not json").unwrap();
+        assert!(load_from(&path).is_empty());
+    }
+
+    #[test]
+    fn listings_filter_paths_in_any_case() {
+        let bookmarks = [String::from("/home/user/Projects"), String::from("/tmp")];
+        let listed = |filter| -> Vec<String> {
+            listing_of(&bookmarks, filter)
+                .into_iter()
+                .map(|app| app.path)
+                .collect()
+        };
+        assert_eq!(listed(""), ["/home/user/Projects", "/tmp"]);
+        assert_eq!(listed("proj"), ["/home/user/Projects"]);
+        assert_eq!(listed("PROJ"), ["/home/user/Projects"]);
+        assert!(listed("music").is_empty());
+    }
+
+    #[test]
+    fn only_the_prefix_starts_a_listing() {
+        assert!(is_listing("bookmarks:"));
+        assert!(is_listing("bookmarks:src"));
+        assert!(!is_listing("bookmarks"));
+        assert!(!is_listing("src bookmarks:"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{exec, launcher::is_executable, log};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, path::Path, sync::RwLock};
+
+pub struct Tool {
+    pub name: &'static str,
+    pub feature: &'static str,
+}
+
+pub const XDG_OPEN: &str = "xdg-open";
+pub const HYPRCTL: &str = "hyprctl";
+pub const WL_COPY: &str = "wl-copy";
+// Stands for the program named by the variable.
+pub const TERMINAL: &str = "$TERMINAL";
+
+// External programs that features depend on but that may not be installed.
+pub const TOOLS: &[Tool] = &[
+    Tool {
+        name: XDG_OPEN,
+        feature: "opening files, folders and links",
+    },
+    Tool {
+        name: HYPRCTL,
+        feature: "focus restoration, capture protection and window rules",
+    },
+    Tool {
+        name: WL_COPY,
+        feature: "keeping copied values after the launcher exits",
+    },
+    Tool {
+        name: TERMINAL,
+        feature: "terminal custom entries and opening folders in a terminal",
+    },
+];
+
+static AVAILABLE: Lazy<RwLock<HashMap<&'static str, bool>>> =
+    Lazy::new(|| RwLock::new(probe_tools()));
+
+fn probe_tools() -> HashMap<&'static str, bool> {
+    let paths: Vec<_> = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    TOOLS
+        .iter()
+        .map(|tool| {
+            let found = program(tool.name).is_some_and(|program| {
+                if program.contains('/') {
+                    is_executable(Path::new(&program))
+                } else {
+                    paths.iter().any(|dir| is_executable(&dir.join(&program)))
+                }
+            });
+            if !found {
+                log!("{}", explain(tool.name));
+            }
+            (tool.name, found)
+        })
+        .collect()
+}
+
+// The program to look for, the first word of $TERMINAL for TERMINAL.
+fn program(name: &str) -> Option<String> {
+    if name != TERMINAL {
+        return Some(name.to_string());
+    }
+    let terminal = std::env::var("TERMINAL").ok()?;
+    exec::split(&terminal).ok()?.into_iter().next()
+}
+
+// Looks for the tools again, e.g. after the config was reloaded, since they
+// may have been installed while the launcher was running.
+pub fn probe() {
+    let available = probe_tools();
+    *AVAILABLE.write().unwrap() = available;
+}
+
+pub fn is_available(name: &str) -> bool {
+    AVAILABLE.read().unwrap().get(name).copied().unwrap_or(true)
+}
+
+pub fn missing() -> Vec<&'static Tool> {
+    TOOLS
+        .iter()
+        .filter(|tool| !is_available(tool.name))
+        .collect()
+}
+
+// A one-line explanation for rows and logs, e.g. "xdg-open not found in PATH,
+// opening files, folders and links is unavailable".
+pub fn explain(name: &str) -> String {
+    let feature = TOOLS
+        .iter()
+        .find(|tool| tool.name == name)
+        .map_or("a feature", |tool| tool.feature);
+    format!("{} not found in PATH, {} is unavailable", name, feature)
+}
@@ -0,0 +1,58 @@
+use crate::launcher::{AppEntry, EntryType};
+use std::collections::HashMap;
+
+pub const CATEGORY_QUERY_PREFIX: &str = "category:";
+
+// XDG main categories with the name and icon shown in the start view.
+pub const MAIN_CATEGORIES: &[(&str, &str, &str)] = &[
+    ("AudioVideo", "Multimedia", "applications-multimedia"),
+    ("Development", "Development", "applications-development"),
+    ("Education", "Education", "applications-education"),
+    ("Game", "Games", "applications-games"),
+    ("Graphics", "Graphics", "applications-graphics"),
+    ("Network", "Internet", "applications-internet"),
+    ("Office", "Office", "applications-office"),
+    ("Science", "Science", "applications-science"),
+    ("Settings", "Settings", "preferences-system"),
+    ("System", "System", "applications-system"),
+    ("Utility", "Accessories", "applications-utilities"),
+];
+
+pub fn category_entries<'a>(apps: impl Iterator<Item = &'a AppEntry>) -> Vec<AppEntry> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for app in apps {
+        for (id, _, _) in MAIN_CATEGORIES {
+            if app.categories.iter().any(|category| category == id) {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+    }
+
+    MAIN_CATEGORIES
+        .iter()
+        .filter_map(|(id, name, icon)| {
+            let count = counts.get(id)?;
+            Some(AppEntry {
+                id: format!("{}{}", CATEGORY_QUERY_PREFIX, id),
+                name: name.to_string(),
+                description: match count {
+                    1 => String::from("1 application"),
+                    count => format!("{} applications", count),
+                },
+                exec: format!("{}{}", CATEGORY_QUERY_PREFIX, id),
+                icon_name: (*icon).into(),
+                entry_type: EntryType::Query,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+pub fn in_category(app: &AppEntry, query: &str) -> bool {
+    let Some(category) = query.strip_prefix(CATEGORY_QUERY_PREFIX) else {
+        return false;
+    };
+    app.categories
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(category))
+}
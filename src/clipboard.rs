@@ -0,0 +1,251 @@
+use crate::{config::Config, log};
+use gtk4::{gdk, gio, glib, prelude::*, subclass::prelude::*};
+use std::{cell::RefCell, future::Future, pin::Pin, time::Duration};
+
+// A value copied through GTK is served by the launcher itself, and Wayland
+// drops it with the launcher. Until it is read once, another client takes
+// over the clipboard or `launcher.clipboard_hold_ms` passes, the application
+// is held and leaving is put off, see when_settled.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Offer {
+    #[default]
+    Settled,
+    Pending(u64),
+}
+
+// Each copy is numbered, so that news of an earlier one, e.g. its grace
+// period running out, doesn't settle a later one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OfferEvent {
+    Offered(u64),
+    Read(u64),
+    Replaced(u64),
+    Expired(u64),
+}
+
+impl Offer {
+    fn after(self, event: OfferEvent) -> Self {
+        match (self, event) {
+            (_, OfferEvent::Offered(id)) => Offer::Pending(id),
+            (
+                Offer::Pending(pending),
+                OfferEvent::Read(id) | OfferEvent::Replaced(id) | OfferEvent::Expired(id),
+            ) if pending == id => Offer::Settled,
+            (offer, _) => offer,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Offers {
+    offer: Offer,
+    last_id: u64,
+    hold: Option<gio::ApplicationHoldGuard>,
+    waiting: Vec<Box<dyn FnOnce()>>,
+}
+
+thread_local! {
+    static OFFERS: RefCell<Offers> = RefCell::new(Offers::default());
+}
+
+fn handle(event: OfferEvent) {
+    let waiting = OFFERS.with_borrow_mut(|offers| {
+        let before = offers.offer;
+        offers.offer = before.after(event);
+        match (before, offers.offer) {
+            (Offer::Pending(_), Offer::Settled) => {
+                log!("Clipboard offer settled: {:?}", event);
+                offers.hold = None;
+                std::mem::take(&mut offers.waiting)
+            }
+            _ => Vec::new(),
+        }
+    });
+    for then in waiting {
+        then();
+    }
+}
+
+// Puts `text` on the clipboard, served by the launcher.
+pub fn offer(text: &str) {
+    let Some(display) = gdk::Display::default() else {
+        return;
+    };
+    let id = OFFERS.with_borrow_mut(|offers| {
+        offers.last_id += 1;
+        offers.last_id
+    });
+    let hold_ms = Config::load().launcher.clipboard_hold_ms;
+    if hold_ms > 0 {
+        handle(OfferEvent::Offered(id));
+        OFFERS.with_borrow_mut(|offers| {
+            if offers.hold.is_none() {
+                offers.hold = gio::Application::default().map(|app| app.hold());
+            }
+        });
+        glib::timeout_add_local_once(Duration::from_millis(hold_ms), move || {
+            handle(OfferEvent::Expired(id))
+        });
+    }
+    display
+        .clipboard()
+        .set_content(Some(&ClipboardText::new(text, id)))
+        .unwrap_or_else(|e| log!("Failed to set the clipboard: {}", e));
+}
+
+// Runs `then` once no copied value is waiting to be read, right away when
+// none is.
+pub fn when_settled(then: impl FnOnce() + 'static) {
+    let then: Box<dyn FnOnce()> = Box::new(then);
+    let now = OFFERS.with_borrow_mut(|offers| match offers.offer {
+        Offer::Settled => Some(then),
+        Offer::Pending(_) => {
+            log!("Waiting for the copied value to be read");
+            offers.waiting.push(then);
+            None
+        }
+    });
+    if let Some(then) = now {
+        then();
+    }
+}
+
+glib::wrapper! {
+    pub struct ClipboardText(ObjectSubclass<imp::ClipboardText>)
+        @extends gdk::ContentProvider;
+}
+
+mod imp {
+    use super::*;
+    use std::cell::{Cell, OnceCell};
+
+    // Serves the text like GTK's own provider, noting when it is read and
+    // when the clipboard lets go of it.
+    #[derive(Default)]
+    pub struct ClipboardText {
+        pub(super) inner: OnceCell<gdk::ContentProvider>,
+        pub(super) offer: Cell<u64>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ClipboardText {
+        const NAME: &'static str = "ClipboardText";
+        type Type = super::ClipboardText;
+        type ParentType = gdk::ContentProvider;
+    }
+
+    impl ObjectImpl for ClipboardText {}
+
+    impl ContentProviderImpl for ClipboardText {
+        fn detach_clipboard(&self, clipboard: &gdk::Clipboard) {
+            self.parent_detach_clipboard(clipboard);
+            handle(OfferEvent::Replaced(self.offer.get()));
+        }
+
+        fn formats(&self) -> gdk::ContentFormats {
+            self.inner().formats()
+        }
+
+        fn storable_formats(&self) -> gdk::ContentFormats {
+            self.inner().storable_formats()
+        }
+
+        fn write_mime_type_future(
+            &self,
+            mime_type: &str,
+            stream: &gio::OutputStream,
+            io_priority: glib::Priority,
+        ) -> Pin<Box<dyn Future<Output = Result<(), glib::Error>> + 'static>> {
+            let offer = self.offer.get();
+            let written = self
+                .inner()
+                .write_mime_type_future(mime_type, stream, io_priority);
+            Box::pin(async move {
+                let result = written.await;
+                handle(OfferEvent::Read(offer));
+                result
+            })
+        }
+
+        fn value(&self, type_: glib::Type) -> Result<glib::Value, glib::Error> {
+            self.inner().value(type_)
+        }
+    }
+
+    impl ClipboardText {
+        fn inner(&self) -> &gdk::ContentProvider {
+            self.inner
+                .get_or_init(|| gdk::ContentProvider::for_value(&"".to_value()))
+        }
+    }
+}
+
+impl ClipboardText {
+    fn new(text: &str, offer: u64) -> Self {
+        let provider: Self = glib::Object::new();
+        let imp = provider.imp();
+        let _ = imp
+            .inner
+            .set(gdk::ContentProvider::for_value(&text.to_value()));
+        imp.offer.set(offer);
+        provider
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_settle_once_read_replaced_or_expired() {
+        for settling in [
+            OfferEvent::Read(1),
+            OfferEvent::Replaced(1),
+            OfferEvent::Expired(1),
+        ] {
+            let offer = Offer::Settled.after(OfferEvent::Offered(1));
+            assert_eq!(offer, Offer::Pending(1));
+            assert_eq!(offer.after(settling), Offer::Settled);
+        }
+    }
+
+    #[test]
+    fn news_of_earlier_offers_leaves_later_ones_pending() {
+        let offer = Offer::Settled
+            .after(OfferEvent::Offered(1))
+            .after(OfferEvent::Offered(2));
+        assert_eq!(offer.after(OfferEvent::Expired(1)), Offer::Pending(2));
+        assert_eq!(offer.after(OfferEvent::Replaced(1)), Offer::Pending(2));
+        assert_eq!(offer.after(OfferEvent::Read(2)), Offer::Settled);
+    }
+
+    #[test]
+    fn settled_offers_stay_settled() {
+        for event in [
+            OfferEvent::Read(1),
+            OfferEvent::Replaced(1),
+            OfferEvent::Expired(1),
+        ] {
+            assert_eq!(Offer::Settled.after(event), Offer::Settled);
+        }
+    }
+
+    #[test]
+    fn waiting_runs_once_the_offer_settles() {
+        let ran = std::rc::Rc::new(std::cell::Cell::new(0));
+        handle(OfferEvent::Offered(7));
+        let counter = ran.clone();
+        when_settled(move || counter.set(counter.get() + 1));
+        assert_eq!(ran.get(), 0);
+
+        handle(OfferEvent::Expired(6));
+        assert_eq!(ran.get(), 0);
+        handle(OfferEvent::Read(7));
+        assert_eq!(ran.get(), 1);
+
+        let counter = ran.clone();
+        when_settled(move || counter.set(counter.get() + 1));
+        assert_eq!(ran.get(), 2);
+    }
+}
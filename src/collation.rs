@@ -0,0 +1,155 @@
+use crate::log;
+use gtk4::glib;
+use once_cell::sync::Lazy;
+use std::{
+    ffi::CString,
+    sync::{Arc, RwLock},
+};
+
+extern "C" {
+    // Part of POSIX 2008, but not bound by the libc crate.
+    fn strxfrm_l(
+        dest: *mut libc::c_char,
+        src: *const libc::c_char,
+        n: libc::size_t,
+        locale: libc::locale_t,
+    ) -> libc::size_t;
+}
+
+// Set from `search.collation_locale` whenever the configuration is loaded.
+static CURRENT: Lazy<RwLock<Arc<Collator>>> =
+    Lazy::new(|| RwLock::new(Arc::new(Collator::new(""))));
+
+struct Locale(libc::locale_t);
+
+// A locale object is only read once created.
+unsafe impl Send for Locale {}
+unsafe impl Sync for Locale {}
+
+impl Drop for Locale {
+    fn drop(&mut self) {
+        unsafe { libc::freelocale(self.0) }
+    }
+}
+
+// Sort keys following the collation rules of a locale. The locale is opened
+// on its own rather than set for the process, which other threads read.
+pub struct Collator {
+    name: String,
+    locale: Option<Locale>,
+}
+
+impl Collator {
+    // An empty name takes LC_COLLATE, LC_ALL or LANG from the environment.
+    pub fn new(name: &str) -> Self {
+        let locale = CString::new(name).ok().and_then(|c_name| {
+            let locale = unsafe {
+                libc::newlocale(libc::LC_COLLATE_MASK, c_name.as_ptr(), std::ptr::null_mut())
+            };
+            (!locale.is_null()).then(|| Locale(locale))
+        });
+        if locale.is_none() {
+            log!(
+                "Collation locale {:?} is not available, sorting names by code point",
+                name
+            );
+        }
+        Self {
+            name: name.to_string(),
+            locale,
+        }
+    }
+
+    // Keys compare like the names would with strcoll, ignoring case.
+    pub fn key(&self, name: &str) -> Vec<u8> {
+        let folded = glib::casefold(name);
+        let (Some(locale), Ok(source)) = (&self.locale, CString::new(folded.as_str())) else {
+            return folded.as_bytes().to_vec();
+        };
+
+        let mut key = vec![0u8; source.as_bytes().len() * 4 + 1];
+        loop {
+            let length = unsafe {
+                strxfrm_l(
+                    key.as_mut_ptr().cast(),
+                    source.as_ptr(),
+                    key.len(),
+                    locale.0,
+                )
+            };
+            if length < key.len() {
+                key.truncate(length);
+                return key;
+            }
+            key.resize(length + 1, 0);
+        }
+    }
+}
+
+pub fn current() -> Arc<Collator> {
+    CURRENT.read().unwrap().clone()
+}
+
+pub fn configure(name: &str) {
+    if current().name != name {
+        *CURRENT.write().unwrap() = Arc::new(Collator::new(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(collator: &Collator, names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by_cached_key(|name| collator.key(name));
+        names
+    }
+
+    #[test]
+    fn names_sort_ignoring_case() {
+        let collator = Collator::new("C.UTF-8");
+        assert_eq!(
+            sorted(&collator, &["cherry", "Banana", "apple", "BANANA split"]),
+            ["apple", "Banana", "BANANA split", "cherry"]
+        );
+    }
+
+    #[test]
+    fn the_c_locale_orders_by_code_point() {
+        let collator = Collator::new("C.UTF-8");
+        assert_eq!(
+            sorted(&collator, &["émile", "Zeta", "Eagle"]),
+            ["Eagle", "Zeta", "émile"]
+        );
+    }
+
+    #[test]
+    fn unknown_locales_fall_back_to_code_points() {
+        let collator = Collator::new("xx_XX.UTF-8");
+        assert!(collator.locale.is_none());
+        assert_eq!(collator.key("Firefox"), b"firefox");
+        assert_eq!(
+            sorted(&collator, &["émile", "Zeta", "Eagle"]),
+            ["Eagle", "Zeta", "émile"]
+        );
+    }
+
+    #[test]
+    fn long_names_get_complete_keys() {
+        let collator = Collator::new("C.UTF-8");
+        let name = "x".repeat(1000);
+        assert_eq!(collator.key(&name).len(), 1000);
+    }
+
+    #[test]
+    fn configuring_the_same_locale_keeps_the_collator() {
+        configure("C.UTF-8");
+        let before = current();
+        configure("C.UTF-8");
+        assert!(Arc::ptr_eq(&before, &current()));
+        configure("POSIX");
+        assert_eq!(current().name, "POSIX");
+        configure("");
+    }
+}
@@ -1,16 +1,20 @@
-use crate::log;
+use crate::{collation, keymap, log};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
     env, fs,
-    path::PathBuf,
+    io::Write,
+    ops::Deref,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::channel,
-        LazyLock,
+        LazyLock, Mutex, OnceLock, RwLock,
     },
     thread,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 static CONFIG_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
@@ -37,6 +41,35 @@ static CONFIG_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
 
 pub static LOGGING_ENABLED: AtomicBool = AtomicBool::new(false);
 
+pub static SAFE_MODE: OnceLock<String> = OnceLock::new();
+
+// Theme preset chosen with `set-theme` for the rest of the session, taking
+// precedence over `theme_name`.
+static SESSION_THEME: RwLock<Option<String>> = RwLock::new(None);
+
+// The config file modification time and theme the last warnings were logged
+// for. Config::load runs for most actions, the warnings only need repeating
+// after a reload.
+type WarningStamp = (Option<SystemTime>, String);
+static WARNED_FOR: Mutex<Option<WarningStamp>> = Mutex::new(None);
+
+// The theme files read by Config::load_theme, with their modification time
+// and the preset merged with the defaults. Config::load runs on every
+// keypress, a file is only read again once it changes.
+type ThemeStamp = (SystemTime, serde_json::Value, Sources);
+static THEMES_READ: LazyLock<Mutex<HashMap<PathBuf, ThemeStamp>>> = LazyLock::new(Default::default);
+
+// Where each setting of a loaded configuration came from, keyed by its
+// dotted path, e.g. "window.width" -> "config.json". See Config::load_traced.
+pub type Sources = BTreeMap<String, String>;
+
+thread_local! {
+    static MASKING: Cell<bool> = const { Cell::new(false) };
+}
+
+const MIN_WINDOW_SIZE: i32 = 100;
+const MAX_BORDER_WIDTH: i32 = 50;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Corners {
     pub window: i32,
@@ -71,6 +104,7 @@ pub struct Colors {
     pub item_path: String,
     pub item_path_selected: String,
     pub border: String,
+    pub entry_icons: String,
 }
 
 impl Default for Colors {
@@ -91,6 +125,7 @@ impl Default for Colors {
             item_path: String::from("#808080"),
             item_path_selected: String::from("#808080"),
             border: String::from("#333333"),
+            entry_icons: String::from("#808080"),
         }
     }
 }
@@ -135,12 +170,114 @@ impl Default for Typography {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Animations {
+    pub enabled: bool,
+    pub duration: u32,
+}
+
+impl Default for Animations {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duration: 200,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Shadow {
+    pub enabled: bool,
+    pub size: i32,
+    pub color: String,
+    pub offset_x: i32,
+    pub offset_y: i32,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            size: 16,
+            color: String::from("rgba(0, 0, 0, 0.5)"),
+            offset_x: 0,
+            offset_y: 4,
+        }
+    }
+}
+
+impl Shadow {
+    // Space around the window the layer surface grows by so the shadow
+    // isn't clipped.
+    pub fn extent(&self) -> i32 {
+        if self.enabled {
+            self.size.max(0) + self.offset_x.abs().max(self.offset_y.abs())
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Theme {
     pub colors: Colors,
     pub corners: Corners,
     pub spacing: Spacing,
     pub typography: Typography,
+    pub animations: Animations,
+    pub shadow: Shadow,
+    pub icon_cache_mb: usize,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            colors: Colors::default(),
+            corners: Corners::default(),
+            spacing: Spacing::default(),
+            typography: Typography::default(),
+            animations: Animations::default(),
+            shadow: Shadow::default(),
+            icon_cache_mb: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConfigChanges {
+    pub theme: bool,
+    pub geometry: bool,
+    pub margins: bool,
+    pub anchors: bool,
+    pub behavior: bool,
+    pub search: bool,
+    pub applications: bool,
+    pub favorites: bool,
+}
+
+impl ConfigChanges {
+    pub fn any(&self) -> bool {
+        self.theme
+            || self.geometry
+            || self.margins
+            || self.anchors
+            || self.behavior
+            || self.search
+            || self.applications
+            || self.favorites
+    }
+
+    // Whether only layer surface state changed, which is applied to the live
+    // surface without touching the contents.
+    pub fn surface_only(&self) -> bool {
+        (self.margins || self.anchors)
+            && *self
+                == ConfigChanges {
+                    margins: self.margins,
+                    anchors: self.anchors,
+                    ..Default::default()
+                }
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -148,7 +285,20 @@ pub struct Theme {
 pub struct Config {
     pub window: Window,
     pub theme: Theme,
+    pub theme_name: String,
     pub debug: Debug,
+    pub keys: Keys,
+    pub search: Search,
+    pub hooks: Hooks,
+    pub ranking: Ranking,
+    pub files: Files,
+    pub launcher: Launcher,
+    pub custom_actions: CustomActions,
+    pub modes: Modes,
+    // Names under `keys` that aren't actions. Merging with the defaults drops
+    // them, so they are kept here to be reported.
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
 }
 
 #[allow(non_camel_case_types)]
@@ -165,17 +315,64 @@ pub enum WindowAnchor {
     bottom_right,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum SurfaceMode {
+    layer,
+    window,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum PathEllipsize {
+    start,
+    middle,
+    end,
+}
+
+// Who serves copied values: the launcher itself, or a `wl-copy` process
+// that keeps them available after the launcher exits.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum ClipboardMode {
+    gtk,
+    wl_copy,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub enum FolderAction {
+    descend,
+    open,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum StartView {
+    frecency,
+    categories,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Window {
+    pub surface_mode: SurfaceMode,
+    pub special_workspace: String,
     pub width: i32,
     pub height: i32,
     pub anchor: WindowAnchor,
+    pub anchor_edges: Vec<String>,
     pub margin_top: i32,
     pub margin_bottom: i32,
     pub margin_left: i32,
     pub margin_right: i32,
     pub show_descriptions: bool,
+    pub description_lines: u32,
     pub show_paths: bool,
+    pub path_ellipsize: PathEllipsize,
+    pub start_view: StartView,
+    pub recent_count: usize,
+    pub recent_separator: bool,
+    pub hover_path_footer: bool,
     pub show_icons: bool,
     pub show_search: bool,
     pub custom_navigate_keys: NavigateKeys,
@@ -183,19 +380,172 @@ pub struct Window {
     pub border_width: i32,
     pub use_gtk_colors: bool,
     pub max_entries: usize,
+    pub hide_from_capture: bool,
+    pub restore_focus: bool,
+    pub hide_on_workspace_switch: bool,
+    pub show_launch_errors: bool,
+}
+
+impl Window {
+    pub fn apply_mode_overrides(&mut self, overrides: &ModeWindow) {
+        if overrides.width > 0 {
+            self.width = overrides.width;
+        }
+        if overrides.height > 0 {
+            self.height = overrides.height;
+        }
+        if !overrides.anchor.is_empty() {
+            match serde_json::from_value(serde_json::Value::from(overrides.anchor.as_str())) {
+                Ok(anchor) => {
+                    self.anchor = anchor;
+                    self.anchor_edges.clear();
+                }
+                Err(_) => log!("Unknown mode anchor {:?}, ignoring", overrides.anchor),
+            }
+        }
+        let mut warnings = Vec::new();
+        self.clamp_geometry(&mut warnings);
+        for warning in warnings {
+            log!("{}", warning);
+        }
+    }
+
+    pub fn edge_anchors(&self) -> Option<[bool; 4]> {
+        if self.anchor_edges.is_empty() {
+            return None;
+        }
+
+        let mut anchors = [false; 4];
+        for edge in &self.anchor_edges {
+            let index = match edge.as_str() {
+                "top" => 0,
+                "right" => 1,
+                "bottom" => 2,
+                "left" => 3,
+                _ => {
+                    log!(
+                        "Unknown edge {:?} in window.anchor_edges, using window.anchor",
+                        edge
+                    );
+                    return None;
+                }
+            };
+            if anchors[index] {
+                log!(
+                    "Edge {:?} is listed twice in window.anchor_edges, using window.anchor",
+                    edge
+                );
+                return None;
+            }
+            anchors[index] = true;
+        }
+
+        if anchors[1] && anchors[3] {
+            log!("window.width is ignored, the window stretches between left and right");
+        }
+        if anchors[0] && anchors[2] {
+            log!("window.height is ignored, the window stretches between top and bottom");
+        }
+        Some(anchors)
+    }
+
+    // Anchors, margins and keyboard modes belong to layer surfaces, Hyprland
+    // places a regular window itself.
+    fn check_surface_mode(&self, debug: &Debug, warnings: &mut Vec<String>) {
+        if self.surface_mode != SurfaceMode::window {
+            if !self.special_workspace.is_empty() {
+                warnings.push(String::from(
+                    "window.special_workspace is ignored in layer surface mode",
+                ));
+            }
+            return;
+        }
+        if self.anchor != WindowAnchor::center || !self.anchor_edges.is_empty() {
+            warnings.push(String::from("window.anchor and window.anchor_edges are ignored, the window is centered in window surface mode"));
+        }
+        if [
+            self.margin_top,
+            self.margin_bottom,
+            self.margin_left,
+            self.margin_right,
+        ]
+        .iter()
+        .any(|&margin| margin != 0)
+        {
+            warnings.push(String::from(
+                "window.margin_* are ignored in window surface mode",
+            ));
+        }
+        if debug.disable_auto_focus {
+            warnings.push(String::from(
+                "debug.disable_auto_focus is ignored in window surface mode",
+            ));
+        }
+    }
+
+    fn clamp_geometry(&mut self, warnings: &mut Vec<String>) {
+        let mut clamped = |name: &str, value: i32, min: i32, max: i32| {
+            let clamped = value.clamp(min, max);
+            if clamped != value {
+                warnings.push(format!(
+                    "window.{} = {} is out of range, using {}",
+                    name, value, clamped
+                ));
+            }
+            clamped
+        };
+        self.width = clamped("width", self.width, MIN_WINDOW_SIZE, i32::MAX);
+        self.height = clamped("height", self.height, MIN_WINDOW_SIZE, i32::MAX);
+        self.border_width = clamped("border_width", self.border_width, 0, MAX_BORDER_WIDTH);
+        self.margin_top = clamped("margin_top", self.margin_top, 0, i32::MAX);
+        self.margin_bottom = clamped("margin_bottom", self.margin_bottom, 0, i32::MAX);
+        self.margin_left = clamped("margin_left", self.margin_left, 0, i32::MAX);
+        self.margin_right = clamped("margin_right", self.margin_right, 0, i32::MAX);
+    }
+}
+
+// Whether warnings for this state of the configuration haven't been logged
+// yet, remembering that they are now.
+fn first_warning_for(warned_for: &Mutex<Option<WarningStamp>>, stamp: WarningStamp) -> bool {
+    let mut warned_for = warned_for.lock().unwrap();
+    if stamp.0.is_some() && warned_for.as_ref() == Some(&stamp) {
+        return false;
+    }
+    *warned_for = Some(stamp);
+    true
+}
+
+fn log_warnings(config_file: &Path, theme_name: &str, warnings: &[String]) {
+    let modified = fs::metadata(config_file)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if first_warning_for(&WARNED_FOR, (modified, theme_name.to_string())) {
+        for warning in warnings {
+            log!("{}", warning);
+        }
+    }
 }
 
 impl Default for Window {
     fn default() -> Self {
         Self {
+            surface_mode: SurfaceMode::layer,
+            special_workspace: String::new(),
             width: 600,
             height: 600,
             show_descriptions: false,
+            description_lines: 1,
             show_paths: false,
+            path_ellipsize: PathEllipsize::end,
+            start_view: StartView::frecency,
+            recent_count: 0,
+            recent_separator: true,
+            hover_path_footer: false,
             show_icons: true,
             show_search: true,
             custom_navigate_keys: NavigateKeys::default(),
             anchor: WindowAnchor::center,
+            anchor_edges: Vec::new(),
             margin_top: 0,
             margin_bottom: 0,
             margin_left: 0,
@@ -204,6 +554,10 @@ impl Default for Window {
             border_width: 2,
             use_gtk_colors: false,
             max_entries: 50,
+            hide_from_capture: false,
+            restore_focus: true,
+            hide_on_workspace_switch: true,
+            show_launch_errors: false,
         }
     }
 }
@@ -212,6 +566,214 @@ impl Default for Window {
 pub struct Debug {
     pub disable_auto_focus: bool,
     pub enable_logging: bool,
+    pub activation_log: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Search {
+    pub collapse_duplicates: bool,
+    pub enable_binary_fallback: bool,
+    pub enable_file_browsing: bool,
+    pub collation_locale: String,
+    pub time_budget_ms: u64,
+    pub help_prefix: String,
+    pub keyword_weight: f64,
+    pub exec_name_weight: f64,
+    pub min_query_length: MinQueryLength,
+    pub history_size: usize,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            collapse_duplicates: true,
+            enable_binary_fallback: true,
+            enable_file_browsing: true,
+            collation_locale: String::new(),
+            time_budget_ms: 8,
+            help_prefix: String::from("?"),
+            keyword_weight: 0.5,
+            exec_name_weight: 0.75,
+            min_query_length: MinQueryLength::default(),
+            history_size: 50,
+        }
+    }
+}
+
+// Characters a query needs before each kind of result is searched. Path
+// searches are asked for explicitly with their prefix, so they default to 0.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MinQueryLength {
+    pub applications: usize,
+    pub commands: usize,
+    pub files: usize,
+}
+
+impl Default for MinQueryLength {
+    fn default() -> Self {
+        Self {
+            applications: 1,
+            commands: 2,
+            files: 0,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub enum CommandIdentity {
+    binary,
+    #[default]
+    full,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Ranking {
+    pub command_identity: CommandIdentity,
+    pub display_cutoff_ratio: f64,
+    pub launch_weight: i64,
+    pub frecency_half_life_days: f64,
+    pub workspace_affinity: f64,
+    pub nodisplay_penalty: i64,
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Self {
+            command_identity: CommandIdentity::default(),
+            display_cutoff_ratio: 0.3,
+            launch_weight: 100,
+            frecency_half_life_days: 30.0,
+            workspace_affinity: 0.0,
+            nodisplay_penalty: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Files {
+    pub document_dirs: Vec<String>,
+    pub roots: BTreeMap<String, String>,
+    pub folder_bonus: i64,
+    pub recency_bonus: i64,
+    pub enter_action: FolderAction,
+}
+
+impl Default for Files {
+    fn default() -> Self {
+        Self {
+            document_dirs: Vec::new(),
+            roots: BTreeMap::new(),
+            folder_bonus: 20,
+            recency_bonus: 10,
+            enter_action: FolderAction::descend,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CustomActions {
+    pub application: Vec<String>,
+    pub file: Vec<String>,
+    pub folder: Vec<String>,
+    pub link: Vec<String>,
+}
+
+impl Default for CustomActions {
+    fn default() -> Self {
+        let actions = |names: &[&str]| names.iter().map(|name| String::from(*name)).collect();
+        Self {
+            application: actions(&["launch", "copy_command", "edit_desktop_file"]),
+            file: actions(&["launch", "open_with", "copy_path"]),
+            folder: actions(&["open", "descend", "copy_path", "open_terminal"]),
+            link: actions(&["launch", "copy_command"]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Launcher {
+    pub verify_exec: bool,
+    pub filter_by_desktop: bool,
+    pub show_nodisplay: bool,
+    pub dbus_activation: bool,
+    pub extra_desktop_paths: Vec<String>,
+    pub appimage_paths: Vec<String>,
+    pub favorites: Vec<String>,
+    pub favorite_bonus: i64,
+    pub blacklist: Vec<String>,
+    pub custom_entries: Vec<CustomEntry>,
+    // "none", "systemd-run", "uwsm" or a command prefix of its own.
+    pub launch_wrapper: String,
+    #[serde(serialize_with = "masked")]
+    pub env: BTreeMap<String, String>,
+    pub clipboard: ClipboardMode,
+    // How long a value copied through GTK keeps the launcher from exiting
+    // while nothing has read it.
+    pub clipboard_hold_ms: u64,
+}
+
+// A launcher row declared in the config instead of a desktop file. Items
+// without `name` or `exec` are skipped when the applications are loaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CustomEntry {
+    pub name: String,
+    pub exec: String,
+    pub icon: String,
+    pub description: String,
+    pub terminal: bool,
+    #[serde(serialize_with = "masked")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Default for Launcher {
+    fn default() -> Self {
+        Self {
+            verify_exec: false,
+            filter_by_desktop: true,
+            show_nodisplay: false,
+            dbus_activation: true,
+            extra_desktop_paths: Vec::new(),
+            appimage_paths: Vec::new(),
+            favorites: Vec::new(),
+            favorite_bonus: 10000,
+            blacklist: Vec::new(),
+            custom_entries: Vec::new(),
+            launch_wrapper: String::from("none"),
+            env: BTreeMap::new(),
+            clipboard: ClipboardMode::gtk,
+            clipboard_hold_ms: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Modes {
+    pub apps: ModeSettings,
+    pub files: ModeSettings,
+    pub categories: ModeSettings,
+    pub windows: ModeSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct ModeSettings {
+    pub window: ModeWindow,
+}
+
+// Zero sizes and an empty anchor keep the values from `window`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct ModeWindow {
+    pub width: i32,
+    pub height: i32,
+    pub anchor: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Hooks {
+    pub on_show: String,
+    pub on_hide: String,
+    pub on_launch: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -231,12 +793,46 @@ impl Default for NavigateKeys {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Keys(BTreeMap<String, String>);
+
+impl Default for Keys {
+    fn default() -> Self {
+        Self(
+            keymap::Action::ALL
+                .iter()
+                .map(|action| {
+                    (
+                        String::from(action.name()),
+                        String::from(action.default_binding()),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Deref for Keys {
+    type Target = BTreeMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl Config {
-    fn config_dir() -> &'static PathBuf {
+    pub fn config_dir() -> &'static PathBuf {
         &CONFIG_DIR
     }
 
     pub fn load() -> Self {
+        Self::load_traced(None)
+    }
+
+    // Config::load, also noting in `sources` where each setting came from.
+    // Settings missing there are defaults.
+    pub fn load_traced(mut sources: Option<&mut Sources>) -> Self {
         let config_file = Self::config_dir().join("config.json");
         log!("Loading configuration from: {:?}", config_file);
         let default_config = Config::default();
@@ -279,6 +875,15 @@ impl Config {
             }
         };
 
+        let unknown_keys = existing_config
+            .get("keys")
+            .map(keymap::unknown_actions)
+            .unwrap_or_default();
+        let mut warnings: Vec<String> = unknown_keys
+            .iter()
+            .map(|name| format!("Ignoring key binding for unknown action: {}", name))
+            .collect();
+
         let default_json = match serde_json::to_value(&default_config) {
             Ok(json) => json,
             Err(e) => {
@@ -287,16 +892,24 @@ impl Config {
             }
         };
 
-        let merged_config = merge_json(existing_config, default_json.clone(), &default_json);
+        let mut trace = sources
+            .as_deref_mut()
+            .map(|sources| Trace::new("config.json", sources));
+        let merged_config = merge_json(
+            existing_config,
+            default_json.clone(),
+            &default_json,
+            trace.as_mut(),
+        );
 
-        if let Ok(pretty_merged) = serde_json::to_string_pretty(&merged_config) {
+        if let Ok(pretty_merged) = to_string_like(&merged_config, &file_contents) {
             if pretty_merged != file_contents {
                 log!("Writing merged configuration back to file");
                 fs::write(&config_file, pretty_merged).unwrap_or_default();
             }
         }
 
-        let config = match serde_json::from_value(merged_config.clone()) {
+        let mut config: Config = match serde_json::from_value(merged_config.clone()) {
             Ok(config) => config,
             Err(e) => {
                 log!("Error converting merged config to struct: {}", e);
@@ -309,25 +922,230 @@ impl Config {
         };
 
         LOGGING_ENABLED.store(config.debug.enable_logging, Ordering::SeqCst);
+        collation::configure(&config.search.collation_locale);
+        config.unknown_keys = unknown_keys;
+        config.window.clamp_geometry(&mut warnings);
         config
+            .window
+            .check_surface_mode(&config.debug, &mut warnings);
+        config.search.keyword_weight = config.search.keyword_weight.clamp(0.0, 1.0);
+        config.window.description_lines = config.window.description_lines.max(1);
+        config.search.exec_name_weight = config.search.exec_name_weight.clamp(0.0, 1.0);
+        config.ranking.frecency_half_life_days = config.ranking.frecency_half_life_days.max(0.0);
+        config.ranking.workspace_affinity = config.ranking.workspace_affinity.clamp(0.0, 1.0);
+        config.ranking.nodisplay_penalty = config.ranking.nodisplay_penalty.max(0);
+        let theme_name = SESSION_THEME
+            .read()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| config.theme_name.clone());
+        // Presets change the looks, so the memory given to icons stays the
+        // user's.
+        let icon_cache_mb = config.theme.icon_cache_mb;
+        let icon_cache_source = sources
+            .as_deref()
+            .and_then(|sources| sources.get("theme.icon_cache_mb").cloned());
+        if !theme_name.is_empty() {
+            match read_theme(&Self::themes_dir(), &theme_name) {
+                Ok((theme, theme_sources)) => {
+                    config.theme = theme;
+                    if let Some(sources) = sources.as_deref_mut() {
+                        retrace_theme(sources, theme_sources);
+                    }
+                }
+                Err(e) => warnings.push(format!("Ignoring theme {:?}: {}", theme_name, e)),
+            }
+        }
+        log_warnings(&config_file, &theme_name, &warnings);
+        if SAFE_MODE.get().is_some() {
+            config.theme = Theme::default();
+            if let Some(sources) = sources.as_deref_mut() {
+                let safe_mode = serde_json::to_value(&config.theme).unwrap_or_default();
+                let mut safe_sources = Sources::new();
+                record_leaves(&safe_mode, &mut Vec::new(), "safe mode", &mut safe_sources);
+                retrace_theme(sources, safe_sources);
+            }
+        }
+        config.theme.icon_cache_mb = icon_cache_mb;
+        if let Some(sources) = sources {
+            match icon_cache_source {
+                Some(source) => sources.insert(String::from("theme.icon_cache_mb"), source),
+                None => sources.remove("theme.icon_cache_mb"),
+            };
+        }
+        config
+    }
+
+    // The configuration as shown to the user, with the values of settings
+    // marked `masked` hidden.
+    pub fn to_masked_value(&self) -> serde_json::Result<serde_json::Value> {
+        MASKING.with(|masking| masking.set(true));
+        let value = serde_json::to_value(self);
+        MASKING.with(|masking| masking.set(false));
+        value
+    }
+
+    pub fn themes_dir() -> PathBuf {
+        Self::config_dir().join("themes")
+    }
+
+    // Reads `themes/<name>.json`, which holds a `theme` section. Settings it
+    // leaves out take their default values.
+    pub fn load_theme(name: &str) -> Result<Theme, String> {
+        load_theme_from(&Self::themes_dir(), name)
+    }
+
+    // Switches to the theme preset `name` until the launcher exits, or for
+    // good by also setting `theme_name` in the config file.
+    pub fn set_session_theme(name: &str, persist: bool) -> Result<(), String> {
+        set_theme_in(Self::config_dir(), name, persist)?;
+        *SESSION_THEME.write().unwrap() = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn diff(&self, new: &Config) -> ConfigChanges {
+        let (old_window, new_window) = (&self.window, &new.window);
+
+        let theme = self.theme.colors != new.theme.colors
+            || self.theme.corners != new.theme.corners
+            || self.theme.spacing != new.theme.spacing
+            || self.theme.typography != new.theme.typography
+            || self.theme.shadow != new.theme.shadow
+            || old_window.show_border != new_window.show_border
+            || old_window.border_width != new_window.border_width
+            || old_window.use_gtk_colors != new_window.use_gtk_colors;
+
+        let geometry = old_window.width != new_window.width
+            || old_window.height != new_window.height
+            || old_window.special_workspace != new_window.special_workspace
+            || self.theme.shadow.extent() != new.theme.shadow.extent();
+
+        // Margins are split from the rest of the geometry so that tweaking
+        // them doesn't restart the animations.
+        let margins = old_window.margin_top != new_window.margin_top
+            || old_window.margin_bottom != new_window.margin_bottom
+            || old_window.margin_left != new_window.margin_left
+            || old_window.margin_right != new_window.margin_right;
+        // Anchors too, but a mapped surface has to be mapped again for them.
+        let anchors = old_window.anchor != new_window.anchor
+            || old_window.anchor_edges != new_window.anchor_edges;
+
+        let search = self.search != new.search
+            || self.ranking != new.ranking
+            || self.files != new.files
+            || old_window.max_entries != new_window.max_entries
+            || old_window.show_icons != new_window.show_icons
+            || old_window.show_descriptions != new_window.show_descriptions
+            || old_window.description_lines != new_window.description_lines
+            || old_window.show_paths != new_window.show_paths
+            || old_window.path_ellipsize != new_window.path_ellipsize
+            || old_window.start_view != new_window.start_view
+            || old_window.recent_count != new_window.recent_count
+            || old_window.recent_separator != new_window.recent_separator;
+
+        // These decide which desktop entries are read and how.
+        let (old_launcher, new_launcher) = (&self.launcher, &new.launcher);
+        let applications = old_launcher.verify_exec != new_launcher.verify_exec
+            || old_launcher.filter_by_desktop != new_launcher.filter_by_desktop
+            || old_launcher.show_nodisplay != new_launcher.show_nodisplay
+            || old_launcher.dbus_activation != new_launcher.dbus_activation
+            || old_launcher.extra_desktop_paths != new_launcher.extra_desktop_paths
+            || old_launcher.appimage_paths != new_launcher.appimage_paths
+            || old_launcher.blacklist != new_launcher.blacklist
+            || old_launcher.custom_entries != new_launcher.custom_entries;
+        // Pinning from the launcher writes these, so they only re-run the
+        // query rather than resetting it.
+        let favorites = old_launcher.favorites != new_launcher.favorites
+            || old_launcher.favorite_bonus != new_launcher.favorite_bonus;
+
+        let mut changes = ConfigChanges {
+            theme,
+            geometry,
+            margins,
+            anchors,
+            behavior: false,
+            search,
+            applications,
+            favorites,
+        };
+        // Everything else (keys, hooks, debug, animations, search bar and
+        // footer visibility, ...) is cheap to re-apply.
+        changes.behavior = self != new && !changes.any()
+            || self.theme.animations != new.theme.animations
+            || self.theme.icon_cache_mb != new.theme.icon_cache_mb
+            || self.debug != new.debug
+            || self.keys != new.keys
+            || self.unknown_keys != new.unknown_keys
+            || old_window.custom_navigate_keys != new_window.custom_navigate_keys
+            || old_window.show_search != new_window.show_search
+            || old_window.hover_path_footer != new_window.hover_path_footer
+            || old_window.hide_from_capture != new_window.hide_from_capture;
+        changes
     }
 
     pub fn get_css(&self) -> String {
+        let mut css = self.base_css();
+        css.push_str(&self.shadow_css());
+        css
+    }
+
+    fn shadow_css(&self) -> String {
         let theme = &self.theme;
-        let window = &self.window;
+        let shadow = &theme.shadow;
+        if !shadow.enabled {
+            return String::new();
+        }
 
-        let border_style = if window.show_border {
+        let background = if self.window.use_gtk_colors {
+            "@theme_bg_color"
+        } else {
+            theme.colors.window_bg.as_str()
+        };
+        format!(
+            "
+                window {{
+                    background: transparent;
+                    border: none;
+                }}
+                .launcher-frame {{
+                    margin: {}px;
+                    background-color: {};
+                    border-radius: {}px;
+                    {}
+                    box-shadow: {}px {}px {}px {};
+                }}",
+            shadow.extent(),
+            background,
+            theme.corners.window,
+            self.border_style(),
+            shadow.offset_x,
+            shadow.offset_y,
+            shadow.size,
+            shadow.color,
+        )
+    }
+
+    fn border_style(&self) -> String {
+        let window = &self.window;
+        if window.show_border {
             if window.use_gtk_colors {
                 format!("border: {}px solid @borders;", window.border_width)
             } else {
                 format!(
                     "border: {}px solid {};",
-                    window.border_width, theme.colors.border
+                    window.border_width, self.theme.colors.border
                 )
             }
         } else {
             String::from("border: none;")
-        };
+        }
+    }
+
+    fn base_css(&self) -> String {
+        let theme = &self.theme;
+        let window = &self.window;
+
+        let border_style = self.border_style();
 
         if window.use_gtk_colors {
             format!(
@@ -365,6 +1183,19 @@ impl Config {
                 entry:focus {{
                     background-color: @theme_base_color;
                 }}
+                entry > image {{
+                    color: mix(@theme_text_color, @theme_base_color, 0.4);
+                }}
+                entry.searching {{
+                    box-shadow: inset 0 -2px 0 @theme_selected_bg_color;
+                }}
+                entry.searching > image:first-child {{
+                    animation: searching-pulse 600ms ease-in-out infinite alternate;
+                }}
+                @keyframes searching-pulse {{
+                    from {{ opacity: 1; }}
+                    to {{ opacity: 0.3; }}
+                }}
                 .app-name {{
                     color: @theme_text_color;
                     font-size: {}px;
@@ -394,6 +1225,37 @@ impl Config {
                 listview > row:hover:not(:selected) .app-path {{
                     color: mix(@theme_selected_fg_color, @theme_bg_color, 0.6);
                 }}
+                .path-footer {{
+                    padding: 4px 12px;
+                    opacity: 0.8;
+                }}
+                .help-entry .app-description {{
+                    font-family: monospace;
+                }}
+                .safe-mode-banner {{
+                    padding: 8px 12px;
+                    color: #ffd0d0;
+                    background: #5c1f1f;
+                }}
+                .launch-error, .config-warning {{
+                    padding: 6px 12px;
+                    color: #ffd0d0;
+                    background: #5c1f1f;
+                }}
+                listview.mark-missing {{
+                    opacity: 0.7;
+                }}
+                .app-nodisplay {{
+                    opacity: 0.6;
+                }}
+                .mode-chip {{
+                    margin: 8px 12px 0;
+                    padding: 2px 10px;
+                    border-radius: 999px;
+                    font-size: 0.85em;
+                    font-weight: bold;
+                    background: alpha(currentColor, 0.15);
+                }}
                 scrollbar {{ opacity: 0; }}",
                 theme.corners.window,
                 border_style,
@@ -445,6 +1307,19 @@ impl Config {
                 entry:focus {{
                     background-color: {};
                 }}
+                entry > image {{
+                    color: {};
+                }}
+                entry.searching {{
+                    box-shadow: inset 0 -2px 0 {};
+                }}
+                entry.searching > image:first-child {{
+                    animation: searching-pulse 600ms ease-in-out infinite alternate;
+                }}
+                @keyframes searching-pulse {{
+                    from {{ opacity: 1; }}
+                    to {{ opacity: 0.3; }}
+                }}
                 .app-name {{
                     color: {};
                     font-size: {}px;
@@ -474,6 +1349,37 @@ impl Config {
                 listview > row:hover:not(:selected) .app-path {{
                     color: {};
                 }}
+                .path-footer {{
+                    padding: 4px 12px;
+                    opacity: 0.8;
+                }}
+                .help-entry .app-description {{
+                    font-family: monospace;
+                }}
+                .safe-mode-banner {{
+                    padding: 8px 12px;
+                    color: #ffd0d0;
+                    background: #5c1f1f;
+                }}
+                .launch-error, .config-warning {{
+                    padding: 6px 12px;
+                    color: #ffd0d0;
+                    background: #5c1f1f;
+                }}
+                listview.mark-missing {{
+                    opacity: 0.7;
+                }}
+                .app-nodisplay {{
+                    opacity: 0.6;
+                }}
+                .mode-chip {{
+                    margin: 8px 12px 0;
+                    padding: 2px 10px;
+                    border-radius: 999px;
+                    font-size: 0.85em;
+                    font-weight: bold;
+                    background: alpha(currentColor, 0.15);
+                }}
                 scrollbar {{ opacity: 0; }}",
                 theme.colors.window_bg,
                 theme.corners.window,
@@ -493,6 +1399,8 @@ impl Config {
                 theme.colors.search_caret,
                 theme.typography.search_font_size,
                 theme.colors.search_bg_focused,
+                theme.colors.entry_icons,
+                theme.colors.entry_icons,
                 theme.colors.item_name,
                 theme.typography.item_name_size,
                 theme.colors.item_name_selected,
@@ -572,41 +1480,168 @@ impl Config {
     }
 }
 
+// Maps of strings whose defaults are empty, so their values have no schema to
+// be checked against. Any other value would fail the whole configuration.
+const STRING_MAPS: &[&str] = &["roots", "env"];
+
+// Numbers and booleans are kept as text, e.g. `"GDK_SCALE": 2` in
+// `launcher.env`, anything else is dropped.
+fn string_map(key: &str, value: serde_json::Value) -> serde_json::Value {
+    let serde_json::Value::Object(map) = value else {
+        return value;
+    };
+    let map = map
+        .into_iter()
+        .filter_map(|(name, value)| match value {
+            serde_json::Value::String(_) => Some((name, value)),
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                Some((name, serde_json::Value::String(value.to_string())))
+            }
+            _ => {
+                log!("Ignoring {}.{}, its value must be a string", key, name);
+                None
+            }
+        })
+        .collect();
+    serde_json::Value::Object(map)
+}
+
 fn merge_json(
     existing: serde_json::Value,
     default: serde_json::Value,
     schema: &serde_json::Value,
+    trace: Option<&mut Trace>,
+) -> serde_json::Value {
+    merge_json_at(existing, default, schema, 0, trace)
+}
+
+// The settings a merge takes from one layer, a file over the defaults, noted
+// as it goes.
+struct Trace<'a> {
+    layer: &'a str,
+    path: Vec<String>,
+    sources: &'a mut Sources,
+}
+
+impl<'a> Trace<'a> {
+    fn new(layer: &'a str, sources: &'a mut Sources) -> Self {
+        Self {
+            layer,
+            path: Vec::new(),
+            sources,
+        }
+    }
+
+    // Notes `value`, found under `key` of the current path, as coming from the
+    // layer or from the defaults.
+    fn record(&mut self, key: Option<&str>, value: &serde_json::Value, from_layer: bool) {
+        let source = if from_layer { self.layer } else { "default" };
+        self.path.extend(key.map(String::from));
+        record_leaves(value, &mut self.path, source, self.sources);
+        if key.is_some() {
+            self.path.pop();
+        }
+    }
+}
+
+// Lists count as one setting, since a layer replaces them as a whole.
+fn record_leaves(
+    value: &serde_json::Value,
+    path: &mut Vec<String>,
+    source: &str,
+    sources: &mut Sources,
+) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                path.push(key.clone());
+                record_leaves(value, path, source, sources);
+                path.pop();
+            }
+        }
+        _ => {
+            sources.insert(path.join("."), source.to_string());
+        }
+    }
+}
+
+// Puts the sources of a theme preset, or of the safe mode defaults, in place
+// of those of the `theme` section.
+fn retrace_theme(sources: &mut Sources, theme: Sources) {
+    sources.retain(|path, _| !path.starts_with("theme."));
+    sources.extend(
+        theme
+            .into_iter()
+            .map(|(path, source)| (format!("theme.{}", path), source)),
+    );
+}
+
+// The depth is passed along rather than kept in a static, since configs are
+// loaded from several threads at once.
+fn merge_json_at(
+    existing: serde_json::Value,
+    default: serde_json::Value,
+    schema: &serde_json::Value,
+    depth: usize,
+    mut trace: Option<&mut Trace>,
 ) -> serde_json::Value {
     match (existing, default) {
         (serde_json::Value::Object(mut existing_obj), serde_json::Value::Object(default_obj)) => {
             let mut result = serde_json::Map::new();
+            let order: Vec<String> = existing_obj.keys().cloned().collect();
 
             let schema_obj = match schema.as_object() {
                 Some(obj) => obj,
-                None => return serde_json::Value::Object(default_obj),
+                None => {
+                    let default = serde_json::Value::Object(default_obj);
+                    if let Some(trace) = trace {
+                        trace.record(None, &default, false);
+                    }
+                    return default;
+                }
             };
 
-            const MAX_DEPTH: usize = 10;
-            static CURRENT_DEPTH: std::sync::atomic::AtomicUsize =
-                std::sync::atomic::AtomicUsize::new(0);
+            // Other maps without defaults keep what the user wrote.
+            if schema_obj.is_empty() {
+                let existing = serde_json::Value::Object(existing_obj);
+                if let Some(trace) = trace {
+                    trace.record(None, &existing, true);
+                }
+                return existing;
+            }
 
-            let depth = CURRENT_DEPTH.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            const MAX_DEPTH: usize = 10;
             if depth >= MAX_DEPTH {
-                CURRENT_DEPTH.store(0, std::sync::atomic::Ordering::SeqCst);
-                return serde_json::Value::Object(default_obj);
+                let default = serde_json::Value::Object(default_obj);
+                if let Some(trace) = trace {
+                    trace.record(None, &default, false);
+                }
+                return default;
             }
 
             for (key, schema_val) in schema_obj {
                 if let Some(existing_val) = existing_obj.remove(key) {
-                    if schema_val.is_object() && existing_val.is_object() {
-                        result.insert(
-                            key.clone(),
-                            merge_json(
-                                existing_val,
-                                default_obj.get(key).cloned().unwrap_or_default(),
-                                schema_val,
-                            ),
+                    if STRING_MAPS.contains(&key.as_str()) && existing_val.is_object() {
+                        let map = string_map(key, existing_val);
+                        if let Some(trace) = trace.as_deref_mut() {
+                            trace.record(Some(key), &map, true);
+                        }
+                        result.insert(key.clone(), map);
+                    } else if schema_val.is_object() && existing_val.is_object() {
+                        if let Some(trace) = trace.as_deref_mut() {
+                            trace.path.push(key.clone());
+                        }
+                        let merged = merge_json_at(
+                            existing_val,
+                            default_obj.get(key).cloned().unwrap_or_default(),
+                            schema_val,
+                            depth + 1,
+                            trace.as_deref_mut(),
                         );
+                        if let Some(trace) = trace.as_deref_mut() {
+                            trace.path.pop();
+                        }
+                        result.insert(key.clone(), merged);
                     } else {
                         let is_valid = match schema_val {
                             serde_json::Value::Null => existing_val.is_null(),
@@ -618,19 +1653,660 @@ fn merge_json(
                         };
 
                         if is_valid {
+                            if let Some(trace) = trace.as_deref_mut() {
+                                trace.record(Some(key), &existing_val, true);
+                            }
                             result.insert(key.clone(), existing_val);
                         } else if let Some(default_val) = default_obj.get(key) {
+                            if let Some(trace) = trace.as_deref_mut() {
+                                trace.record(Some(key), default_val, false);
+                            }
                             result.insert(key.clone(), default_val.clone());
                         }
                     }
                 } else if let Some(default_val) = default_obj.get(key) {
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.record(Some(key), default_val, false);
+                    }
                     result.insert(key.clone(), default_val.clone());
                 }
             }
 
-            CURRENT_DEPTH.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-            serde_json::Value::Object(result)
+            // The user's keys stay in their order, new ones follow.
+            let mut ordered: serde_json::Map<_, _> = order
+                .into_iter()
+                .filter_map(|key| Some((key.clone(), result.shift_remove(&key)?)))
+                .collect();
+            ordered.extend(result);
+            serde_json::Value::Object(ordered)
+        }
+        (_, default) => {
+            if let Some(trace) = trace {
+                trace.record(None, &default, false);
+            }
+            default
+        }
+    }
+}
+
+// Settings marked `#[serde(serialize_with = "masked")]` may hold secrets.
+// Config::to_masked_value shows their names but not their values.
+fn masked<T: Serialize, S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    if !MASKING.with(Cell::get) {
+        return value.serialize(serializer);
+    }
+    serde_json::to_value(value)
+        .map(mask)
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+fn mask(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, mask(value)))
+                .collect(),
+        ),
+        serde_json::Value::Null => serde_json::Value::Null,
+        _ => serde_json::Value::String(String::from("<masked>")),
+    }
+}
+
+// Reads `<name>.json` in `dir`, see Config::load_theme.
+fn load_theme_from(dir: &Path, name: &str) -> Result<Theme, String> {
+    read_theme(dir, name).map(|(theme, _)| theme)
+}
+
+// The preset `name` in `dir` and where each of its settings came from.
+fn read_theme(dir: &Path, name: &str) -> Result<(Theme, Sources), String> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return Err(format!("invalid theme name {:?}", name));
+    }
+    let path = dir.join(format!("{}.json", name));
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let mut read = THEMES_READ.lock().unwrap();
+    let (merged, sources) = match read.get(&path) {
+        Some((read_modified, merged, sources)) if *read_modified == modified => {
+            (merged.clone(), sources.clone())
+        }
+        _ => {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            let preset: serde_json::Value = serde_json::from_str(&contents)
+                .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+            let default = serde_json::to_value(Theme::default()).map_err(|e| e.to_string())?;
+            let layer = format!("theme {}", name);
+            let mut sources = Sources::new();
+            let mut trace = Trace::new(&layer, &mut sources);
+            let merged = merge_json(preset, default.clone(), &default, Some(&mut trace));
+            read.insert(path.clone(), (modified, merged.clone(), sources.clone()));
+            (merged, sources)
         }
-        (_, default) => default,
+    };
+    let theme = serde_json::from_value(merged)
+        .map_err(|e| format!("invalid theme in {}: {}", path.display(), e))?;
+    Ok((theme, sources))
+}
+
+// Checks that the preset `name` exists in the themes directory of the config
+// directory `dir` and, with `persist`, saves it as `theme_name`.
+fn set_theme_in(dir: &Path, name: &str, persist: bool) -> Result<(), String> {
+    load_theme_from(&dir.join("themes"), name)?;
+    if persist {
+        update_json_file(&dir.join("config.json"), |config| {
+            config.insert(String::from("theme_name"), serde_json::json!(name));
+        })
+        .map_err(|e| format!("failed to save theme_name: {}", e))?;
+    }
+    Ok(())
+}
+
+// Edits a JSON config file in place, leaving the keys `update` doesn't touch
+// as they are and in their order. A missing file is created. The file is
+// replaced through a rename, so the watcher and other readers never see it
+// half written.
+pub fn update_json_file(
+    path: &Path,
+    update: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<(), std::io::Error> {
+    let contents = match fs::read_to_string(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::from("{}"),
+        contents => contents?,
+    };
+    let mut config: serde_json::Value = serde_json::from_str(&contents)?;
+    let Some(object) = config.as_object_mut() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "config is not an object",
+        ));
+    };
+    update(object);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(to_string_like(&config, &contents)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+// Pretty-prints `value` with the indentation and final newline of the file
+// it was read from, two spaces when the file has no indented line.
+fn to_string_like(value: &serde_json::Value, contents: &str) -> serde_json::Result<String> {
+    let indent = contents
+        .lines()
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ");
+    let mut output = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut output,
+        formatter,
+    ))?;
+    if contents.ends_with('\n') {
+        output.push(b'\n');
+    }
+    Ok(String::from_utf8(output).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn out_of_range_geometry_is_clamped_with_a_warning() {
+        let mut window = Window {
+            width: 10,
+            border_width: 80,
+            margin_left: -4,
+            ..Window::default()
+        };
+        let mut warnings = Vec::new();
+        window.clamp_geometry(&mut warnings);
+
+        assert_eq!(window.width, MIN_WINDOW_SIZE);
+        assert_eq!(window.border_width, MAX_BORDER_WIDTH);
+        assert_eq!(window.margin_left, 0);
+        assert_eq!(
+            warnings,
+            [
+                "window.width = 10 is out of range, using 100",
+                "window.border_width = 80 is out of range, using 50",
+                "window.margin_left = -4 is out of range, using 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_repeat_only_after_a_reload() {
+        let warned_for = Mutex::new(None);
+        let saved = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        let edited = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+        let stamp = |modified, theme: &str| (modified, theme.to_string());
+
+        assert!(first_warning_for(&warned_for, stamp(saved, "")));
+        assert!(!first_warning_for(&warned_for, stamp(saved, "")));
+        assert!(first_warning_for(&warned_for, stamp(edited, "")));
+        assert!(first_warning_for(&warned_for, stamp(edited, "nord")));
+        assert!(!first_warning_for(&warned_for, stamp(edited, "nord")));
+        // Without a modification time every load warns.
+        assert!(first_warning_for(&warned_for, stamp(None, "")));
+        assert!(first_warning_for(&warned_for, stamp(None, "")));
+    }
+
+    fn merged(existing: serde_json::Value) -> Config {
+        let default_json = serde_json::to_value(Config::default()).unwrap();
+        serde_json::from_value(merge_json(
+            existing,
+            default_json.clone(),
+            &default_json,
+            None,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn environment_values_are_kept_as_text() {
+        let config = merged(serde_json::json!({
+            "launcher": {
+                "env": {
+                    "GTK_THEME": "Adwaita:dark",
+                    "GDK_SCALE": 2,
+                    "MOZ_ENABLE_WAYLAND": true,
+                    "BROKEN": ["a", "b"],
+                    "ALSO_BROKEN": null
+                }
+            }
+        }));
+        assert_eq!(
+            config.launcher.env,
+            BTreeMap::from([
+                (String::from("GDK_SCALE"), String::from("2")),
+                (String::from("GTK_THEME"), String::from("Adwaita:dark")),
+                (String::from("MOZ_ENABLE_WAYLAND"), String::from("true")),
+            ])
+        );
+    }
+
+    #[test]
+    fn roots_keep_only_usable_paths() {
+        let config = merged(serde_json::json!({
+            "files": {
+                "roots": { "work": "~/work", "broken": { "path": "~/x" } },
+                "folder_bonus": 5
+            }
+        }));
+        assert_eq!(
+            config.files.roots,
+            BTreeMap::from([(String::from("work"), String::from("~/work"))])
+        );
+        assert_eq!(config.files.folder_bonus, 5);
+    }
+
+    #[test]
+    fn string_maps_of_the_wrong_type_fall_back_to_defaults() {
+        let config = merged(serde_json::json!({
+            "launcher": { "env": "GDK_SCALE=2", "favorite_bonus": 3 }
+        }));
+        assert!(config.launcher.env.is_empty());
+        assert_eq!(config.launcher.favorite_bonus, 3);
+    }
+
+    #[test]
+    fn other_settings_still_fall_back_to_their_defaults() {
+        let config = merged(serde_json::json!({
+            "window": { "width": "wide", "height": 300 },
+            "launcher": { "custom_entries": [{ "name": "Htop", "exec": "htop", "env": { "TERM": "xterm" } }] }
+        }));
+        assert_eq!(config.window.width, Window::default().width);
+        assert_eq!(config.window.height, 300);
+        assert_eq!(config.launcher.custom_entries[0].env["TERM"], "xterm");
+    }
+
+    fn changes(edit: impl FnOnce(&mut Config)) -> ConfigChanges {
+        let mut new = Config::default();
+        edit(&mut new);
+        Config::default().diff(&new)
+    }
+
+    fn only(set: impl FnOnce(&mut ConfigChanges)) -> ConfigChanges {
+        let mut expected = ConfigChanges::default();
+        set(&mut expected);
+        expected
+    }
+
+    #[test]
+    fn an_unchanged_config_has_no_changes() {
+        assert!(!changes(|_| {}).any());
+    }
+
+    type Edit = Box<dyn FnOnce(&mut Config)>;
+
+    #[test]
+    fn each_setting_maps_to_its_kind_of_change() {
+        let cases: Vec<(&str, Edit, ConfigChanges)> = vec![
+            (
+                "colors",
+                Box::new(|config| config.theme.colors.window_bg = String::from("#000")),
+                only(|c| c.theme = true),
+            ),
+            (
+                "border",
+                Box::new(|config| config.window.border_width += 1),
+                only(|c| c.theme = true),
+            ),
+            (
+                "width",
+                Box::new(|config| config.window.width += 10),
+                only(|c| c.geometry = true),
+            ),
+            (
+                "special workspace",
+                Box::new(|config| config.window.special_workspace = String::from("launcher")),
+                only(|c| c.geometry = true),
+            ),
+            (
+                "margin",
+                Box::new(|config| config.window.margin_top += 10),
+                only(|c| c.margins = true),
+            ),
+            (
+                "search",
+                Box::new(|config| config.search.keyword_weight = 0.25),
+                only(|c| c.search = true),
+            ),
+            (
+                "max entries",
+                Box::new(|config| config.window.max_entries += 1),
+                only(|c| c.search = true),
+            ),
+            (
+                "desktop entries",
+                Box::new(|config| config.launcher.verify_exec = !config.launcher.verify_exec),
+                only(|c| c.applications = true),
+            ),
+            (
+                "favorites",
+                Box::new(|config| config.launcher.favorites.push(String::from("a.desktop"))),
+                only(|c| c.favorites = true),
+            ),
+            (
+                "animations",
+                Box::new(|config| config.theme.animations.duration += 50),
+                only(|c| c.behavior = true),
+            ),
+            (
+                "search bar",
+                Box::new(|config| config.window.show_search = !config.window.show_search),
+                only(|c| c.behavior = true),
+            ),
+            (
+                "unknown keys",
+                Box::new(|config| config.unknown_keys.push(String::from("jump"))),
+                only(|c| c.behavior = true),
+            ),
+        ];
+
+        for (name, edit, expected) in cases {
+            assert_eq!(changes(edit), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unclassified_settings_count_as_behavior() {
+        assert_eq!(
+            changes(|config| config.hooks.on_show = String::from("notify-send shown")),
+            only(|c| c.behavior = true)
+        );
+    }
+
+    #[test]
+    fn margins_and_anchors_are_surface_changes() {
+        let cases: Vec<(&str, Edit, ConfigChanges)> = vec![
+            (
+                "top",
+                Box::new(|config| config.window.margin_top += 10),
+                only(|c| c.margins = true),
+            ),
+            (
+                "bottom",
+                Box::new(|config| config.window.margin_bottom += 10),
+                only(|c| c.margins = true),
+            ),
+            (
+                "left",
+                Box::new(|config| config.window.margin_left += 10),
+                only(|c| c.margins = true),
+            ),
+            (
+                "right",
+                Box::new(|config| config.window.margin_right += 1),
+                only(|c| c.margins = true),
+            ),
+            (
+                "anchor",
+                Box::new(|config| config.window.anchor = WindowAnchor::top_left),
+                only(|c| c.anchors = true),
+            ),
+            (
+                "anchor edges",
+                Box::new(|config| config.window.anchor_edges = vec![String::from("top")]),
+                only(|c| c.anchors = true),
+            ),
+            (
+                "anchor and margin",
+                Box::new(|config| {
+                    config.window.anchor = WindowAnchor::bottom;
+                    config.window.margin_bottom += 20;
+                }),
+                only(|c| {
+                    c.margins = true;
+                    c.anchors = true;
+                }),
+            ),
+        ];
+
+        for (name, edit, expected) in cases {
+            let changes = changes(edit);
+            assert_eq!(changes, expected, "{}", name);
+            assert!(changes.surface_only(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn other_changes_are_not_surface_only() {
+        assert!(!ConfigChanges::default().surface_only());
+        for changes in [
+            changes(|config| {
+                config.window.margin_top += 10;
+                config.window.width += 10;
+            }),
+            changes(|config| {
+                config.window.anchor = WindowAnchor::top;
+                config.window.show_search = !config.window.show_search;
+            }),
+            changes(|config| {
+                config.window.margin_top += 10;
+                config.theme.colors.window_bg = String::from("#000");
+            }),
+            changes(|config| config.window.width += 10),
+        ] {
+            assert!(!changes.surface_only(), "{:?}", changes);
+        }
+    }
+
+    #[test]
+    fn a_shadow_changes_the_theme_and_the_window_size() {
+        assert_eq!(
+            changes(|config| config.theme.shadow.enabled = !config.theme.shadow.enabled),
+            only(|c| {
+                c.theme = true;
+                c.geometry = true;
+            })
+        );
+    }
+
+    #[test]
+    fn several_changes_are_reported_together() {
+        let changes = changes(|config| {
+            config.window.height += 10;
+            config.window.margin_left += 5;
+            config.launcher.blacklist.push(String::from("htop.desktop"));
+            config.debug.enable_logging = !config.debug.enable_logging;
+        });
+        assert_eq!(
+            changes,
+            only(|c| {
+                c.geometry = true;
+                c.margins = true;
+                c.applications = true;
+                c.behavior = true;
+            })
+        );
+    }
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = temp_dir(name);
+        fs::create_dir_all(dir.join("themes")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merged_configs_keep_the_users_key_order() {
+        let default = serde_json::json!({"a": 1, "b": {"x": 1, "y": 2}, "c": 3});
+        let existing = serde_json::json!({"c": 30, "b": {"y": 20}, "unknown": true});
+        let merged = merge_json(existing, default.clone(), &default, None);
+        assert_eq!(
+            serde_json::to_string(&merged).unwrap(),
+            r#"{"c":30,"b":{"y":20,"x":1},"a":1}"#
+        );
+    }
+
+    #[test]
+    fn merges_note_where_settings_came_from() {
+        let default = serde_json::json!({"a": 1, "b": {"x": 1, "y": 2}, "c": [1], "env": {}});
+        let existing =
+            serde_json::json!({"a": 5, "b": {"y": "two"}, "c": [2, 3], "env": {"K": "v"}});
+        let mut sources = Sources::new();
+        let mut trace = Trace::new("config.json", &mut sources);
+        merge_json(existing, default.clone(), &default, Some(&mut trace));
+        assert_eq!(
+            sources,
+            Sources::from([
+                (String::from("a"), String::from("config.json")),
+                (String::from("b.x"), String::from("default")),
+                (String::from("b.y"), String::from("default")),
+                (String::from("c"), String::from("config.json")),
+                (String::from("env.K"), String::from("config.json")),
+            ])
+        );
+    }
+
+    #[test]
+    fn presets_note_the_settings_they_set() {
+        let dir = config_dir("theme-sources");
+        fs::write(
+            dir.join("themes").join("night.json"),
+            r#"{"corners": {"window": 4}}"#,
+        )
+        .unwrap();
+        let (_, sources) = read_theme(&dir.join("themes"), "night").unwrap();
+        assert_eq!(sources["corners.window"], "theme night");
+        assert_eq!(sources["corners.search"], "default");
+
+        let mut config_sources = Sources::from([
+            (
+                String::from("theme.corners.window"),
+                String::from("config.json"),
+            ),
+            (String::from("window.width"), String::from("config.json")),
+        ]);
+        retrace_theme(&mut config_sources, sources);
+        assert_eq!(config_sources["theme.corners.window"], "theme night");
+        assert_eq!(config_sources["window.width"], "config.json");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn masked_settings_only_hide_their_values_when_shown() {
+        let mut config = Config::default();
+        config
+            .launcher
+            .env
+            .insert(String::from("API_TOKEN"), String::from("hunter2"));
+
+        let shown = config.to_masked_value().unwrap();
+        assert_eq!(
+            shown["launcher"]["env"],
+            serde_json::json!({"API_TOKEN": "<masked>"})
+        );
+        let saved = serde_json::to_value(&config).unwrap();
+        assert_eq!(
+            saved["launcher"]["env"],
+            serde_json::json!({"API_TOKEN": "hunter2"})
+        );
+    }
+
+    #[test]
+    fn written_configs_keep_their_formatting() {
+        for contents in [
+            "{\n    \"window\": {\n        \"width\": 600\n    }\n}\n",
+            "{\n\t\"window\": {\n\t\t\"width\": 600\n\t}\n}",
+        ] {
+            let value: serde_json::Value = serde_json::from_str(contents).unwrap();
+            assert_eq!(to_string_like(&value, contents).unwrap(), contents);
+        }
+        let value = serde_json::json!({"a": [1]});
+        assert_eq!(
+            to_string_like(&value, "{}").unwrap(),
+            "{\n  \"a\": [\n    1\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn themes_are_only_persisted_when_asked() {
+        let dir = config_dir("set-theme");
+        let config_path = dir.join("config.json");
+        let contents =
+            "{\n    \"window\": {},\n    \"theme_name\": \"day\",\n    \"debug\": {}\n}\n";
+        fs::write(&config_path, contents).unwrap();
+        fs::write(dir.join("themes").join("night.json"), "{}").unwrap();
+
+        assert!(set_theme_in(&dir, "dusk", true)
+            .unwrap_err()
+            .starts_with("failed to read"));
+        assert!(set_theme_in(&dir, "../night", true)
+            .unwrap_err()
+            .starts_with("invalid theme name"));
+        set_theme_in(&dir, "night", false).unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), contents);
+
+        set_theme_in(&dir, "night", true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&config_path).unwrap(),
+            contents.replace("day", "night")
+        );
+
+        fs::remove_file(&config_path).unwrap();
+        set_theme_in(&dir, "night", true).unwrap();
+        assert_eq!(
+            fs::read_to_string(&config_path).unwrap(),
+            "{\n  \"theme_name\": \"night\"\n}"
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn theme_files_are_read_again_once_they_change() {
+        let dir = config_dir("theme-cache");
+        let path = dir.join("themes").join("night.json");
+        let radius = |radius: i32| format!(r#"{{"corners": {{"window": {}}}}}"#, radius);
+        fs::write(&path, radius(4)).unwrap();
+        let written = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(
+            load_theme_from(&dir.join("themes"), "night")
+                .unwrap()
+                .corners
+                .window,
+            4
+        );
+
+        // Same modification time, so the earlier read is used.
+        fs::write(&path, radius(8)).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(written)
+            .unwrap();
+        assert_eq!(
+            load_theme_from(&dir.join("themes"), "night")
+                .unwrap()
+                .corners
+                .window,
+            4
+        );
+
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(written + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            load_theme_from(&dir.join("themes"), "night")
+                .unwrap()
+                .corners
+                .window,
+            8
+        );
+        let _ = fs::remove_dir_all(dir);
     }
 }
@@ -0,0 +1,273 @@
+use crate::{app, config::Config, log, print, search};
+use gtk4::glib;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::Handle,
+    sync::oneshot,
+};
+
+pub const CONTROL_SOCKET: &str = "control.sock";
+
+// One JSON object per line, answered by one line of
+// `{"ok": true, "result": ...}` or `{"ok": false, "error": "..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case", deny_unknown_fields)]
+enum Request {
+    // Answers with the --print-format json document. Icon paths are resolved
+    // at `icon_size` when given and null otherwise.
+    Search {
+        query: String,
+        #[serde(default)]
+        icon_size: Option<i32>,
+    },
+    // Switches to a theme preset like `--set-theme`, answering with null.
+    SetTheme {
+        name: String,
+        #[serde(default)]
+        persist: bool,
+    },
+}
+
+// Listens on `path` in the runtime directory of the resident instance. A
+// socket left behind by an instance that crashed is replaced.
+pub fn serve(path: PathBuf, rt: &Handle) {
+    rt.spawn(async move {
+        let _ = fs::remove_file(&path);
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                log!("Not listening on control socket {:?}: {}", path, e);
+                return;
+            }
+        };
+        log!("Listening on control socket {:?}", path);
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream));
+                }
+                Err(e) => {
+                    log!("Control socket stopped accepting: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+async fn handle_connection(stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut response = respond(&line).await.to_string();
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn respond(line: &str) -> Value {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(e) => return failure(format!("malformed request: {}", e)),
+    };
+
+    match request {
+        Request::Search { query, icon_size } => {
+            let config = Config::load();
+            let results = match search::search_applications(&query, &config).await {
+                Ok(results) if results.loading => {
+                    return failure(String::from("applications are still loading"))
+                }
+                Ok(results) => results,
+                Err(e) => return failure(format!("search failed: {}", e)),
+            };
+
+            let printed = move || {
+                serde_json::to_value(print::printed_results(&query, &results.matches, icon_size))
+            };
+            // Icons are resolved through the icon theme of the display,
+            // which only the GTK main thread may use.
+            let printed = match icon_size {
+                Some(_) => on_main_thread(printed).await,
+                None => printed().map_err(|e| e.to_string()),
+            };
+            match printed {
+                Ok(printed) => json!({ "ok": true, "result": printed }),
+                Err(e) => failure(e),
+            }
+        }
+
+        Request::SetTheme { name, persist } => {
+            // Unknown presets are turned down before the window is involved.
+            if let Err(e) = Config::load_theme(&name) {
+                return failure(e);
+            }
+            match on_main_thread(move || app::switch_theme(&name, persist)).await {
+                Ok(()) => json!({ "ok": true, "result": null }),
+                Err(e) => failure(e),
+            }
+        }
+    }
+}
+
+async fn on_main_thread<T: Send + 'static, E: ToString>(
+    task: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> Result<T, String> {
+    let (tx, rx) = oneshot::channel();
+    glib::MainContext::default().invoke(move || {
+        let _ = tx.send(task().map_err(|e| e.to_string()));
+    });
+    rx.await
+        .unwrap_or_else(|_| Err(String::from("the launcher is shutting down")))
+}
+
+fn failure(error: String) -> Value {
+    json!({ "ok": false, "error": error })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "hyprlauncher-test-{}-{}.sock",
+            name,
+            std::process::id()
+        ))
+    }
+
+    // Sends each request on one connection and returns the answers.
+    fn exchange(rt: &Runtime, path: &PathBuf, requests: &[&str]) -> Vec<Value> {
+        rt.block_on(async {
+            let mut stream = None;
+            for _ in 0..100 {
+                match UnixStream::connect(path).await {
+                    Ok(connected) => {
+                        stream = Some(connected);
+                        break;
+                    }
+                    Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                }
+            }
+            let (reader, mut writer) = stream.expect("socket never came up").into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut answers = Vec::new();
+            for request in requests {
+                writer
+                    .write_all(format!("{}\n", request).as_bytes())
+                    .await
+                    .unwrap();
+                let line = lines.next_line().await.unwrap().unwrap();
+                answers.push(serde_json::from_str(&line).unwrap());
+            }
+            answers
+        })
+    }
+
+    #[test]
+    fn requests_are_parsed_by_command() {
+        assert!(matches!(
+            serde_json::from_str::<Request>(r#"{"command": "search", "query": "fire"}"#),
+            Ok(Request::Search {
+                icon_size: None,
+                ..
+            })
+        ));
+        assert!(matches!(
+            serde_json::from_str::<Request>(
+                r#"{"command": "search", "query": "fire", "icon_size": 64}"#
+            ),
+            Ok(Request::Search {
+                icon_size: Some(64),
+                ..
+            })
+        ));
+        assert!(matches!(
+            serde_json::from_str::<Request>(r#"{"command": "set-theme", "name": "night"}"#),
+            Ok(Request::SetTheme { persist: false, .. })
+        ));
+        assert!(matches!(
+            serde_json::from_str::<Request>(
+                r#"{"command": "set-theme", "name": "night", "persist": true}"#
+            ),
+            Ok(Request::SetTheme { persist: true, .. })
+        ));
+        assert!(serde_json::from_str::<Request>(r#"{"command": "set-theme"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command": "search"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command": "launch-rockets"}"#).is_err());
+        assert!(
+            serde_json::from_str::<Request>(r#"{"command": "search", "query": "", "x": 1}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn answers_come_back_over_the_socket() {
+        let rt = Runtime::new().unwrap();
+        let path = socket_path("control");
+        // A stale socket file doesn't keep the server from starting.
+        fs::write(&path, "").unwrap();
+        serve(path.clone(), rt.handle());
+
+        let answers = exchange(
+            &rt,
+            &path,
+            &[
+                "not json",
+                r#"{"command": "launch-rockets"}"#,
+                r#"{"command": "search", "query": "firefox"}"#,
+            ],
+        );
+        assert_eq!(answers[0]["ok"], false);
+        assert!(answers[0]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("malformed request"));
+        assert_eq!(answers[1]["ok"], false);
+        // Nothing is loaded in tests.
+        assert_eq!(
+            answers[2],
+            json!({ "ok": false, "error": "applications are still loading" })
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn unknown_themes_are_refused_over_the_socket() {
+        let rt = Runtime::new().unwrap();
+        let path = socket_path("set-theme");
+        serve(path.clone(), rt.handle());
+
+        let missing = format!("no-such-theme-{}", std::process::id());
+        let answers = exchange(
+            &rt,
+            &path,
+            &[
+                r#"{"command": "set-theme", "name": "../night", "persist": true}"#,
+                &format!(r#"{{"command": "set-theme", "name": "{}"}}"#, missing),
+            ],
+        );
+        assert_eq!(
+            answers[0],
+            json!({ "ok": false, "error": "invalid theme name \"../night\"" })
+        );
+        assert_eq!(answers[1]["ok"], false);
+        assert!(answers[1]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("failed to read"));
+        let _ = fs::remove_file(path);
+    }
+}
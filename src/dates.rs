@@ -0,0 +1,211 @@
+use crate::launcher::{AppEntry, EntryType};
+use gtk4::glib::DateTime;
+
+pub const DATE_QUERY_PREFIX: &str = "date ";
+const DAYS_UNTIL_PREFIX: &str = "days until ";
+const WEEKDAY_OF_PREFIX: &str = "weekday of ";
+
+const DATE_ID_PREFIX: &str = "date:";
+
+// Answers `date +3 weeks`, `days until 2025-12-31` and `weekday of
+// 2025-12-31`. Anything else, including dates that don't exist, returns None
+// and is searched as usual.
+pub fn evaluate(query: &str) -> Option<AppEntry> {
+    let query = query.trim();
+    let (name, value) = answer(query, &today()?)?;
+
+    Some(AppEntry {
+        id: format!("{}{}", DATE_ID_PREFIX, query),
+        name,
+        description: format!("Press Enter to copy {}", value),
+        exec: value,
+        icon_name: "x-office-calendar".into(),
+        entry_type: EntryType::Value,
+        ..Default::default()
+    })
+}
+
+fn answer(query: &str, today: &DateTime) -> Option<(String, String)> {
+    if let Some(offset) = query.strip_prefix(DATE_QUERY_PREFIX) {
+        offset_date(offset, today)
+    } else if let Some(date) = query.strip_prefix(DAYS_UNTIL_PREFIX) {
+        days_until(date, today)
+    } else if let Some(date) = query.strip_prefix(WEEKDAY_OF_PREFIX) {
+        weekday_of(date, today)
+    } else {
+        None
+    }
+}
+
+// Dates are kept at midnight UTC so that day counts don't depend on daylight
+// saving time. Today is taken from the local clock.
+fn today() -> Option<DateTime> {
+    let (year, month, day) = DateTime::now_local().ok()?.ymd();
+    DateTime::from_utc(year, month, day, 0, 0, 0.0).ok()
+}
+
+// Only `YYYY-MM-DD` and `today` are accepted, other formats are ambiguous
+// between locales.
+fn parse_date(text: &str, today: &DateTime) -> Option<DateTime> {
+    let text = text.trim();
+    if text == "today" {
+        return Some(today.clone());
+    }
+
+    let mut parts = text.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    DateTime::from_utc(
+        year.parse().ok()?,
+        month.parse().ok()?,
+        day.parse().ok()?,
+        0,
+        0,
+        0.0,
+    )
+    .ok()
+}
+
+fn format(date: &DateTime, format: &str) -> Option<String> {
+    date.format(format).ok().map(String::from)
+}
+
+// Adding months keeps the day when it exists and otherwise ends on the last
+// day of the month, so Jan 31 + 1 month is Feb 28 or 29.
+fn offset_date(offset: &str, today: &DateTime) -> Option<(String, String)> {
+    let (amount, unit) = offset.trim().split_once(' ')?;
+    if !amount.starts_with(['+', '-']) {
+        return None;
+    }
+    let amount: i32 = amount.parse().ok()?;
+    let date = match unit.trim() {
+        "day" | "days" => today.add_days(amount),
+        "week" | "weeks" => today.add_weeks(amount),
+        "month" | "months" => today.add_months(amount),
+        _ => return None,
+    }
+    .ok()?;
+
+    Some((format(&date, "%A, %x")?, format(&date, "%Y-%m-%d")?))
+}
+
+fn days_until(date: &str, today: &DateTime) -> Option<(String, String)> {
+    let date = parse_date(date, today)?;
+    let days = date.difference(today).as_days();
+    let formatted = format(&date, "%x")?;
+    let name = match days {
+        1 => format!("1 day until {}", formatted),
+        -1 => format!("1 day since {}", formatted),
+        days if days < 0 => format!("{} days since {}", -days, formatted),
+        days => format!("{} days until {}", days, formatted),
+    };
+
+    Some((name, days.to_string()))
+}
+
+fn weekday_of(date: &str, today: &DateTime) -> Option<(String, String)> {
+    let date = parse_date(date, today)?;
+    Some((format(&date, "%A, %x")?, format(&date, "%A")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on(year: i32, month: i32, day: i32) -> DateTime {
+        DateTime::from_utc(year, month, day, 0, 0, 0.0).unwrap()
+    }
+
+    fn value(query: &str, today: &DateTime) -> Option<String> {
+        answer(query, today).map(|(_, value)| value)
+    }
+
+    #[test]
+    fn offsets_count_from_today() {
+        let today = on(2025, 3, 10);
+        for (query, expected) in [
+            ("date +1 day", "2025-03-11"),
+            ("date -10 days", "2025-02-28"),
+            ("date +3 weeks", "2025-03-31"),
+            ("date -1 week", "2025-03-03"),
+            ("date +10 months", "2026-01-10"),
+            ("date -3 months", "2024-12-10"),
+            ("date +0 days", "2025-03-10"),
+        ] {
+            assert_eq!(value(query, &today).as_deref(), Some(expected), "{}", query);
+        }
+    }
+
+    #[test]
+    fn months_end_on_the_last_day_when_the_day_is_missing() {
+        for (today, query, expected) in [
+            (on(2025, 1, 31), "date +1 month", "2025-02-28"),
+            (on(2024, 1, 31), "date +1 month", "2024-02-29"),
+            (on(2025, 3, 31), "date -1 month", "2025-02-28"),
+            (on(2025, 5, 31), "date +1 month", "2025-06-30"),
+            (on(2025, 8, 31), "date +13 months", "2026-09-30"),
+            (on(2024, 2, 29), "date +12 months", "2025-02-28"),
+            (on(2024, 2, 29), "date +48 months", "2028-02-29"),
+            (on(2025, 12, 31), "date +1 day", "2026-01-01"),
+        ] {
+            assert_eq!(value(query, &today).as_deref(), Some(expected), "{}", query);
+        }
+    }
+
+    #[test]
+    fn day_counts_include_leap_days() {
+        for (today, query, expected) in [
+            (on(2024, 2, 28), "days until 2024-03-01", "2"),
+            (on(2025, 2, 28), "days until 2025-03-01", "1"),
+            (on(2100, 2, 28), "days until 2100-03-01", "1"),
+            (on(2000, 2, 28), "days until 2000-03-01", "2"),
+            (on(2024, 1, 1), "days until 2025-01-01", "366"),
+            (on(2025, 1, 1), "days until 2026-01-01", "365"),
+            (on(2025, 12, 31), "days until 2025-12-01", "-30"),
+            (on(2025, 6, 1), "days until today", "0"),
+        ] {
+            assert_eq!(value(query, &today).as_deref(), Some(expected), "{}", query);
+        }
+    }
+
+    #[test]
+    fn dates_that_do_not_exist_fall_through() {
+        let today = on(2025, 6, 1);
+        for query in [
+            "days until 2025-02-29",
+            "days until 2100-02-29",
+            "days until 2025-04-31",
+            "days until 2025-13-01",
+            "weekday of 2025-00-10",
+            "days until 25-12-31",
+            "days until 2025-1-5",
+            "days until 2025-12-31-01",
+            "days until 31.12.2025",
+            "date 3 weeks",
+            "date +3 fortnights",
+            "date +many days",
+            "date",
+            "update",
+        ] {
+            assert_eq!(answer(query, &today), None, "{}", query);
+        }
+        assert!(answer("days until 2024-02-29", &today).is_some());
+        assert!(answer("weekday of 2000-02-29", &today).is_some());
+    }
+
+    #[test]
+    fn weekdays_are_found_across_leap_years() {
+        let today = on(2025, 6, 1);
+        for (date, weekday) in [
+            ("2024-02-29", 4),
+            ("2000-02-29", 2),
+            ("2025-12-31", 3),
+            ("2100-03-01", 1),
+        ] {
+            let parsed = parse_date(date, &today).unwrap();
+            assert_eq!(parsed.day_of_week(), weekday, "{}", date);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    launcher::{AppEntry, EntryType},
+    search::SearchResult,
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use once_cell::sync::OnceCell;
+use std::{
+    cmp::Reverse,
+    io::{BufRead, Write},
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+pub const DMENU_ARGUMENT: &str = "--dmenu";
+
+// Exit codes, as rofi and dmenu use them. Accepting with accept_custom_<n>
+// exits with 10 + n.
+pub const ACCEPTED: i32 = 0;
+pub const CANCELLED: i32 = 1;
+const CUSTOM_ACCEPT_BASE: i32 = 10;
+
+// The lines read from standard input, set once at startup.
+static LINES: OnceCell<Vec<String>> = OnceCell::new();
+
+// Returned by App::run once the window closes.
+static EXIT_CODE: AtomicI32 = AtomicI32::new(CANCELLED);
+
+pub fn is_requested(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == DMENU_ARGUMENT)
+}
+
+pub fn read_lines(input: impl BufRead) -> Vec<String> {
+    input
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
+
+pub fn start(lines: Vec<String>) {
+    let _ = LINES.set(lines);
+}
+
+pub fn is_active() -> bool {
+    LINES.get().is_some()
+}
+
+// The lines matching the query, best first. Without a query the lines are
+// listed in the order they were given.
+pub fn candidates(query: &str, max_results: usize) -> Vec<SearchResult> {
+    matching_lines(LINES.get().map_or(&[], Vec::as_slice), query, max_results)
+}
+
+fn matching_lines(lines: &[String], query: &str, max_results: usize) -> Vec<SearchResult> {
+    let query = query.trim();
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut results: Vec<_> = lines
+        .iter()
+        .filter_map(|line| {
+            let score = if query.is_empty() {
+                0
+            } else {
+                matcher.fuzzy_match(line, query)?
+            };
+            Some(SearchResult {
+                app: AppEntry {
+                    id: format!("dmenu:{}", line),
+                    name: line.clone(),
+                    exec: line.clone(),
+                    entry_type: EntryType::Value,
+                    ..Default::default()
+                },
+                score,
+            })
+        })
+        .collect();
+    // Stable, so equal scores keep the input order.
+    results.sort_by_key(|result| Reverse(result.score));
+    results.truncate(max_results);
+    results
+}
+
+// `custom` is the index of the accept_custom_<n> binding that accepted the
+// selection, if any.
+pub fn exit_code(custom: Option<usize>) -> i32 {
+    custom.map_or(ACCEPTED, |index| CUSTOM_ACCEPT_BASE + index as i32 + 1)
+}
+
+// Prints the accepted line and sets the exit code. Closing the window
+// without accepting leaves CANCELLED.
+pub fn accept(line: &str, custom: Option<usize>) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+    EXIT_CODE.store(exit_code(custom), Ordering::SeqCst);
+}
+
+pub fn exit_status() -> i32 {
+    EXIT_CODE.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|result| result.app.name.as_str())
+            .collect()
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        read_lines(text.as_bytes())
+    }
+
+    #[test]
+    fn custom_accepts_exit_with_ten_plus_their_number() {
+        assert_eq!(exit_code(None), ACCEPTED);
+        assert_eq!(exit_code(Some(0)), 11);
+        assert_eq!(exit_code(Some(4)), 15);
+        assert_eq!(exit_code(Some(8)), 19);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        assert_eq!(
+            lines("alpha\n\n  \nbeta\r\ngamma"),
+            ["alpha", "beta", "gamma"]
+        );
+    }
+
+    #[test]
+    fn an_empty_query_keeps_the_input_order() {
+        let lines = lines("zsh\nbash\nfish\n");
+        assert_eq!(
+            names(&matching_lines(&lines, "", 10)),
+            ["zsh", "bash", "fish"]
+        );
+        assert_eq!(names(&matching_lines(&lines, "", 2)), ["zsh", "bash"]);
+    }
+
+    #[test]
+    fn queries_match_regardless_of_case() {
+        let lines = lines("Firefox\nThunderbird\nfile manager\n");
+        assert_eq!(names(&matching_lines(&lines, "FIRE", 10)), ["Firefox"]);
+        assert!(matching_lines(&lines, "xyz", 10).is_empty());
+    }
+
+    #[test]
+    fn accepting_sets_the_exit_status() {
+        assert_eq!(exit_status(), CANCELLED);
+        accept("beta", Some(2));
+        assert_eq!(exit_status(), 13);
+    }
+
+    #[test]
+    fn selections_print_the_whole_line() {
+        let lines = lines("  indented entry\twith a tab\n");
+        let results = matching_lines(&lines, "tab", 10);
+        assert_eq!(results[0].app.exec, "  indented entry\twith a tab");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    capabilities,
+    config::Config,
+    keymap::Keymap,
+    launcher::{self, SkipReason, LOAD_REPORT},
+};
+use tokio::runtime::Runtime;
+
+pub fn run() -> i32 {
+    let config = Config::load();
+
+    println!("Search policy:");
+    println!(
+        "  Binary fallback: {}",
+        enabled(config.search.enable_binary_fallback)
+    );
+    println!(
+        "  File browsing: {}",
+        enabled(config.search.enable_file_browsing)
+    );
+
+    let keymap = Keymap::from_config(&config);
+    println!("Key binding problems: {}", keymap.problems().len());
+    for problem in keymap.problems() {
+        println!("  {}", problem);
+    }
+
+    let missing = capabilities::missing();
+    println!("Missing tools: {}", missing.len());
+    for tool in missing {
+        println!("  {} (needed for {})", tool.name, tool.feature);
+    }
+
+    let rt = Runtime::new().expect("Failed to create Tokio runtime");
+    if let Err(e) = rt.block_on(launcher::load_applications(Default::default())) {
+        eprintln!("Failed to load applications: {}", e);
+        return 1;
+    }
+
+    let report = LOAD_REPORT.lock().unwrap();
+
+    println!("Collapsed duplicate entries: {}", report.collapsed.len());
+    for (kept, collapsed) in &report.collapsed {
+        println!("  {} -> {}", collapsed, kept);
+    }
+
+    let unknown_types: Vec<_> = report
+        .skipped
+        .iter()
+        .filter(|entry| matches!(entry.reason, SkipReason::UnknownType(_)))
+        .collect();
+    println!("Entries skipped for unknown type: {}", unknown_types.len());
+    for entry in unknown_types {
+        println!("  {} ({})", entry.path, entry.reason);
+    }
+
+    let missing_binaries: Vec<_> = report
+        .skipped
+        .iter()
+        .filter(|entry| matches!(entry.reason, SkipReason::MissingBinary(_)))
+        .collect();
+    println!(
+        "Entries skipped for missing binaries: {}",
+        missing_binaries.len()
+    );
+    for entry in missing_binaries {
+        println!("  {} ({})", entry.path, entry.reason);
+    }
+
+    let malformed: Vec<_> = report
+        .skipped
+        .iter()
+        .filter(|entry| matches!(entry.reason, SkipReason::MalformedExec(_)))
+        .collect();
+    println!("Entries skipped for malformed Exec: {}", malformed.len());
+    for entry in malformed {
+        println!("  {} ({})", entry.path, entry.reason);
+    }
+
+    let blacklisted: Vec<_> = report
+        .skipped
+        .iter()
+        .filter(|entry| matches!(entry.reason, SkipReason::Blacklisted(_)))
+        .collect();
+    println!("Blacklisted entries: {}", blacklisted.len());
+    for entry in blacklisted {
+        println!("  {} ({})", entry.path, entry.reason);
+    }
+
+    let mut visibility = report.visibility.clone();
+    visibility.sort_by(|a, b| a.0.cmp(&b.0));
+    println!(
+        "Visibility set by overrides or the blacklist: {}",
+        visibility.len()
+    );
+    for (desktop_id, visibility) in visibility {
+        println!(
+            "  {}: {} ({})",
+            desktop_id,
+            if visibility.shown { "shown" } else { "hidden" },
+            visibility.rule
+        );
+    }
+
+    println!("Visibility contradictions: {}", report.contradictions.len());
+    for contradiction in &report.contradictions {
+        println!("  {}", contradiction);
+    }
+
+    println!(
+        "Overrides for unknown entries: {}",
+        report.unknown_overrides.len()
+    );
+    for unknown in &report.unknown_overrides {
+        println!("  {} ({})", unknown.id, unknown.file);
+    }
+
+    0
+}
+
+fn enabled(value: bool) -> &'static str {
+    if value {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
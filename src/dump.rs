@@ -0,0 +1,173 @@
+use crate::config::{Config, Sources};
+use serde_json::{Map, Value};
+
+// `hyprlauncher --dump-config [--format json|toml]` prints the configuration
+// the launcher ends up with, next to where each setting came from.
+pub fn run(args: &[String]) -> i32 {
+    let format = match args {
+        [] => "json",
+        [flag, format] if flag == "--format" && (format == "json" || format == "toml") => format,
+        _ => {
+            eprintln!("Usage: hyprlauncher --dump-config [--format json|toml]");
+            return 2;
+        }
+    };
+
+    let mut sources = Sources::new();
+    let config = Config::load_traced(Some(&mut sources));
+    let effective = match config.to_masked_value() {
+        Ok(effective) => effective,
+        Err(e) => {
+            eprintln!("Failed to serialize the configuration: {}", e);
+            return 1;
+        }
+    };
+
+    match render(effective, &sources, format) {
+        Ok(printed) => {
+            println!("{}", printed);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to print the configuration: {}", e);
+            1
+        }
+    }
+}
+
+// The configuration and a `sources` map naming the origin of every setting,
+// keyed by its dotted path. Settings the load didn't note are defaults.
+fn render(effective: Value, sources: &Sources, format: &str) -> Result<String, String> {
+    let mut settings = Vec::new();
+    collect_paths(&effective, &mut Vec::new(), &mut settings);
+    let sources: Map<String, Value> = settings
+        .into_iter()
+        .map(|path| {
+            let source = sources.get(&path).map_or("default", String::as_str);
+            (path, Value::String(source.to_string()))
+        })
+        .collect();
+
+    let mut dump = Map::new();
+    dump.insert(String::from("config"), effective);
+    dump.insert(String::from("sources"), Value::Object(sources));
+    let dump = Value::Object(dump);
+
+    let printed = if format == "toml" {
+        toml::to_string_pretty(&without_nulls(dump)).map_err(|e| e.to_string())?
+    } else {
+        serde_json::to_string_pretty(&dump).map_err(|e| e.to_string())?
+    };
+    Ok(printed.trim_end().to_string())
+}
+
+// The dotted path of every setting, in order. Lists count as one setting,
+// since config.json replaces them as a whole.
+fn collect_paths(value: &Value, path: &mut Vec<String>, paths: &mut Vec<String>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                path.push(key.clone());
+                collect_paths(value, path, paths);
+                path.pop();
+            }
+        }
+        _ => paths.push(path.join(".")),
+    }
+}
+
+// TOML has no null, so unset optional settings are left out.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dumped(format: &str) -> String {
+        let effective = serde_json::json!({
+            "window": {"width": 600, "anchor": null},
+            "launcher": {"env": {"TOKEN": "<masked>"}, "blacklist": []},
+            "keys": {},
+        });
+        let sources = Sources::from([
+            (String::from("window.width"), String::from("config.json")),
+            (
+                String::from("launcher.env.TOKEN"),
+                String::from("theme night"),
+            ),
+        ]);
+        render(effective, &sources, format).unwrap()
+    }
+
+    #[test]
+    fn json_dumps_list_a_source_for_every_setting() {
+        assert_eq!(
+            dumped("json"),
+            r#"{
+  "config": {
+    "window": {
+      "width": 600,
+      "anchor": null
+    },
+    "launcher": {
+      "env": {
+        "TOKEN": "<masked>"
+      },
+      "blacklist": []
+    },
+    "keys": {}
+  },
+  "sources": {
+    "window.width": "config.json",
+    "window.anchor": "default",
+    "launcher.env.TOKEN": "theme night",
+    "launcher.blacklist": "default",
+    "keys": "default"
+  }
+}"#
+        );
+    }
+
+    #[test]
+    fn toml_dumps_leave_out_unset_settings() {
+        assert_eq!(
+            dumped("toml"),
+            r#"[config.window]
+width = 600
+
+[config.launcher]
+blacklist = []
+
+[config.launcher.env]
+TOKEN = "<masked>"
+
+[config.keys]
+
+[sources]
+"window.width" = "config.json"
+"window.anchor" = "default"
+"launcher.env.TOKEN" = "theme night"
+"launcher.blacklist" = "default"
+keys = "default""#
+        );
+    }
+
+    #[test]
+    fn dumps_of_the_defaults_are_stable() {
+        let effective = Config::default().to_masked_value().unwrap();
+        let first = render(effective.clone(), &Sources::new(), "json").unwrap();
+        assert_eq!(render(effective, &Sources::new(), "json").unwrap(), first);
+        assert!(first.contains(r#""launcher.env": "default""#));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    launcher::{AppEntry, SkipReason, SkippedEntry},
+    log,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+pub const REFRESH_ARGUMENT: &str = "--refresh-cache";
+
+// Bumped whenever the cached data changes shape or meaning.
+const CACHE_VERSION: u32 = 8;
+
+// Parsed desktop files, so a start only parses the files that changed since
+// the last one. Results also depend on the locale, the current desktop and
+// the launcher options, which make up `environment`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct EntryCache {
+    version: u32,
+    environment: String,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedFile {
+    modified: u128,
+    size: u64,
+    pub entry: Option<AppEntry>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl CachedFile {
+    pub fn new(path: &Path, entry: Option<AppEntry>, skipped: Vec<SkippedEntry>) -> Option<Self> {
+        let (modified, size) = stamp(path)?;
+        Some(Self {
+            modified,
+            size,
+            entry,
+            skipped,
+        })
+    }
+}
+
+impl EntryCache {
+    // A missing, unreadable or outdated cache is replaced without a word, it
+    // only costs a full parse.
+    pub fn load(environment: &str) -> Self {
+        fs::read(cache_path())
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<EntryCache>(&contents).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.environment == environment)
+            .unwrap_or_default()
+    }
+
+    // Entries hidden for a missing binary are parsed again, installing the
+    // binary doesn't touch the desktop file.
+    pub fn get(&self, path: &Path) -> Option<&CachedFile> {
+        let cached = self.files.get(path)?;
+        let missing_binary = cached
+            .skipped
+            .iter()
+            .any(|skip| matches!(skip.reason, SkipReason::MissingBinary(_)));
+        (!missing_binary && stamp(path) == Some((cached.modified, cached.size))).then_some(cached)
+    }
+}
+
+pub fn save(environment: String, files: HashMap<PathBuf, CachedFile>) {
+    let cache = EntryCache {
+        version: CACHE_VERSION,
+        environment,
+        files,
+    };
+    if let Err(e) = write_cache(&cache) {
+        log!("Failed to write desktop entry cache: {}", e);
+    }
+}
+
+pub fn remove() {
+    match fs::remove_file(cache_path()) {
+        Ok(()) => log!("Removed desktop entry cache, all entries will be parsed"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log!("Failed to remove desktop entry cache: {}", e),
+    }
+}
+
+fn write_cache(cache: &EntryCache) -> Result<(), std::io::Error> {
+    let path = cache_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(&serde_json::to_vec(cache)?)?;
+    fs::rename(&temp_path, &path)
+}
+
+fn cache_path() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.cache").as_ref()))
+        .join("hyprlauncher")
+        .join("entries.json")
+}
+
+fn stamp(path: &Path) -> Option<(u128, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((modified, metadata.len()))
+}
@@ -0,0 +1,202 @@
+// Exec values as described in the Desktop Entry Specification, "The Exec key".
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExecError {
+    Empty,
+    UnterminatedQuote,
+    FieldCode(String),
+}
+
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecError::Empty => write!(f, "no command"),
+            ExecError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ExecError::FieldCode(code) => write!(f, "invalid field code {}", code),
+        }
+    }
+}
+
+// Values that field codes expand to. Files given to an Exec without a file
+// field code are appended to the command.
+#[derive(Debug, Default)]
+pub struct Fields<'a> {
+    pub files: &'a [String],
+    pub name: &'a str,
+    pub icon: &'a str,
+    pub desktop_file: &'a str,
+}
+
+// Resolves the escape sequences of a string value, which come before the
+// quoting rules of Exec.
+pub fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+// Splits an Exec value into arguments, leaving field codes in place.
+pub fn split(exec: &str) -> Result<Vec<String>, ExecError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_argument = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_argument = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => current.push(escaped),
+                            Some(other) => {
+                                current.push('\\');
+                                current.push(other);
+                            }
+                            None => return Err(ExecError::UnterminatedQuote),
+                        },
+                        Some(other) => current.push(other),
+                        None => return Err(ExecError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                in_argument = true;
+                current.push(chars.next().unwrap_or('\\'));
+            }
+            c if c.is_whitespace() => {
+                if in_argument {
+                    args.push(std::mem::take(&mut current));
+                    in_argument = false;
+                }
+            }
+            c => {
+                in_argument = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_argument {
+        args.push(current);
+    }
+
+    Ok(args)
+}
+
+// Turns an Exec value into the argv to run.
+pub fn command(exec: &str, fields: &Fields) -> Result<Vec<String>, ExecError> {
+    let mut argv = Vec::new();
+    let mut takes_files = false;
+
+    for arg in split(exec)? {
+        match arg.as_str() {
+            "%f" | "%u" => {
+                takes_files = true;
+                argv.extend(fields.files.first().cloned());
+            }
+            "%F" | "%U" => {
+                takes_files = true;
+                argv.extend(fields.files.iter().cloned());
+            }
+            "%i" => {
+                if !fields.icon.is_empty() {
+                    argv.push(String::from("--icon"));
+                    argv.push(String::from(fields.icon));
+                }
+            }
+            _ => argv.push(expand_field_codes(&arg, fields, &mut takes_files)?),
+        }
+    }
+    if !takes_files {
+        argv.extend(fields.files.iter().cloned());
+    }
+
+    match argv.first() {
+        Some(program) if !program.is_empty() => Ok(argv),
+        _ => Err(ExecError::Empty),
+    }
+}
+
+fn expand_field_codes(
+    arg: &str,
+    fields: &Fields,
+    takes_files: &mut bool,
+) -> Result<String, ExecError> {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            // A list can't be spread inside a word, so it stands for its
+            // first item there.
+            Some('f' | 'u' | 'F' | 'U') => {
+                *takes_files = true;
+                expanded.push_str(fields.files.first().map_or("", String::as_str));
+            }
+            Some('c') => expanded.push_str(fields.name),
+            Some('i') => expanded.push_str(fields.icon),
+            Some('k') => expanded.push_str(fields.desktop_file),
+            // Deprecated codes are removed.
+            Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+            Some(other) => return Err(ExecError::FieldCode(format!("%{}", other))),
+            None => return Err(ExecError::FieldCode(String::from("%"))),
+        }
+    }
+    Ok(expanded)
+}
+
+// Quotes an argument so it reads back unchanged, both as part of an Exec
+// value and in a POSIX shell.
+pub fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,".contains(c));
+    if plain {
+        return String::from(arg);
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+pub fn join(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
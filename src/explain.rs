@@ -0,0 +1,130 @@
+use crate::{
+    config::Config,
+    files, hyprland,
+    launcher::{self, LOAD_REPORT},
+    search::{self, SearchResult},
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use tokio::runtime::Runtime;
+
+const EXPLAIN_RESULT_COUNT: usize = 20;
+
+pub fn run(query: &str) -> i32 {
+    let config = Config::load();
+
+    let rt = Runtime::new().expect("Failed to create Tokio runtime");
+    if let Err(e) = rt.block_on(launcher::load_applications(Default::default())) {
+        eprintln!("Failed to load applications: {}", e);
+        return 1;
+    }
+    rt.block_on(files::load_documents(config.files.document_dirs.clone()));
+    // There is no event socket to follow here, ask for the workspace once.
+    if config.ranking.workspace_affinity > 0.0 {
+        hyprland::active_workspace();
+    }
+
+    let results = match rt.block_on(search::search_applications(query, &config)) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Failed to search applications: {}", e);
+            return 1;
+        }
+    };
+
+    let query = query.to_lowercase();
+    let matcher = SkimMatcherV2::default().smart_case();
+    let bonuses = search::Bonuses::new(&config);
+    let ranked = results
+        .matches
+        .iter()
+        .map(|result| (result, "shown"))
+        .chain(results.hidden.iter().map(|result| (result, "hidden")));
+
+    println!("Query: {:?}", query);
+    println!(
+        "{:>4} {:>7} {:>6} {:>6} {:>8} {:>9} {:>8} {:>5} {:>9}  {:<7} {:<8} Name",
+        "Rank",
+        "Total",
+        "Fuzzy",
+        "Exact",
+        "Frecency",
+        "Workspace",
+        "Favorite",
+        "Icon",
+        "NoDisplay",
+        "Tier",
+        "Policy"
+    );
+    for (rank, (result, tier)) in ranked.take(EXPLAIN_RESULT_COUNT).enumerate() {
+        println!(
+            "{:>4} {:>7} {}  {:<7} {:<8} {}",
+            rank + 1,
+            result.score,
+            components(result, &query, &matcher, &config, &bonuses),
+            tier,
+            if search::is_allowed(&result.app, &config) {
+                "allowed"
+            } else {
+                "excluded"
+            },
+            result.app.name
+        );
+    }
+
+    let report = LOAD_REPORT.lock().unwrap();
+    let matches_query = |name: &str| matcher.fuzzy_match(&name.to_lowercase(), &query).is_some();
+
+    println!("Excluded matches:");
+    for entry in report
+        .skipped
+        .iter()
+        .filter(|entry| matches_query(&entry.name))
+    {
+        println!("  {} ({}): {}", entry.name, entry.path, entry.reason);
+    }
+    for (kept, collapsed) in report
+        .collapsed
+        .iter()
+        .filter(|(_, collapsed)| matches_query(collapsed))
+    {
+        println!("  {}: collapsed into {}", collapsed, kept);
+    }
+
+    0
+}
+
+fn components(
+    result: &SearchResult,
+    query: &str,
+    matcher: &SkimMatcherV2,
+    config: &Config,
+    bonuses: &search::Bonuses,
+) -> String {
+    let (keyword_weight, exec_name_weight) =
+        (config.search.keyword_weight, config.search.exec_name_weight);
+    match search::score_application(
+        &result.app,
+        query,
+        matcher,
+        keyword_weight,
+        exec_name_weight,
+        bonuses,
+    )
+    .filter(|breakdown| breakdown.total() == result.score)
+    {
+        Some(breakdown) => format!(
+            "{:>6} {:>6} {:>8} {:>9} {:>8} {:>5} {:>9}",
+            breakdown.fuzzy,
+            breakdown.exact,
+            breakdown.frecency,
+            breakdown.workspace,
+            breakdown.favorite,
+            breakdown.icon,
+            breakdown.nodisplay
+        ),
+        None => format!(
+            "{:>6} {:>6} {:>8} {:>9} {:>8} {:>5} {:>9}",
+            "-", "-", "-", "-", "-", "-", "-"
+        ),
+    }
+}
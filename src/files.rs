@@ -0,0 +1,477 @@
+use crate::{
+    icons,
+    launcher::{self, AppEntry, EntryType},
+    log, profile,
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use once_cell::sync::Lazy;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use tokio::sync::RwLock;
+
+pub static DOCUMENT_CACHE: Lazy<RwLock<Vec<AppEntry>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+// Listings of the directories browsed in path mode, most recently used last.
+static LISTINGS: Mutex<VecDeque<(PathBuf, Listing)>> = Mutex::new(VecDeque::new());
+
+static BROWSING: AtomicBool = AtomicBool::new(false);
+
+// Listings kept while browsing, and after leaving path mode so that going
+// back to a directory just left doesn't read it again.
+const LISTING_LIMIT: usize = 16;
+const RETAINED_LISTINGS: usize = 3;
+const RETAINED_IMAGES: usize = 16;
+
+pub const EXTENSION_QUERY_PREFIX: &str = "ext:";
+
+const TYPE_FILTER_PREFIX: &str = "type:";
+
+const MAX_DOCUMENTS_PER_DIR: usize = 500;
+
+pub const EXTENSION_GROUPS: &[(&str, &str, &[&str])] = &[
+    ("pdf", "application-pdf", &["pdf"]),
+    (
+        "image",
+        "image-x-generic",
+        &[
+            "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "tiff", "avif",
+        ],
+    ),
+    (
+        "video",
+        "video-x-generic",
+        &["mp4", "mkv", "webm", "avi", "mov", "m4v"],
+    ),
+    (
+        "audio",
+        "audio-x-generic",
+        &["mp3", "flac", "ogg", "opus", "wav", "m4a"],
+    ),
+    (
+        "archive",
+        "package-x-generic",
+        &["zip", "tar", "gz", "xz", "zst", "bz2", "7z", "rar"],
+    ),
+    (
+        "document",
+        "x-office-document",
+        &["pdf", "doc", "docx", "odt", "rtf", "txt", "md", "epub"],
+    ),
+    (
+        "spreadsheet",
+        "x-office-spreadsheet",
+        &["xls", "xlsx", "ods", "csv"],
+    ),
+    (
+        "presentation",
+        "x-office-presentation",
+        &["ppt", "pptx", "odp"],
+    ),
+];
+
+pub fn extensions_for_query(query: &str) -> Option<&'static [&'static str]> {
+    if let Some((_, _, extensions)) = EXTENSION_GROUPS.iter().find(|(name, _, _)| *name == query) {
+        return Some(extensions);
+    }
+
+    EXTENSION_GROUPS
+        .iter()
+        .flat_map(|(_, _, extensions)| extensions.iter())
+        .find(|extension| **extension == query)
+        .map(std::slice::from_ref)
+}
+
+pub fn icon_for_extension(extension: &str) -> &'static str {
+    EXTENSION_GROUPS
+        .iter()
+        .find(|(_, _, extensions)| extensions.contains(&extension))
+        .map(|(_, icon, _)| *icon)
+        .unwrap_or("text-x-generic")
+}
+
+pub fn matching_documents(documents: &[AppEntry], extensions: &[&str]) -> Vec<AppEntry> {
+    documents
+        .iter()
+        .filter(|document| {
+            extension_of(&document.path)
+                .is_some_and(|extension| extensions.contains(&extension.as_str()))
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn expand_root(query: &str, roots: &BTreeMap<String, String>) -> Option<String> {
+    let (name, rest) = query.split_once('/')?;
+    roots
+        .iter()
+        .find(|(root, _)| root.to_lowercase() == name)
+        .map(|(_, path)| format!("{}/{}", path.trim_end_matches('/'), rest))
+}
+
+pub fn root_entries(
+    query: &str,
+    roots: &BTreeMap<String, String>,
+    matcher: &SkimMatcherV2,
+) -> Vec<(AppEntry, i64)> {
+    roots
+        .iter()
+        .filter_map(|(name, path)| {
+            let score = matcher.fuzzy_match(&name.to_lowercase(), query)?;
+            Some((
+                AppEntry {
+                    id: format!("root:{}", name),
+                    name: format!("Browse {} ({})", name, path),
+                    exec: format!("{}/", name),
+                    icon_name: "folder".into(),
+                    entry_type: EntryType::Query,
+                    ..Default::default()
+                },
+                score,
+            ))
+        })
+        .collect()
+}
+
+pub enum PathFilter {
+    Glob(String),
+    Type(&'static [&'static str]),
+}
+
+impl PathFilter {
+    pub fn matches(&self, file_name: &str) -> bool {
+        match self {
+            PathFilter::Glob(pattern) => glob_matches(pattern, &file_name.to_lowercase()),
+            PathFilter::Type(extensions) => extension_of(file_name)
+                .is_some_and(|extension| extensions.contains(&extension.as_str())),
+        }
+    }
+}
+
+pub fn split_path_query(query: &str) -> (&str, Vec<PathFilter>) {
+    let Some(index) = query.find("/ ") else {
+        return (query, Vec::new());
+    };
+
+    let (path, expression) = query.split_at(index + 1);
+    (path, parse_path_filters(expression))
+}
+
+fn parse_path_filters(expression: &str) -> Vec<PathFilter> {
+    filter_tokens(expression)
+        .into_iter()
+        .map(|token| match token.strip_prefix(TYPE_FILTER_PREFIX) {
+            Some(name) => PathFilter::Type(
+                EXTENSION_GROUPS
+                    .iter()
+                    .find(|(group, _, _)| *group == name)
+                    .map(|(_, _, extensions)| *extensions)
+                    .unwrap_or_else(|| {
+                        log!("Unknown file type filter: {}", name);
+                        &[]
+                    }),
+            ),
+            None => PathFilter::Glob(token.to_lowercase()),
+        })
+        .collect()
+}
+
+fn filter_tokens(expression: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in expression.chars() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            None => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+pub struct ListedEntry {
+    pub app: AppEntry,
+    pub name: String,
+    pub is_dir: bool,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Clone)]
+struct Listing {
+    modified: SystemTime,
+    entries: Arc<Vec<ListedEntry>>,
+    bytes: usize,
+}
+
+// The entries of a directory, read again only once its modification time
+// changes, i.e. when files were added, removed or renamed in it.
+pub fn list_directory(dir: &Path) -> Option<Arc<Vec<ListedEntry>>> {
+    let modified = fs::metadata(dir).and_then(|m| m.modified()).ok()?;
+    let mut listings = LISTINGS.lock().unwrap();
+    if let Some(index) = listings.iter().position(|(path, _)| path == dir) {
+        let (path, listing) = listings.remove(index)?;
+        if listing.modified == modified {
+            let entries = listing.entries.clone();
+            listings.push_back((path, listing));
+            return Some(entries);
+        }
+    }
+    drop(listings);
+
+    let entries: Vec<_> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            let app = launcher::create_file_entry(entry.path().to_string_lossy().into_owned())?;
+            Some(ListedEntry {
+                name: app.name.to_lowercase(),
+                app,
+                is_dir,
+                modified,
+            })
+        })
+        .collect();
+    let listing = Listing {
+        modified,
+        bytes: entries.iter().map(entry_bytes).sum(),
+        entries: Arc::new(entries),
+    };
+
+    let mut listings = LISTINGS.lock().unwrap();
+    listings.retain(|(path, _)| path != dir);
+    listings.push_back((dir.to_path_buf(), listing.clone()));
+    keep_recent(&mut listings, LISTING_LIMIT);
+    Some(listing.entries)
+}
+
+// A rough size of what a listed entry keeps alive on the heap.
+fn entry_bytes(entry: &ListedEntry) -> usize {
+    let app = &entry.app;
+    std::mem::size_of::<ListedEntry>()
+        + entry.name.len()
+        + app.id.len()
+        + app.name.len()
+        + app.exec.len()
+        + app.icon_name.len()
+        + app.path.len()
+}
+
+// Approximate memory held by directory listings, as (listings, bytes).
+pub fn listing_usage() -> (usize, usize) {
+    let listings = LISTINGS.lock().unwrap();
+    (
+        listings.len(),
+        listings.iter().map(|(_, listing)| listing.bytes).sum(),
+    )
+}
+
+// Prints the memory held by directory listings with --profile.
+pub fn report_usage() {
+    let (count, bytes) = listing_usage();
+    profile::report(
+        "path listings",
+        format_args!("{} directories ({} KiB)", count, bytes / 1024),
+    );
+}
+
+pub fn is_browsing() -> bool {
+    BROWSING.load(Ordering::Relaxed)
+}
+
+// Records whether the current query browses paths, and unloads what
+// browsing loaded once it leaves path mode. Runs on the main thread, which
+// owns the icon cache.
+pub fn set_browsing(browsing: bool) {
+    if BROWSING.swap(browsing, Ordering::Relaxed) != browsing && !browsing {
+        unload();
+    }
+}
+
+// Keeps only the directories and images seen last, so going back to a
+// folder just left is still quick. The result store is shrunk back to
+// `window.max_entries` by the next update of the list.
+fn unload() {
+    let (count, bytes) = listing_usage();
+    keep_recent(&mut LISTINGS.lock().unwrap(), RETAINED_LISTINGS);
+    let images = icons::retain_images(RETAINED_IMAGES);
+    let (kept, kept_bytes) = listing_usage();
+    log!(
+        "Left path mode, keeping {} of {} directory listings ({} of {} KiB), dropped {} image textures",
+        kept,
+        count,
+        kept_bytes / 1024,
+        bytes / 1024,
+        images
+    );
+    report_usage();
+    icons::report_usage();
+}
+
+// Drops all but the `keep` most recent items, which are at the back.
+fn keep_recent<T>(items: &mut VecDeque<T>, keep: usize) {
+    let excess = items.len().saturating_sub(keep);
+    items.drain(..excess);
+}
+
+pub async fn load_documents(dirs: Vec<String>) {
+    if dirs.is_empty() {
+        return;
+    }
+
+    let documents = tokio::task::spawn_blocking(move || {
+        let mut documents: Vec<(SystemTime, AppEntry)> = dirs
+            .iter()
+            .filter_map(|dir| shellexpand::full(dir).ok())
+            .flat_map(|dir| scan_documents(Path::new(dir.as_ref())))
+            .collect();
+        documents.sort_by_key(|(modified, _)| Reverse(*modified));
+        documents
+            .into_iter()
+            .map(|(_, document)| document)
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    log!("Indexed {} documents", documents.len());
+    *DOCUMENT_CACHE.write().await = documents;
+}
+
+fn scan_documents(dir: &Path) -> Vec<(SystemTime, AppEntry)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        log!("Skipping unreadable document directory: {:?}", dir);
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            let path = entry.path().to_string_lossy().into_owned();
+            let extension = extension_of(&path)?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+            Some((
+                modified,
+                AppEntry {
+                    id: path.clone(),
+                    name,
+                    exec: launcher::open_file_exec(&path),
+                    icon_name: icon_for_extension(&extension).into(),
+                    path,
+                    entry_type: EntryType::File,
+                    ..Default::default()
+                },
+            ))
+        })
+        .take(MAX_DOCUMENTS_PER_DIR)
+        .collect()
+}
+
+fn extension_of(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    #[test]
+    fn only_the_most_recent_items_are_kept() {
+        let mut items: VecDeque<_> = (1..=5).collect();
+        keep_recent(&mut items, 3);
+        assert_eq!(items, [3, 4, 5]);
+        keep_recent(&mut items, 3);
+        assert_eq!(items, [3, 4, 5]);
+        keep_recent(&mut items, 0);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn folders_just_left_are_listed_from_memory() {
+        let dir = temp_dir("listings");
+        let folders: Vec<_> = (0..5).map(|index| dir.join(index.to_string())).collect();
+        for folder in &folders {
+            fs::create_dir_all(folder.join("inner")).unwrap();
+        }
+        let listed: Vec<_> = folders
+            .iter()
+            .map(|folder| list_directory(folder).unwrap())
+            .collect();
+
+        BROWSING.store(true, Ordering::Relaxed);
+        set_browsing(false);
+        let cached = |folder: &PathBuf| {
+            LISTINGS
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(path, _)| path == folder)
+        };
+        assert!(!cached(&folders[0]));
+        assert!(cached(&folders[4]));
+        assert!(Arc::ptr_eq(
+            &list_directory(&folders[4]).unwrap(),
+            &listed[4]
+        ));
+        assert_eq!(list_directory(&folders[0]).unwrap().len(), 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    bookmarks::BOOKMARK_QUERY_PREFIX,
+    categories::CATEGORY_QUERY_PREFIX,
+    config::Config,
+    dates::DATE_QUERY_PREFIX,
+    files::EXTENSION_QUERY_PREFIX,
+    history::HISTORY_QUERY_PREFIX,
+    launcher::{AppEntry, EntryType, BINARY_DIR},
+    providers::{Provider, Registry},
+};
+
+const HELP_ID_PREFIX: &str = "help:";
+
+pub fn is_help_query(query: &str, config: &Config) -> bool {
+    !config.search.help_prefix.is_empty() && query == config.search.help_prefix.to_lowercase()
+}
+
+pub fn is_help_entry(app: &AppEntry) -> bool {
+    app.id.starts_with(HELP_ID_PREFIX)
+}
+
+// E.g. ", from 2 characters", for searches that need a minimum query length.
+fn min_length_note(min_length: usize) -> String {
+    match min_length {
+        0 | 1 => String::new(),
+        min_length => format!(", from {} characters", min_length),
+    }
+}
+
+pub fn help_entries(config: &Config) -> Vec<AppEntry> {
+    let mut modes = Vec::new();
+    let providers = Registry::new(&config.search.min_query_length);
+
+    if config.search.enable_binary_fallback {
+        modes.push((
+            String::new(),
+            String::from("command"),
+            format!(
+                "Run a program from {}{}",
+                BINARY_DIR,
+                min_length_note(providers.min_length(Provider::Commands))
+            ),
+            String::from("htop"),
+            "application-x-executable",
+        ));
+    }
+
+    if config.search.enable_file_browsing {
+        modes.push((
+            String::from("~/"),
+            String::from("~ / $"),
+            format!(
+                "Browse files and folders{}",
+                min_length_note(providers.min_length(Provider::Files))
+            ),
+            String::from("~/Downloads/ *.pdf"),
+            "folder",
+        ));
+        modes.extend(config.files.roots.iter().map(|(name, path)| {
+            (
+                format!("{}/", name),
+                format!("{}/", name),
+                format!("Browse {}", path),
+                format!("{}/", name),
+                "folder",
+            )
+        }));
+        modes.push((
+            String::from(BOOKMARK_QUERY_PREFIX),
+            String::from(BOOKMARK_QUERY_PREFIX),
+            String::from("List bookmarked folders"),
+            String::from(BOOKMARK_QUERY_PREFIX),
+            "user-bookmarks",
+        ));
+    }
+
+    modes.push((
+        String::from(EXTENSION_QUERY_PREFIX),
+        String::from(EXTENSION_QUERY_PREFIX),
+        String::from("Find documents by extension or type"),
+        format!("{}pdf", EXTENSION_QUERY_PREFIX),
+        "x-office-document",
+    ));
+    modes.push((
+        String::from(CATEGORY_QUERY_PREFIX),
+        String::from(CATEGORY_QUERY_PREFIX),
+        String::from("List the applications in a category"),
+        format!("{}Development", CATEGORY_QUERY_PREFIX),
+        "applications-other",
+    ));
+    if config.search.history_size > 0 {
+        modes.push((
+            String::from(HISTORY_QUERY_PREFIX),
+            String::from(HISTORY_QUERY_PREFIX),
+            String::from("List earlier queries"),
+            String::from(HISTORY_QUERY_PREFIX),
+            "document-open-recent",
+        ));
+    }
+    modes.push((
+        String::from(DATE_QUERY_PREFIX),
+        String::from("date / days until / weekday of"),
+        String::from("Calculate dates"),
+        format!("{}+3 weeks", DATE_QUERY_PREFIX),
+        "x-office-calendar",
+    ));
+
+    modes
+        .into_iter()
+        .map(|(prefix, label, description, example, icon)| AppEntry {
+            id: format!("{}{}", HELP_ID_PREFIX, prefix),
+            name: format!("{} — {}", label, description),
+            description: format!("Example: {}", example),
+            exec: prefix,
+            icon_name: icon.into(),
+            entry_type: EntryType::Query,
+            ..Default::default()
+        })
+        .collect()
+}
@@ -0,0 +1,204 @@
+use crate::{
+    config::Config,
+    launcher::{AppEntry, EntryType},
+    log,
+    modes::{self, Mode},
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, path::Path, sync::RwLock};
+
+pub const HISTORY_QUERY_PREFIX: &str = "history:";
+
+const HISTORY_ID_PREFIX: &str = "history:";
+
+static HISTORY_PATH: &str = "~/.local/share/hyprlauncher/query_history.json";
+
+// Queries that led to a launch, oldest first.
+static HISTORY: Lazy<RwLock<Vec<HistoryEntry>>> = Lazy::new(|| RwLock::new(load()));
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub query: String,
+    // Entries written before queries were kept per mode count as searches
+    // for applications.
+    #[serde(default = "default_mode")]
+    pub mode: String,
+}
+
+fn default_mode() -> String {
+    Mode::apps.name().to_string()
+}
+
+fn load() -> Vec<HistoryEntry> {
+    let path = shellexpand::tilde(HISTORY_PATH).to_string();
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log!("Ignoring malformed query history in {}: {}", path, e);
+        Vec::new()
+    })
+}
+
+fn save(history: &[HistoryEntry]) -> Result<(), std::io::Error> {
+    let path = shellexpand::tilde(HISTORY_PATH).to_string();
+    if let Some(dir) = Path::new(&path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = format!("{}.tmp", path);
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(serde_json::to_string_pretty(history)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)
+}
+
+// The mode a query belongs to: the one the launcher was opened in, or the
+// files mode for paths typed in the default mode.
+pub fn mode_for(query: &str) -> Mode {
+    modes::current().unwrap_or(if query.starts_with(['~', '$', '/']) {
+        Mode::files
+    } else {
+        Mode::apps
+    })
+}
+
+// Remembers a query that led to a launch, keeping the newest
+// `search.history_size` queries of its mode. Blocks on file IO.
+pub fn record(query: &str, mode: Mode) {
+    let limit = Config::load().search.history_size;
+    let query = query.trim();
+    if limit == 0 || query.is_empty() || query.starts_with(HISTORY_QUERY_PREFIX) {
+        return;
+    }
+
+    let entry = HistoryEntry {
+        query: query.to_string(),
+        mode: mode.name().to_string(),
+    };
+    let mut history = HISTORY.write().unwrap();
+    let mut updated = history.clone();
+    updated.retain(|known| *known != entry);
+    updated.push(entry);
+
+    let in_mode = updated
+        .iter()
+        .filter(|known| known.mode == mode.name())
+        .count();
+    let mut excess = in_mode.saturating_sub(limit);
+    updated.retain(|known| {
+        let drop = excess > 0 && known.mode == mode.name();
+        excess -= drop as usize;
+        !drop
+    });
+
+    if let Err(e) = save(&updated) {
+        log!("Failed to save query history: {}", e);
+        return;
+    }
+    *history = updated;
+}
+
+// Queries of the mode, newest first, or of every mode when it has none yet.
+pub fn queries(mode: Mode) -> Vec<String> {
+    let history = HISTORY.read().unwrap();
+    let in_mode: Vec<_> = history
+        .iter()
+        .rev()
+        .filter(|known| known.mode == mode.name())
+        .map(|known| known.query.clone())
+        .collect();
+    if !in_mode.is_empty() {
+        return in_mode;
+    }
+
+    let mut all: Vec<String> = Vec::new();
+    for known in history.iter().rev() {
+        if !all.contains(&known.query) {
+            all.push(known.query.clone());
+        }
+    }
+    all
+}
+
+pub fn is_history_entry(app: &AppEntry) -> bool {
+    app.id.starts_with(HISTORY_ID_PREFIX) && matches!(app.entry_type, EntryType::Query)
+}
+
+// Rows for `history:`, newest first. Activating one puts its query back into
+// the search bar.
+pub fn matching_entries(filter: &str, matcher: &SkimMatcherV2) -> Vec<AppEntry> {
+    HISTORY
+        .read()
+        .unwrap()
+        .iter()
+        .rev()
+        .filter(|known| {
+            filter.is_empty()
+                || matcher
+                    .fuzzy_match(&known.query.to_lowercase(), filter)
+                    .is_some()
+        })
+        .map(|known| AppEntry {
+            id: format!("{}{}:{}", HISTORY_ID_PREFIX, known.mode, known.query),
+            name: known.query.clone(),
+            description: Mode::from_name(&known.mode)
+                .map_or_else(|| known.mode.clone(), |mode| mode.label().to_string()),
+            exec: known.query.clone(),
+            icon_name: "document-open-recent".into(),
+            entry_type: EntryType::Query,
+            ..Default::default()
+        })
+        .collect()
+}
+
+// Forgets the query of a `history:` row.
+pub fn remove(app: &AppEntry) -> Result<(), std::io::Error> {
+    let Some((mode, query)) = app
+        .id
+        .strip_prefix(HISTORY_ID_PREFIX)
+        .and_then(|key| key.split_once(':'))
+    else {
+        return Ok(());
+    };
+
+    let mut history = HISTORY.write().unwrap();
+    let mut updated = history.clone();
+    updated.retain(|known| known.mode != mode || known.query != query);
+    save(&updated)?;
+    *history = updated;
+    Ok(())
+}
+
+// Steps through earlier queries with Up and Down on an empty search bar.
+pub struct Recall {
+    queries: Vec<String>,
+    position: usize,
+}
+
+impl Recall {
+    pub fn start(mode: Mode) -> Option<Self> {
+        let queries = queries(mode);
+        (!queries.is_empty()).then_some(Self {
+            queries,
+            position: 0,
+        })
+    }
+
+    pub fn current(&self) -> &str {
+        &self.queries[self.position]
+    }
+
+    pub fn older(&mut self) -> &str {
+        self.position = (self.position + 1).min(self.queries.len() - 1);
+        self.current()
+    }
+
+    // None once past the newest query, which returns to the empty search bar.
+    pub fn newer(&mut self) -> Option<&str> {
+        self.position = self.position.checked_sub(1)?;
+        Some(self.current())
+    }
+}
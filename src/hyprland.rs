@@ -0,0 +1,318 @@
+use crate::{
+    app::APPLICATION_ID,
+    capabilities::{self, HYPRCTL},
+    log,
+    subprocess::{self, Limits},
+};
+use std::{env, path::PathBuf, process::Command, sync::Mutex, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::UnixStream,
+    sync::mpsc::UnboundedSender,
+};
+
+pub const NAMESPACE: &str = "hyprlauncher";
+
+const EVENT_SOCKET: &str = ".socket2.sock";
+const EVENT_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+const HYPRCTL_LIMITS: Limits = Limits {
+    timeout: Duration::from_secs(2),
+    max_output: 4 * 1024 * 1024,
+};
+
+static PREVIOUS_WINDOW: Mutex<Option<String>> = Mutex::new(None);
+// Last known active workspace, kept up to date from workspace events so that
+// searches don't have to ask hyprctl.
+static ACTIVE_WORKSPACE: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn is_running() -> bool {
+    env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some()
+}
+
+pub fn hyprctl(args: &[&str]) -> Option<String> {
+    if !capabilities::is_available(HYPRCTL) {
+        return None;
+    }
+    if !is_running() {
+        return None;
+    }
+
+    run_hyprctl(args).map_err(|e| log!("{}", e)).ok()
+}
+
+// The output of hyprctl, or why there is none.
+fn run_hyprctl(args: &[&str]) -> Result<String, String> {
+    match subprocess::run(
+        "hyprctl",
+        Command::new("hyprctl").args(args),
+        HYPRCTL_LIMITS,
+        None,
+    ) {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        Ok(output) => Err(format!(
+            "hyprctl {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Err(format!("hyprctl {}", e)),
+    }
+}
+
+pub fn keyword(name: &str, value: &str) -> bool {
+    hyprctl(&["keyword", name, value])
+        .map(|output| output.trim() == "ok")
+        .unwrap_or(false)
+}
+
+// Replaces the window rules matching `class` in one batch, so they are all in
+// place before the window maps.
+pub fn window_rules(class: &str, rules: &[String]) -> bool {
+    let batch = window_rule_batch(class, rules);
+    hyprctl(&["--batch", &batch])
+        .map(|output| output.lines().filter(|line| line.trim() == "ok").count() == rules.len() + 1)
+        .unwrap_or(false)
+}
+
+// Rules added with `keyword` accumulate until Hyprland reloads its config, so
+// the earlier ones for the same matcher are unset first.
+fn window_rule_batch(class: &str, rules: &[String]) -> String {
+    let matcher = format!("class:^({})$", class.replace('.', "\\."));
+    std::iter::once("unset")
+        .chain(rules.iter().map(String::as_str))
+        .map(|rule| format!("keyword windowrulev2 {}, {}", rule, matcher))
+        .collect::<Vec<_>>()
+        .join(" ; ")
+}
+
+pub fn active_window_address() -> Option<String> {
+    let output = hyprctl(&["activewindow", "-j"])?;
+    let window: serde_json::Value = serde_json::from_str(&output).ok()?;
+    window["address"].as_str().map(String::from)
+}
+
+// Asks hyprctl for the name of the active workspace and remembers it.
+pub fn active_workspace() -> Option<String> {
+    let output = hyprctl(&["activeworkspace", "-j"])?;
+    let workspace: serde_json::Value = serde_json::from_str(&output).ok()?;
+    let name = workspace["name"].as_str().map(String::from);
+    set_current_workspace(name.clone());
+    name
+}
+
+pub fn current_workspace() -> Option<String> {
+    ACTIVE_WORKSPACE.lock().unwrap().clone()
+}
+
+pub fn set_current_workspace(name: Option<String>) {
+    *ACTIVE_WORKSPACE.lock().unwrap() = name;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Client {
+    pub address: String,
+    pub class: String,
+    pub title: String,
+    pub workspace: String,
+}
+
+// Mapped windows other than the launcher's own, most recently focused first,
+// or why they couldn't be listed.
+pub fn clients() -> Result<Vec<Client>, String> {
+    if !capabilities::is_available(HYPRCTL) {
+        return Err(capabilities::explain(HYPRCTL));
+    }
+    run_hyprctl(&["clients", "-j"]).map(|output| parse_clients(&output))
+}
+
+fn parse_clients(output: &str) -> Vec<Client> {
+    let Ok(serde_json::Value::Array(clients)) = serde_json::from_str(output) else {
+        return Vec::new();
+    };
+    let mut clients: Vec<_> = clients
+        .iter()
+        .filter(|client| client["mapped"].as_bool() != Some(false))
+        .filter(|client| client["class"].as_str() != Some(APPLICATION_ID))
+        .filter_map(|client| {
+            let text = |key: &str| client[key].as_str().unwrap_or_default().to_string();
+            let focus_order = client["focusHistoryID"].as_i64().unwrap_or(i64::MAX);
+            Some((
+                focus_order,
+                Client {
+                    address: client["address"].as_str()?.to_string(),
+                    class: text("class"),
+                    title: text("title"),
+                    workspace: client["workspace"]["name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                },
+            ))
+        })
+        .collect();
+    clients.sort_by_key(|(focus_order, _)| *focus_order);
+    clients.into_iter().map(|(_, client)| client).collect()
+}
+
+pub fn focus_window(address: &str) -> bool {
+    hyprctl(&["dispatch", "focuswindow", &format!("address:{}", address)])
+        .is_some_and(|output| output.trim() == "ok")
+}
+
+fn window_exists(address: &str) -> bool {
+    hyprctl(&["clients", "-j"])
+        .and_then(|output| serde_json::from_str::<serde_json::Value>(&output).ok())
+        .and_then(|clients| {
+            clients.as_array().map(|clients| {
+                clients
+                    .iter()
+                    .any(|client| client["address"].as_str() == Some(address))
+            })
+        })
+        .unwrap_or(false)
+}
+
+pub fn remember_active_window() {
+    *PREVIOUS_WINDOW.lock().unwrap() = active_window_address();
+}
+
+pub fn forget_active_window() {
+    PREVIOUS_WINDOW.lock().unwrap().take();
+}
+
+pub fn restore_focus() {
+    let Some(address) = PREVIOUS_WINDOW.lock().unwrap().take() else {
+        return;
+    };
+
+    if !window_exists(&address) {
+        log!("Previously focused window {} is gone", address);
+        return;
+    }
+
+    log!("Restoring focus to {}", address);
+    focus_window(&address);
+}
+
+fn socket_dirs() -> Vec<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    runtime_dir
+        .into_iter()
+        .map(|dir| dir.join("hypr"))
+        .chain(std::iter::once(PathBuf::from("/tmp/hypr")))
+        .collect()
+}
+
+// The socket of the instance the launcher was started under, or after a
+// Hyprland restart, which changes the signature, the newest one.
+fn event_socket() -> Option<PathBuf> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let dirs = socket_dirs();
+    dirs.iter()
+        .map(|dir| dir.join(&signature).join(EVENT_SOCKET))
+        .find(|socket| socket.exists())
+        .or_else(|| {
+            dirs.iter()
+                .filter_map(|dir| std::fs::read_dir(dir).ok())
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|instance| instance.path().join(EVENT_SOCKET))
+                .filter_map(|socket| Some((socket.metadata().ok()?.modified().ok()?, socket)))
+                .max()
+                .map(|(_, socket)| socket)
+        })
+}
+
+// Forwards Hyprland events in `events` as name and data, e.g. `workspace` and
+// `2` for `workspace>>2`, until the receiver is dropped. Reconnects when
+// Hyprland restarts and returns right away on other compositors.
+pub async fn watch_events(events: &[&str], tx: UnboundedSender<(String, String)>) {
+    if !is_running() {
+        return;
+    }
+
+    while !tx.is_closed() {
+        let stream = match event_socket() {
+            Some(socket) => UnixStream::connect(&socket)
+                .await
+                .map_err(|e| log!("Failed to connect to {:?}: {}", socket, e))
+                .ok(),
+            None => None,
+        };
+
+        if let Some(stream) = stream {
+            let mut lines = BufReader::new(stream).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Some((event, data)) = line.split_once(">>") else {
+                    continue;
+                };
+                if events.contains(&event)
+                    && tx.send((event.to_string(), data.to_string())).is_err()
+                {
+                    return;
+                }
+            }
+            log!("Hyprland event socket closed, reconnecting");
+        }
+
+        tokio::time::sleep(EVENT_RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_are_listed_by_focus_order() {
+        let output = r#"[
+            {"address": "0xa", "mapped": true, "class": "kitty", "title": "shell",
+             "workspace": {"id": 2, "name": "2"}, "focusHistoryID": 1},
+            {"address": "0xb", "mapped": true, "class": "firefox", "title": "Firefox",
+             "workspace": {"id": 1, "name": "1"}, "focusHistoryID": 0},
+            {"address": "0xc", "mapped": false, "class": "hidden", "title": "",
+             "workspace": {"id": -1, "name": ""}, "focusHistoryID": 2},
+            {"address": "0xd", "mapped": true, "class": "hyprutils.hyprlauncher",
+             "title": "Hyprlauncher", "workspace": {"id": 1, "name": "1"},
+             "focusHistoryID": 3}
+        ]"#;
+        let clients = parse_clients(output);
+        assert_eq!(
+            clients,
+            [
+                Client {
+                    address: String::from("0xb"),
+                    class: String::from("firefox"),
+                    title: String::from("Firefox"),
+                    workspace: String::from("1"),
+                },
+                Client {
+                    address: String::from("0xa"),
+                    class: String::from("kitty"),
+                    title: String::from("shell"),
+                    workspace: String::from("2"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_client_lists_are_empty() {
+        assert!(parse_clients("").is_empty());
+        assert!(parse_clients("{\"address\": \"0xa\"}").is_empty());
+        assert!(parse_clients("[{\"class\": \"no address\"}]").is_empty());
+    }
+
+    #[test]
+    fn window_rules_replace_the_earlier_ones() {
+        let rules = [String::from("float"), String::from("size 600 600")];
+        assert_eq!(
+            window_rule_batch("hyprutils.hyprlauncher", &rules),
+            "keyword windowrulev2 unset, class:^(hyprutils\\.hyprlauncher)$ ; \
+             keyword windowrulev2 float, class:^(hyprutils\\.hyprlauncher)$ ; \
+             keyword windowrulev2 size 600 600, class:^(hyprutils\\.hyprlauncher)$"
+        );
+    }
+}
@@ -0,0 +1,606 @@
+use crate::{log, profile};
+use gtk4::{
+    gdk, gdk_pixbuf,
+    gio::prelude::*,
+    glib::{self, ControlFlow},
+    IconLookupFlags, IconTheme, TextDirection,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    rc::Rc,
+};
+
+pub const ICON_SIZE: i32 = 32;
+
+const PRELOAD_BATCH: usize = 4;
+
+const IMAGE_EXTENSIONS: &[&str] = &[".png", ".svg", ".svgz", ".xpm", ".jpg", ".jpeg", ".ico"];
+
+const DEFAULT_CACHE_LIMIT: usize = 32 * 1024 * 1024;
+
+// Icon= values and what they resolved to. Far more than there are icons on
+// screen, but file icons and custom entries keep adding names.
+const RESOLVED_NAMES_LIMIT: usize = 1024;
+
+// An icon name or image path, the size it is drawn at in logical pixels and
+// the scale factor.
+type IconKey = (String, i32, i32);
+
+// Values kept for reuse, least recently used first out once the sum of their
+// costs passes the limit.
+struct Lru<K, V> {
+    entries: HashMap<K, (V, usize, u64)>,
+    recency: BTreeMap<u64, K>,
+    cost: usize,
+    limit: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Lru<K, V> {
+    fn new(limit: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(100),
+            recency: BTreeMap::new(),
+            cost: 0,
+            limit,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let Some((value, _, used)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        self.recency.remove(used);
+        *used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: K, value: V, cost: usize) {
+        self.tick += 1;
+        if let Some((_, old_cost, used)) = self.entries.remove(&key) {
+            self.cost -= old_cost;
+            self.recency.remove(&used);
+        }
+        self.cost += cost;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, cost, self.tick));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.cost > self.limit {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some((_, cost, _)) = self.entries.remove(&key) {
+                self.cost -= cost;
+            }
+        }
+    }
+
+    // Drops the entries `matches` picks, except the `keep` most recently used
+    // of them. Returns how many were dropped.
+    fn retain_recent(&mut self, keep: usize, matches: impl Fn(&K) -> bool) -> usize {
+        let dropped: Vec<u64> = self
+            .recency
+            .iter()
+            .rev()
+            .filter(|(_, key)| matches(key))
+            .skip(keep)
+            .map(|(used, _)| *used)
+            .collect();
+        for used in &dropped {
+            if let Some(key) = self.recency.remove(used) {
+                if let Some((_, cost, _)) = self.entries.remove(&key) {
+                    self.cost -= cost;
+                }
+            }
+        }
+        dropped.len()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.cost = 0;
+    }
+}
+
+// A square texture of `size` logical pixels, four bytes per device pixel.
+fn texture_bytes(size: i32, scale: i32) -> usize {
+    let side = (size.max(0) * scale.max(1)) as usize;
+    side * side * 4
+}
+
+thread_local! {
+    // Theme icons and images, limited by their estimated texture size.
+    static ICON_CACHE: RefCell<Lru<IconKey, gdk::Paintable>> =
+        RefCell::new(Lru::new(DEFAULT_CACHE_LIMIT));
+    static ICON_PATH_CACHE: RefCell<HashMap<(String, i32), Option<String>>> =
+        RefCell::new(HashMap::new());
+    static RESOLVED_NAMES: RefCell<Lru<String, String>> =
+        RefCell::new(Lru::new(RESOLVED_NAMES_LIMIT));
+}
+
+// Variants of an Icon= value to try in order, each with the rule that made
+// it: the basename of a missing absolute path, the name without an image
+// extension, lowercased, and with underscores and spaces turned into dashes.
+fn candidates(icon_name: &str, path_exists: bool) -> Vec<(String, &'static str)> {
+    let mut candidates = vec![(icon_name.to_string(), "as given")];
+    let mut push = |name: String, rule| {
+        if !name.is_empty() && candidates.iter().all(|(known, _)| *known != name) {
+            candidates.push((name, rule));
+        }
+    };
+
+    let mut name = icon_name;
+    if icon_name.starts_with('/') {
+        if path_exists {
+            return candidates;
+        }
+        name = icon_name.rsplit('/').next().unwrap_or_default();
+        push(name.to_string(), "basename of missing path");
+    }
+
+    let lower = name.to_lowercase();
+    if let Some(extension) = IMAGE_EXTENSIONS.iter().find(|ext| lower.ends_with(*ext)) {
+        name = &name[..name.len() - extension.len()];
+        push(name.to_string(), "image extension stripped");
+    }
+
+    let lowercased = name.to_lowercase();
+    push(lowercased.clone(), "lowercased");
+    push(lowercased.replace(['_', ' '], "-"), "dash-normalized");
+    candidates
+}
+
+// Maps an Icon= value to the name or existing path to draw. Vendor mistakes
+// are corrected by the first candidate the theme knows, and the given name is
+// kept when none matches so GTK shows its missing icon. Names the theme
+// doesn't know aren't remembered, so an icon installed later is found.
+pub fn resolve_name(icon_name: &str) -> String {
+    let key = icon_name.to_string();
+    if let Some(name) = RESOLVED_NAMES.with(|names| names.borrow_mut().get(&key)) {
+        return name;
+    }
+
+    let Some(display) = gdk::Display::default() else {
+        return key;
+    };
+    let theme = IconTheme::for_display(&display);
+    let path_exists = icon_name.starts_with('/') && std::path::Path::new(icon_name).exists();
+    match resolve_with(icon_name, path_exists, |name| theme.has_icon(name)) {
+        Some(resolved) => {
+            RESOLVED_NAMES.with(|names| names.borrow_mut().insert(key, resolved.clone(), 1));
+            resolved
+        }
+        None => {
+            log!("Icon {:?} is not in the icon theme", icon_name);
+            key
+        }
+    }
+}
+
+// The first candidate that is an existing path or an icon of the theme.
+fn resolve_with(
+    icon_name: &str,
+    path_exists: bool,
+    has_icon: impl Fn(&str) -> bool,
+) -> Option<String> {
+    candidates(icon_name, path_exists)
+        .into_iter()
+        .enumerate()
+        .find(|(_, (name, _))| {
+            if name.starts_with('/') {
+                path_exists
+            } else {
+                has_icon(name)
+            }
+        })
+        .map(|(index, (name, rule))| {
+            if index > 0 {
+                log!("Icon {:?} resolved as {:?} ({})", icon_name, name, rule);
+            }
+            name
+        })
+}
+
+// Sets the size the icon cache may grow to from `theme.icon_cache_mb`.
+pub fn set_cache_limit(megabytes: usize) {
+    ICON_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.limit = megabytes * 1024 * 1024;
+        cache.evict();
+    });
+}
+
+// Drops the textures of image files, e.g. those shown while browsing
+// paths, except the `keep` drawn last. Theme icons stay. Returns how many
+// were dropped.
+pub fn retain_images(keep: usize) -> usize {
+    ICON_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .retain_recent(keep, |(source, _, _)| source.starts_with('/'))
+    })
+}
+
+// Names resolve differently and paintables come from the old theme once the
+// icon theme changes, so everything cached is dropped.
+pub fn watch_theme(display: &gdk::Display) {
+    IconTheme::for_display(display).connect_changed(|_| {
+        log!("Icon theme changed");
+        clear();
+    });
+}
+
+pub fn clear() {
+    ICON_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        log!(
+            "Dropping {} cached icons ({} KiB, {} hits, {} misses)",
+            cache.entries.len(),
+            cache.cost / 1024,
+            cache.hits,
+            cache.misses
+        );
+        cache.clear();
+    });
+    ICON_PATH_CACHE.with(|cache| cache.borrow_mut().clear());
+    RESOLVED_NAMES.with(|names| names.borrow_mut().clear());
+}
+
+// Prints the use of the icon cache with --profile.
+pub fn report_usage() {
+    ICON_CACHE.with(|cache| {
+        let cache = cache.borrow();
+        profile::report(
+            "icon cache",
+            format_args!(
+                "{} icons ({} of {} KiB), {} hits, {} misses",
+                cache.entries.len(),
+                cache.cost / 1024,
+                cache.limit / 1024,
+                cache.hits,
+                cache.misses
+            ),
+        );
+    });
+}
+
+// The paintable for an Icon= value drawn at `size`: the theme's icon for a
+// name, or the image scaled down to the size for a path.
+pub fn lookup(icon_name: &str, size: i32, scale: i32) -> Option<gdk::Paintable> {
+    let key = (resolve_name(icon_name), size, scale);
+    if let Some(paintable) = ICON_CACHE.with(|cache| cache.borrow_mut().get(&key)) {
+        return Some(paintable);
+    }
+
+    let (paintable, bytes) = if key.0.starts_with('/') {
+        let side = size * scale.max(1);
+        let pixbuf = gdk_pixbuf::Pixbuf::from_file_at_scale(&key.0, side, side, true)
+            .map_err(|e| log!("Failed to load icon {}: {}", key.0, e))
+            .ok()?;
+        let bytes = pixbuf.width() as usize * pixbuf.height() as usize * 4;
+        (
+            gdk::Texture::for_pixbuf(&pixbuf).upcast::<gdk::Paintable>(),
+            bytes,
+        )
+    } else {
+        let display = gdk::Display::default()?;
+        let paintable = IconTheme::for_display(&display).lookup_icon(
+            &key.0,
+            &[],
+            size,
+            scale,
+            TextDirection::None,
+            IconLookupFlags::empty(),
+        );
+        (
+            paintable.upcast::<gdk::Paintable>(),
+            texture_bytes(size, scale),
+        )
+    };
+
+    ICON_CACHE.with(|cache| cache.borrow_mut().insert(key, paintable.clone(), bytes));
+    Some(paintable)
+}
+
+// Resolves an icon name to the file the current theme would draw at `size`,
+// or None when the theme has no such icon or it isn't backed by a file.
+pub fn resolve_path(icon_name: &str, size: i32) -> Option<String> {
+    let icon_name = &resolve_name(icon_name);
+    if icon_name.starts_with('/') {
+        return std::path::Path::new(icon_name)
+            .exists()
+            .then(|| icon_name.to_string());
+    }
+
+    let key = (icon_name.to_string(), size);
+    if let Some(path) = ICON_PATH_CACHE.with(|cache| cache.borrow().get(&key).cloned()) {
+        return path;
+    }
+
+    let path = gdk::Display::default()
+        .map(|display| IconTheme::for_display(&display))
+        .filter(|theme| theme.has_icon(icon_name))
+        .and_then(|theme| {
+            theme
+                .lookup_icon(
+                    icon_name,
+                    &[],
+                    size,
+                    1,
+                    TextDirection::None,
+                    IconLookupFlags::empty(),
+                )
+                .file()
+        })
+        .and_then(|file| file.path())
+        .map(|path| path.to_string_lossy().into_owned());
+
+    ICON_PATH_CACHE.with(|cache| cache.borrow_mut().insert(key, path.clone()));
+    path
+}
+
+pub fn preload(icon_names: Vec<String>, scale: i32, cancelled: Rc<Cell<bool>>) {
+    let mut pending = icon_names
+        .into_iter()
+        .filter(|name| !name.is_empty() && !name.starts_with('/'))
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    glib::idle_add_local(move || {
+        if cancelled.get() {
+            log!("Icon preloading cancelled");
+            return ControlFlow::Break;
+        }
+
+        for _ in 0..PRELOAD_BATCH {
+            match pending.next() {
+                Some(name) => {
+                    lookup(&name, ICON_SIZE, scale);
+                }
+                None => return ControlFlow::Break,
+            }
+        }
+        ControlFlow::Continue
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type CandidatesCase = (&'static str, bool, &'static [(&'static str, &'static str)]);
+
+    #[test]
+    fn candidates_correct_common_vendor_mistakes() {
+        let cases: &[CandidatesCase] = &[
+            ("firefox", false, &[("firefox", "as given")]),
+            (
+                "Steam_Icon",
+                false,
+                &[
+                    ("Steam_Icon", "as given"),
+                    ("steam_icon", "lowercased"),
+                    ("steam-icon", "dash-normalized"),
+                ],
+            ),
+            (
+                "Zoom.png",
+                false,
+                &[
+                    ("Zoom.png", "as given"),
+                    ("Zoom", "image extension stripped"),
+                    ("zoom", "lowercased"),
+                ],
+            ),
+            (
+                "/opt/app/icons/App Icon.SVG",
+                false,
+                &[
+                    ("/opt/app/icons/App Icon.SVG", "as given"),
+                    ("App Icon.SVG", "basename of missing path"),
+                    ("App Icon", "image extension stripped"),
+                    ("app icon", "lowercased"),
+                    ("app-icon", "dash-normalized"),
+                ],
+            ),
+            (
+                "/usr/share/pixmaps/app.png",
+                true,
+                &[("/usr/share/pixmaps/app.png", "as given")],
+            ),
+        ];
+
+        for (icon_name, path_exists, expected) in cases {
+            let candidates = candidates(icon_name, *path_exists);
+            let candidates: Vec<_> = candidates
+                .iter()
+                .map(|(name, rule)| (name.as_str(), *rule))
+                .collect();
+            assert_eq!(candidates, *expected, "candidates of {:?}", icon_name);
+        }
+    }
+
+    #[test]
+    fn names_resolve_to_the_first_candidate_the_theme_knows() {
+        let theme = ["firefox", "steam-icon", "zoom", "app-icon"];
+        let has_icon = |name: &str| theme.contains(&name);
+        let cases: &[(&str, bool, Option<&str>)] = &[
+            ("firefox", false, Some("firefox")),
+            ("Steam_Icon", false, Some("steam-icon")),
+            ("Zoom.png", false, Some("zoom")),
+            ("/opt/app/icons/App Icon.SVG", false, Some("app-icon")),
+            (
+                "/usr/share/pixmaps/app.png",
+                true,
+                Some("/usr/share/pixmaps/app.png"),
+            ),
+            ("/usr/share/pixmaps/gone.png", false, None),
+            ("unknown-app", false, None),
+        ];
+
+        for (icon_name, path_exists, expected) in cases {
+            assert_eq!(
+                resolve_with(icon_name, *path_exists, has_icon).as_deref(),
+                *expected,
+                "resolving {:?}",
+                icon_name
+            );
+        }
+    }
+
+    #[test]
+    fn only_the_most_recent_matching_entries_are_retained() {
+        let mut lru = Lru::new(100);
+        for name in ["/a.png", "folder", "/b.png", "/c.png", "text-x-generic"] {
+            lru.insert(name, name.to_uppercase(), 10);
+        }
+        lru.get(&"/a.png");
+
+        assert_eq!(lru.retain_recent(2, |name| name.starts_with('/')), 1);
+        assert_eq!(lru.get(&"/b.png"), None);
+        for kept in ["/a.png", "/c.png", "folder", "text-x-generic"] {
+            assert!(lru.get(&kept).is_some(), "{} was dropped", kept);
+        }
+        assert_eq!(lru.cost, 40);
+        assert_eq!(lru.retain_recent(2, |name| name.starts_with('/')), 0);
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_first() {
+        let mut lru = Lru::new(3);
+        for name in ["a", "b", "c"] {
+            lru.insert(name, name.to_uppercase(), 1);
+        }
+        assert_eq!(lru.get(&"a").as_deref(), Some("A"));
+        lru.insert("d", String::from("D"), 1);
+
+        assert_eq!(lru.get(&"b"), None);
+        assert!(lru.get(&"a").is_some() && lru.get(&"c").is_some());
+        assert_eq!(lru.cost, 3);
+        assert_eq!((lru.hits, lru.misses), (3, 1));
+    }
+
+    #[test]
+    fn costly_entries_make_room_and_replacements_keep_one_cost() {
+        let mut lru = Lru::new(4);
+        lru.insert("a", 1, 1);
+        lru.insert("b", 2, 1);
+        lru.insert("a", 3, 2);
+        assert_eq!(lru.cost, 3);
+        assert_eq!(lru.get(&"a"), Some(3));
+
+        lru.insert("c", 4, 3);
+        assert_eq!(lru.entries.len(), 1);
+        assert_eq!(lru.cost, 3);
+
+        lru.limit = 0;
+        lru.evict();
+        assert!(lru.entries.is_empty() && lru.recency.is_empty());
+    }
+
+    #[test]
+    fn textures_are_costed_by_size_and_scale() {
+        assert_eq!(texture_bytes(ICON_SIZE, 1), 32 * 32 * 4);
+        assert_eq!(texture_bytes(ICON_SIZE, 2), 64 * 64 * 4);
+        assert_eq!(texture_bytes(48, 1), 48 * 48 * 4);
+        assert_eq!(texture_bytes(ICON_SIZE, 0), texture_bytes(ICON_SIZE, 1));
+    }
+
+    #[test]
+    fn sizes_and_scales_are_cached_apart() {
+        let mut cache: Lru<IconKey, &str> = Lru::new(DEFAULT_CACHE_LIMIT);
+        let key = |size, scale| (String::from("firefox"), size, scale);
+        cache.insert(key(32, 1), "small", texture_bytes(32, 1));
+        cache.insert(key(32, 2), "hidpi", texture_bytes(32, 2));
+
+        assert_eq!(cache.get(&key(32, 1)), Some("small"));
+        assert_eq!(cache.get(&key(32, 2)), Some("hidpi"));
+        assert_eq!(cache.get(&key(48, 1)), None);
+        assert_eq!(cache.cost, texture_bytes(32, 1) + texture_bytes(32, 2));
+    }
+
+    #[test]
+    fn large_textures_push_out_the_oldest_icons() {
+        // Room for four icons at scale 1, or one at scale 2.
+        let mut cache: Lru<IconKey, &str> = Lru::new(texture_bytes(ICON_SIZE, 2));
+        for name in ["a", "b", "c", "d"] {
+            cache.insert(
+                (name.to_string(), ICON_SIZE, 1),
+                name,
+                texture_bytes(ICON_SIZE, 1),
+            );
+        }
+        assert_eq!(cache.entries.len(), 4);
+
+        cache.get(&(String::from("a"), ICON_SIZE, 1));
+        cache.insert(
+            (String::from("e"), ICON_SIZE, 1),
+            "e",
+            texture_bytes(ICON_SIZE, 1),
+        );
+        assert!(!cache
+            .entries
+            .contains_key(&(String::from("b"), ICON_SIZE, 1)));
+
+        cache.insert(
+            (String::from("hidpi"), ICON_SIZE, 2),
+            "hidpi",
+            texture_bytes(ICON_SIZE, 2),
+        );
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.cost, texture_bytes(ICON_SIZE, 2));
+
+        // A limit lowered by a config reload applies at once.
+        cache.limit = texture_bytes(ICON_SIZE, 1);
+        cache.evict();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn invalidation_keeps_the_counters() {
+        let mut cache: Lru<IconKey, &str> = Lru::new(DEFAULT_CACHE_LIMIT);
+        let key = (String::from("firefox"), ICON_SIZE, 1);
+        cache.insert(key.clone(), "old theme", texture_bytes(ICON_SIZE, 1));
+        cache.get(&key);
+        cache.clear();
+
+        assert_eq!(cache.get(&key), None);
+        assert!(cache.recency.is_empty());
+        assert_eq!(cache.cost, 0);
+        assert_eq!((cache.hits, cache.misses), (1, 1));
+    }
+
+    #[test]
+    fn theme_changes_drop_resolved_names_and_paths() {
+        RESOLVED_NAMES.with(|names| {
+            names
+                .borrow_mut()
+                .insert(String::from("Steam_Icon"), String::from("steam-icon"), 1)
+        });
+        ICON_PATH_CACHE.with(|paths| {
+            paths
+                .borrow_mut()
+                .insert((String::from("steam-icon"), 48), None)
+        });
+        clear();
+
+        assert!(RESOLVED_NAMES.with(|names| names.borrow().entries.is_empty()));
+        assert!(ICON_PATH_CACHE.with(|paths| paths.borrow().is_empty()));
+        assert!(ICON_CACHE.with(|cache| cache.borrow().entries.is_empty()));
+    }
+}
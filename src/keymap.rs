@@ -0,0 +1,549 @@
+use crate::{
+    config::{Config, NavigateKeys},
+    log,
+};
+use gtk4::gdk::{Key, ModifierType};
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+
+// Built once and again whenever the config changes, so that key presses
+// don't read the config file.
+static CURRENT: Lazy<RwLock<Arc<Keymap>>> =
+    Lazy::new(|| RwLock::new(Arc::new(Keymap::from_config(&Config::load()))));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    SelectPrevious,
+    SelectNext,
+    DeleteWord,
+    EditDesktopFile,
+    TogglePin,
+    ToggleActions,
+    DescendFolder,
+    OpenFolder,
+    OpenTerminal,
+    ToggleBookmark,
+    DeleteHistoryEntry,
+    RemoveBookmark,
+    AcceptCustom(usize),
+    SetMark,
+    JumpToMark,
+    Close,
+}
+
+const CUSTOM_ACCEPT_NAMES: [&str; 9] = [
+    "accept_custom_1",
+    "accept_custom_2",
+    "accept_custom_3",
+    "accept_custom_4",
+    "accept_custom_5",
+    "accept_custom_6",
+    "accept_custom_7",
+    "accept_custom_8",
+    "accept_custom_9",
+];
+
+const CUSTOM_ACCEPT_BINDINGS: [&str; 9] = [
+    "Ctrl+Alt+1",
+    "Ctrl+Alt+2",
+    "Ctrl+Alt+3",
+    "Ctrl+Alt+4",
+    "Ctrl+Alt+5",
+    "Ctrl+Alt+6",
+    "Ctrl+Alt+7",
+    "Ctrl+Alt+8",
+    "Ctrl+Alt+9",
+];
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::SelectPrevious,
+        Action::SelectNext,
+        Action::DeleteWord,
+        Action::EditDesktopFile,
+        Action::TogglePin,
+        Action::ToggleActions,
+        Action::DescendFolder,
+        Action::OpenFolder,
+        Action::OpenTerminal,
+        Action::ToggleBookmark,
+        Action::DeleteHistoryEntry,
+        Action::RemoveBookmark,
+        Action::AcceptCustom(0),
+        Action::AcceptCustom(1),
+        Action::AcceptCustom(2),
+        Action::AcceptCustom(3),
+        Action::AcceptCustom(4),
+        Action::AcceptCustom(5),
+        Action::AcceptCustom(6),
+        Action::AcceptCustom(7),
+        Action::AcceptCustom(8),
+        Action::SetMark,
+        Action::JumpToMark,
+        Action::Close,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::SelectPrevious => "select_previous",
+            Action::SelectNext => "select_next",
+            Action::DeleteWord => "delete_word",
+            Action::EditDesktopFile => "edit_desktop_file",
+            Action::TogglePin => "toggle_pin",
+            Action::ToggleActions => "toggle_actions",
+            Action::DescendFolder => "descend_folder",
+            Action::OpenFolder => "open_folder",
+            Action::OpenTerminal => "open_terminal",
+            Action::ToggleBookmark => "toggle_bookmark",
+            Action::DeleteHistoryEntry => "delete_history_entry",
+            Action::RemoveBookmark => "remove_bookmark",
+            Action::AcceptCustom(index) => CUSTOM_ACCEPT_NAMES[index],
+            Action::SetMark => "set_mark",
+            Action::JumpToMark => "jump_to_mark",
+            Action::Close => "close",
+        }
+    }
+
+    pub fn default_binding(self) -> &'static str {
+        match self {
+            Action::SelectPrevious => "Ctrl+k",
+            Action::SelectNext => "Ctrl+j",
+            Action::DeleteWord => "Ctrl+h",
+            Action::EditDesktopFile => "Ctrl+Shift+e",
+            Action::TogglePin => "Ctrl+d",
+            Action::ToggleActions => "Alt+Return",
+            Action::DescendFolder => "Tab",
+            Action::OpenFolder => "Ctrl+Return",
+            Action::OpenTerminal => "Ctrl+t",
+            Action::ToggleBookmark => "Ctrl+b",
+            Action::DeleteHistoryEntry => "Alt+Delete",
+            Action::RemoveBookmark => "Delete",
+            Action::AcceptCustom(index) => CUSTOM_ACCEPT_BINDINGS[index],
+            Action::SetMark => "Ctrl+m",
+            Action::JumpToMark => "Ctrl+grave",
+            Action::Close => "Escape",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+}
+
+pub const MODIFIER_MASK: ModifierType = ModifierType::CONTROL_MASK
+    .union(ModifierType::SHIFT_MASK)
+    .union(ModifierType::ALT_MASK)
+    .union(ModifierType::SUPER_MASK);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    key: Key,
+    modifiers: ModifierType,
+}
+
+impl Chord {
+    pub fn parse(binding: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+        let key = Key::from_name(parts.pop().filter(|name| !name.is_empty())?)?;
+
+        let mut modifiers = ModifierType::empty();
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "ctrl" | "control" => ModifierType::CONTROL_MASK,
+                "shift" => ModifierType::SHIFT_MASK,
+                "alt" => ModifierType::ALT_MASK,
+                "super" => ModifierType::SUPER_MASK,
+                _ => return None,
+            };
+        }
+
+        Some(Self {
+            key: key.to_lower(),
+            modifiers,
+        })
+    }
+
+    fn matches(&self, key: Key, modifiers: ModifierType) -> bool {
+        self.key == key.to_lower() && modifiers & MODIFIER_MASK == self.modifiers
+    }
+}
+
+pub struct Keymap {
+    bindings: Vec<(Chord, Action)>,
+    list_bindings: Vec<(Chord, Action)>,
+    // Invalid, duplicate and unknown bindings, shown above the search bar.
+    problems: Vec<String>,
+}
+
+pub fn current() -> Arc<Keymap> {
+    CURRENT.read().unwrap().clone()
+}
+
+pub fn reload(config: &Config) -> Arc<Keymap> {
+    let keymap = Arc::new(Keymap::from_config(config));
+    *CURRENT.write().unwrap() = keymap.clone();
+    keymap
+}
+
+impl Keymap {
+    pub fn from_config(config: &Config) -> Self {
+        let legacy = legacy_bindings(&config.window.custom_navigate_keys);
+        let mut bindings: Vec<(Chord, Action)> = Vec::with_capacity(Action::ALL.len() + 2);
+        let mut problems = Vec::new();
+        for name in &config.unknown_keys {
+            problems.push(format!("keys.{} is not a known action", name));
+        }
+
+        for &action in Action::ALL {
+            let configured = config
+                .keys
+                .get(action.name())
+                .map(String::as_str)
+                .unwrap_or(action.default_binding());
+
+            let binding = match legacy
+                .iter()
+                .find(|(legacy_action, _)| *legacy_action == action)
+            {
+                Some((_, legacy_binding)) if configured == action.default_binding() => {
+                    log!(
+                        "custom_navigate_keys is deprecated, use keys.{} instead",
+                        action.name()
+                    );
+                    legacy_binding.as_str()
+                }
+                _ => configured,
+            };
+
+            let Some(chord) = Chord::parse(binding) else {
+                log!("Invalid key binding for {}: {:?}", action.name(), binding);
+                problems.push(format!(
+                    "keys.{} has an invalid binding {:?}",
+                    action.name(),
+                    binding
+                ));
+                continue;
+            };
+
+            if let Some((_, existing)) = bindings.iter().find(|(bound, _)| *bound == chord) {
+                log!(
+                    "Key binding {:?} is assigned to both {} and {}, keeping {}",
+                    binding,
+                    existing.name(),
+                    action.name(),
+                    existing.name()
+                );
+                problems.push(format!(
+                    "{:?} is bound to both {} and {}, {} is ignored",
+                    binding,
+                    existing.name(),
+                    action.name(),
+                    action.name()
+                ));
+                continue;
+            }
+
+            bindings.push((chord, action));
+        }
+
+        bindings.push((
+            Chord {
+                key: Key::Up,
+                modifiers: ModifierType::empty(),
+            },
+            Action::SelectPrevious,
+        ));
+        bindings.push((
+            Chord {
+                key: Key::Down,
+                modifiers: ModifierType::empty(),
+            },
+            Action::SelectNext,
+        ));
+
+        // Unmodified keys can't be bound while the search entry types them,
+        // so these only apply while the result list has focus.
+        let list_bindings = [("m", Action::SetMark), ("grave", Action::JumpToMark)]
+            .into_iter()
+            .filter_map(|(binding, action)| Some((Chord::parse(binding)?, action)))
+            .collect();
+
+        Self {
+            bindings,
+            list_bindings,
+            problems,
+        }
+    }
+
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    pub fn match_event(&self, key: Key, modifiers: ModifierType) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(chord, _)| chord.matches(key, modifiers))
+            .map(|(_, action)| *action)
+    }
+
+    pub fn match_list_event(&self, key: Key, modifiers: ModifierType) -> Option<Action> {
+        self.match_event(key, modifiers).or_else(|| {
+            self.list_bindings
+                .iter()
+                .find(|(chord, _)| chord.matches(key, modifiers))
+                .map(|(_, action)| *action)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChordStep {
+    Idle,
+    Pending,
+    Completed(Action, char),
+    Cancelled(Action),
+}
+
+// Tracks a two-key sequence started by a chord prefix action, such as
+// set_mark followed by the letter of the mark.
+#[derive(Debug, Default)]
+pub struct PendingChord {
+    action: Option<Action>,
+}
+
+impl PendingChord {
+    pub fn begin(&mut self, action: Action) {
+        self.action = Some(action);
+    }
+
+    // Letters complete the sequence and modifier keys keep it pending, so
+    // Shift can be held for an uppercase letter. Any other key cancels it.
+    pub fn feed(&mut self, key: Key) -> ChordStep {
+        let Some(action) = self.action else {
+            return ChordStep::Idle;
+        };
+        if is_modifier_key(key) {
+            return ChordStep::Pending;
+        }
+
+        self.action = None;
+        match key.to_unicode().filter(char::is_ascii_alphabetic) {
+            Some(letter) => ChordStep::Completed(action, letter),
+            None => ChordStep::Cancelled(action),
+        }
+    }
+}
+
+fn is_modifier_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Shift_L
+            | Key::Shift_R
+            | Key::Control_L
+            | Key::Control_R
+            | Key::Alt_L
+            | Key::Alt_R
+            | Key::Super_L
+            | Key::Super_R
+            | Key::Meta_L
+            | Key::Meta_R
+            | Key::Caps_Lock
+            | Key::ISO_Level3_Shift
+    )
+}
+
+fn legacy_bindings(keys: &NavigateKeys) -> Vec<(Action, String)> {
+    let defaults = NavigateKeys::default();
+    [
+        (Action::SelectPrevious, &keys.up, &defaults.up),
+        (Action::SelectNext, &keys.down, &defaults.down),
+        (Action::DeleteWord, &keys.delete_word, &defaults.delete_word),
+    ]
+    .into_iter()
+    .filter(|(_, key, default)| key != default)
+    .map(|(action, key, _)| (action, format!("Ctrl+{}", key)))
+    .collect()
+}
+
+pub fn unknown_actions(keys: &serde_json::Value) -> Vec<String> {
+    keys.as_object()
+        .map(|keys| {
+            keys.keys()
+                .filter(|name| Action::from_name(name).is_none())
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Keys;
+
+    fn config_with(keys: serde_json::Value) -> Config {
+        let mut bindings = serde_json::to_value(Keys::default()).unwrap();
+        for (name, binding) in keys.as_object().unwrap() {
+            bindings[name] = binding.clone();
+        }
+        Config {
+            keys: serde_json::from_value(bindings).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn key(name: &str) -> Key {
+        Key::from_name(name).unwrap()
+    }
+
+    #[test]
+    fn parses_modifiers_in_any_case() {
+        let chord = Chord::parse("ctrl+SHIFT+e").unwrap();
+        assert_eq!(
+            chord.modifiers,
+            ModifierType::CONTROL_MASK | ModifierType::SHIFT_MASK
+        );
+        assert!(chord.matches(key("E"), chord.modifiers));
+        assert!(Chord::parse("Hyper+e").is_none());
+        assert!(Chord::parse("Ctrl+").is_none());
+    }
+
+    #[test]
+    fn defaults_have_no_problems() {
+        let keymap = Keymap::from_config(&Config::default());
+        assert!(keymap.problems().is_empty());
+        assert_eq!(
+            keymap.match_event(key("k"), ModifierType::CONTROL_MASK),
+            Some(Action::SelectPrevious)
+        );
+        assert_eq!(
+            keymap.match_event(key("Escape"), ModifierType::empty()),
+            Some(Action::Close)
+        );
+    }
+
+    #[test]
+    fn duplicate_binding_keeps_the_first_action() {
+        let keymap = Keymap::from_config(&config_with(serde_json::json!({
+            "toggle_pin": "Ctrl+k",
+        })));
+        assert_eq!(
+            keymap.match_event(key("k"), ModifierType::CONTROL_MASK),
+            Some(Action::SelectPrevious)
+        );
+        assert_eq!(keymap.problems().len(), 1);
+        assert!(keymap.problems()[0].contains("toggle_pin"));
+    }
+
+    #[test]
+    fn invalid_and_unknown_bindings_are_reported() {
+        let mut config = config_with(serde_json::json!({ "close": "Ctrl+Nope+x" }));
+        config.unknown_keys = vec![String::from("launch_rockets")];
+        let keymap = Keymap::from_config(&config);
+        assert_eq!(keymap.problems().len(), 2);
+        assert_eq!(
+            keymap.match_event(key("Escape"), ModifierType::empty()),
+            None
+        );
+    }
+
+    #[test]
+    fn legacy_navigate_keys_apply_unless_overridden() {
+        let mut config = Config::default();
+        config.window.custom_navigate_keys.up = String::from("p");
+        let keymap = Keymap::from_config(&config);
+        assert_eq!(
+            keymap.match_event(key("p"), ModifierType::CONTROL_MASK),
+            Some(Action::SelectPrevious)
+        );
+
+        let mut config = config_with(serde_json::json!({ "select_previous": "Alt+k" }));
+        config.window.custom_navigate_keys.up = String::from("p");
+        let keymap = Keymap::from_config(&config);
+        assert_eq!(
+            keymap.match_event(key("k"), ModifierType::ALT_MASK),
+            Some(Action::SelectPrevious)
+        );
+        assert_eq!(
+            keymap.match_event(key("p"), ModifierType::CONTROL_MASK),
+            None
+        );
+    }
+
+    #[test]
+    fn chords_are_idle_until_begun() {
+        let mut chord = PendingChord::default();
+        assert_eq!(chord.feed(Key::a), ChordStep::Idle);
+        assert_eq!(chord.feed(Key::Escape), ChordStep::Idle);
+    }
+
+    #[test]
+    fn a_letter_completes_the_chord_once() {
+        let mut chord = PendingChord::default();
+        chord.begin(Action::SetMark);
+        assert_eq!(
+            chord.feed(Key::a),
+            ChordStep::Completed(Action::SetMark, 'a')
+        );
+        assert_eq!(chord.feed(Key::b), ChordStep::Idle);
+    }
+
+    #[test]
+    fn modifiers_keep_the_chord_pending() {
+        let mut chord = PendingChord::default();
+        chord.begin(Action::JumpToMark);
+        assert_eq!(chord.feed(Key::Shift_L), ChordStep::Pending);
+        assert_eq!(chord.feed(Key::Control_R), ChordStep::Pending);
+        assert_eq!(
+            chord.feed(Key::A),
+            ChordStep::Completed(Action::JumpToMark, 'A')
+        );
+    }
+
+    #[test]
+    fn other_keys_cancel_the_chord() {
+        for cancel in [Key::Escape, Key::_1, Key::Return, Key::space] {
+            let mut chord = PendingChord::default();
+            chord.begin(Action::SetMark);
+            assert_eq!(chord.feed(cancel), ChordStep::Cancelled(Action::SetMark));
+            assert_eq!(chord.feed(Key::a), ChordStep::Idle);
+        }
+    }
+
+    #[test]
+    fn beginning_again_replaces_the_pending_action() {
+        let mut chord = PendingChord::default();
+        chord.begin(Action::SetMark);
+        chord.begin(Action::JumpToMark);
+        assert_eq!(
+            chord.feed(Key::q),
+            ChordStep::Completed(Action::JumpToMark, 'q')
+        );
+    }
+
+    #[test]
+    fn marks_are_set_and_jumped_to_from_the_list() {
+        let keymap = Keymap::from_config(&Config::default());
+        assert_eq!(
+            keymap.match_list_event(key("grave"), ModifierType::empty()),
+            Some(Action::JumpToMark)
+        );
+        assert_eq!(
+            keymap.match_event(key("grave"), ModifierType::empty()),
+            None
+        );
+    }
+
+    #[test]
+    fn list_only_bindings_need_list_focus() {
+        let keymap = Keymap::from_config(&Config::default());
+        assert_eq!(keymap.match_event(key("m"), ModifierType::empty()), None);
+        assert_eq!(
+            keymap.match_list_event(key("m"), ModifierType::empty()),
+            Some(Action::SetMark)
+        );
+    }
+}
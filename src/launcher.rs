@@ -1,237 +1,4361 @@
-use crate::log;
+use crate::{
+    capabilities::{self, WL_COPY, XDG_OPEN},
+    clipboard,
+    config::{update_json_file, ClipboardMode, CommandIdentity, Config, Launcher},
+    entry_cache::{self, CachedFile, EntryCache},
+    exec, log,
+    overrides::{self, UnknownOverride},
+    profile,
+    visibility::{Candidate, EntryVisibility},
+};
+use gtk4::{
+    gio::{self, prelude::*},
+    glib::{self, Variant, VariantTy},
+};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, os::unix::fs::PermissionsExt, path::PathBuf};
-use tokio::sync::RwLock;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{hash_map, BTreeMap, HashMap, HashSet},
+    fs,
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Condvar, Mutex, Once, RwLock,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    runtime::Handle,
+    sync::{Mutex as AsyncMutex, Notify},
+};
 
-pub static APP_CACHE: Lazy<RwLock<HashMap<String, AppEntry>>> =
-    Lazy::new(|| RwLock::new(HashMap::with_capacity(2000)));
+// Readers take a snapshot and let go of the lock right away, and writers
+// build the new map on the side and only hold the lock to swap it in, so a
+// reload never keeps a search waiting.
+type AppCache = RwLock<Arc<HashMap<String, AppEntry>>>;
 
-#[derive(Clone, Debug)]
+static APP_CACHE: Lazy<AppCache> = Lazy::new(|| RwLock::new(Arc::new(HashMap::new())));
+
+pub static CACHE_STATE: Lazy<Mutex<CacheState>> = Lazy::new(|| Mutex::new(CacheState::Empty));
+
+static CACHE_LOADED: Notify = Notify::const_new();
+static LOAD_GUARD: AsyncMutex<()> = AsyncMutex::const_new(());
+
+pub static LOAD_REPORT: Lazy<Mutex<LoadReport>> = Lazy::new(|| Mutex::new(LoadReport::default()));
+
+// The entries the last load or update collapsed into others.
+static FOLDED: Lazy<Mutex<Folded>> = Lazy::new(|| Mutex::new(Folded::new()));
+
+// Many entries share a TryExec or Exec binary, so $PATH is only searched once
+// per program name and load.
+static BINARY_LOOKUPS: Lazy<Mutex<HashMap<String, bool>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Suffixes tried for localized keys, most specific first, as described in the
+// desktop entry spec: lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER, lang.
+// Icons of files without one, by file_icon_key. Guessing is cheap, but rows
+// are bound again on every keystroke.
+static FILE_ICONS: Lazy<Mutex<HashMap<String, &'static str>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static LOCALE_SUFFIXES: Lazy<Vec<String>> = Lazy::new(|| {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|variable| std::env::var(variable).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale_suffixes(&locale)
+});
+
+static CURRENT_DESKTOPS: Lazy<Vec<String>> = Lazy::new(|| {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(String::from)
+        .collect()
+});
+
+// Fields that never change after parsing are boxed to save the spare capacity
+// of a String, and icon names are shared between entries, see intern_icons.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct AppEntry {
+    pub id: String,
+    pub desktop_id: String,
     pub name: String,
+    pub untranslated_name: Box<str>,
+    pub generic_name: Box<str>,
+    pub exec_name: Box<str>,
     pub description: String,
     pub path: String,
     pub exec: String,
-    pub icon_name: String,
+    pub working_dir: Box<str>,
+    pub dbus_activatable: bool,
+    pub icon_name: Arc<str>,
     pub launch_count: u32,
+    pub last_launched: u64,
+    // Times of the latest launches, see Launches.
+    pub recent_launches: Vec<u64>,
+    pub workspace_launches: BTreeMap<String, WorkspaceLaunches>,
     pub entry_type: EntryType,
     pub score_boost: i64,
+    pub startup_wm_class: Box<str>,
+    pub mime_types: Vec<String>,
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    pub actions: Vec<DesktopAction>,
+    pub description_markup: Option<String>,
+    // Variables set for this entry's launches, from `custom_entries`.
+    pub env: BTreeMap<String, String>,
+    // NoDisplay=true, listed because of `launcher.show_nodisplay`.
+    pub no_display: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DisplayText {
+    Plain(String),
+    Markup(String),
+}
+
+impl DisplayText {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            DisplayText::Plain(text) | DisplayText::Markup(text) => text.is_empty(),
+        }
+    }
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub enum EntryType {
+    #[default]
     Application,
     File,
+    Link,
+    Query,
+    // A computed answer, such as a date, that is copied when activated.
+    Value,
+    // An open Hyprland window, focused when activated.
+    Window,
+    Divider,
 }
 
-static HEATMAP_PATH: &str = "~/.local/share/hyprlauncher/heatmap.json";
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DesktopAction {
+    pub name: String,
+    pub exec: String,
+    pub icon_name: Arc<str>,
+}
 
-static DESKTOP_PATHS: &[&str] = &[
-    "/usr/share/applications",
-    "/usr/local/share/applications",
-    "/var/lib/flatpak/exports/share/applications",
-    "~/.local/share/applications",
-    "~/.local/share/flatpak/exports/share/applications",
-];
+#[derive(Clone, Debug, PartialEq)]
+pub enum CacheState {
+    Empty,
+    Loading,
+    Ready { at: Instant },
+    // The first load failed. A later load that succeeds replaces it.
+    Failed(String),
+}
 
-const DEFAULT_SCORE_BOOST: i64 = 2000;
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub collapsed: Vec<(String, String)>,
+    pub skipped: Vec<SkippedEntry>,
+    pub unknown_overrides: Vec<UnknownOverride>,
+    // Entries whose visibility an override or the blacklist decides.
+    pub visibility: Vec<(String, EntryVisibility)>,
+    // Favorites that an override or the blacklist hides.
+    pub contradictions: Vec<String>,
+}
 
-pub fn increment_launch_count(app: &AppEntry) -> Result<(), std::io::Error> {
-    let app_name = app.name.clone();
-    let count = app.launch_count + 1;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    #[serde(default)]
+    pub desktop_id: String,
+    pub name: String,
+    pub path: String,
+    pub reason: SkipReason,
+}
 
-    std::thread::spawn(move || {
-        save_heatmap(&app_name, count).unwrap();
-    });
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SkipReason {
+    NoDisplay,
+    Hidden,
+    HiddenByOverride,
+    UnknownType(String),
+    MissingBinary(String),
+    NotShownIn(String),
+    MalformedExec(exec::ExecError),
+    Blacklisted(String),
+}
 
-    Ok(())
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::NoDisplay => write!(f, "NoDisplay=true"),
+            SkipReason::Hidden => write!(f, "Hidden=true"),
+            SkipReason::HiddenByOverride => write!(f, "hidden by an override"),
+            SkipReason::UnknownType(entry_type) => write!(f, "unknown Type={}", entry_type),
+            SkipReason::MissingBinary(program) => write!(f, "{} is not installed", program),
+            SkipReason::NotShownIn(desktops) => write!(f, "not shown in {}", desktops),
+            SkipReason::MalformedExec(e) => write!(f, "malformed Exec, {}", e),
+            SkipReason::Blacklisted(pattern) => write!(f, "blacklisted by {:?}", pattern),
+        }
+    }
 }
 
-#[inline]
-fn save_heatmap(name: &str, count: u32) -> Result<(), std::io::Error> {
-    let path = shellexpand::tilde(HEATMAP_PATH).to_string();
+// How long a launch is watched for an immediate failure with
+// `window.show_launch_errors`.
+const LAUNCH_WATCH: Duration = Duration::from_millis(300);
+const LAUNCH_WATCH_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub enum LaunchError {
+    Spawn(std::io::Error),
+    Exited(std::process::ExitStatus),
+}
+
+impl std::fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchError::Spawn(e) => write!(f, "failed to start: {}", e),
+            LaunchError::Exited(status) => match status.code() {
+                Some(127) => write!(f, "command not found (exit code 127)"),
+                Some(code) => write!(f, "exited with code {}", code),
+                None => write!(f, "exited, {}", status),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LaunchPlan {
+    pub name: String,
+    pub exec: String,
+    pub path: String,
+    pub working_dir: String,
+    // Run directly when set, otherwise `exec` goes through the shell.
+    pub argv: Vec<String>,
+    pub dbus_activatable: bool,
+    pub env: BTreeMap<String, String>,
+}
+
+impl LaunchPlan {
+    pub fn new(app: &AppEntry, exec: String) -> Self {
+        Self {
+            name: app.name.clone(),
+            exec,
+            path: app.path.clone(),
+            working_dir: app.working_dir.to_string(),
+            argv: Vec::new(),
+            dbus_activatable: false,
+            env: app.env.clone(),
+        }
+    }
+
+    pub fn command(app: &AppEntry, argv: Vec<String>) -> Self {
+        Self {
+            exec: exec::join(&argv),
+            argv,
+            dbus_activatable: app.dbus_activatable,
+            ..Self::new(app, String::new())
+        }
+    }
 
-    if let Some(dir) = std::path::Path::new(&path).parent() {
-        let _ = std::fs::create_dir_all(dir);
+    // With `watch`, the program is given a moment to fail, so that a broken
+    // command isn't reported as launched just because the shell started.
+    pub fn launch(&self, watch: bool) -> Result<(), LaunchError> {
+        let options = Config::load().launcher;
+        if self.dbus_activatable && options.dbus_activation {
+            match self.activate() {
+                // The service is started by the bus, which knows nothing of
+                // our variables.
+                Ok(()) if !options.env.is_empty() => {
+                    log!("Started {} through D-Bus without launcher.env", self.name);
+                    return Ok(());
+                }
+                Ok(()) => return Ok(()),
+                Err(e) => log!(
+                    "D-Bus activation of {} failed, running its Exec: {}",
+                    self.name,
+                    e
+                ),
+            }
+        }
+
+        let mut command = self.command_for(&options);
+        if watch {
+            spawn_watched(&mut command, LAUNCH_WATCH)
+        } else {
+            spawn_detached(&mut command).map_err(LaunchError::Spawn)
+        }
     }
 
-    let mut heatmap = load_heatmap()?;
-    heatmap.insert(name.to_string(), count);
+    // The process to start: the launch wrapper, then the entry's argv or its
+    // Exec through the shell, with `launcher.env` and the entry's variables.
+    fn command_for(&self, options: &Launcher) -> Command {
+        let mut argv = launch_wrapper(&options.launch_wrapper);
+        if self.argv.is_empty() {
+            argv.extend([String::from("sh"), String::from("-c"), self.exec.clone()]);
+        } else {
+            argv.extend(self.argv.iter().cloned());
+        }
 
-    if let Ok(contents) = serde_json::to_string(&heatmap) {
-        let _ = fs::write(path, contents);
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command
+            .current_dir(self.current_dir())
+            .envs(launch_env(&options.env, &self.env));
+        command
     }
 
-    Ok(())
+    // GIO calls org.freedesktop.Application.Activate on the bus name taken from
+    // the desktop file ID.
+    fn activate(&self) -> Result<(), glib::Error> {
+        let app_info = gio::DesktopAppInfo::from_filename(&self.path).ok_or_else(|| {
+            glib::Error::new(gio::IOErrorEnum::InvalidData, "invalid desktop file")
+        })?;
+        app_info.launch(&[], None::<&gio::AppLaunchContext>)
+    }
+
+    // The entry's Path= if it exists, the home directory otherwise.
+    fn current_dir(&self) -> PathBuf {
+        if !self.working_dir.is_empty() {
+            let dir = PathBuf::from(&self.working_dir);
+            if dir.is_dir() {
+                return dir;
+            }
+            log!(
+                "Warning: working directory {} of {} does not exist, starting from home",
+                self.working_dir,
+                self.name
+            );
+        }
+        glib::home_dir()
+    }
+
+    pub fn variant_type() -> Cow<'static, VariantTy> {
+        <(
+            String,
+            String,
+            String,
+            String,
+            Vec<String>,
+            bool,
+            BTreeMap<String, String>,
+        )>::static_variant_type()
+    }
+
+    pub fn to_variant(&self) -> Variant {
+        (
+            self.name.clone(),
+            self.exec.clone(),
+            self.path.clone(),
+            self.working_dir.clone(),
+            self.argv.clone(),
+            self.dbus_activatable,
+            self.env.clone(),
+        )
+            .to_variant()
+    }
+
+    pub fn from_variant(variant: &Variant) -> Option<Self> {
+        let (name, exec, path, working_dir, argv, dbus_activatable, env) = variant.get::<(
+            String,
+            String,
+            String,
+            String,
+            Vec<String>,
+            bool,
+            BTreeMap<String, String>,
+        )>()?;
+        Some(Self {
+            name,
+            exec,
+            path,
+            working_dir,
+            argv,
+            dbus_activatable,
+            env,
+        })
+    }
 }
 
-#[inline]
-fn load_heatmap() -> Result<HashMap<String, u32>, std::io::Error> {
-    let path = shellexpand::tilde(HEATMAP_PATH).to_string();
-    Ok(fs::read_to_string(path)
+// The command prefix that puts launched applications in a cgroup of their
+// own, so they outlive the launcher being stopped or killed. A wrapper that
+// doesn't parse is logged and ignored.
+fn launch_wrapper(wrapper: &str) -> Vec<String> {
+    let prefix = match wrapper.trim() {
+        "" | "none" => return Vec::new(),
+        "systemd-run" => "systemd-run --user --scope --quiet --collect",
+        "uwsm" => "uwsm app --",
+        custom => custom,
+    };
+    exec::split(prefix).unwrap_or_else(|e| {
+        log!("Ignoring launch wrapper {:?}: {}", wrapper, e);
+        Vec::new()
+    })
+}
+
+// Flags that set the starting directory of terminals that have one. Others
+// start in the directory the launcher spawns them from.
+const TERMINAL_DIRECTORY_FLAGS: &[(&str, &str)] = &[
+    ("kitty", "--directory"),
+    ("foot", "--working-directory"),
+    ("alacritty", "--working-directory"),
+];
+
+fn directory_flag(program: &str) -> Option<&'static str> {
+    let name = Path::new(program).file_name()?.to_str()?;
+    TERMINAL_DIRECTORY_FLAGS
+        .iter()
+        .find(|(terminal, _)| *terminal == name)
+        .map(|(_, flag)| *flag)
+}
+
+// Opens $TERMINAL in `dir`, e.g. `kitty --directory ~/src`.
+pub fn terminal_plan(app: &AppEntry, dir: &str) -> Option<LaunchPlan> {
+    let terminal = std::env::var("TERMINAL")
         .ok()
-        .and_then(|contents| serde_json::from_str(&contents).ok())
-        .unwrap_or_else(|| HashMap::with_capacity(100)))
+        .filter(|value| !value.is_empty());
+    let Some(terminal) = terminal else {
+        log!("Not opening a terminal in {}, $TERMINAL is not set", dir);
+        return None;
+    };
+    terminal_plan_for(&terminal, app, dir)
 }
 
-pub fn get_desktop_paths() -> Vec<PathBuf> {
-    let mut paths = Vec::with_capacity(10);
+fn terminal_plan_for(terminal: &str, app: &AppEntry, dir: &str) -> Option<LaunchPlan> {
+    let mut argv = exec::split(terminal)
+        .map_err(|e| {
+            log!(
+                "Not opening a terminal, malformed $TERMINAL {:?}: {}",
+                terminal,
+                e
+            )
+        })
+        .ok()
+        .filter(|argv| !argv.is_empty())?;
 
-    if let Ok(xdg_dirs) = std::env::var("XDG_DATA_DIRS") {
-        paths.extend(
-            xdg_dirs
-                .split(':')
-                .map(|dir| PathBuf::from(format!("{}/applications", dir))),
-        );
+    if let Some(flag) = directory_flag(&argv[0]) {
+        argv.push(flag.to_string());
+        argv.push(dir.to_string());
     }
 
-    paths.extend(
-        DESKTOP_PATHS
+    Some(LaunchPlan {
+        working_dir: dir.to_string(),
+        ..LaunchPlan::command(app, argv)
+    })
+}
+
+// `launcher.env` with the entry's own variables on top. `$VAR` and `~` in
+// values expand against the launcher's environment; a value naming an unset
+// variable is logged and skipped.
+fn launch_env(
+    global: &BTreeMap<String, String>,
+    entry: &BTreeMap<String, String>,
+) -> Vec<(String, String)> {
+    let mut env = global.clone();
+    env.extend(
+        entry
             .iter()
-            .map(|&path| PathBuf::from(shellexpand::tilde(path).to_string())),
+            .map(|(name, value)| (name.clone(), value.clone())),
     );
+    env.into_iter()
+        .filter_map(|(name, value)| match shellexpand::full(&value) {
+            Ok(expanded) => Some((name, expanded.into_owned())),
+            Err(e) => {
+                log!("Not setting {}: {}", name, e);
+                None
+            }
+        })
+        .collect()
+}
 
-    paths
+// Launched programs that haven't exited yet, checked by a single thread
+// with waitpid(WNOHANG) rather than one blocked thread per program. It only
+// waits on these, not on any child, so the exit status of commands run
+// elsewhere, e.g. through subprocess, stays with their caller.
+static DETACHED: Mutex<Vec<Child>> = Mutex::new(Vec::new());
+static DETACHED_ADDED: Condvar = Condvar::new();
+static REAPER: Once = Once::new();
+const REAP_INTERVAL: Duration = Duration::from_millis(500);
+
+// Starts `command` in a session of its own, so neither signals sent to the
+// launcher's process group, e.g. Ctrl+C in the terminal it was started from,
+// nor the end of the launcher's session reach it. Its output is discarded
+// rather than mixed into the launcher's.
+pub fn spawn_detached(command: &mut Command) -> Result<(), std::io::Error> {
+    let child = detached_command(command).spawn()?;
+    detach(child);
+    Ok(())
 }
 
-pub async fn load_applications() -> Result<(), std::io::Error> {
-    log!("Starting application loading process");
-    let heatmap_future = tokio::task::spawn_blocking(load_heatmap);
+// Like spawn_detached, with `input` as the program's standard input. The
+// pipe is closed once written, so the program sees its end.
+pub fn spawn_detached_with_input(
+    command: &mut Command,
+    input: &[u8],
+) -> Result<(), std::io::Error> {
+    let mut child = detached_command(command).stdin(Stdio::piped()).spawn()?;
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(input),
+        None => Ok(()),
+    };
+    detach(child);
+    written
+}
 
-    let desktop_paths = get_desktop_paths();
-    log!("Scanning desktop entry paths: {:?}", desktop_paths);
-    let mut apps = HashMap::with_capacity(2000);
+// Like spawn_detached, but fails when the program exits unsuccessfully
+// within `watch`. Programs still running by then count as launched.
+fn spawn_watched(command: &mut Command, watch: Duration) -> Result<(), LaunchError> {
+    let mut child = detached_command(command)
+        .spawn()
+        .map_err(LaunchError::Spawn)?;
+    let deadline = Instant::now() + watch;
+    while Instant::now() < deadline {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(LaunchError::Exited(status)),
+            Ok(None) => thread::sleep(LAUNCH_WATCH_INTERVAL),
+            Err(_) => break,
+        }
+    }
+    detach(child);
+    Ok(())
+}
 
-    let entries: Vec<_> = desktop_paths
-        .par_iter()
-        .flat_map_iter(|path| {
-            if let Ok(entries) = std::fs::read_dir(path) {
-                entries
-                    .filter_map(Result::ok)
-                    .filter(|e| {
-                        matches!(
-                            e.path().extension().and_then(|e| e.to_str()),
-                            Some("desktop")
-                        )
-                    })
-                    .filter_map(|entry| parse_desktop_entry(&entry.path()))
-                    .collect::<Vec<_>>()
-            } else {
-                Vec::new()
+fn detached_command(command: &mut Command) -> &mut Command {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Only async-signal-safe calls are allowed between fork and exec.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
             }
+            Ok(())
         })
-        .collect();
+    }
+}
 
-    let heatmap = heatmap_future.await?;
-    for mut entry in entries {
-        if let Some(count) = heatmap.as_ref().unwrap().get(&entry.name) {
-            entry.launch_count = *count;
+// Hands the program to the reaper, so it doesn't linger as a zombie once it
+// exits, however long the launcher stays idle.
+fn detach(child: Child) {
+    DETACHED.lock().unwrap().push(child);
+    DETACHED_ADDED.notify_one();
+    REAPER.call_once(|| {
+        if let Err(e) = thread::Builder::new()
+            .name(String::from("reaper"))
+            .spawn(reap_detached)
+        {
+            log!("Failed to start reaping launched programs: {}", e);
         }
-        apps.insert(entry.name.clone(), entry);
+    });
+}
+
+// Sleeps while no launched program is running, and otherwise collects those
+// that exited every REAP_INTERVAL.
+fn reap_detached() {
+    loop {
+        let mut detached = DETACHED_ADDED
+            .wait_while(DETACHED.lock().unwrap(), |detached| detached.is_empty())
+            .unwrap();
+        detached.retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+        drop(detached);
+        thread::sleep(REAP_INTERVAL);
     }
+}
 
-    log!("Loaded {} total applications", apps.len());
-    let mut cache = APP_CACHE.write().await;
-    *cache = apps;
+pub fn spawn_shell(command: &str, envs: &[(&str, &str)]) -> Result<(), std::io::Error> {
+    spawn_detached(shell_command(command).envs(envs.iter().copied()))
+}
 
-    Ok(())
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
 }
 
-#[inline]
-fn parse_desktop_entry(path: &std::path::Path) -> Option<AppEntry> {
-    let entry = freedesktop_entry_parser::parse_entry(path).ok()?;
-    let section = entry.section("Desktop Entry");
+pub fn open_uri(uri: &str) -> bool {
+    if !capabilities::is_available(XDG_OPEN) {
+        log!("{}", capabilities::explain(XDG_OPEN));
+        return false;
+    }
+    spawn_detached(Command::new("xdg-open").arg(uri)).is_ok()
+}
 
-    if section.attr("NoDisplay").map_or(false, |v| v == "true") {
-        return None;
+// Must run before the window hides: Wayland compositors only accept a new
+// selection from a client that has keyboard focus. With `launcher.clipboard`
+// set to `wl_copy`, a detached wl-copy serves the value instead, so it
+// survives the launcher exiting or being replaced. The value is written to
+// its standard input, since arguments show up in `ps` and are limited in
+// size.
+pub fn copy_to_clipboard(text: &str) {
+    if Config::load().launcher.clipboard == ClipboardMode::wl_copy {
+        if !capabilities::is_available(WL_COPY) {
+            log!("{}", capabilities::explain(WL_COPY));
+        } else if let Err(e) =
+            spawn_detached_with_input(&mut Command::new(WL_COPY), text.as_bytes())
+        {
+            log!("Failed to run wl-copy, copying through GTK: {}", e);
+        } else {
+            return;
+        }
     }
+    clipboard::offer(text);
+}
 
-    let name = String::from(section.attr("Name")?);
-    let exec = String::from(section.attr("Exec").unwrap_or_default());
-    let icon = String::from(section.attr("Icon").unwrap_or("application-x-executable"));
-    let desc = String::from(
-        section
-            .attr("Comment")
-            .or_else(|| section.attr("GenericName"))
-            .unwrap_or(""),
-    );
+pub fn edit_desktop_file(path: &str) -> bool {
+    let has_env = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    let command = if has_env("VISUAL") {
+        "$VISUAL \"$DESKTOP_FILE\""
+    } else if has_env("EDITOR") && has_env("TERMINAL") {
+        "$TERMINAL -e $EDITOR \"$DESKTOP_FILE\""
+    } else if capabilities::is_available(XDG_OPEN) {
+        "xdg-open \"$DESKTOP_FILE\""
+    } else {
+        log!("{}", capabilities::explain(XDG_OPEN));
+        return false;
+    };
 
-    Some(AppEntry {
-        name,
-        exec,
-        icon_name: icon,
-        description: desc,
-        path: path.to_string_lossy().into_owned(),
-        launch_count: 0,
-        entry_type: EntryType::Application,
-        score_boost: 0,
-    })
+    log!("Editing desktop file: {}", path);
+    spawn_shell(command, &[("DESKTOP_FILE", path)]).is_ok()
 }
 
-pub fn create_file_entry(path: String) -> Option<AppEntry> {
-    let path = if path.starts_with('~') || path.starts_with('$') {
-        shellexpand::full(&path).ok()?.to_string()
+pub fn desktop_origin(path: &str, options: &Launcher) -> Option<&'static str> {
+    let path = Path::new(path);
+    let dir = get_desktop_paths(options)
+        .into_iter()
+        .find(|dir| path.starts_with(dir))?;
+
+    if dir.components().any(|part| part.as_os_str() == "flatpak") {
+        Some("flatpak exports")
+    } else if dir.starts_with(shellexpand::tilde("~").as_ref()) {
+        Some("user")
     } else {
-        path
-    };
+        Some("system")
+    }
+}
 
-    let metadata = std::fs::metadata(&path).ok()?;
+pub fn run_hook(command: &str, envs: &[(&str, &str)]) {
+    if command.is_empty() {
+        return;
+    }
 
-    if !metadata.is_file() && !metadata.is_dir() {
-        return None;
+    log!("Running hook: {}", command);
+    if let Err(e) = spawn_shell(command, envs) {
+        log!("Failed to run hook {:?}: {}", command, e);
     }
+}
 
-    let name = std::path::Path::new(&path)
-        .file_name()?
-        .to_str()?
-        .to_string();
+static HEATMAP_PATH: &str = "~/.local/share/hyprlauncher/heatmap.json";
+static HEATMAP_JOURNAL_PATH: &str = "~/.local/share/hyprlauncher/heatmap.journal";
+static HEATMAP_LOCK_PATH: &str = "~/.local/share/hyprlauncher/heatmap.lock";
+static HEATMAP_LOCK: Mutex<()> = Mutex::new(());
 
-    let (icon_name, exec, score_boost) = if metadata.is_dir() {
-        ("folder", String::new(), DEFAULT_SCORE_BOOST)
-    } else if metadata.permissions().mode() & 0o111 != 0 {
-        ("application-x-executable", format!("\"{}\"", path), 0)
-    } else {
-        let (icon, exec) = get_mime_type_info(&path);
-        (icon, exec, 0)
-    };
+// The heatmap files, kept apart from the paths so tests can use a temporary
+// directory.
+struct HeatmapFiles {
+    snapshot: PathBuf,
+    journal: PathBuf,
+    lock: PathBuf,
+}
 
-    Some(AppEntry {
-        name,
-        exec,
-        icon_name: icon_name.to_string(),
-        description: String::new(),
-        path,
-        launch_count: 0,
-        entry_type: EntryType::File,
-        score_boost,
-    })
+impl HeatmapFiles {
+    fn user() -> Self {
+        Self {
+            snapshot: PathBuf::from(shellexpand::tilde(HEATMAP_PATH).as_ref()),
+            journal: PathBuf::from(shellexpand::tilde(HEATMAP_JOURNAL_PATH).as_ref()),
+            lock: PathBuf::from(shellexpand::tilde(HEATMAP_LOCK_PATH).as_ref()),
+        }
+    }
 }
 
-#[inline]
-fn get_mime_type_info(path: &str) -> (&'static str, String) {
-    let output = std::process::Command::new("file")
-        .arg("--mime-type")
-        .arg(path)
-        .output()
+// Held while the heatmap files are written. The mutex orders the threads of
+// this process and the lock file orders separate launcher processes, e.g. a
+// `--print` run next to the daemon.
+struct HeatmapGuard {
+    _thread: std::sync::MutexGuard<'static, ()>,
+    _process: Option<fs::File>,
+}
+
+fn lock_heatmap(files: &HeatmapFiles) -> HeatmapGuard {
+    let thread = HEATMAP_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(dir) = files.lock.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let process = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&files.lock)
+        .and_then(|file| file.lock().map(|_| file))
+        .map_err(|e| {
+            log!(
+                "Failed to lock the heatmap, other instances may race: {}",
+                e
+            )
+        })
         .ok();
 
-    let mime_type = output
-        .and_then(|o| String::from_utf8(o.stdout).ok())
-        .unwrap_or_default();
+    HeatmapGuard {
+        _thread: thread,
+        _process: process,
+    }
+}
 
-    let icon = if mime_type.contains("text/") {
-        "text-x-generic"
-    } else {
-        match std::path::Path::new(path)
-            .extension()
-            .and_then(|s| s.to_str())
-        {
-            Some("pdf") => "application-pdf",
-            _ => "application-x-generic",
+// About 200 launches.
+const MAX_JOURNAL_BYTES: u64 = 16 * 1024;
+
+// Enough of the journal's end to hold its last complete record.
+const JOURNAL_TAIL_BYTES: u64 = 4096;
+
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    // Numbers records in the order they were written. Records from before
+    // numbering have 0 and are always replayed.
+    #[serde(default)]
+    seq: u64,
+    key: String,
+    at: u64,
+    // Name of the active Hyprland workspace, absent on other compositors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+}
+
+type Heatmap = HashMap<String, Launches>;
+
+// The heatmap as of the last load plus the launches since, so that lookups
+// for entries outside the application cache, like the executable fallback on
+// every keystroke, don't read the file.
+static HEATMAP: Lazy<RwLock<Arc<Heatmap>>> =
+    Lazy::new(|| RwLock::new(Arc::new(load_heatmap().unwrap_or_default())));
+
+// Launch times kept per entry and workspace. Older launches are only counted,
+// and ranked as if they were made at the oldest time kept.
+pub const RECENT_LAUNCHES: usize = 32;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Launches {
+    pub count: u32,
+    // Unix time of the latest launch.
+    pub last: u64,
+    // Unix times of the latest RECENT_LAUNCHES launches, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent: Vec<u64>,
+    // The same, counting only launches from each Hyprland workspace.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub workspaces: BTreeMap<String, WorkspaceLaunches>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceLaunches {
+    pub count: u32,
+    pub last: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recent: Vec<u64>,
+}
+
+fn add_recent(recent: &mut Vec<u64>, times: impl IntoIterator<Item = u64>) {
+    recent.extend(times);
+    recent.sort_unstable();
+    let excess = recent.len().saturating_sub(RECENT_LAUNCHES);
+    recent.drain(..excess);
+}
+
+// Histories written before launch times were kept take all of their launches
+// to be from the last one. Returns whether times were filled in.
+fn fill_recent(count: u32, last: u64, recent: &mut Vec<u64>) -> bool {
+    if count == 0 || !recent.is_empty() {
+        return false;
+    }
+    recent.resize((count as usize).min(RECENT_LAUNCHES), last);
+    true
+}
+
+impl WorkspaceLaunches {
+    fn single(at: u64) -> Self {
+        Self {
+            count: 1,
+            last: at,
+            recent: vec![at],
         }
-    };
+    }
+
+    fn add(&mut self, other: WorkspaceLaunches) {
+        self.count += other.count;
+        self.last = self.last.max(other.last);
+        add_recent(&mut self.recent, other.recent);
+    }
+}
+
+impl Launches {
+    fn single(at: u64, workspace: Option<String>) -> Self {
+        Self {
+            count: 1,
+            last: at,
+            recent: vec![at],
+            workspaces: workspace
+                .map(|workspace| BTreeMap::from([(workspace, WorkspaceLaunches::single(at))]))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn add(&mut self, other: Launches) {
+        self.count += other.count;
+        self.last = self.last.max(other.last);
+        add_recent(&mut self.recent, other.recent);
+        for (workspace, launches) in other.workspaces {
+            self.workspaces.entry(workspace).or_default().add(launches);
+        }
+    }
+
+    fn fill_recent(&mut self) -> bool {
+        let mut filled = fill_recent(self.count, self.last, &mut self.recent);
+        for launches in self.workspaces.values_mut() {
+            filled |= fill_recent(launches.count, launches.last, &mut launches.recent);
+        }
+        filled
+    }
+}
+
+// Heatmaps written before launch times were kept map each key to a count.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredLaunches {
+    Count(u32),
+    Launches(Launches),
+}
+
+// The snapshot holds the number of the last journal record folded into it,
+// so records of a journal left behind by an interrupted compaction aren't
+// counted twice.
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    journal_seq: u64,
+    launches: &'a Heatmap,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredHeatmap {
+    Snapshot {
+        journal_seq: u64,
+        launches: HashMap<String, StoredLaunches>,
+    },
+    // Written before journal records were numbered.
+    Unnumbered(HashMap<String, StoredLaunches>),
+}
+
+struct LoadedHeatmap {
+    heatmap: Heatmap,
+    // The number of the last record it contains.
+    seq: u64,
+    // Whether the snapshot comes from an older version, holding plain counts
+    // or launches without their times.
+    outdated: bool,
+}
 
-    (icon, format!("xdg-open \"{}\"", path))
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Searched after `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, highest precedence
+// first, for systems that leave them unset or incomplete.
+static DESKTOP_PATHS: &[&str] = &[
+    "/var/lib/flatpak/exports/share/applications",
+    "/usr/local/share/applications",
+    "/usr/share/applications",
+];
+
+// Subdirectories of an applications directory become part of the desktop
+// file ID, e.g. kde4/dolphin.desktop is kde4-dolphin.desktop.
+const MAX_DESKTOP_DIR_DEPTH: usize = 4;
+
+const DEFAULT_SCORE_BOOST: i64 = 2000;
+
+// Package managers write many files at once, so changes are applied once the
+// applications directories have been quiet for this long.
+const DESKTOP_WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub const BINARY_DIR: &str = "/usr/bin/";
+
+pub const CUSTOM_ID_PREFIX: &str = "custom:";
+const APPIMAGE_ID_PREFIX: &str = "appimage:";
+
+// File name parts after the application name that only describe the build,
+// as in `Obsidian-1.5.3-x86_64.AppImage`.
+const APPIMAGE_BUILD_TAGS: &[&str] = &[
+    "x86", "x64", "amd64", "aarch64", "arm64", "armhf", "i386", "i686", "linux",
+];
+
+// Runs the launch and counts `counted` only once it succeeded, so entries that
+// fail to start don't climb the rankings.
+pub fn count_after<E>(
+    launch: impl FnOnce() -> Result<(), E>,
+    counted: Option<&AppEntry>,
+    count: impl FnOnce(&AppEntry),
+) -> Result<(), E> {
+    launch()?;
+    if let Some(app) = counted {
+        count(app);
+    }
+    Ok(())
+}
+
+// Counts the launch in the cache right away, so the next search ranks by it,
+// and records it in the heatmap in the background. The cached count is
+// incremented rather than taken from `app`, which may be a stale copy from an
+// earlier search.
+pub fn increment_launch_count(app: &AppEntry) -> Result<(), std::io::Error> {
+    let id = app.id.clone();
+    // Only asked for when the ranking uses it, as it runs hyprctl.
+    let workspace = (Config::load().ranking.workspace_affinity > 0.0)
+        .then(crate::hyprland::active_workspace)
+        .flatten();
+    let now = unix_now();
+
+    Arc::make_mut(&mut HEATMAP.write().unwrap())
+        .entry(id.clone())
+        .or_default()
+        .add(Launches::single(now, workspace.clone()));
+    let counted = update_entry(&APP_CACHE, &id, |entry| {
+        entry.launch_count += 1;
+        entry.last_launched = now;
+        add_recent(&mut entry.recent_launches, [now]);
+        if let Some(workspace) = &workspace {
+            entry
+                .workspace_launches
+                .entry(workspace.clone())
+                .or_default()
+                .add(WorkspaceLaunches::single(now));
+        }
+    });
+    if counted {
+        crate::search::mark_scores_changed();
+    }
+
+    std::thread::spawn(move || {
+        if let Err(e) = record_launch(&id, now, workspace) {
+            log!("Failed to record launch of {}: {}", id, e);
+        }
+    });
+
+    Ok(())
+}
+
+pub fn launches(id: &str) -> Launches {
+    HEATMAP.read().unwrap().get(id).cloned().unwrap_or_default()
+}
+
+// The entry as the visibility rules see it once overrides are applied.
+fn candidate(app: &AppEntry, forced_hidden: Option<bool>) -> Candidate<'_> {
+    Candidate {
+        desktop_id: &app.desktop_id,
+        name: &app.name,
+        path: &app.path,
+        desktop_file: None,
+        forced_hidden,
+    }
+}
+
+// Returns the first `launcher.blacklist` pattern naming the entry.
+pub fn blacklist_match<'a>(app: &AppEntry, blacklist: &'a [String]) -> Option<&'a str> {
+    candidate(app, None).blacklisted_by(blacklist)
+}
+
+// Evaluates the entry's visibility, adding it to `report` when an override or
+// the blacklist decides and logging the favorites this hides.
+fn record_visibility(
+    key: &str,
+    candidate: &Candidate,
+    options: &Launcher,
+    report: &mut LoadReport,
+) -> EntryVisibility {
+    let visibility = EntryVisibility::evaluate(candidate, options);
+    if let Some(contradiction) = visibility.contradiction(candidate, options) {
+        log!("{}", contradiction);
+        report.contradictions.push(contradiction);
+    }
+    if visibility.is_configured() {
+        report
+            .visibility
+            .push((key.to_string(), visibility.clone()));
+    }
+    visibility
+}
+
+// Drops the entries the visibility rules hide once overrides are applied and
+// adds them to the skipped entries in `report`, returning their keys.
+fn remove_hidden(
+    apps: &mut HashMap<String, AppEntry>,
+    forced_hidden: &HashMap<String, bool>,
+    options: &Launcher,
+    report: &mut LoadReport,
+) -> Vec<String> {
+    let mut removed = Vec::new();
+    apps.retain(|key, app| {
+        let forced_hidden = forced_hidden.get(&app.desktop_id).copied();
+        let visibility = record_visibility(key, &candidate(app, forced_hidden), options, report);
+        let Some(reason) = visibility.skip_reason() else {
+            return true;
+        };
+        log!("Skipping {}, {}", key, visibility.rule);
+        report.skipped.push(SkippedEntry {
+            desktop_id: app.desktop_id.clone(),
+            name: app.name.clone(),
+            path: app.path.clone(),
+            reason,
+        });
+        removed.push(key.clone());
+        false
+    });
+    removed
+}
+
+// Favorites name entries by desktop ID or, ignoring case, by name.
+pub fn is_favorite(app: &AppEntry, favorites: &[String]) -> bool {
+    matches!(app.entry_type, EntryType::Application)
+        && favorites.iter().any(|favorite| {
+            !favorite.is_empty()
+                && (*favorite == app.desktop_id
+                    || *favorite == app.id
+                    || favorite.eq_ignore_ascii_case(&app.name))
+        })
+}
+
+// Custom entries and AppImages have no desktop ID and are pinned by their ID.
+fn favorite_key(app: &AppEntry) -> &str {
+    if app.desktop_id.is_empty() {
+        &app.id
+    } else {
+        &app.desktop_id
+    }
+}
+
+// Pins the application, or unpins it when it is a favorite already, by
+// editing `launcher.favorites` in the config file. Returns whether it is a
+// favorite now.
+pub fn toggle_favorite(app: &AppEntry) -> Result<bool, std::io::Error> {
+    toggle_favorite_in(&Config::config_dir().join("config.json"), app)
+}
+
+fn toggle_favorite_in(path: &Path, app: &AppEntry) -> Result<bool, std::io::Error> {
+    let mut pinned = false;
+    update_json_file(path, |config| {
+        let launcher = config
+            .entry("launcher")
+            .or_insert_with(|| serde_json::json!({}));
+        let favorites: Vec<String> = launcher
+            .get("favorites")
+            .and_then(|favorites| serde_json::from_value(favorites.clone()).ok())
+            .unwrap_or_default();
+
+        pinned = !is_favorite(app, &favorites);
+        let favorites: Vec<String> = if pinned {
+            favorites
+                .into_iter()
+                .chain([favorite_key(app).to_string()])
+                .collect()
+        } else {
+            favorites
+                .into_iter()
+                .filter(|favorite| !is_favorite(app, std::slice::from_ref(favorite)))
+                .collect()
+        };
+        launcher["favorites"] = serde_json::json!(favorites);
+    })?;
+    Ok(pinned)
+}
+
+// The `count` most recently launched heatmap keys, latest first.
+pub fn recent_launches(count: usize) -> Vec<(String, Launches)> {
+    most_recent(&HEATMAP.read().unwrap(), count)
+}
+
+fn most_recent(heatmap: &Heatmap, count: usize) -> Vec<(String, Launches)> {
+    let mut recent: Vec<_> = heatmap
+        .iter()
+        .filter(|(_, launches)| launches.last > 0)
+        .collect();
+    recent.sort_by(|a, b| b.1.last.cmp(&a.1.last).then_with(|| a.0.cmp(b.0)));
+    recent
+        .into_iter()
+        .take(count)
+        .map(|(key, launches)| (key.clone(), launches.clone()))
+        .collect()
+}
+
+pub fn command_identity(command: &str, mode: &CommandIdentity) -> String {
+    let mut parts = command.split_whitespace();
+    let binary = parts.next().unwrap_or_default();
+
+    match mode {
+        CommandIdentity::binary => binary.to_string(),
+        CommandIdentity::full => std::iter::once(binary)
+            .chain(parts)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+// Launches are appended to the journal and folded into the snapshot by
+// `compact_heatmap`, so a crash never loses more than a partial last line.
+fn record_launch(id: &str, at: u64, workspace: Option<String>) -> Result<(), std::io::Error> {
+    record_launch_in(&HeatmapFiles::user(), id, at, workspace)
+}
+
+fn record_launch_in(
+    files: &HeatmapFiles,
+    id: &str,
+    at: u64,
+    workspace: Option<String>,
+) -> Result<(), std::io::Error> {
+    let _guard = lock_heatmap(files);
+    if let Some(dir) = files.journal.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let record = serde_json::to_string(&JournalRecord {
+        seq: next_journal_seq(files),
+        key: id.to_string(),
+        at,
+        workspace,
+    })?;
+    let mut journal = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .read(true)
+        .open(&files.journal)?;
+    drop_partial_record(&mut journal)?;
+    writeln!(journal, "{}", record)?;
+    journal.sync_data()?;
+
+    let size = journal.metadata()?.len();
+    if size >= MAX_JOURNAL_BYTES {
+        log!("Heatmap journal has grown to {} bytes, compacting", size);
+        let loaded = read_heatmap(files)?;
+        write_heatmap(files, &loaded.heatmap, loaded.seq)?;
+    }
+
+    Ok(())
+}
+
+// A crash while appending can leave half a record at the end, which the
+// next record would otherwise be written onto, losing both.
+fn drop_partial_record(journal: &mut fs::File) -> Result<(), std::io::Error> {
+    let mut contents = Vec::new();
+    journal.seek(SeekFrom::Start(0))?;
+    journal.read_to_end(&mut contents)?;
+    if contents.is_empty() || contents.ends_with(b"\n") {
+        return Ok(());
+    }
+    let complete = contents
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    log!(
+        "Dropping {} bytes of a partially written heatmap journal record",
+        contents.len() - complete
+    );
+    journal.set_len(complete as u64)
+}
+
+// Only the end of the journal is read, unless it isn't newer than the
+// snapshot, as after an interrupted compaction, or holds no numbered record.
+fn next_journal_seq(files: &HeatmapFiles) -> u64 {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let last = last_journal_seq(&files.journal).unwrap_or(0);
+    let journal_is_newer = matches!(
+        (modified(&files.journal), modified(&files.snapshot)),
+        (Some(journal), Some(snapshot)) if journal > snapshot
+    );
+    if journal_is_newer && last > 0 {
+        return last + 1;
+    }
+    let snapshot = read_snapshot(&files.snapshot).map_or(0, |(_, seq)| seq);
+    last.max(snapshot) + 1
+}
+
+fn last_journal_seq(path: &Path) -> Option<u64> {
+    let mut file = fs::File::open(path).ok()?;
+    let length = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(JOURNAL_TAIL_BYTES)))
+        .ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+    String::from_utf8_lossy(&tail)
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str::<JournalRecord>(line).ok())
+        .map(|record| record.seq)
+}
+
+fn compact_heatmap(
+    mode: &CommandIdentity,
+    legacy_ids: &HashMap<String, Vec<String>>,
+) -> Result<Heatmap, std::io::Error> {
+    compact_heatmap_in(&HeatmapFiles::user(), mode, legacy_ids)
+}
+
+fn compact_heatmap_in(
+    files: &HeatmapFiles,
+    mode: &CommandIdentity,
+    legacy_ids: &HashMap<String, Vec<String>>,
+) -> Result<Heatmap, std::io::Error> {
+    let _guard = lock_heatmap(files);
+    let loaded = read_heatmap(files)?;
+    let (heatmap, commands_migrated) =
+        migrate_command_keys(loaded.heatmap, mode, legacy_ids, Path::new(BINARY_DIR));
+    let (heatmap, names_migrated) = migrate_name_keys(heatmap, legacy_ids);
+    let migrated = loaded.outdated || commands_migrated || names_migrated;
+
+    if migrated || files.journal.exists() {
+        write_heatmap(files, &heatmap, loaded.seq)?;
+    }
+
+    Ok(heatmap)
+}
+
+// Writes the snapshot atomically and drops the journal it already contains.
+// Callers must hold the lock_heatmap guard.
+fn write_heatmap(files: &HeatmapFiles, heatmap: &Heatmap, seq: u64) -> Result<(), std::io::Error> {
+    if let Some(dir) = files.snapshot.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = files.snapshot.with_extension("json.tmp");
+    let mut file = fs::File::create(&temp_path)?;
+    let snapshot = Snapshot {
+        journal_seq: seq,
+        launches: heatmap,
+    };
+    file.write_all(serde_json::to_string(&snapshot)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &files.snapshot)?;
+
+    match fs::remove_file(&files.journal) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn load_heatmap() -> Result<Heatmap, std::io::Error> {
+    read_heatmap(&HeatmapFiles::user()).map(|loaded| loaded.heatmap)
+}
+
+// None when there's no snapshot or it can't be parsed.
+fn read_snapshot(path: &Path) -> Option<(HashMap<String, StoredLaunches>, u64)> {
+    let contents = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents).ok()? {
+        StoredHeatmap::Snapshot {
+            journal_seq,
+            launches,
+        } => Some((launches, journal_seq)),
+        StoredHeatmap::Unnumbered(launches) => Some((launches, 0)),
+    }
+}
+
+// Launches from snapshots holding only counts are taken to be from when the
+// snapshot was written, and those without their times from the last launch.
+// The next write stores them with these times.
+fn read_heatmap(files: &HeatmapFiles) -> Result<LoadedHeatmap, std::io::Error> {
+    let written = fs::metadata(&files.snapshot)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(unix_now, |modified| modified.as_secs());
+    let (stored, snapshot_seq) =
+        read_snapshot(&files.snapshot).unwrap_or_else(|| (HashMap::with_capacity(100), 0));
+
+    let mut outdated = false;
+    let mut heatmap: Heatmap = stored
+        .into_iter()
+        .map(|(key, launches)| {
+            let mut launches = match launches {
+                StoredLaunches::Count(count) => Launches {
+                    count,
+                    last: written,
+                    ..Default::default()
+                },
+                StoredLaunches::Launches(launches) => launches,
+            };
+            outdated |= launches.fill_recent();
+            (key, launches)
+        })
+        .collect();
+    if outdated {
+        log!("Heatmap has no launch times, assuming the last launch or write");
+    }
+
+    let seq = match fs::read_to_string(&files.journal) {
+        Ok(contents) => replay_journal(&contents, &mut heatmap, snapshot_seq),
+        Err(_) => snapshot_seq,
+    };
+
+    Ok(LoadedHeatmap {
+        heatmap,
+        seq,
+        outdated,
+    })
+}
+
+// Skips the records the snapshot already holds, up to `after`, and returns
+// the number of the last record.
+fn replay_journal(contents: &str, heatmap: &mut Heatmap, after: u64) -> u64 {
+    let complete = contents.ends_with('\n');
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut last = after;
+
+    for (index, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<JournalRecord>(line) {
+            Ok(record) if record.seq != 0 && record.seq <= after => {}
+            Ok(record) => {
+                last = last.max(record.seq);
+                heatmap
+                    .entry(record.key)
+                    .or_default()
+                    .add(Launches::single(record.at, record.workspace))
+            }
+            Err(_) if index + 1 == lines.len() && !complete => {
+                log!("Ignoring partially written heatmap journal record");
+            }
+            Err(e) => log!("Skipping corrupt heatmap journal line {}: {}", index + 1, e),
+        }
+    }
+    last
+}
+
+// Applications used to be counted under their untranslated Name. Returns the
+// desktop IDs each such name now stands for.
+fn legacy_heatmap_ids(apps: &HashMap<String, AppEntry>) -> HashMap<String, Vec<String>> {
+    let mut legacy_ids: HashMap<String, Vec<String>> = HashMap::new();
+    for (desktop_id, app) in apps {
+        let name = if app.untranslated_name.is_empty() {
+            &app.name
+        } else {
+            &*app.untranslated_name
+        };
+        legacy_ids
+            .entry(name.to_string())
+            .or_default()
+            .push(desktop_id.clone());
+    }
+    legacy_ids
+}
+
+// Moves counts kept under an application name to its desktop ID. Apps that
+// shared a name also shared their count, so each of them keeps it.
+fn migrate_name_keys(
+    heatmap: Heatmap,
+    legacy_ids: &HashMap<String, Vec<String>>,
+) -> (Heatmap, bool) {
+    let mut migrated = Heatmap::with_capacity(heatmap.len());
+    let mut changed = false;
+
+    for (key, launches) in heatmap {
+        match legacy_ids.get(&key) {
+            Some(ids) if !key.ends_with(".desktop") && !key.starts_with('/') => {
+                changed = true;
+                for id in ids {
+                    migrated
+                        .entry(id.clone())
+                        .or_default()
+                        .add(launches.clone());
+                }
+            }
+            _ => migrated.entry(key).or_default().add(launches),
+        }
+    }
+
+    if changed {
+        log!("Migrating application names in heatmap to desktop IDs");
+    }
+
+    (migrated, changed)
+}
+
+// Executables used to be counted under the text typed to run them, such as
+// `ssh  somewhere`. Such keys, and keys stored under the other
+// `ranking.command_identity`, are moved to the identity of the command.
+// Application names are left to migrate_name_keys.
+fn migrate_command_keys(
+    heatmap: Heatmap,
+    mode: &CommandIdentity,
+    legacy_ids: &HashMap<String, Vec<String>>,
+    binary_dir: &Path,
+) -> (Heatmap, bool) {
+    let mut migrated = Heatmap::with_capacity(heatmap.len());
+    let mut changed = false;
+
+    for (key, launches) in heatmap {
+        let command = if key.starts_with(&*binary_dir.to_string_lossy()) {
+            Some(key.clone())
+        } else if key.contains(".desktop")
+            || key.starts_with('/')
+            || key.contains(':')
+            || legacy_ids.contains_key(&key)
+        {
+            None
+        } else {
+            key.split_whitespace()
+                .next()
+                .filter(|program| !program.contains('/'))
+                .filter(|program| is_executable(&binary_dir.join(program)))
+                .map(|_| {
+                    binary_dir
+                        .join(key.trim_start())
+                        .to_string_lossy()
+                        .into_owned()
+                })
+        };
+        let key = match command {
+            Some(command) => {
+                let identity = command_identity(&command, mode);
+                changed |= identity != key;
+                identity
+            }
+            None => key,
+        };
+        migrated.entry(key).or_default().add(launches);
+    }
+
+    if changed {
+        log!("Migrating command entries in heatmap");
+    }
+
+    (migrated, changed)
+}
+
+// Returns the applications directories in XDG precedence order: the user's
+// data directory first, then $XDG_DATA_DIRS in order, then the fallbacks.
+pub fn get_desktop_paths(options: &Launcher) -> Vec<PathBuf> {
+    desktop_paths(
+        std::env::var("XDG_DATA_HOME").ok(),
+        std::env::var("XDG_DATA_DIRS").ok(),
+        options,
+    )
+}
+
+fn desktop_paths(
+    data_home: Option<String>,
+    data_dirs: Option<String>,
+    options: &Launcher,
+) -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(10);
+
+    let data_home = PathBuf::from(
+        data_home
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| shellexpand::tilde("~/.local/share").to_string()),
+    );
+    paths.push(data_home.join("applications"));
+
+    if let Some(xdg_dirs) = data_dirs {
+        paths.extend(
+            xdg_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| PathBuf::from(dir).join("applications")),
+        );
+    }
+
+    // Flatpak exports user installs below the data home as well.
+    paths.push(data_home.join("flatpak/exports/share/applications"));
+    paths.extend(DESKTOP_PATHS.iter().map(PathBuf::from));
+    paths.extend(extra_desktop_paths(options));
+
+    // PathBuf compares by component, so `/usr/share/` and `/usr/share` from
+    // XDG_DATA_DIRS are the same directory here.
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+    paths
+}
+
+// `launcher.extra_desktop_paths`, which have the lowest precedence.
+fn extra_desktop_paths(options: &Launcher) -> Vec<PathBuf> {
+    options
+        .extra_desktop_paths
+        .iter()
+        .filter_map(|path| match shellexpand::full(path) {
+            Ok(expanded) => Some(PathBuf::from(expanded.as_ref())),
+            Err(e) => {
+                log!("Skipping extra desktop path {:?}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn apps() -> Arc<HashMap<String, AppEntry>> {
+    snapshot(&APP_CACHE)
+}
+
+fn snapshot(cache: &AppCache) -> Arc<HashMap<String, AppEntry>> {
+    cache.read().unwrap().clone()
+}
+
+fn update_apps(update: impl Fn(&mut HashMap<String, AppEntry>)) {
+    update_apps_in(&APP_CACHE, update)
+}
+
+// Applies `update` to a copy of the current map and swaps it in, starting
+// over if the map was replaced in the meantime.
+fn update_apps_in(cache: &AppCache, update: impl Fn(&mut HashMap<String, AppEntry>)) {
+    loop {
+        let snapshot = snapshot(cache);
+        let mut updated = (*snapshot).clone();
+        update(&mut updated);
+
+        let mut cache = cache.write().unwrap();
+        if Arc::ptr_eq(&cache, &snapshot) {
+            *cache = Arc::new(updated);
+            return;
+        }
+    }
+}
+
+// Applies `update` to the entry with the given ID, returning whether there is
+// one. When no search holds a snapshot, the entry is changed in place under
+// the write lock, which only takes as long as finding it. Otherwise the map is
+// copied as by update_apps, leaving the snapshots as they were.
+fn update_entry(cache: &AppCache, id: &str, update: impl Fn(&mut AppEntry)) -> bool {
+    if let Some(apps) = Arc::get_mut(&mut cache.write().unwrap()) {
+        return find_entry(apps, id).map(&update).is_some();
+    }
+
+    let found = AtomicBool::new(false);
+    update_apps_in(cache, |apps| {
+        found.store(
+            find_entry(apps, id).map(&update).is_some(),
+            Ordering::Relaxed,
+        );
+    });
+    found.into_inner()
+}
+
+// Entries are looked up by key first, and by ID for any stored under
+// another key.
+fn find_entry<'a>(apps: &'a mut HashMap<String, AppEntry>, id: &str) -> Option<&'a mut AppEntry> {
+    if apps.contains_key(id) {
+        return apps.get_mut(id);
+    }
+    apps.values_mut().find(|entry| entry.id == id)
+}
+
+pub fn is_cache_ready() -> bool {
+    matches!(*CACHE_STATE.lock().unwrap(), CacheState::Ready { .. })
+}
+
+pub fn load_error() -> Option<String> {
+    match &*CACHE_STATE.lock().unwrap() {
+        CacheState::Failed(error) => Some(error.clone()),
+        _ => None,
+    }
+}
+
+// Also returns when loading fails, see load_error.
+pub async fn wait_until_loaded() {
+    let loaded = CACHE_LOADED.notified();
+    tokio::pin!(loaded);
+    loaded.as_mut().enable();
+
+    if !is_cache_ready() {
+        loaded.await;
+    }
+}
+
+// A failed reload keeps the applications that were loaded before.
+fn state_after_load(
+    result: &Result<(), std::io::Error>,
+    previous: CacheState,
+    now: Instant,
+) -> CacheState {
+    match (result, previous) {
+        (Ok(()), _) => CacheState::Ready { at: now },
+        (Err(_), previous @ CacheState::Ready { .. }) => previous,
+        (Err(e), _) => CacheState::Failed(e.to_string()),
+    }
+}
+
+pub async fn load_applications(cancelled: Arc<AtomicBool>) -> Result<(), std::io::Error> {
+    let requested = Instant::now();
+    let _guard = LOAD_GUARD.lock().await;
+
+    if let CacheState::Ready { at } = *CACHE_STATE.lock().unwrap() {
+        if at >= requested {
+            log!("Applications were loaded by a concurrent call, skipping");
+            return Ok(());
+        }
+    }
+
+    let previous = std::mem::replace(&mut *CACHE_STATE.lock().unwrap(), CacheState::Loading);
+    let result = populate_cache(&cancelled).await;
+    if result.is_ok() {
+        crate::search::apply_score_changes();
+    }
+    *CACHE_STATE.lock().unwrap() = state_after_load(&result, previous, Instant::now());
+    CACHE_LOADED.notify_waiters();
+
+    result
+}
+
+async fn populate_cache(cancelled: &AtomicBool) -> Result<(), std::io::Error> {
+    log!("Starting application loading process");
+    let span = profile::start("load applications");
+    let config = Config::load();
+    let command_identity = config.ranking.command_identity.clone();
+    BINARY_LOOKUPS.lock().unwrap().clear();
+
+    let desktop_paths = get_desktop_paths(&config.launcher);
+    log!("Scanning desktop entry paths: {:?}", desktop_paths);
+    for path in extra_desktop_paths(&config.launcher) {
+        if !path.is_dir() {
+            log!("Skipping extra desktop path {:?}, not a directory", path);
+        }
+    }
+    let overrides = overrides::load_overrides(&overrides::overrides_dir(Config::config_dir()));
+    let forced_hidden = overrides::forced_visibility(&overrides);
+    let environment = parse_environment(&config.launcher, &forced_hidden);
+    let entry_cache = EntryCache::load(&environment);
+
+    // Directories are parsed in parallel and reduced pairwise, which rayon
+    // does in the order of `desktop_paths`, so the first file with a given
+    // desktop ID wins. Files that are skipped still hide lower ones, which is
+    // how NoDisplay or Hidden in the user's directory removes a system entry.
+    let scanned = desktop_paths
+        .par_iter()
+        .map(|path| {
+            scan_desktop_dir(
+                path,
+                &entry_cache,
+                &config.launcher,
+                &forced_hidden,
+                cancelled,
+            )
+        })
+        .reduce(DirScan::default, DirScan::merge);
+
+    let DirScan {
+        found,
+        skipped,
+        cached: cached_files,
+    } = scanned;
+    let mut icons = HashSet::new();
+    let mut apps: HashMap<String, AppEntry> = found
+        .into_iter()
+        .filter_map(|(desktop_id, entry)| Some((desktop_id, entry?)))
+        .map(|(desktop_id, mut entry)| {
+            intern_icons(&mut entry, &mut icons);
+            (desktop_id, entry)
+        })
+        .collect();
+
+    let legacy_ids = legacy_heatmap_ids(&apps);
+    let heatmap_future =
+        tokio::task::spawn_blocking(move || compact_heatmap(&command_identity, &legacy_ids));
+
+    if cancelled.load(Ordering::Relaxed) {
+        log!(
+            "Application loading cancelled, keeping {} parsed entries",
+            apps.len()
+        );
+    } else {
+        tokio::task::spawn_blocking(move || entry_cache::save(environment, cached_files));
+    }
+
+    let unknown_overrides = overrides::apply_overrides(&mut apps, overrides);
+    for unknown in &unknown_overrides {
+        log!("{} overrides unknown entry {}", unknown.file, unknown.id);
+    }
+    let mut report = LoadReport {
+        unknown_overrides,
+        ..Default::default()
+    };
+    // Entries their desktop file hides are skipped while parsing, the ones an
+    // override or the blacklist hides as well are reported here.
+    for entry in &skipped {
+        if matches!(
+            entry.reason,
+            SkipReason::HiddenByOverride | SkipReason::Blacklisted(_)
+        ) {
+            let candidate = Candidate {
+                desktop_id: &entry.desktop_id,
+                name: &entry.name,
+                path: &entry.path,
+                desktop_file: None,
+                forced_hidden: forced_hidden.get(&entry.desktop_id).copied(),
+            };
+            record_visibility(&entry.desktop_id, &candidate, &config.launcher, &mut report);
+        }
+    }
+    report.skipped = skipped;
+    remove_hidden(&mut apps, &forced_hidden, &config.launcher, &mut report);
+
+    // A broken heatmap costs the launch history, not the applications.
+    let heatmap = match heatmap_future.await {
+        Ok(Ok(heatmap)) => heatmap,
+        Ok(Err(e)) => {
+            log!(
+                "Failed to load heatmap, starting without launch history: {}",
+                e
+            );
+            Heatmap::new()
+        }
+        Err(e) => {
+            log!(
+                "Heatmap loading panicked, starting without launch history: {}",
+                e
+            );
+            Heatmap::new()
+        }
+    };
+    let heatmap = Arc::new(heatmap);
+    *HEATMAP.write().unwrap() = heatmap.clone();
+    for entry in apps.values_mut() {
+        if let Some(launches) = heatmap.get(&entry.id) {
+            entry.launch_count = launches.count;
+            entry.last_launched = launches.last;
+            entry.recent_launches = launches.recent.clone();
+            entry.workspace_launches = launches.workspaces.clone();
+        }
+    }
+
+    let mut folded = Folded::new();
+    if config.search.collapse_duplicates {
+        report.collapsed = collapse_duplicates(&mut apps, &mut folded);
+        log!("Collapsed {} duplicate entries", report.collapsed.len());
+    }
+    *FOLDED.lock().unwrap() = folded;
+
+    // Added after collapsing, so they never stand in for a desktop entry.
+    let mut custom = custom_entries(&config.launcher);
+    custom.extend(appimage_entries(&config.launcher));
+    remove_hidden(&mut custom, &HashMap::new(), &config.launcher, &mut report);
+    for entry in custom.values_mut() {
+        if let Some(launches) = heatmap.get(&entry.id) {
+            entry.launch_count = launches.count;
+            entry.last_launched = launches.last;
+            entry.recent_launches = launches.recent.clone();
+            entry.workspace_launches = launches.workspaces.clone();
+        }
+    }
+    apps.extend(custom);
+    *LOAD_REPORT.lock().unwrap() = report;
+
+    log!("Loaded {} total applications", apps.len());
+    span.finish(format_args!(
+        "{} applications, {} distinct icon names",
+        apps.len(),
+        icons.len()
+    ));
+    // The old map is dropped after the lock is released.
+    let _previous = std::mem::replace(&mut *APP_CACHE.write().unwrap(), Arc::new(apps));
+
+    Ok(())
+}
+
+enum WatchMessage {
+    Event(notify::Result<notify::Event>),
+    // The configured directories may have changed.
+    Rewatch,
+}
+
+static DESKTOP_WATCHER: Mutex<Option<Sender<WatchMessage>>> = Mutex::new(None);
+
+// Watches the applications directories and re-parses the desktop files that
+// changed, so installing or removing a package updates the cache without a
+// full reload. AppImage directories are rescanned as a whole when an AppImage
+// in them changes. For directories that don't exist yet, the nearest parent
+// that does is watched until they are created.
+pub fn watch_desktop_dirs(rt: Handle) {
+    let (tx, rx) = channel();
+    *DESKTOP_WATCHER.lock().unwrap() = Some(tx.clone());
+    thread::spawn(move || {
+        let handler = move |event| {
+            let _ = tx.send(WatchMessage::Event(event));
+        };
+        let mut watcher = match RecommendedWatcher::new(handler, notify::Config::default()) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log!("Failed to create desktop entry watcher: {}", e);
+                return;
+            }
+        };
+        let mut watches = DesktopWatches::default();
+        let options = Config::load().launcher;
+        watches.update(
+            &mut watcher,
+            &get_desktop_paths(&options),
+            &appimage_paths(&options),
+        );
+
+        let mut changed = HashSet::new();
+        let mut appimages_changed = false;
+        loop {
+            let message = if changed.is_empty() && !appimages_changed {
+                rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                rx.recv_timeout(DESKTOP_WATCH_DEBOUNCE)
+            };
+            let rewatch = match message {
+                Ok(WatchMessage::Event(Ok(event))) => {
+                    let created = watches.creates_missing(&event.paths);
+                    appimages_changed |= event.paths.iter().any(|path| is_appimage_file(path));
+                    changed.extend(
+                        event
+                            .paths
+                            .into_iter()
+                            .filter(|path| path.extension().is_some_and(|ext| ext == "desktop")),
+                    );
+                    created
+                }
+                Ok(WatchMessage::Event(Err(e))) => {
+                    log!("Desktop entry watcher error: {}", e);
+                    false
+                }
+                Ok(WatchMessage::Rewatch) => true,
+                Err(RecvTimeoutError::Timeout) => {
+                    if !changed.is_empty() {
+                        rt.block_on(update_desktop_files(changed.drain().collect()));
+                    }
+                    if std::mem::take(&mut appimages_changed) {
+                        rt.block_on(update_appimages());
+                    }
+                    false
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            if rewatch {
+                let options = Config::load().launcher;
+                let appimage_dirs = appimage_paths(&options);
+                let appeared =
+                    watches.update(&mut watcher, &get_desktop_paths(&options), &appimage_dirs);
+                // Files may have been written to a directory before it was
+                // watched.
+                for dir in appeared {
+                    log!("Watching {:?}, which was created", dir);
+                    appimages_changed |= appimage_dirs.contains(&dir);
+                    let mut files = Vec::new();
+                    collect_desktop_files(&dir, "", 0, &mut files, &AtomicBool::new(false));
+                    changed.extend(files.into_iter().map(|(_, path)| path));
+                }
+            }
+        }
+    });
+}
+
+// Re-registers the watches after the configured directories changed.
+pub fn rewatch_desktop_dirs() {
+    if let Some(tx) = &*DESKTOP_WATCHER.lock().unwrap() {
+        let _ = tx.send(WatchMessage::Rewatch);
+    }
+}
+
+// The directories registered with the watcher, and the configured ones that
+// don't exist yet.
+#[derive(Default)]
+struct DesktopWatches {
+    watched: BTreeMap<PathBuf, RecursiveMode>,
+    missing: Vec<PathBuf>,
+}
+
+impl DesktopWatches {
+    // Registers the watches for the given directories and drops those no
+    // longer needed. Returns the directories that were missing before and
+    // exist now.
+    fn update(
+        &mut self,
+        watcher: &mut impl Watcher,
+        desktop_dirs: &[PathBuf],
+        appimage_dirs: &[PathBuf],
+    ) -> Vec<PathBuf> {
+        let (watched, missing) = watch_plan(desktop_dirs, appimage_dirs);
+
+        for (path, mode) in &self.watched {
+            if watched.get(path) != Some(mode) {
+                let _ = watcher.unwatch(path);
+            }
+        }
+        for (path, mode) in &watched {
+            if self.watched.get(path) != Some(mode) {
+                if let Err(e) = watcher.watch(path, *mode) {
+                    log!("Failed to watch {:?} for applications: {}", path, e);
+                }
+            }
+        }
+
+        let appeared = self
+            .missing
+            .iter()
+            .filter(|dir| !missing.contains(dir) && watched.contains_key(*dir))
+            .cloned()
+            .collect();
+        self.watched = watched;
+        self.missing = missing;
+        appeared
+    }
+
+    // Whether the paths of an event create one of the missing directories or
+    // a parent of one.
+    fn creates_missing(&self, paths: &[PathBuf]) -> bool {
+        paths
+            .iter()
+            .any(|path| self.missing.iter().any(|missing| missing.starts_with(path)))
+    }
+}
+
+// Applications directories are watched recursively for desktop files in
+// subdirectories, AppImage directories only for the files in them. For a
+// missing directory, the nearest existing parent is watched for it to be
+// created, step by step.
+fn watch_plan(
+    desktop_dirs: &[PathBuf],
+    appimage_dirs: &[PathBuf],
+) -> (BTreeMap<PathBuf, RecursiveMode>, Vec<PathBuf>) {
+    let mut watched = BTreeMap::new();
+    let mut missing = Vec::new();
+    let dirs = desktop_dirs
+        .iter()
+        .map(|dir| (dir, RecursiveMode::Recursive))
+        .chain(
+            appimage_dirs
+                .iter()
+                .map(|dir| (dir, RecursiveMode::NonRecursive)),
+        );
+
+    for (dir, mode) in dirs {
+        let (path, mode) = if dir.is_dir() {
+            (dir.clone(), mode)
+        } else {
+            let Some(parent) = dir.ancestors().skip(1).find(|parent| parent.is_dir()) else {
+                continue;
+            };
+            if !missing.contains(dir) {
+                missing.push(dir.clone());
+            }
+            (parent.to_path_buf(), RecursiveMode::NonRecursive)
+        };
+        let entry = watched.entry(path).or_insert(mode);
+        if mode == RecursiveMode::Recursive {
+            *entry = mode;
+        }
+    }
+    (watched, missing)
+}
+
+// Re-parses the given desktop files and updates their cache entries. The file
+// that now wins for each desktop ID is used, so removing a user entry brings
+// back the system one it shadowed.
+async fn update_desktop_files(paths: Vec<PathBuf>) {
+    let _guard = LOAD_GUARD.lock().await;
+    if !is_cache_ready() {
+        return;
+    }
+
+    let config = Config::load();
+    BINARY_LOOKUPS.lock().unwrap().clear();
+    let roots = get_desktop_paths(&config.launcher);
+    let heatmap = load_heatmap().unwrap_or_default();
+    let overrides = overrides::load_overrides(&overrides::overrides_dir(Config::config_dir()));
+    let forced_hidden = overrides::forced_visibility(&overrides);
+
+    let mut updated = HashMap::new();
+    let mut removed = Vec::new();
+    for path in paths {
+        let Some(relative) = roots.iter().find_map(|root| path.strip_prefix(root).ok()) else {
+            continue;
+        };
+        let desktop_id = relative.to_string_lossy().replace('/', "-");
+        let entry = roots
+            .iter()
+            .flat_map(|root| [root.join(relative), root.join(&desktop_id)])
+            .find(|candidate| candidate.is_file())
+            .and_then(|file| {
+                let forced_hidden = forced_hidden.get(&desktop_id).copied();
+                parse_desktop_entry(
+                    &file,
+                    &desktop_id,
+                    &mut Vec::new(),
+                    &config.launcher,
+                    forced_hidden,
+                )
+            });
+
+        match entry {
+            Some(entry) => {
+                log!("Desktop entry {} changed", desktop_id);
+                let launches = heatmap.get(&desktop_id).cloned().unwrap_or_default();
+                let entry = AppEntry {
+                    id: desktop_id.clone(),
+                    desktop_id: desktop_id.clone(),
+                    launch_count: launches.count,
+                    last_launched: launches.last,
+                    recent_launches: launches.recent,
+                    workspace_launches: launches.workspaces,
+                    ..entry
+                };
+                updated.insert(desktop_id, entry);
+            }
+            None => removed.push(desktop_id),
+        }
+    }
+
+    overrides::apply_overrides(&mut updated, overrides);
+    removed.extend(remove_hidden(
+        &mut updated,
+        &forced_hidden,
+        &config.launcher,
+        &mut LoadReport::default(),
+    ));
+
+    let collapse = config.search.collapse_duplicates;
+    let folded = FOLDED.lock().unwrap().clone();
+    let collapsed = Mutex::new(None);
+    update_apps(|apps| {
+        *collapsed.lock().unwrap() =
+            apply_desktop_updates(apps, &folded, &updated, &removed, collapse);
+    });
+    if let Some((collapsed, folded)) = collapsed.into_inner().unwrap() {
+        LOAD_REPORT.lock().unwrap().collapsed = collapsed;
+        *FOLDED.lock().unwrap() = folded;
+    }
+}
+
+// Replaces the AppImage entries with a fresh scan of `launcher.appimage_paths`.
+async fn update_appimages() {
+    let _guard = LOAD_GUARD.lock().await;
+    if !is_cache_ready() {
+        return;
+    }
+
+    let config = Config::load();
+    let heatmap = load_heatmap().unwrap_or_default();
+    let mut appimages = appimage_entries(&config.launcher);
+    remove_hidden(
+        &mut appimages,
+        &HashMap::new(),
+        &config.launcher,
+        &mut LoadReport::default(),
+    );
+    for entry in appimages.values_mut() {
+        if let Some(launches) = heatmap.get(&entry.id) {
+            entry.launch_count = launches.count;
+            entry.last_launched = launches.last;
+            entry.recent_launches = launches.recent.clone();
+            entry.workspace_launches = launches.workspaces.clone();
+        }
+    }
+    log!("AppImages changed, {} found", appimages.len());
+
+    update_apps(|apps| {
+        apps.retain(|id, _| !id.starts_with(APPIMAGE_ID_PREFIX));
+        apps.extend(appimages.clone());
+    });
+}
+
+// Everything besides the file itself that parse_desktop_entry depends on.
+fn parse_environment(options: &Launcher, forced_hidden: &HashMap<String, bool>) -> String {
+    let mut forced: Vec<_> = forced_hidden.iter().collect();
+    forced.sort();
+    format!(
+        "{:?} {:?} verify_exec={} filter_by_desktop={} show_nodisplay={} forced={:?} blacklist={:?}",
+        *LOCALE_SUFFIXES,
+        *CURRENT_DESKTOPS,
+        options.verify_exec,
+        options.filter_by_desktop,
+        options.show_nodisplay,
+        forced,
+        options.blacklist
+    )
+}
+
+// The desktop files of one or more directories: every desktop ID with the
+// parsed entry when it should be shown, the skipped entries, and the result
+// for every file to be written to the entry cache.
+#[derive(Default)]
+struct DirScan {
+    found: HashMap<String, Option<AppEntry>>,
+    skipped: Vec<SkippedEntry>,
+    cached: HashMap<PathBuf, CachedFile>,
+}
+
+impl DirScan {
+    fn with_capacity(files: usize) -> Self {
+        Self {
+            found: HashMap::with_capacity(files),
+            skipped: Vec::new(),
+            cached: HashMap::with_capacity(files),
+        }
+    }
+
+    fn insert(&mut self, desktop_id: String, entry: Option<AppEntry>) {
+        match self.found.entry(desktop_id) {
+            hash_map::Entry::Occupied(existing) => {
+                if let Some(entry) = entry {
+                    log!("{} is shadowed by {}", entry.path, existing.key());
+                }
+            }
+            hash_map::Entry::Vacant(slot) => {
+                slot.insert(entry);
+            }
+        }
+    }
+
+    // Entries of `self` take precedence over those of `lower`. The larger
+    // map is kept, so most entries are only moved once.
+    fn merge(mut self, mut lower: Self) -> Self {
+        if self.found.len() < lower.found.len() {
+            std::mem::swap(&mut self.found, &mut lower.found);
+            for (desktop_id, entry) in lower.found {
+                match self.found.entry(desktop_id) {
+                    hash_map::Entry::Occupied(mut existing) => {
+                        if let Some(shadowed) = existing.insert(entry) {
+                            log!("{} is shadowed by {}", shadowed.path, existing.key());
+                        }
+                    }
+                    hash_map::Entry::Vacant(slot) => {
+                        slot.insert(entry);
+                    }
+                }
+            }
+        } else {
+            for (desktop_id, entry) in lower.found {
+                self.insert(desktop_id, entry);
+            }
+        }
+        self.skipped.extend(lower.skipped);
+        self.cached.extend(lower.cached);
+        self
+    }
+}
+
+// Entries share one allocation per icon name, most of their actions use the
+// entry's icon and many entries use the same fallback.
+fn intern_icons(app: &mut AppEntry, icons: &mut HashSet<Arc<str>>) {
+    let mut intern = |icon: &mut Arc<str>| match icons.get(icon) {
+        Some(interned) => *icon = interned.clone(),
+        None => {
+            icons.insert(icon.clone());
+        }
+    };
+    intern(&mut app.icon_name);
+    for action in &mut app.actions {
+        intern(&mut action.icon_name);
+    }
+}
+
+// Scans the desktop files below `path`. Files unchanged since `cache` was
+// written aren't parsed again.
+fn scan_desktop_dir(
+    path: &Path,
+    cache: &EntryCache,
+    options: &Launcher,
+    forced_hidden: &HashMap<String, bool>,
+    cancelled: &AtomicBool,
+) -> DirScan {
+    let mut files = Vec::new();
+    collect_desktop_files(path, "", 0, &mut files, cancelled);
+
+    let mut scan = DirScan::with_capacity(files.len());
+    for (desktop_id, path) in files {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        let file = match cache.get(&path) {
+            Some(file) => Some(file.clone()),
+            None => {
+                let mut skipped = Vec::new();
+                let forced_hidden = forced_hidden.get(&desktop_id).copied();
+                let entry =
+                    parse_desktop_entry(&path, &desktop_id, &mut skipped, options, forced_hidden);
+                CachedFile::new(&path, entry, skipped)
+            }
+        };
+        let Some(file) = file else {
+            continue;
+        };
+        let entry = file.entry.clone().map(|entry| AppEntry {
+            id: desktop_id.clone(),
+            desktop_id: desktop_id.clone(),
+            ..entry
+        });
+        scan.skipped
+            .extend(file.skipped.iter().map(|skipped| SkippedEntry {
+                desktop_id: desktop_id.clone(),
+                ..skipped.clone()
+            }));
+        scan.insert(desktop_id, entry);
+        scan.cached.insert(path, file);
+    }
+    scan
+}
+
+fn collect_desktop_files(
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    files: &mut Vec<(String, PathBuf)>,
+    cancelled: &AtomicBool,
+) {
+    if cancelled.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries
+        .take_while(|_| !cancelled.load(Ordering::Relaxed))
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+    {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            if depth < MAX_DESKTOP_DIR_DEPTH {
+                let prefix = format!("{}{}-", prefix, name);
+                collect_desktop_files(&path, &prefix, depth + 1, files, cancelled);
+            }
+        } else if name.ends_with(".desktop") {
+            files.push((format!("{}{}", prefix, name), path));
+        }
+    }
+}
+
+// Entries collapse_duplicates folded into another, by desktop ID, with the
+// key of the entry they were folded into.
+type Folded = HashMap<String, (String, AppEntry)>;
+
+fn collapse_duplicates(
+    apps: &mut HashMap<String, AppEntry>,
+    folded: &mut Folded,
+) -> Vec<(String, String)> {
+    let mut groups: HashMap<(String, &str), Vec<String>> = HashMap::new();
+    for (key, app) in apps.iter() {
+        let binary = exec_binary(&app.exec);
+        if binary.is_empty() || app.startup_wm_class.is_empty() {
+            continue;
+        }
+        groups
+            .entry((binary, &app.startup_wm_class))
+            .or_default()
+            .push(key.clone());
+    }
+
+    let mut duplicates: Vec<Vec<String>> =
+        groups.into_values().filter(|keys| keys.len() > 1).collect();
+
+    let mut collapsed = Vec::new();
+    for keys in duplicates.iter_mut() {
+        keys.sort_by_key(|key| (Reverse(metadata_richness(&apps[key])), key.clone()));
+        let kept = keys.remove(0);
+
+        for key in keys.drain(..) {
+            let Some(duplicate) = apps.remove(&key) else {
+                continue;
+            };
+            if let Some(app) = apps.get_mut(&kept) {
+                log!("Collapsing {} into {}", duplicate.name, app.name);
+                collapsed.push((app.name.clone(), duplicate.name.clone()));
+                app.actions.push(DesktopAction {
+                    name: duplicate.name.clone(),
+                    exec: duplicate.exec.clone(),
+                    icon_name: duplicate.icon_name.clone(),
+                });
+                folded.insert(key, (kept.clone(), duplicate));
+            }
+        }
+    }
+
+    collapsed.sort();
+    collapsed
+}
+
+// Undoes collapse_duplicates for the folded entries other than `changed`,
+// which were re-parsed or removed, so that they are collapsed again along
+// with the changed ones.
+fn restore_folded(apps: &mut HashMap<String, AppEntry>, folded: &Folded, changed: &HashSet<&str>) {
+    for (key, (kept, duplicate)) in folded {
+        if let Some(app) = apps.get_mut(kept) {
+            if let Some(index) = app
+                .actions
+                .iter()
+                .rposition(|action| action.name == duplicate.name && action.exec == duplicate.exec)
+            {
+                app.actions.remove(index);
+            }
+        }
+        if !changed.contains(key.as_str()) && !apps.contains_key(key) {
+            apps.insert(key.clone(), duplicate.clone());
+        }
+    }
+}
+
+// Applies re-parsed and removed desktop entries to `apps`. With
+// `collapse_duplicates`, the folded entries are restored and everything is
+// collapsed again, so a changed entry collapses into its duplicate or takes
+// the place of the one it was folded into. Returns what the new collapse
+// folded, or None without collapsing.
+fn apply_desktop_updates(
+    apps: &mut HashMap<String, AppEntry>,
+    folded: &Folded,
+    updated: &HashMap<String, AppEntry>,
+    removed: &[String],
+    collapse: bool,
+) -> Option<(Vec<(String, String)>, Folded)> {
+    if collapse {
+        let changed: HashSet<&str> = updated.keys().chain(removed).map(String::as_str).collect();
+        restore_folded(apps, folded, &changed);
+    }
+    for desktop_id in removed {
+        if apps.remove(desktop_id).is_some() {
+            log!("Desktop entry {} removed", desktop_id);
+        }
+    }
+    apps.extend(updated.clone());
+
+    collapse.then(|| {
+        let mut folded = Folded::new();
+        let collapsed = collapse_duplicates(apps, &mut folded);
+        (collapsed, folded)
+    })
+}
+
+#[inline]
+fn metadata_richness(app: &AppEntry) -> u8 {
+    u8::from(&*app.icon_name != "application-x-executable") + u8::from(!app.description.is_empty())
+}
+
+pub fn exec_binary(exec: &str) -> String {
+    let program = exec_program(exec);
+    String::from(program.rsplit('/').next().unwrap_or_default())
+}
+
+fn exec_program(exec: &str) -> String {
+    exec::split(exec)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|arg| arg != "env" && !arg.contains('='))
+        .unwrap_or_default()
+}
+
+// Programs that start another one. Their name says nothing about the entry,
+// and matching it would give every Flatpak or Steam game the same bonus.
+const EXEC_WRAPPERS: &[&str] = &["flatpak", "sh", "bash", "env", "steam"];
+
+// The file name of the program, e.g. `gimp-2.10` for `/usr/bin/gimp-2.10 %U`.
+// Empty for wrappers.
+fn exec_name(exec: &str) -> Box<str> {
+    let program = exec_program(exec);
+    Path::new(&program)
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .filter(|name| !EXEC_WRAPPERS.contains(&name.as_ref()))
+        .map(|name| name.into())
+        .unwrap_or_default()
+}
+
+pub fn binary_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+    if let Some(found) = BINARY_LOOKUPS.lock().unwrap().get(program) {
+        return *found;
+    }
+
+    let found = std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(program)))
+    });
+    BINARY_LOOKUPS
+        .lock()
+        .unwrap()
+        .insert(program.to_string(), found);
+    found
+}
+
+fn locale_suffixes(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (language, country) = match locale.split_once('_') {
+        Some((language, country)) => (language, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut suffixes = Vec::with_capacity(4);
+    if let Some(country) = country {
+        if let Some(modifier) = modifier {
+            suffixes.push(format!("{}_{}@{}", language, country, modifier));
+        }
+        suffixes.push(format!("{}_{}", language, country));
+    }
+    if let Some(modifier) = modifier {
+        suffixes.push(format!("{}@{}", language, modifier));
+    }
+    suffixes.push(String::from(language));
+    suffixes
+}
+
+fn localized_attr<'a, T: AsRef<str>>(
+    section: &'a freedesktop_entry_parser::AttrSelector<'_, T>,
+    key: &str,
+) -> Option<&'a str> {
+    LOCALE_SUFFIXES
+        .iter()
+        .find_map(|suffix| section.attr_with_param(key, suffix))
+        .or_else(|| section.attr(key))
+}
+
+// Follows GLib: the first current desktop listed in either key decides, and
+// entries with OnlyShowIn are hidden when no current desktop is listed.
+fn shown_in_current_desktop(only_show_in: Option<&str>, not_show_in: Option<&str>) -> bool {
+    let lists = |list: Option<&str>, desktop: &str| {
+        list.is_some_and(|list| list.split(';').any(|listed| listed == desktop))
+    };
+
+    for desktop in CURRENT_DESKTOPS.iter() {
+        if lists(only_show_in, desktop) {
+            return true;
+        }
+        if lists(not_show_in, desktop) {
+            return false;
+        }
+    }
+    only_show_in.is_none()
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+// `forced_hidden` is the `hidden` value of the entry's overrides. Entries the
+// desktop file hides are skipped here unless an override shows them, the
+// others are checked by remove_hidden once overrides are applied.
+#[inline]
+fn parse_desktop_entry(
+    path: &Path,
+    desktop_id: &str,
+    skipped: &mut Vec<SkippedEntry>,
+    options: &Launcher,
+    forced_hidden: Option<bool>,
+) -> Option<AppEntry> {
+    let entry = freedesktop_entry_parser::parse_entry(path).ok()?;
+    let section = entry.section("Desktop Entry");
+    let mut skip = |reason: SkipReason| {
+        skipped.push(SkippedEntry {
+            desktop_id: desktop_id.to_string(),
+            name: String::from(section.attr("Name").unwrap_or_default()),
+            path: path.to_string_lossy().into_owned(),
+            reason,
+        });
+    };
+
+    let only_show_in = section.attr("OnlyShowIn");
+    let not_show_in = section.attr("NotShowIn");
+    let no_display = section.attr("NoDisplay").map_or(false, |v| v == "true");
+    let hidden_by = if no_display && !options.show_nodisplay {
+        Some(SkipReason::NoDisplay)
+    } else if section.attr("Hidden").map_or(false, |v| v == "true") {
+        Some(SkipReason::Hidden)
+    } else if options.filter_by_desktop && !shown_in_current_desktop(only_show_in, not_show_in) {
+        let desktops = CURRENT_DESKTOPS.join(":");
+        log!(
+            "{:?} is not shown in {:?} (OnlyShowIn={:?}, NotShowIn={:?})",
+            path,
+            desktops,
+            only_show_in.unwrap_or_default(),
+            not_show_in.unwrap_or_default()
+        );
+        Some(SkipReason::NotShownIn(if desktops.is_empty() {
+            String::from("an unknown desktop")
+        } else {
+            desktops
+        }))
+    } else {
+        None
+    };
+
+    // Entries an override shows are meant to be there and aren't dimmed.
+    let no_display = no_display && hidden_by.is_none();
+    if let Some(reason) = &hidden_by {
+        let visibility = EntryVisibility::evaluate(
+            &Candidate {
+                desktop_id,
+                name: section.attr("Name").unwrap_or_default(),
+                path: &path.to_string_lossy(),
+                desktop_file: Some(reason),
+                forced_hidden,
+            },
+            options,
+        );
+        match visibility.skip_reason() {
+            None => log!(
+                "Showing {:?} despite {}, an override shows it",
+                path,
+                reason
+            ),
+            Some(reason) => {
+                skip(reason);
+                return None;
+            }
+        }
+    }
+
+    let (entry_type, exec, default_icon) = match section.attr("Type").unwrap_or("Application") {
+        "Application" => (
+            EntryType::Application,
+            exec::unescape(section.attr("Exec").unwrap_or_default()),
+            "application-x-executable",
+        ),
+        "Link" => (
+            EntryType::Link,
+            String::from(section.attr("URL")?),
+            "web-browser",
+        ),
+        "Directory" => return None,
+        other => {
+            log!("Skipping {:?} with unknown type {:?}", path, other);
+            skip(SkipReason::UnknownType(other.to_string()));
+            return None;
+        }
+    };
+
+    if matches!(entry_type, EntryType::Application) {
+        if let Err(e) = exec::command(&exec, &exec::Fields::default()) {
+            log!("Skipping {:?}, malformed Exec {:?}: {}", path, exec, e);
+            skip(SkipReason::MalformedExec(e));
+            return None;
+        }
+
+        let program = section
+            .attr("TryExec")
+            .map(|program| String::from(program.trim()))
+            .or_else(|| options.verify_exec.then(|| exec_program(&exec)))
+            .filter(|program| !program.is_empty());
+        if let Some(program) = program {
+            if !binary_exists(&program) {
+                log!("Skipping {:?}, {} is not installed", path, program);
+                skip(SkipReason::MissingBinary(program));
+                return None;
+            }
+        }
+    }
+
+    let untranslated_name = section.attr("Name")?;
+    let name = String::from(localized_attr(&section, "Name").unwrap_or(untranslated_name));
+    let working_dir = Box::from(section.attr("Path").unwrap_or_default());
+    let dbus_activatable = section.attr("DBusActivatable") == Some("true");
+    let icon: Arc<str> = Arc::from(section.attr("Icon").unwrap_or(default_icon));
+    let generic_name: Box<str> =
+        Box::from(localized_attr(&section, "GenericName").unwrap_or_default());
+    let desc = String::from(localized_attr(&section, "Comment").unwrap_or(&generic_name));
+    let startup_wm_class = Box::from(section.attr("StartupWMClass").unwrap_or_default());
+    let mime_types = section
+        .attr("MimeType")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|mime_type| !mime_type.is_empty())
+        .map(String::from)
+        .collect();
+    let actions = section
+        .attr("Actions")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|id| !id.is_empty())
+        .filter_map(|id| {
+            let action = entry.section(format!("Desktop Action {}", id));
+            let exec = exec::unescape(action.attr("Exec")?);
+            if let Err(e) = exec::command(&exec, &exec::Fields::default()) {
+                log!(
+                    "Skipping action {} of {:?}, malformed Exec: {}",
+                    id,
+                    path,
+                    e
+                );
+                return None;
+            }
+            Some(DesktopAction {
+                name: String::from(localized_attr(&action, "Name")?),
+                exec,
+                icon_name: action.attr("Icon").map_or_else(|| icon.clone(), Arc::from),
+            })
+        })
+        .collect();
+    let keywords = section
+        .attr("Keywords")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|keyword| !keyword.is_empty())
+        .map(String::from)
+        .collect();
+    let categories = section
+        .attr("Categories")
+        .unwrap_or_default()
+        .split(';')
+        .filter(|category| !category.is_empty())
+        .map(String::from)
+        .collect();
+
+    Some(AppEntry {
+        untranslated_name: if name == untranslated_name {
+            Box::default()
+        } else {
+            Box::from(untranslated_name)
+        },
+        name,
+        generic_name,
+        exec_name: exec_name(&exec),
+        exec,
+        working_dir,
+        dbus_activatable,
+        icon_name: icon,
+        description: desc,
+        path: path.to_string_lossy().into_owned(),
+        entry_type,
+        startup_wm_class,
+        mime_types,
+        categories,
+        keywords,
+        actions,
+        no_display,
+        ..Default::default()
+    })
+}
+
+pub fn is_custom_entry(app: &AppEntry) -> bool {
+    app.id.starts_with(CUSTOM_ID_PREFIX)
+}
+
+pub fn is_appimage(app: &AppEntry) -> bool {
+    app.id.starts_with(APPIMAGE_ID_PREFIX)
+}
+
+fn appimage_paths(options: &Launcher) -> Vec<PathBuf> {
+    options
+        .appimage_paths
+        .iter()
+        .filter_map(|path| match shellexpand::full(path) {
+            Ok(expanded) => Some(PathBuf::from(expanded.as_ref())),
+            Err(e) => {
+                log!("Skipping AppImage path {:?}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn is_appimage_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("appimage"))
+}
+
+// `Obsidian-1.5.3-x86_64.AppImage` is listed as Obsidian: the version and
+// architecture parts at the end of the name are dropped.
+fn appimage_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut parts: Vec<&str> = stem.split(['-', '_']).collect();
+    while parts.len() > 1 {
+        let last = parts[parts.len() - 1].to_lowercase();
+        let version = last.trim_start_matches('v');
+        let is_build_tag = version.starts_with(|c: char| c.is_ascii_digit())
+            || APPIMAGE_BUILD_TAGS.contains(&last.as_str());
+        if !is_build_tag {
+            break;
+        }
+        parts.pop();
+    }
+    let name_len = parts.iter().map(|part| part.len() + 1).sum::<usize>() - 1;
+    stem[..name_len].to_string()
+}
+
+// Executable AppImages directly inside `launcher.appimage_paths`, keyed by
+// `appimage:<name>` so that updating to a new version keeps the launch
+// history. When several versions are present, the newest file is listed.
+fn appimage_entries(options: &Launcher) -> HashMap<String, AppEntry> {
+    let mut found: HashMap<String, (SystemTime, AppEntry)> = HashMap::new();
+    for dir in appimage_paths(options) {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            log!("Skipping AppImage path {:?}, not a directory", dir);
+            continue;
+        };
+        for path in read_dir.flatten().map(|entry| entry.path()) {
+            if !is_appimage_file(&path) || !is_executable(&path) {
+                continue;
+            }
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(UNIX_EPOCH);
+            let name = appimage_name(&path);
+            let id = format!("{}{}", APPIMAGE_ID_PREFIX, name.to_lowercase());
+            if found
+                .get(&id)
+                .is_some_and(|(newest, _)| *newest >= modified)
+            {
+                continue;
+            }
+
+            let path = path.to_string_lossy().into_owned();
+            let exec = exec::quote(&path);
+            let entry = AppEntry {
+                id: id.clone(),
+                exec_name: name.to_lowercase().into(),
+                name,
+                exec,
+                icon_name: "application-x-executable".into(),
+                path,
+                entry_type: EntryType::Application,
+                ..Default::default()
+            };
+            found.insert(id, (modified, entry));
+        }
+    }
+    found
+        .into_iter()
+        .map(|(id, (_, entry))| (id, entry))
+        .collect()
+}
+
+// Turns `launcher.custom_entries` into entries keyed by `custom:<name>`, so
+// they keep their launch history as long as the name stays the same.
+fn custom_entries(options: &Launcher) -> HashMap<String, AppEntry> {
+    let terminal = std::env::var("TERMINAL").unwrap_or_default();
+    custom_entries_in(options, &terminal)
+}
+
+// `terminal` runs the entries with `terminal: true`, which are skipped
+// without one.
+fn custom_entries_in(options: &Launcher, terminal: &str) -> HashMap<String, AppEntry> {
+    let mut entries = HashMap::with_capacity(options.custom_entries.len());
+    for custom in &options.custom_entries {
+        if custom.name.is_empty() || custom.exec.trim().is_empty() {
+            log!(
+                "Skipping custom entry {:?}, it needs both name and exec",
+                custom.name
+            );
+            continue;
+        }
+
+        if custom.terminal && terminal.is_empty() {
+            log!(
+                "Skipping custom entry {:?}, it runs in a terminal but $TERMINAL is not set",
+                custom.name
+            );
+            continue;
+        }
+        let exec = if custom.terminal {
+            in_terminal(terminal, &custom.exec)
+        } else {
+            Ok(custom.exec.clone())
+        };
+        let exec = match exec
+            .and_then(|exec| exec::command(&exec, &exec::Fields::default()).map(|_| exec))
+        {
+            Ok(exec) => exec,
+            Err(e) => {
+                log!(
+                    "Skipping custom entry {:?}, malformed exec {:?}: {}",
+                    custom.name,
+                    custom.exec,
+                    e
+                );
+                continue;
+            }
+        };
+
+        let id = format!("{}{}", CUSTOM_ID_PREFIX, custom.name);
+        if entries.contains_key(&id) {
+            log!("Skipping custom entry {:?}, the name is taken", custom.name);
+            continue;
+        }
+        entries.insert(
+            id.clone(),
+            AppEntry {
+                id,
+                name: custom.name.clone(),
+                description: custom.description.clone(),
+                exec_name: exec_name(&exec),
+                exec,
+                icon_name: if custom.icon.is_empty() {
+                    "application-x-executable".into()
+                } else {
+                    custom.icon.as_str().into()
+                },
+                entry_type: EntryType::Application,
+                env: custom.env.clone(),
+                ..Default::default()
+            },
+        );
+    }
+    entries
+}
+
+// The Exec value running `exec` with `terminal -e`. Both are split into
+// arguments and joined back, so quoting in either survives.
+fn in_terminal(terminal: &str, exec: &str) -> Result<String, exec::ExecError> {
+    let mut argv = exec::split(terminal)?;
+    argv.push(String::from("-e"));
+    argv.extend(exec::split(exec)?);
+    Ok(exec::join(&argv))
+}
+
+// Runs through the shell, so the path is quoted for file names with quotes,
+// `$` or backticks.
+pub fn open_file_exec(path: &str) -> String {
+    format!("{} {}", XDG_OPEN, exec::quote(path))
+}
+
+pub fn create_file_entry(path: String) -> Option<AppEntry> {
+    let path = if path.starts_with('~') || path.starts_with('$') {
+        shellexpand::full(&path).ok()?.to_string()
+    } else {
+        path
+    };
+
+    let metadata = std::fs::metadata(&path).ok()?;
+
+    if !metadata.is_file() && !metadata.is_dir() {
+        return None;
+    }
+
+    let name = std::path::Path::new(&path)
+        .file_name()?
+        .to_str()?
+        .to_string();
+
+    let (icon_name, exec, score_boost) = if metadata.is_dir() {
+        ("folder", String::new(), DEFAULT_SCORE_BOOST)
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        ("application-x-executable", exec::quote(&path), 0)
+    } else {
+        // The icon is guessed from the content type when the row is shown,
+        // see icon_name.
+        ("", open_file_exec(&path), 0)
+    };
+
+    Some(AppEntry {
+        id: path.clone(),
+        name,
+        exec,
+        icon_name: icon_name.into(),
+        path,
+        entry_type: EntryType::File,
+        score_boost,
+        ..Default::default()
+    })
+}
+
+pub fn icon_name(app: &AppEntry) -> &str {
+    if app.icon_name.is_empty() && matches!(app.entry_type, EntryType::File) {
+        detect_file_icon(&app.path)
+    } else {
+        &app.icon_name
+    }
+}
+
+fn detect_file_icon(path: &str) -> &'static str {
+    let mut icons = FILE_ICONS.lock().unwrap();
+    let icon = icons
+        .entry(file_icon_key(path))
+        .or_insert_with(|| guess_file_icon(path));
+    icon
+}
+
+// Files are told apart by extension, or by their whole name when they have
+// none, such as a Makefile.
+fn file_icon_key(path: &str) -> String {
+    let path = Path::new(path);
+    match path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy().to_lowercase()),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+// Rows are bound on the main thread, so the content type is guessed from the
+// name alone without reading the file.
+fn guess_file_icon(path: &str) -> &'static str {
+    let (content_type, _) = gio::content_type_guess(Some(path), &[]);
+    let is_text = gio::content_type_get_mime_type(&content_type)
+        .is_some_and(|mime_type| mime_type.starts_with("text/"))
+        || gio::content_type_is_a(&content_type, "text/plain");
+    if is_text {
+        "text-x-generic"
+    } else if gio::content_type_is_a(&content_type, "application/pdf") {
+        "application-pdf"
+    } else {
+        "application-x-generic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn entry(path: &str, icon: &str) -> AppEntry {
+        AppEntry {
+            name: path.to_string(),
+            path: path.to_string(),
+            icon_name: icon.into(),
+            ..Default::default()
+        }
+    }
+
+    fn scan(entries: &[(&str, Option<&str>)]) -> DirScan {
+        let mut scan = DirScan::default();
+        for (desktop_id, path) in entries {
+            scan.insert(desktop_id.to_string(), path.map(|path| entry(path, "icon")));
+        }
+        scan
+    }
+
+    fn path_of(scan: &DirScan, desktop_id: &str) -> Option<Option<String>> {
+        scan.found
+            .get(desktop_id)
+            .map(|entry| entry.as_ref().map(|entry| entry.path.clone()))
+    }
+
+    #[test]
+    fn higher_directory_wins_whichever_scan_is_larger() {
+        let user = || scan(&[("a.desktop", Some("user/a")), ("b.desktop", None)]);
+        let system = || {
+            scan(&[
+                ("a.desktop", Some("system/a")),
+                ("b.desktop", Some("system/b")),
+                ("c.desktop", Some("system/c")),
+            ])
+        };
+
+        for merged in [
+            user().merge(system()),
+            scan(&[]).merge(user()).merge(system()),
+        ] {
+            assert_eq!(path_of(&merged, "a.desktop"), Some(Some("user/a".into())));
+            assert_eq!(path_of(&merged, "b.desktop"), Some(None));
+            assert_eq!(path_of(&merged, "c.desktop"), Some(Some("system/c".into())));
+        }
+
+        let larger_first = system().merge(user());
+        assert_eq!(
+            path_of(&larger_first, "a.desktop"),
+            Some(Some("system/a".into()))
+        );
+    }
+
+    #[test]
+    fn first_file_of_a_directory_wins() {
+        let scan = scan(&[("a.desktop", Some("first")), ("a.desktop", Some("second"))]);
+        assert_eq!(path_of(&scan, "a.desktop"), Some(Some("first".into())));
+    }
+
+    #[test]
+    fn command_identity_collapses_whitespace_or_drops_arguments() {
+        let full = |command| command_identity(command, &CommandIdentity::full);
+        let binary = |command| command_identity(command, &CommandIdentity::binary);
+
+        assert_eq!(full("/usr/bin/ssh  somewhere"), "/usr/bin/ssh somewhere");
+        assert_eq!(full("/usr/bin/ssh\tsomewhere "), "/usr/bin/ssh somewhere");
+        assert_ne!(
+            full("/usr/bin/ssh somewhere"),
+            full("/usr/bin/ssh elsewhere")
+        );
+        assert_eq!(
+            binary("/usr/bin/ssh somewhere"),
+            binary("/usr/bin/ssh elsewhere")
+        );
+        assert_eq!(binary("/usr/bin/ssh"), "/usr/bin/ssh");
+    }
+
+    fn launches(count: u32, last: u64) -> Launches {
+        Launches {
+            count,
+            last,
+            ..Default::default()
+        }
+    }
+
+    // Launches made at these times, as the heatmap records them.
+    fn timed(recent: &[u64]) -> Launches {
+        Launches {
+            count: recent.len() as u32,
+            last: recent.last().copied().unwrap_or_default(),
+            recent: recent.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn typed_commands_migrate_to_their_identity() {
+        let bin = temp_dir("migrate-commands");
+        let ssh = bin.join("ssh");
+        fs::write(&ssh, "").unwrap();
+        fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
+        let ssh = ssh.to_string_lossy().into_owned();
+
+        let heatmap = Heatmap::from([
+            (String::from("ssh  somewhere"), launches(2, 10)),
+            (format!("{} somewhere", ssh), launches(1, 20)),
+            (String::from("ssh elsewhere"), launches(4, 5)),
+            (String::from("Firefox"), launches(3, 1)),
+            (String::from("firefox.desktop"), launches(1, 1)),
+            (String::from("custom:ssh"), launches(1, 1)),
+            (String::from("nothere --flag"), launches(1, 1)),
+        ]);
+        let legacy_ids = HashMap::from([(String::from("Firefox"), vec![])]);
+
+        let (full, changed) =
+            migrate_command_keys(heatmap.clone(), &CommandIdentity::full, &legacy_ids, &bin);
+        assert!(changed);
+        assert_eq!(full[&format!("{} somewhere", ssh)], launches(3, 20));
+        assert_eq!(full[&format!("{} elsewhere", ssh)], launches(4, 5));
+        for kept in ["Firefox", "firefox.desktop", "custom:ssh", "nothere --flag"] {
+            assert!(full.contains_key(kept), "{} was migrated", kept);
+        }
+
+        let (binary, _) =
+            migrate_command_keys(heatmap, &CommandIdentity::binary, &legacy_ids, &bin);
+        assert_eq!(binary[&ssh], launches(7, 20));
+
+        let (again, changed) =
+            migrate_command_keys(full, &CommandIdentity::full, &legacy_ids, &bin);
+        assert!(!changed);
+        assert_eq!(again.len(), 6);
+        fs::remove_dir_all(bin).unwrap();
+    }
+
+    #[test]
+    fn failed_first_load_is_reported_until_a_load_succeeds() {
+        let failure = || Err(std::io::Error::other("no such directory"));
+        let earlier = Instant::now();
+        let now = earlier + Duration::from_secs(1);
+
+        let failed = state_after_load(&failure(), CacheState::Empty, now);
+        assert_eq!(
+            failed,
+            CacheState::Failed(String::from("no such directory"))
+        );
+        assert_eq!(state_after_load(&failure(), failed.clone(), now), failed);
+        assert_eq!(
+            state_after_load(&Ok(()), failed, now),
+            CacheState::Ready { at: now }
+        );
+        assert_eq!(
+            state_after_load(&failure(), CacheState::Ready { at: earlier }, now),
+            CacheState::Ready { at: earlier }
+        );
+    }
+
+    #[test]
+    fn file_entries_run_awkward_paths_through_the_shell_unchanged() {
+        let dir = temp_dir("awkward-file-names");
+        for name in [
+            "plain.txt",
+            "two words.txt",
+            "\"quoted\".txt",
+            "$HOME `id`.txt",
+            "it's.txt",
+        ] {
+            let path = dir.join(name).to_string_lossy().into_owned();
+            std::fs::write(&path, "").unwrap();
+            let app = create_file_entry(path.clone()).unwrap();
+
+            let (opener, argument) = app.exec.split_once(' ').unwrap();
+            assert_eq!(opener, XDG_OPEN);
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf %s {}", argument))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), path);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn heatmap_files(name: &str) -> HeatmapFiles {
+        let dir = temp_dir(name);
+        HeatmapFiles {
+            snapshot: dir.join("heatmap.json"),
+            journal: dir.join("heatmap.journal"),
+            lock: dir.join("heatmap.lock"),
+        }
+    }
+
+    fn count(files: &HeatmapFiles, id: &str) -> u32 {
+        read_heatmap(files)
+            .unwrap()
+            .heatmap
+            .get(id)
+            .map_or(0, |launches| launches.count)
+    }
+
+    fn record(seq: u64, key: &str, at: u64) -> String {
+        format!(r#"{{"seq":{},"key":"{}","at":{}}}"#, seq, key, at) + "\n"
+    }
+
+    #[test]
+    fn launches_are_counted_only_after_they_succeed() {
+        let app = duplicate("foo", "foo", "foo");
+        for (result, expected) in [
+            (Ok(()), &["launch", "count foo.desktop"][..]),
+            (Err("failed to start"), &["launch"][..]),
+        ] {
+            let events = std::cell::RefCell::new(Vec::new());
+            let launched = count_after(
+                || {
+                    events.borrow_mut().push(String::from("launch"));
+                    result
+                },
+                Some(&app),
+                |app| events.borrow_mut().push(format!("count {}", app.id)),
+            );
+            assert_eq!(launched, result);
+            assert_eq!(*events.borrow(), expected);
+        }
+
+        let launched: Result<(), ()> = count_after(|| Ok(()), None, |_| unreachable!());
+        assert!(launched.is_ok());
+    }
+
+    #[test]
+    fn counted_launches_reorder_the_cache_right_away() {
+        let cache: AppCache = RwLock::new(Arc::new(
+            ["a", "b"]
+                .into_iter()
+                .map(|name| (format!("{}.desktop", name), duplicate(name, name, name)))
+                .collect(),
+        ));
+        let most_launched = |cache: &AppCache| {
+            snapshot(cache)
+                .values()
+                .max_by_key(|app| (app.launch_count, std::cmp::Reverse(app.id.clone())))
+                .map(|app| app.id.clone())
+        };
+        assert_eq!(most_launched(&cache).as_deref(), Some("a.desktop"));
+
+        let app = snapshot(&cache)["b.desktop"].clone();
+        count_after(
+            || Err("failed"),
+            Some(&app),
+            |app| {
+                update_entry(&cache, &app.id, |entry| entry.launch_count += 1);
+            },
+        )
+        .unwrap_err();
+        assert_eq!(most_launched(&cache).as_deref(), Some("a.desktop"));
+
+        count_after(
+            || Ok::<(), ()>(()),
+            Some(&app),
+            |app| {
+                update_entry(&cache, &app.id, |entry| entry.launch_count += 1);
+            },
+        )
+        .unwrap();
+        assert_eq!(most_launched(&cache).as_deref(), Some("b.desktop"));
+    }
+
+    #[test]
+    fn launches_from_many_threads_are_not_lost() {
+        const THREADS: usize = 16;
+        const LAUNCHES: usize = 50;
+        let files = heatmap_files("heatmap-hammer");
+        std::thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let files = &files;
+                scope.spawn(move || {
+                    for launch in 0..LAUNCHES {
+                        let id = if launch % 2 == 0 {
+                            "a.desktop"
+                        } else {
+                            "b.desktop"
+                        };
+                        let workspace = Some(thread.to_string());
+                        record_launch_in(files, id, launch as u64, workspace).unwrap();
+                    }
+                });
+            }
+            // Compactions race with the writers as well as the ones that
+            // the journal's size triggers.
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    compact_heatmap_in(&files, &CommandIdentity::default(), &HashMap::new())
+                        .unwrap();
+                }
+            });
+        });
+
+        let loaded = read_heatmap(&files).unwrap();
+        assert_eq!(
+            loaded.heatmap["a.desktop"].count as usize,
+            THREADS * LAUNCHES / 2
+        );
+        assert_eq!(
+            loaded.heatmap["b.desktop"].count as usize,
+            THREADS * LAUNCHES / 2
+        );
+        assert_eq!(loaded.seq as usize, THREADS * LAUNCHES);
+        let journal = fs::read_to_string(&files.journal).unwrap_or_default();
+        assert!(journal
+            .lines()
+            .all(|line| serde_json::from_str::<JournalRecord>(line).is_ok()));
+        fs::remove_dir_all(files.snapshot.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn replay_skips_records_already_in_the_snapshot() {
+        let journal = [
+            record(4, "a.desktop", 10),
+            record(5, "a.desktop", 20),
+            record(6, "b.desktop", 30),
+            String::from("{\"key\":\"legacy.desktop\",\"at\":5}\n"),
+            String::from("{\"seq\":7,\"key\":\"partial"),
+        ]
+        .concat();
+
+        let mut heatmap = Heatmap::new();
+        assert_eq!(replay_journal(&journal, &mut heatmap, 5), 6);
+        assert_eq!(heatmap.get("a.desktop"), None);
+        assert_eq!(heatmap["b.desktop"], timed(&[30]));
+        assert_eq!(heatmap["legacy.desktop"], timed(&[5]));
+
+        let mut heatmap = Heatmap::new();
+        assert_eq!(replay_journal(&journal, &mut heatmap, 0), 6);
+        assert_eq!(heatmap["a.desktop"], timed(&[10, 20]));
+    }
+
+    #[test]
+    fn launches_after_a_partial_record_are_counted() {
+        let files = heatmap_files("heatmap-partial-record");
+        let journal = [
+            record(1, "firefox.desktop", 100),
+            String::from("{\"seq\":2,\"key\":\"fire"),
+        ]
+        .concat();
+        fs::write(&files.journal, journal).unwrap();
+
+        record_launch_in(&files, "firefox.desktop", 200, None).unwrap();
+        assert_eq!(count(&files, "firefox.desktop"), 2);
+        let contents = fs::read_to_string(&files.journal).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.ends_with('\n'));
+    }
+
+    #[test]
+    fn compaction_folds_the_journal_into_the_snapshot() {
+        let files = heatmap_files("heatmap-compaction");
+        for at in [100, 200, 300] {
+            record_launch_in(&files, "firefox.desktop", at, None).unwrap();
+        }
+        assert_eq!(last_journal_seq(&files.journal), Some(3));
+
+        let mode = CommandIdentity::default();
+        let heatmap = compact_heatmap_in(&files, &mode, &HashMap::new()).unwrap();
+        assert_eq!(heatmap["firefox.desktop"], timed(&[100, 200, 300]));
+        assert!(!files.journal.exists());
+        assert_eq!(read_heatmap(&files).unwrap().seq, 3);
+
+        // Compacting again changes nothing.
+        compact_heatmap_in(&files, &mode, &HashMap::new()).unwrap();
+        assert_eq!(count(&files, "firefox.desktop"), 3);
+
+        record_launch_in(&files, "firefox.desktop", 400, None).unwrap();
+        assert_eq!(last_journal_seq(&files.journal), Some(4));
+        assert_eq!(count(&files, "firefox.desktop"), 4);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn an_interrupted_compaction_counts_nothing_twice() {
+        let files = heatmap_files("heatmap-interrupted");
+        for at in [100, 200] {
+            record_launch_in(&files, "kitty.desktop", at, None).unwrap();
+        }
+        let journal = fs::read_to_string(&files.journal).unwrap();
+
+        // The snapshot was written, but the journal wasn't removed.
+        let loaded = read_heatmap(&files).unwrap();
+        write_heatmap(&files, &loaded.heatmap, loaded.seq).unwrap();
+        fs::write(&files.journal, &journal).unwrap();
+        let snapshot = fs::metadata(&files.snapshot).unwrap().modified().unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&files.journal)
+            .unwrap()
+            .set_modified(snapshot - Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(count(&files, "kitty.desktop"), 2);
+
+        // A launch recorded before the next compaction is still counted.
+        record_launch_in(&files, "kitty.desktop", 300, None).unwrap();
+        assert_eq!(count(&files, "kitty.desktop"), 3);
+        let heatmap =
+            compact_heatmap_in(&files, &CommandIdentity::default(), &HashMap::new()).unwrap();
+        assert_eq!(heatmap["kitty.desktop"], timed(&[100, 200, 300]));
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn a_full_journal_is_compacted_while_recording() {
+        let files = heatmap_files("heatmap-full-journal");
+        let mut launched = 0;
+        while !files.snapshot.exists() {
+            launched += 1;
+            record_launch_in(&files, "foot.desktop", launched, None).unwrap();
+            assert!(launched < 1000, "the journal was never compacted");
+        }
+        assert!(!files.journal.exists());
+        record_launch_in(&files, "foot.desktop", launched + 1, None).unwrap();
+        assert_eq!(count(&files, "foot.desktop") as u64, launched + 1);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn unnumbered_snapshots_are_still_read() {
+        let files = heatmap_files("heatmap-unnumbered");
+        fs::write(
+            &files.snapshot,
+            r#"{"old.desktop": 4, "new.desktop": {"count": 2, "last": 50}}"#,
+        )
+        .unwrap();
+        fs::write(&files.journal, record(0, "new.desktop", 60)).unwrap();
+
+        let loaded = read_heatmap(&files).unwrap();
+        assert!(loaded.outdated);
+        assert_eq!(loaded.seq, 0);
+        assert_eq!(loaded.heatmap["old.desktop"].count, 4);
+        assert_eq!(loaded.heatmap["new.desktop"], timed(&[50, 50, 60]));
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn journal_records_keep_their_workspace() {
+        let files = heatmap_files("heatmap-workspaces");
+        record_launch_in(&files, "kitty.desktop", 100, Some(String::from("3"))).unwrap();
+        record_launch_in(&files, "kitty.desktop", 200, None).unwrap();
+        let journal = fs::read_to_string(&files.journal).unwrap();
+        assert_eq!(
+            journal.lines().collect::<Vec<_>>(),
+            [
+                r#"{"seq":1,"key":"kitty.desktop","at":100,"workspace":"3"}"#,
+                r#"{"seq":2,"key":"kitty.desktop","at":200}"#,
+            ]
+        );
+
+        // Records from before workspaces were kept count only globally.
+        let mut heatmap = Heatmap::new();
+        replay_journal(
+            &(journal + &record(0, "kitty.desktop", 300)),
+            &mut heatmap,
+            0,
+        );
+        let kitty = &heatmap["kitty.desktop"];
+        assert_eq!(kitty.count, 3);
+        assert_eq!(kitty.last, 300);
+        assert_eq!(
+            kitty.workspaces,
+            BTreeMap::from([(String::from("3"), WorkspaceLaunches::single(100))])
+        );
+
+        let heatmap =
+            compact_heatmap_in(&files, &CommandIdentity::default(), &HashMap::new()).unwrap();
+        assert_eq!(read_heatmap(&files).unwrap().heatmap, heatmap);
+        assert_eq!(heatmap["kitty.desktop"].workspaces["3"].count, 1);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn favorites_are_toggled_in_place() {
+        let dir = temp_dir("toggle-favorite");
+        let path = dir.join("hyprlauncher").join("config.json");
+        let app = duplicate("kitty", "kitty", "kitty");
+
+        assert!(toggle_favorite_in(&path, &app).unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"launcher\": {\n    \"favorites\": [\n      \"kitty.desktop\"\n    ]\n  }\n}"
+        );
+
+        fs::write(
+            &path,
+            r#"{"window": {"width": 600}, "launcher": {"favorites": ["kitty.desktop", "foot.desktop"], "blacklist": []}, "debug": {}}"#,
+        )
+        .unwrap();
+        assert!(!toggle_favorite_in(&path, &app).unwrap());
+        let config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let keys = |value: &serde_json::Value| {
+            value
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&config), ["window", "launcher", "debug"]);
+        assert_eq!(keys(&config["launcher"]), ["favorites", "blacklist"]);
+        assert_eq!(
+            config["launcher"]["favorites"],
+            serde_json::json!(["foot.desktop"])
+        );
+        assert!(!dir.join("hyprlauncher").join("config.json.tmp").exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn entries_without_desktop_ids_are_pinned_one_by_one() {
+        let dir = temp_dir("pin-custom");
+        let path = dir.join("config.json");
+        let options = Launcher {
+            custom_entries: ["Suspend", "Work VPN"]
+                .map(|name| crate::config::CustomEntry {
+                    name: String::from(name),
+                    exec: String::from("true"),
+                    ..Default::default()
+                })
+                .to_vec(),
+            ..Default::default()
+        };
+        let entries = custom_entries_in(&options, "");
+        let (suspend, vpn) = (&entries["custom:Suspend"], &entries["custom:Work VPN"]);
+
+        assert!(toggle_favorite_in(&path, suspend).unwrap());
+        let config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let favorites: Vec<String> =
+            serde_json::from_value(config["launcher"]["favorites"].clone()).unwrap();
+        assert_eq!(favorites, ["custom:Suspend"]);
+        assert!(is_favorite(suspend, &favorites));
+        assert!(!is_favorite(vpn, &favorites));
+
+        assert!(!is_favorite(vpn, &[String::new()]));
+        assert!(!toggle_favorite_in(&path, suspend).unwrap());
+        assert!(!fs::read_to_string(&path).unwrap().contains("custom:"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn terminal_entries_compose_their_arguments() {
+        let options = Launcher {
+            custom_entries: vec![
+                crate::config::CustomEntry {
+                    name: String::from("Top"),
+                    exec: String::from("sh -c \"htop; read\""),
+                    terminal: true,
+                    ..Default::default()
+                },
+                crate::config::CustomEntry {
+                    name: String::from("Broken"),
+                    exec: String::from("sh -c \"unterminated"),
+                    terminal: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let entries = custom_entries_in(&options, "\"/opt/My Term/term\" --single-instance");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            exec::command(&entries["custom:Top"].exec, &exec::Fields::default()).unwrap(),
+            [
+                "/opt/My Term/term",
+                "--single-instance",
+                "-e",
+                "sh",
+                "-c",
+                "htop; read"
+            ]
+        );
+        assert!(custom_entries_in(&options, "").is_empty());
+    }
+
+    #[test]
+    fn recent_launches_are_latest_first() {
+        let heatmap = Heatmap::from([
+            (String::from("a.desktop"), launches(9, 10)),
+            (String::from("b.desktop"), launches(1, 30)),
+            (String::from("c.desktop"), launches(2, 30)),
+            (String::from("d.desktop"), launches(3, 20)),
+            (String::from("never.desktop"), launches(4, 0)),
+        ]);
+        let keys = |count| {
+            most_recent(&heatmap, count)
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(3), ["b.desktop", "c.desktop", "d.desktop"]);
+        assert_eq!(keys(10).len(), 4);
+        assert!(keys(0).is_empty());
+    }
+
+    #[test]
+    fn histories_without_times_take_them_from_the_last_launch() {
+        let files = heatmap_files("heatmap-recent");
+        fs::write(
+            &files.snapshot,
+            r#"{"journal_seq": 0, "launches": {"kitty.desktop": {"count": 40, "last": 70,
+                "workspaces": {"2": {"count": 1, "last": 60}}}}}"#,
+        )
+        .unwrap();
+
+        let loaded = read_heatmap(&files).unwrap();
+        assert!(loaded.outdated);
+        let kitty = &loaded.heatmap["kitty.desktop"];
+        assert_eq!(kitty.recent, [70; RECENT_LAUNCHES]);
+        assert_eq!(kitty.workspaces["2"].recent, [60]);
+
+        record_launch_in(&files, "kitty.desktop", 80, None).unwrap();
+        let heatmap =
+            compact_heatmap_in(&files, &CommandIdentity::default(), &HashMap::new()).unwrap();
+        let kitty = &heatmap["kitty.desktop"];
+        assert_eq!(kitty.count, 41);
+        assert_eq!(kitty.recent.len(), RECENT_LAUNCHES);
+        assert_eq!(kitty.recent.last(), Some(&80));
+
+        let loaded = read_heatmap(&files).unwrap();
+        assert!(!loaded.outdated);
+        assert_eq!(loaded.heatmap, heatmap);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn locale_suffixes_go_from_most_to_least_specific() {
+        assert_eq!(
+            locale_suffixes("de_DE.UTF-8@euro"),
+            ["de_DE@euro", "de_DE", "de@euro", "de"]
+        );
+        assert_eq!(locale_suffixes("pt_BR.UTF-8"), ["pt_BR", "pt"]);
+        assert_eq!(locale_suffixes("fr"), ["fr"]);
+        assert!(locale_suffixes("C.UTF-8").is_empty());
+        assert!(locale_suffixes("").is_empty());
+    }
+
+    #[test]
+    fn translated_entries_keep_their_desktop_id() {
+        let dir = temp_dir("translated-id");
+        fs::write(
+            dir.join("org.gnome.Nautilus.desktop"),
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=Files\n\
+             Name[de]=Dateien\n\
+             Exec=nautilus --new-window\n\
+             Actions=new-window;\n\
+             \n\
+             [Desktop Action new-window]\n\
+             Name=New Window\n\
+             Name[de]=Neues Fenster\n\
+             Exec=nautilus --new-window\n",
+        )
+        .unwrap();
+
+        let scan = scan_desktop_dir(
+            &dir,
+            &EntryCache::default(),
+            &Launcher::default(),
+            &HashMap::new(),
+            &AtomicBool::new(false),
+        );
+        let entry = scan.found["org.gnome.Nautilus.desktop"].as_ref().unwrap();
+        assert_eq!(entry.id, "org.gnome.Nautilus.desktop");
+        assert_eq!(entry.desktop_id, "org.gnome.Nautilus.desktop");
+        assert_eq!(entry.actions.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_under_untranslated_names_move_to_desktop_ids() {
+        let nautilus = AppEntry {
+            id: String::from("org.gnome.Nautilus.desktop"),
+            desktop_id: String::from("org.gnome.Nautilus.desktop"),
+            name: String::from("Dateien"),
+            untranslated_name: Box::from("Files"),
+            ..Default::default()
+        };
+        let apps = HashMap::from([(nautilus.id.clone(), nautilus)]);
+        let heatmap = Heatmap::from([(
+            String::from("Files"),
+            Launches {
+                count: 3,
+                last: 100,
+                ..Default::default()
+            },
+        )]);
+
+        let (migrated, changed) = migrate_name_keys(heatmap, &legacy_heatmap_ids(&apps));
+        assert!(changed);
+        assert_eq!(migrated.len(), 1);
+        assert_eq!(migrated["org.gnome.Nautilus.desktop"].count, 3);
+
+        let (again, changed) = migrate_name_keys(migrated, &legacy_heatmap_ids(&apps));
+        assert!(!changed);
+        assert_eq!(again["org.gnome.Nautilus.desktop"].count, 3);
+    }
+
+    fn terminals() -> HashMap<String, AppEntry> {
+        ["org.gnome.Terminal.desktop", "foot.desktop"]
+            .into_iter()
+            .map(|desktop_id| {
+                let app = AppEntry {
+                    id: desktop_id.to_string(),
+                    desktop_id: desktop_id.to_string(),
+                    name: String::from("Terminal"),
+                    ..Default::default()
+                };
+                (desktop_id.to_string(), app)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn name_keyed_heatmap_files_migrate_on_first_load() {
+        let files = heatmap_files("heatmap-name-keys");
+        fs::write(
+            &files.snapshot,
+            r#"{"Terminal": 4, "/home/me/notes.txt": {"count": 2, "last": 50}}"#,
+        )
+        .unwrap();
+        fs::write(&files.journal, record(0, "Terminal", 60)).unwrap();
+
+        let mode = CommandIdentity::default();
+        let legacy_ids = legacy_heatmap_ids(&terminals());
+        let heatmap = compact_heatmap_in(&files, &mode, &legacy_ids).unwrap();
+        assert_eq!(heatmap.get("Terminal"), None);
+        assert_eq!(heatmap["org.gnome.Terminal.desktop"].count, 5);
+        assert_eq!(heatmap["foot.desktop"].count, 5);
+        assert_eq!(heatmap["/home/me/notes.txt"], timed(&[50, 50]));
+
+        // The migrated heatmap is written back, so the next load finds the
+        // desktop IDs without migrating again.
+        assert!(!files.journal.exists());
+        let loaded = read_heatmap(&files).unwrap();
+        assert!(!loaded.outdated);
+        assert_eq!(loaded.heatmap, heatmap);
+        let (_, changed) = migrate_name_keys(loaded.heatmap, &legacy_ids);
+        assert!(!changed);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn same_named_apps_count_independently() {
+        let files = heatmap_files("heatmap-same-name");
+        for at in [100, 200] {
+            record_launch_in(&files, "org.gnome.Terminal.desktop", at, None).unwrap();
+        }
+        record_launch_in(&files, "foot.desktop", 300, None).unwrap();
+
+        let legacy_ids = legacy_heatmap_ids(&terminals());
+        let heatmap = compact_heatmap_in(&files, &CommandIdentity::default(), &legacy_ids).unwrap();
+        assert_eq!(heatmap["org.gnome.Terminal.desktop"], timed(&[100, 200]));
+        assert_eq!(heatmap["foot.desktop"], timed(&[300]));
+        assert_eq!(heatmap.get("Terminal"), None);
+        let _ = fs::remove_dir_all(files.snapshot.parent().unwrap());
+    }
+
+    #[test]
+    fn interned_icons_share_one_allocation() {
+        let mut icons = HashSet::new();
+        let mut first = entry("first", "firefox");
+        first.actions.push(DesktopAction {
+            name: String::from("New Window"),
+            exec: String::from("firefox --new-window"),
+            icon_name: "firefox".into(),
+        });
+        let mut second = entry("second", "firefox");
+        intern_icons(&mut first, &mut icons);
+        intern_icons(&mut second, &mut icons);
+
+        assert!(Arc::ptr_eq(&first.icon_name, &second.icon_name));
+        assert!(Arc::ptr_eq(&first.icon_name, &first.actions[0].icon_name));
+        assert_eq!(icons.len(), 1);
+    }
+
+    #[test]
+    fn missing_directories_are_watched_through_their_nearest_parent() {
+        let dir = temp_dir("watch-plan");
+        fs::create_dir_all(dir.join("applications")).unwrap();
+        fs::create_dir_all(dir.join("AppImages")).unwrap();
+        let desktop_dirs = [
+            dir.join("applications"),
+            dir.join("later/deep/applications"),
+        ];
+        let appimage_dirs = [
+            dir.join("AppImages"),
+            dir.join("applications/images"),
+            dir.join("later/deep/applications"),
+        ];
+
+        let (watched, missing) = watch_plan(&desktop_dirs, &appimage_dirs);
+        assert_eq!(
+            watched.into_iter().collect::<Vec<_>>(),
+            [
+                (dir.clone(), RecursiveMode::NonRecursive),
+                (dir.join("AppImages"), RecursiveMode::NonRecursive),
+                (dir.join("applications"), RecursiveMode::Recursive),
+            ]
+        );
+        assert_eq!(
+            missing,
+            [
+                dir.join("later/deep/applications"),
+                dir.join("applications/images"),
+            ]
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn created_and_configured_directories_are_watched() {
+        let dir = temp_dir("watch-update");
+        fs::create_dir_all(dir.join("applications")).unwrap();
+        let mut watcher = RecommendedWatcher::new(|_| {}, notify::Config::default()).unwrap();
+        let mut watches = DesktopWatches::default();
+        let mut desktop_dirs = vec![dir.join("applications"), dir.join("later/applications")];
+
+        assert!(watches.update(&mut watcher, &desktop_dirs, &[]).is_empty());
+        assert!(watches.creates_missing(&[dir.join("later")]));
+        assert!(watches.creates_missing(&[dir.join("later/applications")]));
+        assert!(!watches.creates_missing(&[dir.join("applications/new.desktop")]));
+
+        // Created step by step, as by `mkdir -p`.
+        fs::create_dir(dir.join("later")).unwrap();
+        assert!(watches.update(&mut watcher, &desktop_dirs, &[]).is_empty());
+        assert_eq!(watches.watched.get(&dir), None);
+        fs::create_dir(dir.join("later/applications")).unwrap();
+        assert_eq!(
+            watches.update(&mut watcher, &desktop_dirs, &[]),
+            [dir.join("later/applications")]
+        );
+        assert!(watches.missing.is_empty());
+        assert_eq!(
+            watches.watched.get(&dir.join("later/applications")),
+            Some(&RecursiveMode::Recursive)
+        );
+
+        // A directory added to the config is watched without being reported
+        // as created, since the reload that follows reads it.
+        fs::create_dir(dir.join("extra")).unwrap();
+        desktop_dirs.push(dir.join("extra"));
+        assert!(watches.update(&mut watcher, &desktop_dirs, &[]).is_empty());
+        assert!(watches.watched.contains_key(&dir.join("extra")));
+        assert_eq!(watches.watched.len(), 3);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    fn duplicate(name: &str, exec: &str, icon: &str) -> AppEntry {
+        AppEntry {
+            id: format!("{}.desktop", name),
+            desktop_id: format!("{}.desktop", name),
+            name: name.to_string(),
+            exec: exec.to_string(),
+            icon_name: icon.into(),
+            startup_wm_class: "Foo".into(),
+            ..Default::default()
+        }
+    }
+
+    fn action_names(app: &AppEntry) -> Vec<&str> {
+        app.actions
+            .iter()
+            .map(|action| action.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn incremental_updates_collapse_duplicates_again() {
+        let rich = duplicate("rich", "/usr/bin/foo %U", "foo");
+        let plain = duplicate("plain", "foo", "application-x-executable");
+        let mut apps = HashMap::from([
+            (rich.id.clone(), rich.clone()),
+            (plain.id.clone(), plain.clone()),
+        ]);
+        let mut folded = Folded::new();
+        collapse_duplicates(&mut apps, &mut folded);
+        assert_eq!(apps.len(), 1);
+        assert_eq!(action_names(&apps["rich.desktop"]), ["plain"]);
+
+        // Re-parsing the folded entry doesn't add its action twice.
+        let updated = HashMap::from([(plain.id.clone(), plain.clone())]);
+        let (collapsed, folded) =
+            apply_desktop_updates(&mut apps, &folded, &updated, &[], true).unwrap();
+        assert_eq!(collapsed, [(String::from("rich"), String::from("plain"))]);
+        assert_eq!(apps.len(), 1);
+        assert_eq!(action_names(&apps["rich.desktop"]), ["plain"]);
+
+        // Nor does re-parsing the entry it was folded into.
+        let updated = HashMap::from([(rich.id.clone(), rich.clone())]);
+        let (_, folded) = apply_desktop_updates(&mut apps, &folded, &updated, &[], true).unwrap();
+        assert_eq!(action_names(&apps["rich.desktop"]), ["plain"]);
+
+        // Removing that one brings the folded entry back.
+        let removed = [rich.id.clone()];
+        let (collapsed, folded) =
+            apply_desktop_updates(&mut apps, &folded, &HashMap::new(), &removed, true).unwrap();
+        assert!(collapsed.is_empty() && folded.is_empty());
+        assert_eq!(apps.keys().collect::<Vec<_>>(), ["plain.desktop"]);
+        assert!(apps["plain.desktop"].actions.is_empty());
+
+        // An added duplicate collapses right away, unless collapsing is off.
+        let updated = HashMap::from([(rich.id.clone(), rich.clone())]);
+        let mut uncollapsed = apps.clone();
+        apply_desktop_updates(&mut apps, &folded, &updated, &[], true).unwrap();
+        assert_eq!(apps.len(), 1);
+        assert_eq!(action_names(&apps["rich.desktop"]), ["plain"]);
+        assert!(apply_desktop_updates(&mut uncollapsed, &folded, &updated, &[], false).is_none());
+        assert_eq!(uncollapsed.len(), 2);
+    }
+
+    fn cache_of(count: usize) -> HashMap<String, AppEntry> {
+        (0..count)
+            .map(|index| {
+                let id = format!("app{}.desktop", index);
+                let app = AppEntry {
+                    id: id.clone(),
+                    name: format!("Application {}", index),
+                    ..Default::default()
+                };
+                (id, app)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_updated_in_place_unless_a_snapshot_is_held() {
+        let cache = AppCache::new(Arc::new(cache_of(3)));
+        let before = Arc::as_ptr(&snapshot(&cache));
+        assert!(update_entry(&cache, "app1.desktop", |app| app
+            .launch_count +=
+            1));
+        assert_eq!(Arc::as_ptr(&snapshot(&cache)), before);
+        assert_eq!(snapshot(&cache)["app1.desktop"].launch_count, 1);
+
+        let held = snapshot(&cache);
+        assert!(update_entry(&cache, "app1.desktop", |app| app
+            .launch_count +=
+            1));
+        assert_eq!(held["app1.desktop"].launch_count, 1);
+        assert_eq!(snapshot(&cache)["app1.desktop"].launch_count, 2);
+
+        assert!(!update_entry(&cache, "gone.desktop", |app| app
+            .launch_count +=
+            1));
+    }
+
+    // Searches take a snapshot and scan it while reloads swap in new maps and
+    // launches bump counts, as happens when typing during a reload.
+    #[test]
+    fn searches_stay_fast_while_the_cache_reloads() {
+        let cache = Arc::new(AppCache::new(Arc::new(cache_of(2000))));
+        let stop = Arc::new(AtomicBool::new(false));
+        let writers: Vec<_> = (0..2)
+            .map(|writer| {
+                let (cache, stop) = (cache.clone(), stop.clone());
+                thread::spawn(move || {
+                    let mut round = 0;
+                    while !stop.load(Ordering::Relaxed) {
+                        round += 1;
+                        if writer == 0 {
+                            let apps = cache_of(2000);
+                            let _previous =
+                                std::mem::replace(&mut *cache.write().unwrap(), Arc::new(apps));
+                        } else {
+                            let id = format!("app{}.desktop", round % 2000);
+                            update_entry(&cache, &id, |app| app.launch_count += 1);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut latencies: Vec<Duration> = (0..500)
+            .map(|_| {
+                let started = Instant::now();
+                let apps = snapshot(&cache);
+                let matches = apps.values().filter(|app| app.name.ends_with("7")).count();
+                assert_eq!(matches, 200);
+                started.elapsed()
+            })
+            .collect();
+        stop.store(true, Ordering::Relaxed);
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        latencies.sort();
+        let p99 = latencies[latencies.len() * 99 / 100];
+        assert!(p99 < Duration::from_millis(50), "p99 search took {:?}", p99);
+    }
+
+    #[test]
+    fn file_icons_are_shared_by_extension() {
+        assert_eq!(file_icon_key("/home/me/notes.txt"), ".txt");
+        assert_eq!(file_icon_key("/home/me/REPORT.PDF"), ".pdf");
+        assert_eq!(file_icon_key("archive.tar.gz"), ".gz");
+        assert_eq!(file_icon_key("/src/Makefile"), "Makefile");
+        assert_eq!(file_icon_key("/src/.bashrc"), ".bashrc");
+    }
+
+    #[test]
+    fn data_directories_come_in_xdg_order() {
+        let options = Launcher::default();
+        let paths = desktop_paths(
+            Some(String::from("/home/me/.data")),
+            Some(String::from("/opt/share:/usr/share/::/usr/local/share")),
+            &options,
+        );
+        assert_eq!(
+            paths,
+            [
+                "/home/me/.data/applications",
+                "/opt/share/applications",
+                "/usr/share/applications",
+                "/usr/local/share/applications",
+                "/home/me/.data/flatpak/exports/share/applications",
+                "/var/lib/flatpak/exports/share/applications",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn unset_or_empty_variables_fall_back_to_the_defaults() {
+        let options = Launcher::default();
+        let home = PathBuf::from(shellexpand::tilde("~/.local/share").as_ref());
+        let expected = [
+            home.join("applications"),
+            home.join("flatpak/exports/share/applications"),
+            PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+            PathBuf::from("/usr/local/share/applications"),
+            PathBuf::from("/usr/share/applications"),
+        ];
+        assert_eq!(desktop_paths(None, None, &options), expected);
+        assert_eq!(
+            desktop_paths(Some(String::new()), Some(String::new()), &options),
+            expected
+        );
+    }
+
+    #[test]
+    fn extra_paths_come_last_and_duplicates_are_dropped() {
+        let options = Launcher {
+            extra_desktop_paths: vec![
+                String::from("/nix/profile/share/applications"),
+                String::from("/usr/share/applications"),
+            ],
+            ..Default::default()
+        };
+        let paths = desktop_paths(
+            Some(String::from("/data")),
+            Some(String::from("/usr/share")),
+            &options,
+        );
+        assert_eq!(paths.first(), Some(&PathBuf::from("/data/applications")));
+        assert_eq!(
+            paths.last(),
+            Some(&PathBuf::from("/nix/profile/share/applications"))
+        );
+        assert_eq!(
+            paths
+                .iter()
+                .filter(|path| **path == Path::new("/usr/share/applications"))
+                .count(),
+            1
+        );
+        assert_eq!(paths[1], PathBuf::from("/usr/share/applications"));
+    }
+
+    #[test]
+    fn exec_names_skip_wrappers() {
+        for (exec, name) in [
+            ("/usr/bin/gimp-2.10 %U", "gimp-2.10"),
+            ("env MOZ_ENABLE_WAYLAND=1 firefox %u", "firefox"),
+            ("/usr/bin/env GDK_BACKEND=x11 firefox", ""),
+            ("/usr/bin/flatpak run --branch=stable org.gimp.GIMP", ""),
+            ("flatpak run com.spotify.Client", ""),
+            ("sh -c \"nvim ~/notes.md\"", ""),
+            ("bash -c foo", ""),
+            ("steam steam://rungameid/620", ""),
+            ("", ""),
+        ] {
+            assert_eq!(&*exec_name(exec), name, "{}", exec);
+        }
+    }
+
+    #[test]
+    fn hidden_overrides_and_the_blacklist_remove_entries() {
+        let mut apps: HashMap<_, _> = ["firefox", "gimp", "avahi", "vim"]
+            .into_iter()
+            .map(|name| {
+                let app = duplicate(name, name, "icon");
+                (app.desktop_id.clone(), app)
+            })
+            .collect();
+        let forced_hidden = HashMap::from([
+            (String::from("gimp.desktop"), true),
+            (String::from("vim.desktop"), false),
+        ]);
+        let options = Launcher {
+            blacklist: vec![String::from("avahi*"), String::from("vim.desktop")],
+            favorites: vec![String::from("gimp.desktop")],
+            ..Default::default()
+        };
+        let mut report = LoadReport::default();
+        let mut removed = remove_hidden(&mut apps, &forced_hidden, &options, &mut report);
+        removed.sort();
+
+        assert_eq!(removed, ["avahi.desktop", "gimp.desktop", "vim.desktop"]);
+        assert!(apps.contains_key("firefox.desktop"));
+        report.visibility.sort_by(|a, b| a.0.cmp(&b.0));
+        let rules: Vec<_> = report
+            .visibility
+            .iter()
+            .map(|(id, visibility)| (id.as_str(), visibility.rule.to_string()))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                ("avahi.desktop", String::from("blacklisted by \"avahi*\"")),
+                ("gimp.desktop", String::from("override hidden = true")),
+                (
+                    "vim.desktop",
+                    String::from("blacklisted by \"vim.desktop\"")
+                ),
+            ]
+        );
+        assert_eq!(
+            report.contradictions,
+            ["gimp.desktop is both in favorites and hidden by an override; the override wins"]
+        );
+        assert!(report
+            .skipped
+            .iter()
+            .any(|entry| entry.desktop_id == "gimp.desktop"
+                && entry.reason == SkipReason::HiddenByOverride));
+    }
+
+    #[test]
+    fn overrides_show_entries_unless_the_desktop_file_deletes_them() {
+        let dir = temp_dir("visibility-desktop-files");
+        let cases = [
+            ("NoDisplay=true", None, false),
+            ("NoDisplay=true", Some(false), true),
+            ("Hidden=true", Some(false), false),
+            ("", Some(true), true),
+            ("", None, true),
+        ];
+        let options = Launcher::default();
+        for (key, forced_hidden, shown) in cases {
+            let path = dir.join("app.desktop");
+            fs::write(
+                &path,
+                format!("[Desktop Entry]\nName=App\nExec=app\n{}\n", key),
+            )
+            .unwrap();
+            let mut skipped = Vec::new();
+            let entry =
+                parse_desktop_entry(&path, "app.desktop", &mut skipped, &options, forced_hidden);
+            // Entries the desktop file shows are left to remove_hidden.
+            assert_eq!(entry.is_some(), shown, "{} {:?}", key, forced_hidden);
+            assert_eq!(skipped.len(), usize::from(!shown));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn known_terminals_have_a_directory_flag() {
+        for (program, flag) in [
+            ("kitty", Some("--directory")),
+            ("/usr/bin/kitty", Some("--directory")),
+            ("foot", Some("--working-directory")),
+            ("alacritty", Some("--working-directory")),
+            ("wezterm", None),
+            ("kitty-wrapper", None),
+            ("", None),
+        ] {
+            assert_eq!(directory_flag(program), flag, "{}", program);
+        }
+    }
+
+    #[test]
+    fn terminals_are_opened_in_the_folder() {
+        let folder = AppEntry {
+            name: String::from("my src"),
+            path: String::from("/home/user/my src"),
+            entry_type: EntryType::File,
+            ..Default::default()
+        };
+        let dir = folder.path.as_str();
+        for (terminal, argv) in [
+            ("kitty", vec!["kitty", "--directory", dir]),
+            (
+                "kitty --single-instance",
+                vec!["kitty", "--single-instance", "--directory", dir],
+            ),
+            ("foot", vec!["foot", "--working-directory", dir]),
+            ("wezterm start", vec!["wezterm", "start"]),
+        ] {
+            let plan = terminal_plan_for(terminal, &folder, dir).unwrap();
+            assert_eq!(plan.argv, argv, "{}", terminal);
+            assert_eq!(plan.exec, exec::join(&plan.argv));
+            // Terminals without a flag start where they are spawned.
+            assert_eq!(plan.working_dir, dir);
+        }
+    }
+
+    #[test]
+    fn malformed_terminals_open_nothing() {
+        let folder = AppEntry::default();
+        for terminal in ["", "   ", "kitty \"unterminated"] {
+            assert_eq!(terminal_plan_for(terminal, &folder, "/tmp"), None);
+        }
+    }
+
+    // Set for the copy of the tests that plays the launcher in
+    // detached_children_outlive_the_launcher, to the file its child creates.
+    const DETACH_TEST_VAR: &str = "HYPRLAUNCHER_TEST_DETACHED_FILE";
+
+    fn wait_until(done: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        done()
+    }
+
+    #[test]
+    fn input_reaches_detached_children_through_stdin() {
+        let dir = temp_dir("detached-input");
+        let file = dir.join("copied");
+        // Larger than a pipe buffer, and far too large for an argument.
+        let text = "copied \"value\"\n".repeat(16 * 1024);
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("cat > \"$1.tmp\" && mv \"$1.tmp\" \"$1\"")
+            .arg("sh")
+            .arg(&file);
+        spawn_detached_with_input(&mut command, text.as_bytes()).unwrap();
+
+        assert!(wait_until(|| file.exists()));
+        assert_eq!(fs::read_to_string(&file).unwrap(), text);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn detached_children_lead_a_session_of_their_own() {
+        let mut child = detached_command(Command::new("sleep").arg("5"))
+            .spawn()
+            .unwrap();
+        // spawn returns once the child has run exec, so setsid is done.
+        let pid = child.id() as libc::pid_t;
+        let session = unsafe { libc::getsid(pid) };
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(session, pid);
+        assert_ne!(session, unsafe { libc::getsid(0) });
+    }
+
+    #[test]
+    fn exited_children_leave_no_zombie() {
+        let child = detached_command(&mut Command::new("true")).spawn().unwrap();
+        let stat = PathBuf::from(format!("/proc/{}/stat", child.id()));
+        detach(child);
+        assert!(
+            wait_until(|| !stat.exists()),
+            "{} was not reaped",
+            stat.display()
+        );
+    }
+
+    #[test]
+    fn detached_children_share_one_reaper() {
+        for _ in 0..8 {
+            spawn_detached(Command::new("sleep").arg("1")).unwrap();
+        }
+        let reapers = fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter_map(|task| fs::read_to_string(task.path().join("comm")).ok())
+            .filter(|name| name.starts_with("reap"))
+            .count();
+        assert_eq!(reapers, 1);
+    }
+
+    // Does nothing unless started by detached_children_outlive_the_launcher.
+    #[test]
+    fn launcher_of_a_detached_child() {
+        let Ok(file) = std::env::var(DETACH_TEST_VAR) else {
+            return;
+        };
+        spawn_shell(
+            "sleep 0.5; touch \"$DETACHED_FILE\"",
+            &[("DETACHED_FILE", &file)],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn detached_children_outlive_the_launcher() {
+        let file = temp_dir("detached").join("alive");
+        let mut launcher = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "launcher::tests::launcher_of_a_detached_child"])
+            .env(DETACH_TEST_VAR, &file)
+            .stdout(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let group = launcher.id() as libc::pid_t;
+        assert!(launcher.wait().unwrap().success());
+        // What is left of the launcher's process group is killed, as when
+        // the terminal it ran in closes.
+        unsafe { libc::kill(-group, libc::SIGKILL) };
+
+        assert!(
+            wait_until(|| file.exists()),
+            "the child died with the launcher"
+        );
+    }
+
+    fn variables(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn command_env(command: &Command) -> Vec<(String, String)> {
+        command
+            .get_envs()
+            .map(|(name, value)| {
+                (
+                    name.to_string_lossy().into_owned(),
+                    value.unwrap_or_default().to_string_lossy().into_owned(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn launch_plans_survive_the_trip_through_a_variant() {
+        let plan = LaunchPlan {
+            name: String::from("Firefox Work"),
+            exec: String::from("firefox -P work"),
+            path: String::from("/usr/share/applications/firefox.desktop"),
+            working_dir: String::from("/tmp/work dir"),
+            argv: vec![
+                String::from("firefox"),
+                String::from("-P"),
+                String::from("work"),
+            ],
+            dbus_activatable: true,
+            env: variables(&[("MOZ_ENABLE_WAYLAND", "1"), ("LANG", "de_DE.UTF-8")]),
+        };
+        let variant = plan.to_variant();
+        assert_eq!(variant.type_(), &*LaunchPlan::variant_type());
+        assert_eq!(LaunchPlan::from_variant(&variant), Some(plan));
+
+        assert_eq!(LaunchPlan::from_variant(&"firefox".to_variant()), None);
+        assert_eq!(
+            LaunchPlan::from_variant(&("firefox", "firefox").to_variant()),
+            None
+        );
+    }
+
+    #[test]
+    fn entry_variables_override_global_ones() {
+        let options = Launcher {
+            env: variables(&[("GDK_BACKEND", "wayland"), ("MOZ_ENABLE_WAYLAND", "0")]),
+            ..Default::default()
+        };
+        let plan = LaunchPlan {
+            name: String::from("Firefox Work"),
+            exec: String::from("firefox -P work"),
+            env: variables(&[("MOZ_ENABLE_WAYLAND", "1")]),
+            ..Default::default()
+        };
+
+        let command = plan.command_for(&options);
+        assert_eq!(
+            command_env(&command),
+            [
+                (String::from("GDK_BACKEND"), String::from("wayland")),
+                (String::from("MOZ_ENABLE_WAYLAND"), String::from("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_expand_against_the_launcher_environment() {
+        let home = std::env::var("HOME").unwrap();
+        let env = launch_env(
+            &variables(&[("DATA", "$HOME/data"), ("CONFIG", "~/.config")]),
+            &variables(&[("BROKEN", "$HYPRLAUNCHER_TEST_UNSET_VARIABLE")]),
+        );
+        assert_eq!(
+            env,
+            [
+                (String::from("CONFIG"), format!("{}/.config", home)),
+                (String::from("DATA"), format!("{}/data", home)),
+            ]
+        );
+    }
+
+    #[test]
+    fn commands_start_inside_the_launch_wrapper() {
+        let options = Launcher {
+            launch_wrapper: String::from("uwsm"),
+            ..Default::default()
+        };
+        let shell = LaunchPlan {
+            exec: String::from("firefox -P work"),
+            ..Default::default()
+        };
+        let direct = LaunchPlan {
+            argv: vec![String::from("code"), String::from("/tmp/a b")],
+            ..Default::default()
+        };
+        let argv = |command: Command| -> Vec<String> {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(
+            argv(shell.command_for(&options)),
+            ["uwsm", "app", "--", "sh", "-c", "firefox -P work"]
+        );
+        assert_eq!(
+            argv(direct.command_for(&Launcher::default())),
+            ["code", "/tmp/a b"]
+        );
+    }
 }
@@ -1,8 +1,41 @@
+mod activation_log;
 mod app;
+mod bookmarks;
+mod capabilities;
+mod categories;
+mod clipboard;
+mod collation;
 mod config;
+mod control;
+mod dates;
+mod dmenu;
+mod doctor;
+mod dump;
+mod entry_cache;
+mod exec;
+mod explain;
+mod files;
+mod help;
+mod history;
+mod hyprland;
+mod icons;
+mod keymap;
 mod launcher;
+mod modes;
+mod open_with;
+mod overrides;
+mod print;
+mod profile;
+mod providers;
+mod query;
+mod result_row;
 mod search;
+mod subprocess;
+#[cfg(test)]
+mod test_util;
 mod ui;
+mod visibility;
+mod windows;
 
 #[macro_export]
 macro_rules! log {
@@ -13,7 +46,36 @@ macro_rules! log {
     }};
 }
 
+#[global_allocator]
+static ALLOCATOR: profile::CountingAllocator = profile::CountingAllocator;
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == profile::PROFILE_ARGUMENT) {
+        profile::enable();
+    }
+    if args.iter().any(|arg| arg == "--doctor") {
+        std::process::exit(doctor::run());
+    }
+    if args.iter().any(|arg| arg == "--safe-mode") {
+        let _ = config::SAFE_MODE.set(String::from("started with --safe-mode"));
+    }
+    if args.iter().any(|arg| arg == entry_cache::REFRESH_ARGUMENT) {
+        entry_cache::remove();
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--dump-config") {
+        std::process::exit(dump::run(&args[index + 1..]));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--print") {
+        std::process::exit(print::run(&args[index + 1..]));
+    }
+    if args.first().is_some_and(|arg| arg == "stats") {
+        std::process::exit(activation_log::run(&args[1..]));
+    }
+    if args.first().is_some_and(|arg| arg == "explain") {
+        std::process::exit(explain::run(&args[1..].join(" ")));
+    }
+
     log!("Starting Hyprlauncher...");
     let app = app::App::new();
     std::process::exit(app.run());
@@ -0,0 +1,98 @@
+use crate::{
+    config::{ModeWindow, Modes},
+    log,
+};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+static MODE: Lazy<Mutex<Option<Mode>>> = Lazy::new(|| Mutex::new(None));
+
+pub const MODE_ARGUMENT: &str = "--mode";
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    apps,
+    files,
+    categories,
+    windows,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::apps, Mode::files, Mode::categories, Mode::windows];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::apps => "apps",
+            Mode::files => "files",
+            Mode::categories => "categories",
+            Mode::windows => "windows",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::apps => "Applications",
+            Mode::files => "Files",
+            Mode::categories => "Categories",
+            Mode::windows => "Windows",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn window(self, modes: &Modes) -> &ModeWindow {
+        match self {
+            Mode::apps => &modes.apps.window,
+            Mode::files => &modes.files.window,
+            Mode::categories => &modes.categories.window,
+            Mode::windows => &modes.windows.window,
+        }
+    }
+}
+
+// Returns the value given to --mode, whether it names a known mode or not.
+pub fn requested_mode(args: &[String]) -> Option<&str> {
+    args.iter()
+        .position(|arg| arg == MODE_ARGUMENT)
+        .and_then(|index| args.get(index + 1))
+        .map(String::as_str)
+}
+
+// An empty name returns to the default mode.
+pub fn set(name: &str) {
+    let mode = Mode::from_name(name);
+    if mode.is_none() && !name.is_empty() {
+        log!(
+            "Unknown mode {:?}, expected one of {:?}",
+            name,
+            Mode::ALL.map(Mode::name)
+        );
+    }
+    *MODE.lock().unwrap() = mode;
+}
+
+pub fn clear() -> Option<Mode> {
+    MODE.lock().unwrap().take()
+}
+
+pub fn current() -> Option<Mode> {
+    *MODE.lock().unwrap()
+}
+
+// In the files mode, the query is taken relative to the home directory unless
+// it already is a path.
+pub fn lock_query(query: String, mode: Option<Mode>) -> String {
+    match mode {
+        Some(Mode::files) if !query.starts_with(['~', '$', '/']) => format!("~/{}", query),
+        _ => query,
+    }
+}
+
+// Prefixes of other modes are treated as plain text in the application and
+// windows modes.
+pub fn allows_prefixes(mode: Option<Mode>) -> bool {
+    !matches!(mode, Some(Mode::apps | Mode::windows))
+}
@@ -0,0 +1,215 @@
+use crate::{
+    exec,
+    launcher::{AppEntry, EntryType},
+    log,
+    search::{self, SearchResult},
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use gtk4::{gio, prelude::*};
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, sync::Mutex};
+
+static TARGETS: Lazy<Mutex<Vec<OpenTarget>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Clone, Debug)]
+pub struct OpenTarget {
+    pub argument: String,
+    pub content_type: String,
+    pub exists: bool,
+}
+
+impl OpenTarget {
+    fn from_file(file: &gio::File) -> Self {
+        match (file.uri_scheme().as_deref(), file.path()) {
+            (Some("file") | None, Some(path)) => {
+                let argument = path.to_string_lossy().into_owned();
+                let (content_type, _) = gio::content_type_guess(Some(&path), &[]);
+                Self {
+                    exists: path.exists(),
+                    content_type: gio::content_type_get_mime_type(&content_type)
+                        .map(String::from)
+                        .unwrap_or_else(|| content_type.to_string()),
+                    argument,
+                }
+            }
+            (scheme, _) => Self {
+                argument: file.uri().to_string(),
+                content_type: format!("x-scheme-handler/{}", scheme.unwrap_or_default()),
+                exists: true,
+            },
+        }
+    }
+}
+
+pub fn set_targets(files: &[gio::File]) {
+    let targets: Vec<_> = files.iter().map(OpenTarget::from_file).collect();
+    for target in &targets {
+        log!("Opening {} ({})", target.argument, target.content_type);
+    }
+    *TARGETS.lock().unwrap() = targets;
+}
+
+pub fn clear() {
+    TARGETS.lock().unwrap().clear();
+}
+
+pub fn is_active() -> bool {
+    !TARGETS.lock().unwrap().is_empty()
+}
+
+pub fn candidates(apps: &HashMap<String, AppEntry>, query: &str) -> Vec<SearchResult> {
+    let targets = TARGETS.lock().unwrap().clone();
+
+    let missing: Vec<_> = targets
+        .iter()
+        .filter(|target| !target.exists)
+        .map(|target| SearchResult {
+            app: AppEntry {
+                name: format!("File not found: {}", target.argument),
+                icon_name: "dialog-error-symbolic".into(),
+                entry_type: EntryType::Divider,
+                ..Default::default()
+            },
+            score: 0,
+        })
+        .collect();
+    if !missing.is_empty() {
+        return missing;
+    }
+
+    let matcher = SkimMatcherV2::default().smart_case();
+    let mut results: Vec<_> = apps
+        .values()
+        .filter(|app| matches!(app.entry_type, EntryType::Application))
+        .filter(|app| {
+            targets.iter().all(|target| {
+                app.mime_types
+                    .iter()
+                    .any(|mime_type| gio::content_type_is_a(&target.content_type, mime_type))
+            })
+        })
+        .filter_map(|app| {
+            let score = if query.is_empty() {
+                0
+            } else {
+                matcher.fuzzy_match(&app.name.to_lowercase(), query)?
+            };
+            Some(SearchResult {
+                app: AppEntry {
+                    exec: substitute_field_codes(app, &targets)?,
+                    // Activation would start the application without the files.
+                    dbus_activatable: false,
+                    description: format!("Open {}", describe(&targets)),
+                    ..app.clone()
+                },
+                score: score + app.launch_count as i64,
+            })
+        })
+        .collect();
+
+    search::sort_results(&mut results);
+
+    if results.is_empty() {
+        results.push(SearchResult {
+            app: AppEntry {
+                name: format!("No application can open {}", describe(&targets)),
+                icon_name: "dialog-information-symbolic".into(),
+                entry_type: EntryType::Divider,
+                ..Default::default()
+            },
+            score: 0,
+        });
+    }
+    results
+}
+
+fn describe(targets: &[OpenTarget]) -> String {
+    match targets {
+        [target] => target.argument.clone(),
+        targets => format!("{} items", targets.len()),
+    }
+}
+
+// The result is an Exec value again, with a literal % doubled so file names
+// aren't read as field codes when it is launched.
+fn substitute_field_codes(app: &AppEntry, targets: &[OpenTarget]) -> Option<String> {
+    let files: Vec<_> = targets
+        .iter()
+        .map(|target| target.argument.clone())
+        .collect();
+    let fields = exec::Fields {
+        files: &files,
+        name: &app.name,
+        icon: &app.icon_name,
+        desktop_file: &app.path,
+    };
+    let argv = exec::command(&app.exec, &fields)
+        .map_err(|e| log!("Malformed Exec in {}: {}", app.path, e))
+        .ok()?;
+    Some(exec::join(&argv).replace('%', "%%"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AWKWARD_PATHS: &[&str] = &[
+        "/home/user/My Documents/report.pdf",
+        "/tmp/\"quoted\" name.txt",
+        "/tmp/$HOME `date`.txt",
+        "/tmp/back\\slash.txt",
+        "/tmp/100% done.txt",
+        "/tmp/it's.txt",
+    ];
+
+    fn viewer(exec: &str) -> AppEntry {
+        AppEntry {
+            name: String::from("Viewer"),
+            exec: exec.to_string(),
+            path: String::from("/usr/share/applications/viewer.desktop"),
+            ..Default::default()
+        }
+    }
+
+    fn target(argument: &str) -> OpenTarget {
+        OpenTarget {
+            argument: argument.to_string(),
+            content_type: String::from("text/plain"),
+            exists: true,
+        }
+    }
+
+    #[test]
+    fn substituted_paths_read_back_unchanged() {
+        for path in AWKWARD_PATHS {
+            let exec =
+                substitute_field_codes(&viewer("viewer --open %f"), &[target(path)]).unwrap();
+            let argv = exec::command(&exec, &exec::Fields::default()).unwrap();
+            assert_eq!(argv, ["viewer", "--open", path], "{}", exec);
+        }
+    }
+
+    #[test]
+    fn every_target_is_substituted_for_a_list_field_code() {
+        let targets: Vec<_> = AWKWARD_PATHS.iter().map(|path| target(path)).collect();
+        let exec = substitute_field_codes(&viewer("viewer %F"), &targets).unwrap();
+        let argv = exec::command(&exec, &exec::Fields::default()).unwrap();
+        assert_eq!(argv[0], "viewer");
+        assert_eq!(argv[1..], *AWKWARD_PATHS);
+    }
+
+    #[test]
+    fn targets_are_appended_without_a_field_code() {
+        let exec = substitute_field_codes(&viewer("viewer"), &[target(AWKWARD_PATHS[1])]).unwrap();
+        let argv = exec::command(&exec, &exec::Fields::default()).unwrap();
+        assert_eq!(argv, ["viewer", AWKWARD_PATHS[1]]);
+    }
+
+    #[test]
+    fn malformed_exec_is_rejected() {
+        assert_eq!(
+            substitute_field_codes(&viewer("viewer \"%f"), &[target("/tmp/a")]),
+            None
+        );
+    }
+}
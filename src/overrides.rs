@@ -0,0 +1,344 @@
+use crate::{launcher::AppEntry, log};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    thread,
+};
+
+pub const OVERRIDES_DIR: &str = "entries.d";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EntryOverride {
+    pub id: String,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub exec_prefix: Option<String>,
+    pub hidden: Option<bool>,
+}
+
+#[derive(Debug)]
+pub struct UnknownOverride {
+    pub file: String,
+    pub id: String,
+}
+
+pub fn overrides_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join(OVERRIDES_DIR)
+}
+
+pub fn load_overrides(dir: &Path) -> Vec<(String, EntryOverride)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        })
+        .collect();
+    files.sort();
+
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let file = path.file_name()?.to_string_lossy().into_owned();
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| log!("Failed to read override {:?}: {}", path, e))
+                .ok()?;
+            match toml::from_str::<EntryOverride>(&contents) {
+                Ok(entry) if entry.id.is_empty() => {
+                    log!("Override {} does not name a desktop ID, ignoring", file);
+                    None
+                }
+                Ok(entry) => Some((file, entry)),
+                Err(e) => {
+                    log!("Failed to parse override {}: {}", file, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Returns the `hidden` value of each desktop ID whose overrides set one. When
+// files disagree, the later one wins as for every other field.
+pub fn forced_visibility(overrides: &[(String, EntryOverride)]) -> HashMap<String, bool> {
+    let mut forced: HashMap<String, (bool, &str)> = HashMap::new();
+    for (file, entry) in overrides {
+        let Some(hidden) = entry.hidden else {
+            continue;
+        };
+        if let Some((previous, previous_file)) = forced.insert(entry.id.clone(), (hidden, file)) {
+            if previous != hidden {
+                log!(
+                    "{} is {} by {} and {} by {}; {} wins",
+                    entry.id,
+                    if previous { "hidden" } else { "shown" },
+                    previous_file,
+                    if hidden { "hidden" } else { "shown" },
+                    file,
+                    file
+                );
+            }
+        }
+    }
+    forced
+        .into_iter()
+        .map(|(id, (hidden, _))| (id, hidden))
+        .collect()
+}
+
+// Applies overrides in file name order and returns the ones whose desktop ID
+// matched no entry. `hidden` is left to visibility::EntryVisibility, see
+// forced_visibility.
+pub fn apply_overrides(
+    apps: &mut HashMap<String, AppEntry>,
+    overrides: Vec<(String, EntryOverride)>,
+) -> Vec<UnknownOverride> {
+    let mut applied: HashMap<String, String> = HashMap::new();
+    let mut unknown = Vec::new();
+
+    for (file, entry) in overrides {
+        let Some(key) = apps
+            .iter()
+            .find(|(_, app)| app.desktop_id == entry.id)
+            .map(|(key, _)| key.clone())
+        else {
+            unknown.push(UnknownOverride { file, id: entry.id });
+            continue;
+        };
+
+        if let Some(previous) = applied.insert(entry.id.clone(), file.clone()) {
+            log!(
+                "{} overrides {} again after {}, later fields win",
+                file,
+                entry.id,
+                previous
+            );
+        }
+
+        let Some(app) = apps.get_mut(&key) else {
+            continue;
+        };
+        if let Some(name) = entry.name {
+            app.name = name;
+        }
+        if let Some(icon) = entry.icon {
+            app.icon_name = icon.into();
+        }
+        if let Some(keywords) = entry.keywords {
+            app.keywords = keywords;
+        }
+        if let Some(prefix) = entry.exec_prefix {
+            app.exec = format!("{} {}", prefix, app.exec);
+        }
+    }
+
+    unknown
+}
+
+// Watches the config directory recursively so the drop-in directory is
+// picked up even if it is created after startup.
+pub fn watch_overrides<F: Fn() + Send + 'static>(config_dir: PathBuf, callback: F) {
+    let dir = overrides_dir(&config_dir);
+    thread::spawn(move || {
+        let (tx, rx) = channel();
+        let mut watcher = match RecommendedWatcher::new(tx, notify::Config::default()) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log!("Failed to create override watcher: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&config_dir, RecursiveMode::Recursive) {
+            log!("Failed to watch {:?} for overrides: {}", config_dir, e);
+            return;
+        }
+
+        for event in rx.into_iter().flatten() {
+            if event.paths.iter().any(|path| path.starts_with(&dir)) {
+                log!("Entry overrides changed, reloading applications");
+                callback();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn fixture_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = temp_dir(name);
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    fn app(desktop_id: &str, name: &str) -> AppEntry {
+        AppEntry {
+            id: desktop_id.to_string(),
+            desktop_id: desktop_id.to_string(),
+            name: name.to_string(),
+            exec: format!("{} %U", name.to_lowercase()),
+            icon_name: "vendor-icon".into(),
+            ..Default::default()
+        }
+    }
+
+    fn apps(entries: &[AppEntry]) -> HashMap<String, AppEntry> {
+        entries
+            .iter()
+            .map(|app| (app.id.clone(), app.clone()))
+            .collect()
+    }
+
+    fn ids(overrides: &[(String, EntryOverride)]) -> Vec<(&str, &str)> {
+        overrides
+            .iter()
+            .map(|(file, entry)| (file.as_str(), entry.id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn override_files_load_in_name_order() {
+        let dir = fixture_dir(
+            "overrides-order",
+            &[
+                ("20-gimp.toml", "id = \"gimp.desktop\"\nicon = \"gimp\"\n"),
+                (
+                    "10-firefox.toml",
+                    "id = \"firefox.desktop\"\nname = \"Web\"\n",
+                ),
+                ("notes.txt", "id = \"ignored.desktop\"\n"),
+            ],
+        );
+        let overrides = load_overrides(&dir);
+        assert_eq!(
+            ids(&overrides),
+            [
+                ("10-firefox.toml", "firefox.desktop"),
+                ("20-gimp.toml", "gimp.desktop")
+            ]
+        );
+        assert_eq!(overrides[0].1.name.as_deref(), Some("Web"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn broken_override_files_are_skipped() {
+        let dir = fixture_dir(
+            "overrides-broken",
+            &[
+                ("no-id.toml", "name = \"Nameless\"\n"),
+                ("typo.toml", "id = \"gimp.desktop\"\nicno = \"gimp\"\n"),
+                ("syntax.toml", "id = \"gimp.desktop\n"),
+                ("good.toml", "id = \"gimp.desktop\"\nhidden = true\n"),
+            ],
+        );
+        assert_eq!(ids(&load_overrides(&dir)), [("good.toml", "gimp.desktop")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_directory_has_no_overrides() {
+        let dir = std::env::temp_dir().join("hyprlauncher-test-no-such-entries.d");
+        assert!(load_overrides(&dir).is_empty());
+    }
+
+    #[test]
+    fn overrides_replace_fields_and_prefix_exec() {
+        let dir = fixture_dir(
+            "overrides-fields",
+            &[(
+                "firefox.toml",
+                "id = \"firefox.desktop\"\n\
+                 name = \"Web\"\n\
+                 icon = \"web-browser\"\n\
+                 keywords = [\"internet\", \"www\"]\n\
+                 exec_prefix = \"env MOZ_ENABLE_WAYLAND=1\"\n",
+            )],
+        );
+        let mut apps = apps(&[
+            app("firefox.desktop", "Firefox"),
+            app("gimp.desktop", "GIMP"),
+        ]);
+        let unknown = apply_overrides(&mut apps, load_overrides(&dir));
+        assert!(unknown.is_empty());
+
+        let firefox = &apps["firefox.desktop"];
+        assert_eq!(firefox.name, "Web");
+        assert_eq!(&*firefox.icon_name, "web-browser");
+        assert_eq!(firefox.keywords, ["internet", "www"]);
+        assert_eq!(firefox.exec, "env MOZ_ENABLE_WAYLAND=1 firefox %U");
+
+        let gimp = &apps["gimp.desktop"];
+        assert_eq!(gimp.name, "GIMP");
+        assert_eq!(&*gimp.icon_name, "vendor-icon");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn later_files_win_field_by_field() {
+        let dir = fixture_dir(
+            "overrides-later",
+            &[
+                (
+                    "10-a.toml",
+                    "id = \"gimp.desktop\"\nname = \"Editor\"\nicon = \"a\"\n",
+                ),
+                ("20-b.toml", "id = \"gimp.desktop\"\nicon = \"b\"\n"),
+            ],
+        );
+        let mut apps = apps(&[app("gimp.desktop", "GIMP")]);
+        apply_overrides(&mut apps, load_overrides(&dir));
+        assert_eq!(apps["gimp.desktop"].name, "Editor");
+        assert_eq!(&*apps["gimp.desktop"].icon_name, "b");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overrides_for_unknown_ids_are_reported() {
+        let dir = fixture_dir(
+            "overrides-unknown",
+            &[
+                ("typo.toml", "id = \"firefx.desktop\"\nname = \"Web\"\n"),
+                ("firefox.toml", "id = \"firefox.desktop\"\nname = \"Web\"\n"),
+            ],
+        );
+        let mut apps = apps(&[app("firefox.desktop", "Firefox")]);
+        let unknown = apply_overrides(&mut apps, load_overrides(&dir));
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].file, "typo.toml");
+        assert_eq!(unknown[0].id, "firefx.desktop");
+        assert_eq!(apps["firefox.desktop"].name, "Web");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn the_last_hidden_value_decides_visibility() {
+        let dir = fixture_dir(
+            "overrides-visibility",
+            &[
+                ("10-hide.toml", "id = \"gimp.desktop\"\nhidden = true\n"),
+                ("20-show.toml", "id = \"gimp.desktop\"\nhidden = false\n"),
+                ("30-name.toml", "id = \"firefox.desktop\"\nname = \"Web\"\n"),
+            ],
+        );
+        let forced = forced_visibility(&load_overrides(&dir));
+        assert_eq!(forced.get("gimp.desktop"), Some(&false));
+        assert_eq!(forced.get("firefox.desktop"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    config::Config,
+    icons,
+    launcher::{self, EntryType},
+    search::{self, SearchResult},
+};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+
+// Bump when a field is removed, renamed or changes meaning. Adding fields is
+// not a breaking change for consumers.
+pub const PRINT_SCHEMA_VERSION: u32 = 1;
+
+const DEFAULT_ICON_SIZE: i32 = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrintFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+pub struct PrintedResults<'a> {
+    version: u32,
+    query: &'a str,
+    entries: Vec<PrintedEntry>,
+}
+
+#[derive(Serialize)]
+pub struct PrintedEntry {
+    id: String,
+    desktop_id: Option<String>,
+    name: String,
+    description: String,
+    #[serde(rename = "type")]
+    entry_type: &'static str,
+    score: i64,
+    icon: String,
+    icon_path: Option<String>,
+    exec: String,
+}
+
+pub struct PrintOptions {
+    pub format: PrintFormat,
+    pub icon_size: i32,
+    pub query: String,
+}
+
+impl PrintOptions {
+    // Parses the arguments following --print: --print-format, --icon-size and
+    // the query words.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            format: PrintFormat::Text,
+            icon_size: DEFAULT_ICON_SIZE,
+            query: String::new(),
+        };
+        let mut words = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--print-format" => {
+                    options.format = match args.next().map(String::as_str) {
+                        Some("text") => PrintFormat::Text,
+                        Some("json") => PrintFormat::Json,
+                        other => return Err(format!("unknown print format {:?}", other)),
+                    }
+                }
+                "--icon-size" => {
+                    options.icon_size = args
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .filter(|size| *size > 0)
+                        .ok_or("--icon-size expects a positive number")?;
+                }
+                _ => words.push(arg.as_str()),
+            }
+        }
+        options.query = words.join(" ");
+        Ok(options)
+    }
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let options = match PrintOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let config = Config::load();
+
+    let rt = Runtime::new().expect("Failed to create Tokio runtime");
+    if let Err(e) = rt.block_on(launcher::load_applications(Default::default())) {
+        eprintln!("Failed to load applications: {}", e);
+        return 1;
+    }
+
+    let results = match rt.block_on(search::search_applications(&options.query, &config)) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Failed to search applications: {}", e);
+            return 1;
+        }
+    };
+
+    match options.format {
+        PrintFormat::Text => {
+            for result in &results.matches {
+                println!("{}", result.app.name);
+            }
+        }
+        PrintFormat::Json => {
+            // Icon themes are looked up through the display, so without one
+            // every icon_path is null.
+            let icon_size = gtk4::init().is_ok().then_some(options.icon_size);
+            let printed = printed_results(&options.query, &results.matches, icon_size);
+            match serde_json::to_string_pretty(&printed) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    eprintln!("Failed to serialize results: {}", e);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    0
+}
+
+// The schema shared by --print-format json and the control socket. Icon paths
+// are resolved at `icon_size` when given, which needs the GTK main thread,
+// and are null otherwise or when the theme has no file for the icon.
+pub fn printed_results<'a>(
+    query: &'a str,
+    results: &[SearchResult],
+    icon_size: Option<i32>,
+) -> PrintedResults<'a> {
+    PrintedResults {
+        version: PRINT_SCHEMA_VERSION,
+        query,
+        entries: results
+            .iter()
+            .map(|result| printed_entry(result, icon_size))
+            .collect(),
+    }
+}
+
+fn printed_entry(result: &SearchResult, icon_size: Option<i32>) -> PrintedEntry {
+    let app = &result.app;
+    PrintedEntry {
+        id: app.id.clone(),
+        desktop_id: Some(app.desktop_id.clone()).filter(|id| !id.is_empty()),
+        name: app.name.clone(),
+        description: app.description.clone(),
+        entry_type: entry_type_name(&app.entry_type),
+        score: result.score,
+        icon: launcher::icon_name(app).to_string(),
+        icon_path: icon_size.and_then(|size| icons::resolve_path(launcher::icon_name(app), size)),
+        exec: app.exec.clone(),
+    }
+}
+
+fn entry_type_name(entry_type: &EntryType) -> &'static str {
+    match entry_type {
+        EntryType::Application => "application",
+        EntryType::File => "file",
+        EntryType::Link => "link",
+        EntryType::Query => "query",
+        EntryType::Value => "value",
+        EntryType::Window => "window",
+        EntryType::Divider => "divider",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::launcher::AppEntry;
+
+    fn results() -> Vec<SearchResult> {
+        vec![
+            SearchResult {
+                app: AppEntry {
+                    id: String::from("org.gnome.Terminal.desktop"),
+                    desktop_id: String::from("org.gnome.Terminal.desktop"),
+                    name: String::from("Terminal"),
+                    description: String::from("Use the command line"),
+                    icon_name: "org.gnome.Terminal".into(),
+                    exec: String::from("gnome-terminal"),
+                    ..Default::default()
+                },
+                score: 1520,
+            },
+            SearchResult {
+                app: AppEntry {
+                    id: String::from("/usr/bin/top"),
+                    name: String::from("top"),
+                    path: String::from("/usr/bin/top"),
+                    icon_name: "utilities-terminal".into(),
+                    exec: String::from("top"),
+                    entry_type: EntryType::File,
+                    ..Default::default()
+                },
+                score: 40,
+            },
+        ]
+    }
+
+    // External consumers parse this document. A change here has to keep
+    // existing fields as they are or bump PRINT_SCHEMA_VERSION.
+    #[test]
+    fn json_schema_is_stable() {
+        let printed = printed_results("term", &results(), None);
+        assert_eq!(
+            serde_json::to_value(&printed).unwrap(),
+            serde_json::json!({
+                "version": 1,
+                "query": "term",
+                "entries": [
+                    {
+                        "id": "org.gnome.Terminal.desktop",
+                        "desktop_id": "org.gnome.Terminal.desktop",
+                        "name": "Terminal",
+                        "description": "Use the command line",
+                        "type": "application",
+                        "score": 1520,
+                        "icon": "org.gnome.Terminal",
+                        "icon_path": null,
+                        "exec": "gnome-terminal"
+                    },
+                    {
+                        "id": "/usr/bin/top",
+                        "desktop_id": null,
+                        "name": "top",
+                        "description": "",
+                        "type": "file",
+                        "score": 40,
+                        "icon": "utilities-terminal",
+                        "icon_path": null,
+                        "exec": "top"
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn print_arguments_parse_into_options() {
+        let args: Vec<String> = [
+            "--print-format",
+            "json",
+            "web",
+            "--icon-size",
+            "64",
+            "browser",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let options = PrintOptions::parse(&args).unwrap();
+        assert_eq!(options.format, PrintFormat::Json);
+        assert_eq!(options.icon_size, 64);
+        assert_eq!(options.query, "web browser");
+
+        let bad = |args: &[&str]| {
+            PrintOptions::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+                .is_err()
+        };
+        assert!(bad(&["--print-format", "yaml"]));
+        assert!(bad(&["--icon-size", "0"]));
+        assert!(bad(&["--icon-size"]));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt::Display,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Instant,
+};
+
+pub const PROFILE_ARGUMENT: &str = "--profile";
+
+// Set by --profile, which prints how long loading takes and how much it
+// allocates, along with cache statistics.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+// Counts the allocations of every thread while profiling. Memory allocated by
+// GTK itself doesn't go through it.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[inline(always)]
+fn count(size: usize) {
+    if ENABLED.load(Ordering::Relaxed) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Prints a line of the profile, whether or not logging is enabled.
+pub fn report(label: &str, detail: impl Display) {
+    if is_enabled() {
+        println!("profile: {}: {}", label, detail);
+    }
+}
+
+// Measures the time and allocations from `start` to `finish`, including those
+// of other threads in the meantime.
+pub struct Span {
+    label: &'static str,
+    started: Instant,
+    allocations: u64,
+    bytes: u64,
+}
+
+pub fn start(label: &'static str) -> Span {
+    Span {
+        label,
+        started: Instant::now(),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+impl Span {
+    pub fn finish(self, detail: impl Display) {
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - self.allocations;
+        let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - self.bytes;
+        report(
+            self.label,
+            format_args!(
+                "{:.1?}, {} allocations ({} KiB), {}",
+                self.started.elapsed(),
+                allocations,
+                bytes / 1024,
+                detail
+            ),
+        );
+    }
+}
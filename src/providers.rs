@@ -0,0 +1,130 @@
+use crate::config::MinQueryLength;
+
+// The sources a typed query is searched in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Applications,
+    // The executable fallback in /usr/bin.
+    Commands,
+    // Paths, file roots and bookmarks.
+    Files,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 3] = [Provider::Applications, Provider::Commands, Provider::Files];
+}
+
+// How many characters a query needs before each provider searches it, from
+// `search.min_query_length`. The search asks again on every keystroke, so a
+// provider joins in as soon as the query is long enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    thresholds: [(Provider, usize); 3],
+}
+
+impl Registry {
+    pub fn new(min_length: &MinQueryLength) -> Self {
+        Self {
+            thresholds: Provider::ALL.map(|provider| {
+                let threshold = match provider {
+                    Provider::Applications => min_length.applications,
+                    Provider::Commands => min_length.commands,
+                    Provider::Files => min_length.files,
+                };
+                (provider, threshold)
+            }),
+        }
+    }
+
+    pub fn min_length(&self, provider: Provider) -> usize {
+        self.thresholds
+            .iter()
+            .find(|(registered, _)| *registered == provider)
+            .map_or(0, |(_, threshold)| *threshold)
+    }
+
+    // Under-length providers return nothing rather than an error.
+    pub fn accepts(&self, provider: Provider, query: &str) -> bool {
+        query.chars().count() >= self.min_length(provider)
+    }
+
+    pub fn eligible(&self, query: &str) -> Vec<Provider> {
+        Provider::ALL
+            .into_iter()
+            .filter(|provider| self.accepts(*provider, query))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_keep_commands_from_single_letters() {
+        let registry = Registry::new(&MinQueryLength::default());
+        assert_eq!(registry.eligible(""), [Provider::Files]);
+        assert_eq!(
+            registry.eligible("f"),
+            [Provider::Applications, Provider::Files]
+        );
+        assert_eq!(registry.eligible("fo"), Provider::ALL);
+    }
+
+    #[test]
+    fn providers_join_in_as_the_query_grows() {
+        let registry = Registry::new(&MinQueryLength {
+            applications: 2,
+            commands: 4,
+            files: 3,
+        });
+        let mut query = String::new();
+        let mut joined = Vec::new();
+        for letter in "firefox".chars() {
+            query.push(letter);
+            let eligible = registry.eligible(&query);
+            assert!(joined.iter().all(|provider| eligible.contains(provider)));
+            for provider in eligible {
+                if !joined.contains(&provider) {
+                    joined.push(provider);
+                    assert_eq!(query.chars().count(), registry.min_length(provider));
+                }
+            }
+        }
+        assert_eq!(
+            joined,
+            [Provider::Applications, Provider::Files, Provider::Commands]
+        );
+    }
+
+    #[test]
+    fn thresholds_count_characters() {
+        let registry = Registry::new(&MinQueryLength {
+            applications: 2,
+            ..Default::default()
+        });
+        assert!(!registry.accepts(Provider::Applications, "é"));
+        assert!(registry.accepts(Provider::Applications, "éa"));
+    }
+
+    #[test]
+    fn every_provider_is_registered() {
+        let registry = Registry::new(&MinQueryLength {
+            applications: 5,
+            commands: 6,
+            files: 7,
+        });
+        let thresholds: Vec<_> = Provider::ALL
+            .into_iter()
+            .map(|provider| (provider, registry.min_length(provider)))
+            .collect();
+        assert_eq!(
+            thresholds,
+            [
+                (Provider::Applications, 5),
+                (Provider::Commands, 6),
+                (Provider::Files, 7)
+            ]
+        );
+    }
+}
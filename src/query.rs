@@ -0,0 +1,355 @@
+use crate::launcher::AppEntry;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+
+// Weights of matches outside the name. Keywords and categories use
+// `keyword_weight` instead.
+const DESCRIPTION_WEIGHT: f64 = 0.5;
+const LOCATION_WEIGHT: f64 = 0.25;
+
+// A GenericName such as "Web Browser" describes the application better than
+// its keywords but is not what it's called, so it ranks halfway between.
+pub fn generic_name_weight(keyword_weight: f64) -> f64 {
+    (1.0 + keyword_weight) / 2.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Name,
+    GenericName,
+    Description,
+    Exec,
+    Path,
+    Keywords,
+    Category,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Field::Name),
+            "generic" => Some(Field::GenericName),
+            "desc" => Some(Field::Description),
+            "exec" => Some(Field::Exec),
+            "path" => Some(Field::Path),
+            "keywords" => Some(Field::Keywords),
+            "category" => Some(Field::Category),
+            _ => None,
+        }
+    }
+
+    fn weight(self, keyword_weight: f64) -> f64 {
+        match self {
+            Field::Name => 1.0,
+            Field::GenericName => generic_name_weight(keyword_weight),
+            Field::Keywords | Field::Category => keyword_weight,
+            Field::Description => DESCRIPTION_WEIGHT,
+            Field::Exec | Field::Path => LOCATION_WEIGHT,
+        }
+    }
+
+    fn values(self, app: &AppEntry) -> Vec<&str> {
+        match self {
+            Field::Name => vec![&app.name, &app.untranslated_name],
+            Field::GenericName => vec![&app.generic_name],
+            Field::Description => vec![&app.description],
+            Field::Exec => vec![&app.exec],
+            Field::Path => vec![&app.path],
+            Field::Keywords => app.keywords.iter().map(String::as_str).collect(),
+            Field::Category => app.categories.iter().map(String::as_str).collect(),
+        }
+    }
+
+    // Commands and paths are long enough for the letters of almost any term
+    // to appear in order, so they have to contain the term.
+    fn requires_substring(self) -> bool {
+        matches!(self, Field::Exec | Field::Path | Field::Category)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub field: Option<Field>,
+    pub text: String,
+    pub excluded: bool,
+}
+
+// Splits a query into terms when at least one of them names a field, e.g.
+// `exec:electron -path:flatpak chat`. Other queries return None and are
+// matched as a whole. Unknown field names are kept as literal text.
+pub fn field_terms(query: &str) -> Option<Vec<Term>> {
+    let terms: Vec<_> = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let (excluded, word) = match word.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, word),
+            };
+            let (field, text) = match word.split_once(':') {
+                Some((name, text)) => match Field::from_name(name) {
+                    Some(field) => (Some(field), text),
+                    None => (None, word),
+                },
+                None => (None, word),
+            };
+            (!text.is_empty()).then(|| Term {
+                field,
+                text: text.to_lowercase(),
+                excluded,
+            })
+        })
+        .collect();
+
+    terms
+        .iter()
+        .any(|term| term.field.is_some())
+        .then_some(terms)
+}
+
+// Every included term has to match and no excluded one may. Terms without a
+// field match the name, falling back to the generic name and keywords.
+// Exclusions only apply to fields that contain the term, so `-a` doesn't hide
+// every name with an a.
+pub fn score_terms(
+    app: &AppEntry,
+    terms: &[Term],
+    matcher: &SkimMatcherV2,
+    keyword_weight: f64,
+) -> Option<i64> {
+    let mut total = 0;
+    for term in terms {
+        let score_in = |field| score_field(app, field, term, matcher, keyword_weight);
+        let score = match term.field {
+            Some(field) => score_in(field),
+            None => score_in(Field::Name)
+                .or_else(|| score_in(Field::GenericName))
+                .or_else(|| score_in(Field::Keywords)),
+        };
+        match (score, term.excluded) {
+            (Some(_), true) | (None, false) => return None,
+            (Some(score), false) => total += score,
+            (None, true) => {}
+        }
+    }
+    Some(total)
+}
+
+fn score_field(
+    app: &AppEntry,
+    field: Field,
+    term: &Term,
+    matcher: &SkimMatcherV2,
+    keyword_weight: f64,
+) -> Option<i64> {
+    let substring = term.excluded || field.requires_substring();
+    field
+        .values(app)
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .map(str::to_lowercase)
+        .filter(|value| !substring || value.contains(&term.text))
+        .filter_map(|value| matcher.fuzzy_match(&value, &term.text))
+        .max()
+        .map(|fuzzy| (fuzzy as f64 * field.weight(keyword_weight)) as i64)
+}
+
+// Removes the word before the cursor along with the whitespace after it, for
+// the `delete_word` key. Positions count characters, not bytes.
+pub fn delete_word(text: &str, cursor_pos: usize) -> Option<(String, usize)> {
+    if text.is_empty() || cursor_pos == 0 {
+        return None;
+    }
+
+    let mut chars: Vec<char> = text.chars().collect();
+    let mut new_pos = cursor_pos;
+
+    while new_pos > 0 && chars[new_pos - 1].is_whitespace() {
+        new_pos -= 1;
+    }
+
+    while new_pos > 0 && !chars[new_pos - 1].is_whitespace() {
+        new_pos -= 1;
+    }
+
+    chars.drain(new_pos..cursor_pos);
+    let result: String = chars.into_iter().collect();
+    let trimmed = result.trim_end().to_string();
+    let new_pos = new_pos.min(trimmed.len());
+
+    Some((trimmed, new_pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(field: Option<Field>, text: &str, excluded: bool) -> Term {
+        Term {
+            field,
+            text: text.to_string(),
+            excluded,
+        }
+    }
+
+    fn apps() -> Vec<AppEntry> {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        vec![
+            AppEntry {
+                name: String::from("Visual Studio Code"),
+                description: String::from("Code Editing. Redefined."),
+                exec: String::from("/usr/bin/electron /usr/lib/code/out/cli.js %F"),
+                path: String::from("/usr/share/applications/code.desktop"),
+                categories: strings(&["Development", "IDE"]),
+                ..Default::default()
+            },
+            AppEntry {
+                name: String::from("Signal"),
+                description: String::from("Private messenger"),
+                exec: String::from("/var/lib/flatpak/exports/bin/org.signal.Signal"),
+                path: String::from(
+                    "/var/lib/flatpak/exports/share/applications/org.signal.Signal.desktop",
+                ),
+                keywords: strings(&["chat", "sms"]),
+                categories: strings(&["Network", "InstantMessaging"]),
+                ..Default::default()
+            },
+            AppEntry {
+                name: String::from("Firefox"),
+                generic_name: Box::from("Web Browser"),
+                description: String::from("Browse the World Wide Web"),
+                exec: String::from("firefox %u"),
+                path: String::from("/usr/share/applications/firefox.desktop"),
+                keywords: strings(&["internet", "www"]),
+                categories: strings(&["Network", "WebBrowser"]),
+                ..Default::default()
+            },
+        ]
+    }
+
+    // Names of the matching apps, best first.
+    fn ranked(query: &str) -> Vec<String> {
+        let terms = field_terms(query).expect("not a field query");
+        let matcher = SkimMatcherV2::default();
+        let mut scored: Vec<_> = apps()
+            .into_iter()
+            .filter_map(|app| Some((score_terms(&app, &terms, &matcher, 0.5)?, app.name)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        scored.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn field_prefixes_split_the_query_into_terms() {
+        assert_eq!(
+            field_terms("exec:Electron -path:flatpak Chat"),
+            Some(vec![
+                term(Some(Field::Exec), "electron", false),
+                term(Some(Field::Path), "flatpak", true),
+                term(None, "chat", false),
+            ])
+        );
+        assert_eq!(
+            field_terms("name:code -sms"),
+            Some(vec![
+                term(Some(Field::Name), "code", false),
+                term(None, "sms", true),
+            ])
+        );
+    }
+
+    #[test]
+    fn queries_without_a_known_field_are_matched_whole() {
+        assert_eq!(field_terms("firefox"), None);
+        assert_eq!(field_terms("web browser"), None);
+        assert_eq!(field_terms("https://example.com"), None);
+        assert_eq!(field_terms("size:large"), None);
+        // A prefix with nothing after it names no field.
+        assert_eq!(field_terms("name: code"), None);
+    }
+
+    #[test]
+    fn unknown_fields_and_lone_dashes_are_literal_text() {
+        assert_eq!(
+            field_terms("size:large - desc:editor"),
+            Some(vec![
+                term(None, "size:large", false),
+                term(None, "-", false),
+                term(Some(Field::Description), "editor", false),
+            ])
+        );
+    }
+
+    #[test]
+    fn terms_only_match_their_field() {
+        assert_eq!(ranked("exec:electron"), ["Visual Studio Code"]);
+        assert_eq!(ranked("path:flatpak"), ["Signal"]);
+        assert_eq!(ranked("desc:messenger"), ["Signal"]);
+        assert_eq!(ranked("keywords:www"), ["Firefox"]);
+        assert_eq!(ranked("generic:browser"), ["Firefox"]);
+        assert!(ranked("name:browser").is_empty());
+    }
+
+    #[test]
+    fn commands_and_paths_have_to_contain_the_term() {
+        // The letters of "fx" appear in order in "firefox %u".
+        assert!(ranked("exec:fx").is_empty());
+        assert!(ranked("path:fxd").is_empty());
+        assert_eq!(ranked("exec:firefox"), ["Firefox"]);
+    }
+
+    #[test]
+    fn plain_terms_fall_back_to_the_generic_name_and_keywords() {
+        assert_eq!(ranked("category:network chat"), ["Signal"]);
+        assert_eq!(ranked("category:network browser"), ["Firefox"]);
+        assert_eq!(ranked("category:network fire"), ["Firefox"]);
+    }
+
+    #[test]
+    fn exclusions_combine_with_fields_and_plain_terms() {
+        let mut network = ranked("category:network");
+        network.sort();
+        assert_eq!(network, ["Firefox", "Signal"]);
+        assert_eq!(ranked("category:network -path:flatpak"), ["Firefox"]);
+        assert_eq!(ranked("category:network -chat"), ["Firefox"]);
+        assert_eq!(
+            ranked("category:network -exec:firefox -sms"),
+            Vec::<String>::new()
+        );
+        // An exclusion has to appear as written, not just as scattered letters.
+        assert_eq!(ranked("category:network -x"), ["Signal"]);
+    }
+
+    #[test]
+    fn names_outrank_other_fields() {
+        let app = AppEntry {
+            name: String::from("Editor"),
+            generic_name: Box::from("Editor"),
+            description: String::from("Editor"),
+            keywords: vec![String::from("editor")],
+            exec: String::from("editor"),
+            ..Default::default()
+        };
+        let matcher = SkimMatcherV2::default();
+        let score =
+            |query| score_terms(&app, &field_terms(query).unwrap(), &matcher, 0.25).unwrap();
+
+        let name = score("name:editor");
+        assert!(name > score("generic:editor"));
+        assert!(score("generic:editor") > score("desc:editor"));
+        assert!(score("desc:editor") > score("keywords:editor"));
+        assert_eq!(score("keywords:editor"), score("exec:editor"));
+        assert_eq!(score("desc:editor"), name / 2);
+    }
+
+    #[test]
+    fn every_included_term_adds_to_the_score() {
+        let firefox = &apps()[2];
+        let matcher = SkimMatcherV2::default();
+        let score = |query| score_terms(firefox, &field_terms(query).unwrap(), &matcher, 0.5);
+        let both = score("name:fire desc:web").unwrap();
+        assert_eq!(
+            both,
+            score("name:fire").unwrap() + score("desc:web").unwrap()
+        );
+        assert_eq!(score("name:fire desc:chat"), None);
+    }
+}
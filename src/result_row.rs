@@ -0,0 +1,200 @@
+use crate::{
+    config::{Config, PathEllipsize},
+    help, icons,
+    launcher::{self, DisplayText, EntryType},
+    ui::AppEntryObject,
+};
+use gtk4::{
+    glib,
+    pango::{EllipsizeMode, WrapMode},
+    prelude::*,
+    subclass::prelude::*,
+    Label, Orientation,
+};
+
+const ROW_MARGIN: i32 = 12;
+const ACTION_INDENT: i32 = 36;
+
+glib::wrapper! {
+    pub struct ResultRow(ObjectSubclass<imp::ResultRow>)
+        @extends gtk4::Box, gtk4::Widget,
+        @implements gtk4::Accessible, gtk4::Buildable, gtk4::ConstraintTarget, gtk4::Orientable;
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct ResultRow {
+        pub(crate) icon: gtk4::Image,
+        pub(crate) name: Label,
+        pub(crate) description: Label,
+        pub(crate) path: Label,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ResultRow {
+        const NAME: &'static str = "ResultRow";
+        type Type = super::ResultRow;
+        type ParentType = gtk4::Box;
+    }
+
+    impl ObjectImpl for ResultRow {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let row = self.obj();
+            row.set_orientation(Orientation::Horizontal);
+            row.set_spacing(12);
+            row.set_margin_start(ROW_MARGIN);
+            row.set_margin_end(12);
+            row.set_margin_top(6);
+            row.set_margin_bottom(6);
+
+            self.icon.set_icon_size(gtk4::IconSize::Large);
+            row.append(&self.icon);
+
+            let text_box = gtk4::Box::builder()
+                .orientation(Orientation::Vertical)
+                .spacing(3)
+                .hexpand(true)
+                .build();
+
+            for (label, css_class) in [
+                (&self.name, "app-name"),
+                (&self.description, "app-description"),
+                (&self.path, "app-path"),
+            ] {
+                setup_result_label(label, css_class);
+                text_box.append(label);
+            }
+
+            row.append(&text_box);
+        }
+    }
+
+    impl WidgetImpl for ResultRow {}
+    impl BoxImpl for ResultRow {}
+}
+
+impl Default for ResultRow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResultRow {
+    pub fn new() -> Self {
+        glib::Object::new()
+    }
+
+    pub fn bind(&self, app_entry: &AppEntryObject, config: &Config) {
+        let imp = self.imp();
+        let entry = app_entry.imp();
+
+        if app_entry.is_divider() {
+            self.add_css_class("result-divider");
+        } else {
+            self.remove_css_class("result-divider");
+        }
+        if app_entry.is_action() {
+            self.add_css_class("desktop-action");
+            self.set_margin_start(ACTION_INDENT);
+        } else {
+            self.remove_css_class("desktop-action");
+            self.set_margin_start(ROW_MARGIN);
+        }
+
+        if help::is_help_entry(entry.app_entry()) {
+            self.add_css_class("help-entry");
+        } else {
+            self.remove_css_class("help-entry");
+        }
+
+        if launcher::is_custom_entry(entry.app_entry()) {
+            self.add_css_class("custom-entry");
+        } else {
+            self.remove_css_class("custom-entry");
+        }
+
+        if entry.app_entry().no_display {
+            self.add_css_class("app-nodisplay");
+        } else {
+            self.remove_css_class("app-nodisplay");
+        }
+
+        if !app_entry.is_action()
+            && launcher::is_favorite(entry.app_entry(), &config.launcher.favorites)
+        {
+            self.add_css_class("app-pinned");
+        } else {
+            self.remove_css_class("app-pinned");
+        }
+
+        imp.icon.set_visible(config.window.show_icons);
+        if config.window.show_icons {
+            let scale = imp.icon.scale_factor();
+            imp.icon
+                .set_paintable(icons::lookup(entry.icon_name(), icons::ICON_SIZE, scale).as_ref());
+        }
+
+        set_display_text(&imp.name, entry.name());
+
+        let description = entry.description();
+        imp.description
+            .set_visible(config.window.show_descriptions && !description.is_empty());
+        if imp.description.is_visible() {
+            set_display_text(&imp.description, description);
+            // Wrapped labels are measured height-for-width, so rows with a
+            // long description grow up to the configured number of lines.
+            let lines = config.window.description_lines;
+            imp.description.set_wrap(lines > 1);
+            imp.description.set_lines(lines as i32);
+        }
+
+        let path = entry.path();
+        imp.path
+            .set_visible(config.window.show_paths && !path.is_empty());
+        if imp.path.is_visible() {
+            match launcher::desktop_origin(path, &config.launcher) {
+                Some(origin) if matches!(entry.app_entry().entry_type, EntryType::Application) => {
+                    imp.path.set_text(&format!("{} ({})", path, origin))
+                }
+                _ => imp.path.set_text(path),
+            }
+            imp.path.set_ellipsize(match config.window.path_ellipsize {
+                PathEllipsize::start => EllipsizeMode::Start,
+                PathEllipsize::middle => EllipsizeMode::Middle,
+                PathEllipsize::end => EllipsizeMode::End,
+            });
+            imp.path.set_has_tooltip(!config.window.hover_path_footer);
+        }
+    }
+}
+
+fn set_display_text(label: &Label, text: &DisplayText) {
+    match text {
+        DisplayText::Plain(text) => label.set_text(text),
+        DisplayText::Markup(markup) => label.set_markup(markup),
+    }
+}
+
+fn setup_result_label(label: &Label, css_class: &str) {
+    label.set_halign(gtk4::Align::Start);
+    label.set_ellipsize(EllipsizeMode::End);
+    label.set_wrap_mode(WrapMode::WordChar);
+    label.set_max_width_chars(1);
+    label.set_hexpand(true);
+    label.set_xalign(0.0);
+    label.set_has_tooltip(true);
+    label.add_css_class(css_class);
+
+    label.connect_query_tooltip(|label, _, _, _, tooltip| {
+        if label.layout().is_ellipsized() {
+            tooltip.set_text(Some(&label.text()));
+            true
+        } else {
+            false
+        }
+    });
+}
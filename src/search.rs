@@ -1,91 +1,545 @@
 use crate::{
-    config::Config,
-    launcher::{self, AppEntry, EntryType, APP_CACHE},
+    bookmarks::{self, BOOKMARK_QUERY_PREFIX},
+    capabilities::{self, XDG_OPEN},
+    categories::{self, CATEGORY_QUERY_PREFIX},
+    collation,
+    config::{CommandIdentity, Config, StartView},
+    dates, dmenu,
+    files::{self, DOCUMENT_CACHE, EXTENSION_QUERY_PREFIX},
+    help,
+    history::{self, HISTORY_QUERY_PREFIX},
+    hyprland,
+    launcher::{self, AppEntry, EntryType, BINARY_DIR},
+    log,
+    modes::{self, Mode},
+    open_with,
+    providers::{self, Provider},
+    query, windows,
 };
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-use std::{os::unix::fs::PermissionsExt, path::PathBuf};
-use tokio::sync::oneshot;
+use gtk4::glib;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
 
-const BONUS_SCORE_LAUNCH_COUNT: i64 = 100;
 const BONUS_SCORE_ICON_NAME: i64 = 1000;
 const BONUS_SCORE_BINARY: i64 = 3000;
 const BONUS_SCORE_FOLDER: i64 = 2000;
 
+const DOCUMENT_PREVIEW_COUNT: usize = 5;
+
+const BUDGET_CHECK_INTERVAL: usize = 64;
+
+const RECENCY_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+struct ListingOrder {
+    dirty: bool,
+    ids: Vec<String>,
+}
+
+// Order of the last empty-query listing. While launch counts change under a
+// visible window the listing keeps this order instead of re-sorting, so the
+// selected row doesn't move away under the pointer or the keyboard. The new
+// scores apply once the window is shown again or a query other than the
+// empty one runs, see apply_score_changes.
+static LISTING_ORDER: Mutex<ListingOrder> = Mutex::new(ListingOrder {
+    dirty: false,
+    ids: Vec::new(),
+});
+
+impl ListingOrder {
+    // Puts `results` in the order last listed while scores changed since,
+    // entries that weren't listed then going last. Otherwise their order is
+    // remembered.
+    fn keep(&mut self, results: &mut [SearchResult]) {
+        if self.dirty {
+            results.sort_by_key(|result| {
+                self.ids
+                    .iter()
+                    .position(|id| *id == result.app.id)
+                    .unwrap_or(usize::MAX)
+            });
+        } else {
+            self.ids = results.iter().map(|result| result.app.id.clone()).collect();
+        }
+    }
+
+    // Returns whether scores had changed.
+    fn apply(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+}
+
+pub fn mark_scores_changed() {
+    LISTING_ORDER.lock().unwrap().dirty = true;
+}
+
+pub fn apply_score_changes() -> bool {
+    LISTING_ORDER.lock().unwrap().apply()
+}
+
+fn keep_listing_order(results: &mut [SearchResult]) {
+    LISTING_ORDER.lock().unwrap().keep(results);
+}
+
 pub struct SearchResult {
     pub app: AppEntry,
     pub score: i64,
 }
 
+#[derive(Default)]
+pub struct SearchResults {
+    pub matches: Vec<SearchResult>,
+    pub hidden: Vec<SearchResult>,
+    pub loading: bool,
+    // Applications skipped once the time budget ran out.
+    pub deferred: usize,
+    // Whether the query browses paths, see files::set_browsing.
+    pub browsing: bool,
+}
+
+pub enum SearchEvent {
+    Results(SearchResults),
+    // Every application scored after a search ran over its budget. Replaces
+    // the rows shown so far, so the list ends up as without a budget.
+    Complete(SearchResults),
+    Done,
+}
+
+pub struct SearchBatch {
+    pub generation: u64,
+    pub event: SearchEvent,
+}
+
+// Sends the results of a provider tagged with the generation of their search,
+// leaving out entries the configuration disables so the window doesn't need
+// to load it for every batch.
+#[derive(Clone)]
+struct BatchSender {
+    tx: mpsc::UnboundedSender<SearchBatch>,
+    generation: u64,
+    config: Arc<Config>,
+}
+
+impl BatchSender {
+    fn send(&self, results: SearchResults) {
+        self.send_event(SearchEvent::Results(self.allowed(results)));
+    }
+
+    fn complete(&self, results: SearchResults) {
+        self.send_event(SearchEvent::Complete(self.allowed(results)));
+    }
+
+    fn allowed(&self, mut results: SearchResults) -> SearchResults {
+        results
+            .matches
+            .retain(|result| is_allowed(&result.app, &self.config));
+        results
+            .hidden
+            .retain(|result| is_allowed(&result.app, &self.config));
+        results
+    }
+
+    fn send_event(&self, event: SearchEvent) {
+        let _ = self.tx.send(SearchBatch {
+            generation: self.generation,
+            event,
+        });
+    }
+}
+
+// Sends Done once every provider has finished.
+fn finish_after(rt: &Handle, sender: BatchSender, providers: Vec<JoinHandle<()>>) {
+    rt.spawn(async move {
+        for provider in providers {
+            let _ = provider.await;
+        }
+        sender.send_event(SearchEvent::Done);
+    });
+}
+
+// Where a streamed result goes among the rows shown so far, given their
+// scores and None for notices: before the first notice or lower score, so
+// batches arriving in any order stay sorted.
+pub fn merge_position(rows: impl IntoIterator<Item = Option<i64>>, score: i64) -> usize {
+    let mut count = 0;
+    for row in rows {
+        if row.is_none_or(|row| row < score) {
+            return count;
+        }
+        count += 1;
+    }
+    count
+}
+
+pub fn stream_search(
+    rt: &Handle,
+    query: String,
+    config: Config,
+    generation: u64,
+) -> mpsc::UnboundedReceiver<SearchBatch> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let config = Arc::new(config);
+    let sender = BatchSender {
+        tx,
+        generation,
+        config: config.clone(),
+    };
+
+    // Dropped once the application results are final, so documents aren't
+    // sent before a complete pass that would replace them.
+    let (applications_done, applications_finished) = oneshot::channel::<()>();
+
+    let applications = {
+        let (sender, query, config) = (sender.clone(), query.clone(), config.clone());
+        rt.spawn(async move {
+            let _applications_done = applications_done;
+            let budget = Duration::from_millis(config.search.time_budget_ms);
+            let results = search_applications_within(&query, &config, Some(budget))
+                .await
+                .unwrap_or_default();
+            let deferred = results.deferred;
+            sender.send(results);
+
+            if deferred > 0 {
+                log!(
+                    "Search over budget, scoring all entries with {} skipped",
+                    deferred
+                );
+                let results = search_applications(&query, &config)
+                    .await
+                    .unwrap_or_default();
+                sender.complete(results);
+            }
+        })
+    };
+
+    let documents = {
+        let sender = sender.clone();
+        let max_results = config.window.max_entries;
+        rt.spawn(async move {
+            if open_with::is_active()
+                || dmenu::is_active()
+                || !modes::allows_prefixes(modes::current())
+            {
+                return;
+            }
+            let results = search_documents(&query.to_lowercase(), max_results).await;
+            let _ = applications_finished.await;
+            if !results.matches.is_empty() {
+                sender.send(results);
+            }
+        })
+    };
+
+    finish_after(rt, sender, vec![applications, documents]);
+    rx
+}
+
+impl From<Vec<SearchResult>> for SearchResults {
+    fn from(matches: Vec<SearchResult>) -> Self {
+        Self {
+            matches,
+            ..Default::default()
+        }
+    }
+}
+
 pub async fn search_applications(
     query: &str,
     config: &Config,
-) -> Result<Vec<SearchResult>, std::io::Error> {
-    let (tx, rx) = oneshot::channel();
+) -> Result<SearchResults, std::io::Error> {
+    search_applications_within(query, config, None).await
+}
+
+fn browsable_roots(config: &Config) -> BTreeMap<String, String> {
+    if config.search.enable_file_browsing && modes::allows_prefixes(modes::current()) {
+        config.files.roots.clone()
+    } else {
+        Default::default()
+    }
+}
+
+fn normalize_query(query: &str, roots: &BTreeMap<String, String>) -> String {
     let query = query.to_lowercase();
+    files::expand_root(&query, roots).unwrap_or(query)
+}
+
+// With a budget, fuzzy matching stops scoring once it runs out of time and
+// only keeps the remaining entries whose name starts with the query. The
+// keys of the skipped entries are returned in `deferred`.
+async fn search_applications_within(
+    query: &str,
+    config: &Config,
+    budget: Option<Duration>,
+) -> Result<SearchResults, std::io::Error> {
+    let (tx, rx) = oneshot::channel();
     let max_results = config.window.max_entries;
+    let enable_binary_fallback = config.search.enable_binary_fallback;
+    let mode = modes::current();
+    let prefixes = modes::allows_prefixes(mode);
+    let enable_file_browsing =
+        mode == Some(Mode::files) || config.search.enable_file_browsing && prefixes;
+    let roots = browsable_roots(config);
+    let query = modes::lock_query(normalize_query(query, &roots), mode);
+    let deadline = budget
+        .filter(|budget| !budget.is_zero())
+        .map(|budget| Instant::now() + budget);
+    let folder_bonus = config.files.folder_bonus;
+    let recency_bonus = config.files.recency_bonus;
+    let command_identity = config.ranking.command_identity.clone();
+    let blacklist = config.launcher.blacklist.clone();
+    let display_cutoff_ratio = config.ranking.display_cutoff_ratio;
+    let keyword_weight = config.search.keyword_weight;
+    let exec_name_weight = config.search.exec_name_weight;
+    let bonuses = Bonuses::new(config);
+    let providers = providers::Registry::new(&config.search.min_query_length);
+    let recent_count = config.window.recent_count;
+    let recent_separator = config.window.recent_separator;
+    let loading = !launcher::is_cache_ready();
+    let opening = open_with::is_active();
+    let category_view = match mode {
+        Some(mode) => mode == Mode::categories,
+        None => matches!(config.window.start_view, StartView::categories),
+    };
+    let help =
+        (prefixes && help::is_help_query(&query, config)).then(|| help::help_entries(config));
+    let dmenu = dmenu::is_active();
+    let windows = mode == Some(Mode::windows);
 
     tokio::task::spawn_blocking(move || {
-        let cache = APP_CACHE.blocking_read();
+        let cache = launcher::apps();
+        let date = prefixes.then(|| dates::evaluate(&query)).flatten();
+        let eligible = providers.eligible(&query);
+        let mut browsing = false;
 
         let results = match query.chars().next() {
-            Some('~' | '$' | '/') => handle_path_search(&query),
+            _ if dmenu => dmenu::candidates(&query, max_results).into(),
+
+            _ if windows => windows::candidates(&cache, &query, max_results).into(),
+
+            _ if loading && opening => SearchResults {
+                loading: true,
+                ..Default::default()
+            },
+
+            _ if opening => open_with::candidates(&cache, &query).into(),
+
+            _ if help.is_some() => help
+                .unwrap_or_default()
+                .into_iter()
+                .map(|app| SearchResult { app, score: 0 })
+                .collect::<Vec<_>>()
+                .into(),
+
+            _ if date.is_some() => vec![SearchResult {
+                app: date.unwrap_or_default(),
+                score: 0,
+            }]
+            .into(),
+
+            Some('~' | '$' | '/')
+                if enable_file_browsing && !eligible.contains(&Provider::Files) =>
+            {
+                browsing = true;
+                SearchResults::default()
+            }
+
+            Some('~' | '$' | '/') if enable_file_browsing => {
+                browsing = true;
+                handle_path_search(&query, folder_bonus, recency_bonus).into()
+            }
+
+            _ if loading => SearchResults {
+                loading: true,
+                ..Default::default()
+            },
+
+            None if category_view => categories::category_entries(cache.values())
+                .into_iter()
+                .map(|app| SearchResult { app, score: 0 })
+                .collect::<Vec<_>>()
+                .into(),
 
             None => {
-                let mut results = Vec::with_capacity(max_results);
-                for app in cache.values() {
-                    if app.path.ends_with(".desktop") {
-                        results.push(SearchResult {
-                            score: calculate_bonus_score(app),
-                            app: app.clone(),
-                        });
-
-                        if results.len() >= max_results {
-                            break;
-                        }
-                    }
+                let mut top = favorite_results(&cache, &bonuses);
+                top.truncate(max_results);
+                let recent_count = recent_count.min(max_results - top.len());
+                let recent: Vec<_> =
+                    recent_results(&cache, recent_count + top.len(), &bonuses, &blacklist)
+                        .into_iter()
+                        .filter(|recent| !top.iter().any(|result| result.app.id == recent.app.id))
+                        .take(recent_count)
+                        .collect();
+                top.extend(recent);
+
+                let mut results: Vec<_> = cache
+                    .values()
+                    .filter(|app| {
+                        app.path.ends_with(".desktop")
+                            || launcher::is_custom_entry(app)
+                            || launcher::is_appimage(app)
+                    })
+                    .filter(|app| !top.iter().any(|result| result.app.id == app.id))
+                    .map(|app| SearchResult {
+                        score: calculate_bonus_score(app, &bonuses),
+                        app: app.clone(),
+                    })
+                    .collect();
+                sort_results(&mut results);
+                results.truncate(max_results - top.len());
+
+                if recent_separator && !top.is_empty() && !results.is_empty() {
+                    results.insert(
+                        0,
+                        SearchResult {
+                            app: AppEntry {
+                                name: String::from("More applications"),
+                                entry_type: EntryType::Divider,
+                                ..Default::default()
+                            },
+                            score: 0,
+                        },
+                    );
                 }
-                results.sort_unstable_by_key(|item| -item.score);
-                results
+                results.splice(0..0, top);
+                keep_listing_order(&mut results);
+                results.into()
+            }
+
+            Some(_) if prefixes && query.starts_with(HISTORY_QUERY_PREFIX) => {
+                let filter = query[HISTORY_QUERY_PREFIX.len()..].trim();
+                let matcher = SkimMatcherV2::default().smart_case();
+                history::matching_entries(filter, &matcher)
+                    .into_iter()
+                    .take(max_results)
+                    .map(|app| SearchResult { app, score: 0 })
+                    .collect::<Vec<_>>()
+                    .into()
+            }
+
+            Some(_) if enable_file_browsing && query.starts_with(BOOKMARK_QUERY_PREFIX) => {
+                let filter = query[BOOKMARK_QUERY_PREFIX.len()..].trim();
+                let mut results: Vec<_> = bookmarks::listing(filter)
+                    .into_iter()
+                    .map(|app| bookmark_result(app, 0, &bonuses))
+                    .collect();
+                sort_results(&mut results);
+                results.truncate(max_results);
+                results.into()
+            }
+
+            Some(_)
+                if prefixes
+                    && query.starts_with(CATEGORY_QUERY_PREFIX)
+                    && !query.contains(char::is_whitespace) =>
+            {
+                let mut results: Vec<_> = cache
+                    .values()
+                    .filter(|app| categories::in_category(app, &query))
+                    .map(|app| SearchResult {
+                        score: calculate_bonus_score(app, &bonuses),
+                        app: app.clone(),
+                    })
+                    .collect();
+                sort_results(&mut results);
+                results.truncate(max_results);
+                results.into()
+            }
+
+            Some(_) if prefixes && query.starts_with(EXTENSION_QUERY_PREFIX) => {
+                let extensions =
+                    files::extensions_for_query(&query[EXTENSION_QUERY_PREFIX.len()..])
+                        .unwrap_or_default();
+                files::matching_documents(&DOCUMENT_CACHE.blocking_read(), extensions)
+                    .into_iter()
+                    .take(max_results)
+                    .map(|app| SearchResult { app, score: 0 })
+                    .collect::<Vec<_>>()
+                    .into()
             }
 
             Some(_) => {
+                apply_score_changes();
                 let matcher = SkimMatcherV2::default().smart_case();
-                let mut results = Vec::with_capacity(max_results);
-                let mut seen_names = std::collections::HashSet::new();
-
-                for app in cache.values() {
-                    let name_lower = app.name.to_lowercase();
-                    if name_lower == query {
-                        results.push(SearchResult {
-                            app: app.clone(),
-                            score: BONUS_SCORE_BINARY + calculate_bonus_score(app),
-                        });
-                        seen_names.insert(name_lower);
-                        continue;
-                    }
+                let applications = if eligible.contains(&Provider::Applications) {
+                    cache.len()
+                } else {
+                    0
+                };
+                let scoring = Scoring {
+                    matcher: &matcher,
+                    keyword_weight,
+                    exec_name_weight,
+                    bonuses: &bonuses,
+                };
+                let (mut results, deferred) = score_applications(
+                    cache.values().take(applications),
+                    &query,
+                    &scoring,
+                    deadline,
+                );
+                let seen_names: std::collections::HashSet<_> = results
+                    .iter()
+                    .map(|result| result.app.name.to_lowercase())
+                    .collect();
 
-                    if let Some(score) = matcher.fuzzy_match(&name_lower, &query) {
-                        results.push(SearchResult {
-                            app: app.clone(),
-                            score: score + calculate_bonus_score(app),
-                        });
-                        seen_names.insert(name_lower);
-                    }
+                let files = eligible.contains(&Provider::Files);
+                if files {
+                    results.extend(
+                        files::root_entries(&query, &roots, &matcher)
+                            .into_iter()
+                            .map(|(app, score)| SearchResult {
+                                app,
+                                score: score + BONUS_SCORE_ICON_NAME,
+                            }),
+                    );
+                }
+                if enable_file_browsing && files {
+                    results.extend(
+                        bookmarks::matching_entries(&query, &matcher)
+                            .into_iter()
+                            .map(|(app, score)| bookmark_result(app, score, &bonuses)),
+                    );
                 }
 
-                if !seen_names.contains(&query) {
-                    if let Some(result) = check_binary(&query) {
+                if enable_binary_fallback
+                    && eligible.contains(&Provider::Commands)
+                    && !seen_names.contains(&query)
+                    && query::field_terms(&query).is_none()
+                {
+                    if let Some(result) =
+                        check_binary(&query, &command_identity, &bonuses, &blacklist)
+                    {
                         results.push(result);
                     }
                 }
 
-                results.sort_unstable_by_key(|item| -item.score);
-                if results.len() > max_results {
-                    results.truncate(max_results);
+                sort_results(&mut results);
+                let split = display_cutoff(&results, display_cutoff_ratio).min(max_results);
+                let hidden = results.split_off(split);
+
+                SearchResults {
+                    matches: results,
+                    hidden,
+                    deferred,
+                    ..Default::default()
                 }
-                results
             }
         };
+        let results = SearchResults {
+            browsing,
+            ..results
+        };
 
         tx.send(results)
             .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to send results"))
@@ -95,61 +549,410 @@ pub async fn search_applications(
         .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Failed to receive results"))
 }
 
+async fn search_documents(query: &str, max_results: usize) -> SearchResults {
+    let Some(extensions) = files::extensions_for_query(query) else {
+        return SearchResults::default();
+    };
+    let documents = files::matching_documents(&DOCUMENT_CACHE.read().await, extensions);
+    if documents.is_empty() {
+        return SearchResults::default();
+    }
+
+    let shown = documents.len().min(DOCUMENT_PREVIEW_COUNT).min(max_results);
+    let hidden = documents.len() - shown;
+    let mut results: Vec<_> = documents
+        .into_iter()
+        .take(shown)
+        .map(|app| SearchResult { app, score: 0 })
+        .collect();
+
+    if hidden > 0 {
+        results.push(SearchResult {
+            app: AppEntry {
+                name: format!("Show {} more {} files", hidden, query),
+                description_markup: Some(format!(
+                    "Browse all <b>{}</b> files",
+                    glib::markup_escape_text(query)
+                )),
+                exec: format!("{}{}", EXTENSION_QUERY_PREFIX, query),
+                icon_name: "view-more-symbolic".into(),
+                entry_type: EntryType::Query,
+                ..Default::default()
+            },
+            score: 0,
+        });
+    }
+
+    results.into()
+}
+
+fn display_cutoff(results: &[SearchResult], ratio: f64) -> usize {
+    let Some(top) = results.first().filter(|top| top.score > 0) else {
+        return results.len();
+    };
+
+    let threshold = top.score as f64 * ratio.clamp(0.0, 1.0);
+    results.partition_point(|result| result.score as f64 >= threshold)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScoreBreakdown {
+    pub fuzzy: i64,
+    pub exact: i64,
+    pub frecency: i64,
+    pub workspace: i64,
+    pub favorite: i64,
+    pub icon: i64,
+    pub nodisplay: i64,
+}
+
+impl ScoreBreakdown {
+    pub fn total(&self) -> i64 {
+        self.fuzzy
+            + self.exact
+            + self.frecency
+            + self.workspace
+            + self.favorite
+            + self.icon
+            + self.nodisplay
+    }
+}
+
+// Keywords and the untranslated name are secondary fields, their matches are
+// scaled by `keyword_weight`. GenericName matches rank between the two, see
+// query::generic_name_weight. The program named by Exec is matched as well,
+// scaled by `exec_name_weight`, so `nvim` finds Neovim and `gimp` finds GNU
+// Image Manipulation Program. Queries with `field:` terms are scored term by
+// term, see query::score_terms.
+pub fn score_application(
+    app: &AppEntry,
+    query: &str,
+    matcher: &SkimMatcherV2,
+    keyword_weight: f64,
+    exec_name_weight: f64,
+    bonuses: &Bonuses,
+) -> Option<ScoreBreakdown> {
+    let bonus = bonus_breakdown(app, bonuses);
+    if let Some(terms) = query::field_terms(query) {
+        return query::score_terms(app, &terms, matcher, keyword_weight)
+            .map(|fuzzy| ScoreBreakdown { fuzzy, ..bonus });
+    }
+
+    let name_lower = app.name.to_lowercase();
+
+    if name_lower == query {
+        return Some(ScoreBreakdown {
+            exact: BONUS_SCORE_BINARY,
+            ..bonus
+        });
+    }
+
+    let exec_name = Some(app.exec_name.to_lowercase())
+        .filter(|exec_name| !exec_name.is_empty() && exec_name_weight > 0.0);
+    let exact = match &exec_name {
+        Some(exec_name) if exec_name == query => {
+            (BONUS_SCORE_BINARY as f64 * exec_name_weight) as i64
+        }
+        _ => 0,
+    };
+    let exec_fuzzy = exec_name
+        .and_then(|exec_name| matcher.fuzzy_match(&exec_name, query))
+        .map(|fuzzy| (fuzzy as f64 * exec_name_weight) as i64);
+
+    let fuzzy = matcher
+        .fuzzy_match(&name_lower, query)
+        .or_else(|| {
+            Some(&app.generic_name)
+                .filter(|generic_name| !generic_name.is_empty())
+                .and_then(|generic_name| matcher.fuzzy_match(&generic_name.to_lowercase(), query))
+                .map(|fuzzy| (fuzzy as f64 * query::generic_name_weight(keyword_weight)) as i64)
+        })
+        .or_else(|| {
+            app.keywords
+                .iter()
+                .map(String::as_str)
+                .chain(std::iter::once(&*app.untranslated_name))
+                .filter(|field| !field.is_empty())
+                .filter_map(|field| matcher.fuzzy_match(&field.to_lowercase(), query))
+                .max()
+                .map(|fuzzy| (fuzzy as f64 * keyword_weight) as i64)
+        });
+
+    fuzzy.max(exec_fuzzy).map(|fuzzy| ScoreBreakdown {
+        fuzzy,
+        exact,
+        ..bonus
+    })
+}
+
+// Score added to entries regardless of how well they match the query.
+//
+// Each launch is worth `ranking.launch_weight`, halved for every
+// `ranking.frecency_half_life_days` since it was made, so applications used
+// often long ago sink below the ones used now. Favorites
+// get `launcher.favorite_bonus` on top.
+pub struct Bonuses {
+    launch_weight: f64,
+    half_life: f64,
+    now: u64,
+    affinity: f64,
+    workspace: Option<String>,
+    favorites: Vec<String>,
+    favorite_bonus: i64,
+    nodisplay_penalty: i64,
+}
+
+impl Bonuses {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            launch_weight: config.ranking.launch_weight as f64,
+            half_life: config.ranking.frecency_half_life_days * 24.0 * 60.0 * 60.0,
+            now: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            affinity: config.ranking.workspace_affinity,
+            workspace: (config.ranking.workspace_affinity > 0.0)
+                .then(hyprland::current_workspace)
+                .flatten(),
+            favorites: config.launcher.favorites.clone(),
+            favorite_bonus: config.launcher.favorite_bonus,
+            nodisplay_penalty: config.ranking.nodisplay_penalty,
+        }
+    }
+
+    pub fn is_favorite(&self, app: &AppEntry) -> bool {
+        launcher::is_favorite(app, &self.favorites)
+    }
+
+    // Launches beyond the `recent` times decay from the oldest of them, or
+    // from the last launch without any. A half-life of 0 turns the decay off.
+    pub fn frecency(&self, launch_count: u32, last_launched: u64, recent: &[u64]) -> i64 {
+        let decay = |at: u64| {
+            if self.half_life <= 0.0 {
+                return 1.0;
+            }
+            0.5f64.powf(self.now.saturating_sub(at) as f64 / self.half_life)
+        };
+        let older = launch_count.saturating_sub(recent.len() as u32);
+        let oldest = recent.first().copied().unwrap_or(last_launched);
+        let launches =
+            recent.iter().map(|&at| decay(at)).sum::<f64>() + older as f64 * decay(oldest);
+        (launches * self.launch_weight) as i64
+    }
+
+    // Splits the bonus into the history of all workspaces and that of the
+    // active one, weighted by `ranking.workspace_affinity`. Without a known
+    // workspace, e.g. outside Hyprland, all of it comes from the global
+    // history.
+    pub fn blend(&self, app: &AppEntry) -> (i64, i64) {
+        let global = self.frecency(app.launch_count, app.last_launched, &app.recent_launches);
+        let Some(workspace) = &self.workspace else {
+            return (global, 0);
+        };
+        let local = app.workspace_launches.get(workspace).map_or(0, |launches| {
+            self.frecency(launches.count, launches.last, &launches.recent)
+        });
+        (
+            ((1.0 - self.affinity) * global as f64) as i64,
+            (self.affinity * local as f64) as i64,
+        )
+    }
+}
+
 #[inline(always)]
-fn calculate_bonus_score(app: &AppEntry) -> i64 {
-    (app.launch_count as i64 * BONUS_SCORE_LAUNCH_COUNT)
-        + if app.icon_name == "application-x-executable" {
+fn bonus_breakdown(app: &AppEntry, bonuses: &Bonuses) -> ScoreBreakdown {
+    let (global, workspace) = bonuses.blend(app);
+    ScoreBreakdown {
+        frecency: global,
+        workspace,
+        favorite: if bonuses.is_favorite(app) {
+            bonuses.favorite_bonus
+        } else {
+            0
+        },
+        icon: if &*app.icon_name == "application-x-executable" {
             0
         } else {
             BONUS_SCORE_ICON_NAME
-        }
+        },
+        nodisplay: if app.no_display {
+            -bonuses.nodisplay_penalty
+        } else {
+            0
+        },
+        ..Default::default()
+    }
 }
 
 #[inline(always)]
-fn check_binary(query: &str) -> Option<SearchResult> {
+fn calculate_bonus_score(app: &AppEntry, bonuses: &Bonuses) -> i64 {
+    bonus_breakdown(app, bonuses).total()
+}
+
+pub fn is_allowed(app: &AppEntry, config: &Config) -> bool {
+    match app.entry_type {
+        EntryType::Application
+        | EntryType::Link
+        | EntryType::Query
+        | EntryType::Value
+        | EntryType::Window
+        | EntryType::Divider => true,
+        EntryType::File if is_binary_fallback(app) => config.search.enable_binary_fallback,
+        EntryType::File => config.search.enable_file_browsing,
+    }
+}
+
+#[inline(always)]
+pub fn is_binary_fallback(app: &AppEntry) -> bool {
+    app.score_boost == BONUS_SCORE_BINARY && app.path.starts_with(BINARY_DIR)
+}
+
+#[inline(always)]
+fn check_binary(
+    query: &str,
+    command_identity: &CommandIdentity,
+    bonuses: &Bonuses,
+    blacklist: &[String],
+) -> Option<SearchResult> {
     let parts: Vec<&str> = query.split_whitespace().collect();
     if parts.is_empty() {
         return None;
     }
 
-    let bin_path = format!("/usr/bin/{}", parts[0]);
-    std::fs::metadata(&bin_path)
-        .ok()
-        .filter(|metadata| metadata.permissions().mode() & 0o111 != 0)
-        .map(|_| SearchResult {
-            app: AppEntry {
-                name: query.to_string(),
-                description: String::new(),
-                path: bin_path.clone(),
-                exec: if parts.len() > 1 {
-                    format!("{} {}", bin_path, parts[1..].join(" "))
-                } else {
-                    bin_path
-                },
-                icon_name: String::from("application-x-executable"),
-                launch_count: 0,
-                entry_type: EntryType::File,
-                score_boost: BONUS_SCORE_BINARY,
-            },
-            score: BONUS_SCORE_BINARY,
+    let bin_path = format!("{}{}", BINARY_DIR, parts[0]);
+    let exec = if parts.len() > 1 {
+        format!("{} {}", bin_path, parts[1..].join(" "))
+    } else {
+        bin_path.clone()
+    };
+
+    if !launcher::is_executable(Path::new(&bin_path)) {
+        return None;
+    }
+    let id = launcher::command_identity(&exec, command_identity);
+    let launches = launcher::launches(&id);
+    Some(binary_result(query, id, exec, launches, bonuses))
+        .filter(|result| launcher::blacklist_match(&result.app, blacklist).is_none())
+}
+
+fn binary_result(
+    name: &str,
+    id: String,
+    exec: String,
+    launches: launcher::Launches,
+    bonuses: &Bonuses,
+) -> SearchResult {
+    let app = AppEntry {
+        id,
+        name: name.to_string(),
+        path: exec
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        exec,
+        icon_name: "application-x-executable".into(),
+        launch_count: launches.count,
+        last_launched: launches.last,
+        recent_launches: launches.recent,
+        workspace_launches: launches.workspaces,
+        entry_type: EntryType::File,
+        score_boost: BONUS_SCORE_BINARY,
+        ..Default::default()
+    };
+    let (global, workspace) = bonuses.blend(&app);
+    SearchResult {
+        score: BONUS_SCORE_BINARY + global + workspace,
+        app,
+    }
+}
+
+// Bookmarked folders rank by their launch history like applications, and
+// above other folders with the same score.
+fn bookmark_result(mut app: AppEntry, score: i64, bonuses: &Bonuses) -> SearchResult {
+    let launches = launcher::launches(&app.id);
+    app.launch_count = launches.count;
+    app.last_launched = launches.last;
+    app.recent_launches = launches.recent;
+    app.workspace_launches = launches.workspaces;
+    let (global, workspace) = bonuses.blend(&app);
+    SearchResult {
+        score: score + BONUS_SCORE_FOLDER + global + workspace,
+        app,
+    }
+}
+
+// Favorites in the order they are listed. Names that match several entries
+// list all of them, and favorites that match nothing are skipped.
+fn favorite_results(cache: &HashMap<String, AppEntry>, bonuses: &Bonuses) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = Vec::new();
+    for favorite in &bonuses.favorites {
+        let mut matches: Vec<_> = cache
+            .values()
+            .filter(|app| launcher::is_favorite(app, std::slice::from_ref(favorite)))
+            .filter(|app| !results.iter().any(|result| result.app.id == app.id))
+            .map(|app| SearchResult {
+                score: calculate_bonus_score(app, bonuses),
+                app: app.clone(),
+            })
+            .collect();
+        sort_results(&mut matches);
+        results.extend(matches);
+    }
+    results
+}
+
+// Looks up the most recently launched heatmap keys: desktop IDs in the cache,
+// and commands run through the executable fallback whose binary still exists.
+// Keys of entries that are gone are skipped.
+fn recent_results(
+    cache: &HashMap<String, AppEntry>,
+    count: usize,
+    bonuses: &Bonuses,
+    blacklist: &[String],
+) -> Vec<SearchResult> {
+    launcher::recent_launches(count)
+        .into_iter()
+        .filter_map(|(key, launches)| {
+            if let Some(app) = cache
+                .get(&key)
+                .or_else(|| cache.values().find(|app| app.id == key))
+            {
+                return Some(SearchResult {
+                    score: calculate_bonus_score(app, bonuses),
+                    app: app.clone(),
+                });
+            }
+            let command = key.strip_prefix(BINARY_DIR)?.to_string();
+            launcher::is_executable(Path::new(key.split_whitespace().next()?))
+                .then(|| binary_result(&command, key.clone(), key, launches, bonuses))
+                .filter(|result| launcher::blacklist_match(&result.app, blacklist).is_none())
         })
+        .collect()
 }
 
 #[inline(always)]
-fn handle_path_search(query: &str) -> Vec<SearchResult> {
+fn handle_path_search(query: &str, folder_bonus: i64, recency_bonus: i64) -> Vec<SearchResult> {
+    let (query, filters) = files::split_path_query(query);
     let expanded_path = shellexpand::full(query).unwrap_or(std::borrow::Cow::Borrowed(query));
     let path = std::path::Path::new(expanded_path.as_ref());
 
-    let dir = if path.is_dir() {
-        path.to_path_buf()
+    let (dir, fragment) = if path.is_dir() {
+        (path.to_path_buf(), String::new())
     } else {
-        path.parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| PathBuf::from("/"))
+        (
+            path.parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_else(|| PathBuf::from("/")),
+            // Listed names are lowercased, see files::list_directory.
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        )
     };
 
-    std::fs::read_dir(&dir)
-        .ok()
+    files::list_directory(&dir)
         .map(|entries| {
             let mut results: Vec<SearchResult> = Vec::new();
 
@@ -166,35 +969,541 @@ fn handle_path_search(query: &str) -> Vec<SearchResult> {
                 }
             }
 
+            let matcher = SkimMatcherV2::default().ignore_case();
+            let now = SystemTime::now();
             let mut entries: Vec<_> = entries
-                .filter_map(Result::ok)
+                .iter()
                 .filter_map(|entry| {
-                    let path = entry.path().to_string_lossy().into_owned();
-                    launcher::create_file_entry(path).map(|mut app| {
-                        let score = if app.icon_name == "folder" {
-                            BONUS_SCORE_FOLDER
-                        } else {
-                            BONUS_SCORE_ICON_NAME
-                        };
-                        app.score_boost = score;
-                        SearchResult { app, score }
+                    let is_dir = entry.is_dir;
+                    if !is_dir && !filters.iter().all(|filter| filter.matches(&entry.name)) {
+                        return None;
+                    }
+
+                    let fuzzy = if fragment.is_empty() {
+                        0
+                    } else {
+                        matcher.fuzzy_match(&entry.name, &fragment)?
+                    };
+                    let recency = entry
+                        .modified
+                        .map(|modified| recency_score(now, modified, recency_bonus))
+                        .unwrap_or(0);
+
+                    let mut app = entry.app.clone();
+                    app.score_boost = if is_dir {
+                        BONUS_SCORE_FOLDER
+                    } else {
+                        BONUS_SCORE_ICON_NAME
+                    };
+                    let folder = if is_dir { folder_bonus } else { 0 };
+                    Some(SearchResult {
+                        app,
+                        score: fuzzy + folder + recency,
                     })
                 })
                 .collect();
 
-            entries.sort_by(|a, b| {
-                let a_is_folder = a.app.icon_name == "folder";
-                let b_is_folder = b.app.icon_name == "folder";
-
-                match (a_is_folder, b_is_folder) {
-                    (true, false) => std::cmp::Ordering::Less,
-                    (false, true) => std::cmp::Ordering::Greater,
-                    _ => a.app.name.to_lowercase().cmp(&b.app.name.to_lowercase()),
-                }
+            entries.sort_by_cached_key(|entry| {
+                (
+                    Reverse(entry.score),
+                    glib::FilenameCollationKey::from(glib::casefold(&entry.app.name)),
+                )
             });
 
+            // Explains an empty listing rather than files that can't be opened.
+            if entries.is_empty() && !capabilities::is_available(XDG_OPEN) {
+                results.push(SearchResult {
+                    app: AppEntry {
+                        name: capabilities::explain(XDG_OPEN),
+                        icon_name: "dialog-information-symbolic".into(),
+                        entry_type: EntryType::Divider,
+                        ..Default::default()
+                    },
+                    score: 0,
+                });
+            }
             results.extend(entries);
             results
         })
         .unwrap_or_default()
 }
+
+struct Scoring<'a> {
+    matcher: &'a SkimMatcherV2,
+    keyword_weight: f64,
+    exec_name_weight: f64,
+    bonuses: &'a Bonuses,
+}
+
+// Past the deadline only applications whose name starts with the query are
+// scored, the number of the others is returned with the results.
+fn score_applications<'a>(
+    applications: impl Iterator<Item = &'a AppEntry>,
+    query: &str,
+    scoring: &Scoring,
+    deadline: Option<Instant>,
+) -> (Vec<SearchResult>, usize) {
+    let mut results = Vec::new();
+    let mut deferred = 0;
+    let mut over_budget = false;
+    for (index, app) in applications.enumerate() {
+        if !over_budget && index % BUDGET_CHECK_INTERVAL == 0 {
+            over_budget = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        }
+        if over_budget && !app.name.to_lowercase().starts_with(query) {
+            deferred += 1;
+            continue;
+        }
+        if let Some(breakdown) = score_application(
+            app,
+            query,
+            scoring.matcher,
+            scoring.keyword_weight,
+            scoring.exec_name_weight,
+            scoring.bonuses,
+        ) {
+            results.push(SearchResult {
+                app: app.clone(),
+                score: breakdown.total(),
+            });
+        }
+    }
+    (results, deferred)
+}
+
+// Orders by score, then by name using the collation rules of
+// `search.collation_locale`. Keys are computed once per result since
+// collation is costly.
+pub fn sort_results(results: &mut [SearchResult]) {
+    let collator = collation::current();
+    results.sort_by_cached_key(|result| (Reverse(result.score), collator.key(&result.app.name)));
+}
+
+fn recency_score(now: SystemTime, modified: SystemTime, recency_bonus: i64) -> i64 {
+    let age = now.duration_since(modified).unwrap_or_default();
+    if age >= RECENCY_WINDOW {
+        return 0;
+    }
+
+    let remaining = 1.0 - age.as_secs_f64() / RECENCY_WINDOW.as_secs_f64();
+    (recency_bonus as f64 * remaining).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    fn listed_names(query: &str) -> Vec<String> {
+        handle_path_search(query, 0, 0)
+            .into_iter()
+            .filter(|result| matches!(result.app.entry_type, EntryType::File))
+            .map(|result| result.app.name)
+            .filter(|name| name != "..")
+            .collect()
+    }
+
+    fn result(name: &str, score: i64, entry_type: EntryType) -> SearchResult {
+        SearchResult {
+            app: AppEntry {
+                id: name.to_string(),
+                name: name.to_string(),
+                entry_type,
+                ..Default::default()
+            },
+            score,
+        }
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|result| result.app.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn listings_keep_their_order_until_score_changes_apply() {
+        let mut order = ListingOrder {
+            dirty: false,
+            ids: Vec::new(),
+        };
+        let mut listed = vec![
+            result("firefox", 30, EntryType::Application),
+            result("kitty", 20, EntryType::Application),
+            result("gimp", 10, EntryType::Application),
+        ];
+        order.keep(&mut listed);
+
+        // Launching gimp ranks it first, but the open listing stays put.
+        order.dirty = true;
+        let mut rescored = vec![
+            result("gimp", 40, EntryType::Application),
+            result("firefox", 30, EntryType::Application),
+            result("kitty", 20, EntryType::Application),
+        ];
+        order.keep(&mut rescored);
+        assert_eq!(ids(&rescored), ["firefox", "kitty", "gimp"]);
+
+        // Hiding and showing the window, or another query, applies them.
+        assert!(order.apply());
+        let mut rescored = vec![
+            result("gimp", 40, EntryType::Application),
+            result("firefox", 30, EntryType::Application),
+            result("kitty", 20, EntryType::Application),
+        ];
+        order.keep(&mut rescored);
+        assert_eq!(ids(&rescored), ["gimp", "firefox", "kitty"]);
+        assert!(!order.apply());
+    }
+
+    #[test]
+    fn entries_new_to_a_kept_listing_go_last() {
+        let mut order = ListingOrder {
+            dirty: true,
+            ids: vec![String::from("firefox"), String::from("kitty")],
+        };
+        let mut results = vec![
+            result("blender", 50, EntryType::Application),
+            result("kitty", 40, EntryType::Application),
+            result("gimp", 30, EntryType::Application),
+            result("firefox", 20, EntryType::Application),
+        ];
+        order.keep(&mut results);
+        assert_eq!(ids(&results), ["firefox", "kitty", "blender", "gimp"]);
+        assert_eq!(order.ids, ["firefox", "kitty"]);
+    }
+
+    // A provider sending each batch after a delay.
+    fn fake_provider(
+        rt: &tokio::runtime::Runtime,
+        sender: &BatchSender,
+        batches: Vec<(u64, Vec<SearchResult>)>,
+    ) -> JoinHandle<()> {
+        let sender = sender.clone();
+        rt.spawn(async move {
+            for (delay, matches) in batches {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                sender.send(matches.into());
+            }
+        })
+    }
+
+    // Streams the providers and merges their batches the way the window
+    // does, returning the names of the rows and the number of batches.
+    fn stream_and_merge(
+        config: Config,
+        providers: impl FnOnce(&tokio::runtime::Runtime, &BatchSender) -> Vec<JoinHandle<()>>,
+    ) -> (Vec<String>, usize) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sender = BatchSender {
+            tx,
+            generation: 7,
+            config: Arc::new(config),
+        };
+        let handles = providers(&rt, &sender);
+        finish_after(rt.handle(), sender, handles);
+
+        let mut rows: Vec<(String, i64)> = Vec::new();
+        let mut batches = 0;
+        loop {
+            let batch = rx.blocking_recv().expect("the stream ends with Done");
+            assert_eq!(batch.generation, 7);
+            match batch.event {
+                SearchEvent::Results(results) => {
+                    batches += 1;
+                    for result in results.matches {
+                        let position = merge_position(
+                            rows.iter().map(|(_, score)| Some(*score)),
+                            result.score,
+                        );
+                        rows.insert(position, (result.app.name, result.score));
+                    }
+                }
+                SearchEvent::Complete(results) => {
+                    batches += 1;
+                    rows = results
+                        .matches
+                        .into_iter()
+                        .map(|result| (result.app.name, result.score))
+                        .collect();
+                }
+                SearchEvent::Done => break,
+            }
+        }
+        assert!(rx.blocking_recv().is_none(), "nothing is sent after Done");
+        (rows.into_iter().map(|(name, _)| name).collect(), batches)
+    }
+
+    #[test]
+    fn streamed_batches_merge_in_score_order() {
+        let (rows, batches) = stream_and_merge(Config::default(), |rt, sender| {
+            vec![
+                fake_provider(
+                    rt,
+                    sender,
+                    vec![
+                        (0, vec![result("firefox", 90, EntryType::Application)]),
+                        (30, vec![result("files", 50, EntryType::Application)]),
+                    ],
+                ),
+                fake_provider(
+                    rt,
+                    sender,
+                    vec![(
+                        10,
+                        vec![
+                            result("fish", 70, EntryType::Application),
+                            result("fonts", 10, EntryType::Application),
+                        ],
+                    )],
+                ),
+            ]
+        });
+        assert_eq!(rows, ["firefox", "fish", "files", "fonts"]);
+        assert_eq!(batches, 3);
+    }
+
+    #[test]
+    fn done_waits_for_the_slowest_provider() {
+        let (rows, _) = stream_and_merge(Config::default(), |rt, sender| {
+            vec![
+                fake_provider(
+                    rt,
+                    sender,
+                    vec![(60, vec![result("late", 5, EntryType::Value)])],
+                ),
+                fake_provider(rt, sender, Vec::new()),
+            ]
+        });
+        assert_eq!(rows, ["late"]);
+    }
+
+    #[test]
+    fn batches_leave_out_disabled_results() {
+        let mut config = Config::default();
+        config.search.enable_file_browsing = false;
+        let (rows, batches) = stream_and_merge(config, |rt, sender| {
+            vec![fake_provider(
+                rt,
+                sender,
+                vec![(
+                    0,
+                    vec![
+                        result("notes.txt", 80, EntryType::File),
+                        result("notes", 40, EntryType::Application),
+                    ],
+                )],
+            )]
+        });
+        assert_eq!(rows, ["notes"]);
+        assert_eq!(batches, 1);
+    }
+
+    #[test]
+    fn a_complete_pass_replaces_the_preview() {
+        let (rows, batches) = stream_and_merge(Config::default(), |rt, sender| {
+            let sender = sender.clone();
+            vec![rt.spawn(async move {
+                sender.send(vec![result("terminal", 40, EntryType::Application)].into());
+                sender.complete(
+                    vec![
+                        result("kitty", 60, EntryType::Application),
+                        result("terminal", 40, EntryType::Application),
+                    ]
+                    .into(),
+                );
+            })]
+        });
+        assert_eq!(rows, ["kitty", "terminal"]);
+        assert_eq!(batches, 2);
+    }
+
+    fn apps(names: &[&str]) -> Vec<AppEntry> {
+        names
+            .iter()
+            .map(|name| AppEntry {
+                id: name.to_string(),
+                name: name.to_string(),
+                path: format!("/usr/share/applications/{}.desktop", name),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn scored(
+        applications: &[AppEntry],
+        query: &str,
+        deadline: Option<Instant>,
+    ) -> (Vec<String>, usize) {
+        let matcher = SkimMatcherV2::default().smart_case();
+        let bonuses = Bonuses::new(&Config::default());
+        let scoring = Scoring {
+            matcher: &matcher,
+            keyword_weight: 0.5,
+            exec_name_weight: 0.5,
+            bonuses: &bonuses,
+        };
+        let (mut results, deferred) =
+            score_applications(applications.iter(), query, &scoring, deadline);
+        sort_results(&mut results);
+        (
+            results.into_iter().map(|result| result.app.name).collect(),
+            deferred,
+        )
+    }
+
+    #[test]
+    fn over_budget_only_prefix_matches_are_scored() {
+        let applications = apps(&["Firefox", "Fire Alarm", "Thunderbird Firefox Sync", "Files"]);
+        let expired = Some(Instant::now());
+
+        let (names, deferred) = scored(&applications, "fire", expired);
+        assert_eq!(names.len(), 2);
+        assert!(names
+            .iter()
+            .all(|name| name.to_lowercase().starts_with("fire")));
+        assert_eq!(deferred, 2);
+    }
+
+    #[test]
+    fn without_a_budget_every_application_is_scored() {
+        let applications = apps(&["Firefox", "Fire Alarm", "Thunderbird Firefox Sync", "Files"]);
+        let (names, deferred) = scored(&applications, "fire", None);
+        assert_eq!(deferred, 0);
+        assert_eq!(names.len(), 3);
+        assert!(names.contains(&String::from("Thunderbird Firefox Sync")));
+
+        let later = Some(Instant::now() + Duration::from_secs(60));
+        assert_eq!(scored(&applications, "fire", later), (names, 0));
+    }
+
+    #[test]
+    fn merged_results_go_before_notices_and_lower_scores() {
+        assert_eq!(merge_position([], 10), 0);
+        assert_eq!(merge_position([Some(30), Some(20)], 10), 2);
+        assert_eq!(merge_position([Some(30), Some(20)], 25), 1);
+        // Equal scores keep the row that arrived first.
+        assert_eq!(merge_position([Some(30), Some(20)], 20), 2);
+        assert_eq!(merge_position([Some(30), None, Some(5)], 10), 1);
+    }
+
+    #[test]
+    fn path_fragments_match_regardless_of_case() {
+        let dir = temp_dir("path-fragment-case");
+        for name in ["Documents", "downloads", "Music"] {
+            std::fs::create_dir(dir.join(name)).unwrap();
+        }
+        std::fs::write(dir.join("README.md"), "").unwrap();
+        let query = |fragment: &str| format!("{}/{}", dir.display(), fragment);
+
+        for fragment in ["doc", "Doc", "DOC"] {
+            assert_eq!(
+                listed_names(&query(fragment)),
+                ["Documents"],
+                "{}",
+                fragment
+            );
+        }
+        assert_eq!(listed_names(&query("readme")), ["README.md"]);
+        assert_eq!(listed_names(&query("Readme")), ["README.md"]);
+        assert_eq!(
+            listed_names(&query("")),
+            ["Documents", "downloads", "Music", "README.md"]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 1000 * DAY;
+
+    fn bonuses(half_life_days: f64) -> Bonuses {
+        Bonuses {
+            launch_weight: 100.0,
+            half_life: half_life_days * DAY as f64,
+            now: NOW,
+            affinity: 0.0,
+            workspace: None,
+            favorites: Vec::new(),
+            favorite_bonus: 0,
+            nodisplay_penalty: 0,
+        }
+    }
+
+    #[test]
+    fn each_launch_halves_every_half_life() {
+        let bonuses = bonuses(7.0);
+        assert_eq!(bonuses.frecency(1, NOW, &[NOW]), 100);
+        assert_eq!(bonuses.frecency(1, NOW - 7 * DAY, &[NOW - 7 * DAY]), 50);
+        assert_eq!(bonuses.frecency(1, NOW - 14 * DAY, &[NOW - 14 * DAY]), 25);
+        assert_eq!(
+            bonuses.frecency(2, NOW, &[NOW - 7 * DAY, NOW]),
+            150,
+            "launches decay separately"
+        );
+        // Launches older than the kept times decay from the oldest of them.
+        assert_eq!(bonuses.frecency(3, NOW, &[NOW - 7 * DAY, NOW]), 200);
+        assert_eq!(bonuses.frecency(2, NOW - 7 * DAY, &[]), 100);
+    }
+
+    #[test]
+    fn a_half_life_of_zero_keeps_every_launch() {
+        let bonuses = bonuses(0.0);
+        assert_eq!(bonuses.frecency(3, 0, &[0, 0, 0]), 300);
+        assert_eq!(bonuses.frecency(40, NOW - 500 * DAY, &[]), 4000);
+    }
+
+    #[test]
+    fn old_heavy_use_sinks_below_daily_use() {
+        let bonuses = bonuses(14.0);
+        let year_ago = NOW - 365 * DAY;
+        let mut recent = vec![year_ago; launcher::RECENT_LAUNCHES - 1];
+        recent.push(NOW);
+        let old = bonuses.frecency(501, NOW, &recent);
+        // Little more than today's launch, far from 501 launches' worth.
+        assert!(old < 2 * 100, "{}", old);
+
+        let daily: Vec<u64> = (0..30).map(|day| NOW - day * DAY).collect();
+        let daily = bonuses.frecency(30, NOW, &daily);
+        assert!(daily > old, "{} <= {}", daily, old);
+    }
+
+    #[test]
+    fn workspace_affinity_blends_the_two_histories() {
+        let app = AppEntry {
+            launch_count: 4,
+            recent_launches: vec![NOW; 4],
+            workspace_launches: BTreeMap::from([(
+                String::from("3"),
+                launcher::WorkspaceLaunches {
+                    count: 1,
+                    last: NOW,
+                    recent: vec![NOW],
+                },
+            )]),
+            ..Default::default()
+        };
+        for (workspace, affinity, expected) in [
+            (Some("3"), 0.0, (400, 0)),
+            (Some("3"), 0.5, (200, 50)),
+            (Some("3"), 1.0, (0, 100)),
+            (Some("1"), 0.5, (200, 0)),
+            (None, 0.5, (400, 0)),
+        ] {
+            let bonuses = Bonuses {
+                affinity,
+                workspace: workspace.map(String::from),
+                ..bonuses(0.0)
+            };
+            assert_eq!(
+                bonuses.blend(&app),
+                expected,
+                "workspace={:?} affinity={}",
+                workspace,
+                affinity
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::log;
+use std::{
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub timeout: Duration,
+    pub max_output: usize,
+}
+
+pub struct Captured {
+    pub status: ExitStatus,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    Spawn(io::Error),
+    TimedOut(Duration),
+    Cancelled,
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Spawn(e) => write!(f, "failed to start: {}", e),
+            RunError::TimedOut(timeout) => write!(f, "killed after {}ms", timeout.as_millis()),
+            RunError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+// Runs `command` in its own process group and captures its output. The whole
+// group is killed when it outlives `limits.timeout` or `cancelled` is set, and
+// output beyond `limits.max_output` bytes is read but dropped, so a process
+// that floods its output can neither block on a full pipe nor grow memory.
+pub fn run(
+    name: &str,
+    command: &mut Command,
+    limits: Limits,
+    cancelled: Option<&AtomicBool>,
+) -> Result<Captured, RunError> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(RunError::Spawn)?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| capture(name, "stdout", pipe, limits.max_output));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| capture(name, "stderr", pipe, limits.max_output));
+
+    let deadline = Instant::now() + limits.timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {}
+            Err(e) => {
+                kill_group(&mut child);
+                return Err(RunError::Spawn(e));
+            }
+        }
+        if cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Relaxed)) {
+            kill_group(&mut child);
+            return Err(RunError::Cancelled);
+        }
+        if Instant::now() >= deadline {
+            log!(
+                "{} did not exit within {:?}, killing it",
+                name,
+                limits.timeout
+            );
+            kill_group(&mut child);
+            return Err(RunError::TimedOut(limits.timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // Something the process started may still hold the pipes open, so wait
+    // for them until the deadline and then kill whatever is left.
+    let pid = child.id();
+    let collect = |reader: Option<Receiver<Vec<u8>>>| {
+        let reader = reader?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        reader.recv_timeout(remaining).ok().or_else(|| {
+            log!(
+                "{} left processes holding its output open, killing them",
+                name
+            );
+            kill_process_group(pid);
+            reader.recv().ok()
+        })
+    };
+    Ok(Captured {
+        status,
+        stdout: collect(stdout).unwrap_or_default(),
+        stderr: collect(stderr).unwrap_or_default(),
+    })
+}
+
+fn capture(
+    name: &str,
+    stream: &'static str,
+    mut pipe: impl Read + Send + 'static,
+    max_output: usize,
+) -> Receiver<Vec<u8>> {
+    let name = name.to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buffer = [0; 8192];
+        let mut truncated = false;
+        while let Ok(read) = pipe.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            let room = max_output.saturating_sub(captured.len());
+            captured.extend_from_slice(&buffer[..read.min(room)]);
+            if read > room && !truncated {
+                truncated = true;
+                log!(
+                    "Warning: {} wrote more than {} bytes to {}, truncating",
+                    name,
+                    max_output,
+                    stream
+                );
+            }
+        }
+        let _ = tx.send(captured);
+    });
+    rx
+}
+
+// The child leads its own process group, so anything it started is killed
+// along with it.
+fn kill_group(child: &mut Child) {
+    if !kill_process_group(child.id()) {
+        let _ = child.kill();
+    }
+    let _ = child.wait();
+}
+
+fn kill_process_group(group: u32) -> bool {
+    unsafe { libc::kill(-(group as libc::pid_t), libc::SIGKILL) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        timeout: Duration::from_millis(300),
+        max_output: 1024,
+    };
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[test]
+    fn output_is_captured() {
+        let output = run("echo", &mut shell("echo out; echo err >&2"), LIMITS, None).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    #[test]
+    fn hanging_scripts_are_killed_at_the_deadline() {
+        let started = Instant::now();
+        let result = run("hang", &mut shell("sleep 30"), LIMITS, None);
+        assert!(matches!(result, Err(RunError::TimedOut(timeout)) if timeout == LIMITS.timeout));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn children_holding_the_output_open_are_killed() {
+        let started = Instant::now();
+        let output = run(
+            "background",
+            &mut shell("echo started; sleep 30 &"),
+            LIMITS,
+            None,
+        );
+        assert_eq!(output.unwrap().stdout, b"started\n");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn spammy_scripts_are_truncated() {
+        let output = run(
+            "spam",
+            &mut shell("head -c 1000000 /dev/zero"),
+            LIMITS,
+            None,
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), LIMITS.max_output);
+
+        // One that never stops is both truncated and killed.
+        let started = Instant::now();
+        let result = run("yes", &mut shell("yes"), LIMITS, None);
+        assert!(matches!(result, Err(RunError::TimedOut(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn cancelling_kills_the_script() {
+        let cancelled = AtomicBool::new(true);
+        let result = run(
+            "cancelled",
+            &mut shell("sleep 30"),
+            LIMITS,
+            Some(&cancelled),
+        );
+        assert!(matches!(result, Err(RunError::Cancelled)));
+    }
+
+    #[test]
+    fn missing_programs_fail_to_start() {
+        let result = run(
+            "missing",
+            &mut Command::new("/nonexistent/hyprlauncher-test"),
+            LIMITS,
+            None,
+        );
+        assert!(matches!(result, Err(RunError::Spawn(_))));
+    }
+}
@@ -0,0 +1,11 @@
+use std::{fs, path::PathBuf};
+
+// An empty directory for one test, named after it and this process so that
+// tests running side by side don't share one.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("hyprlauncher-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
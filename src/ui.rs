@@ -1,30 +1,71 @@
 use crate::{
-    config::{Config, WindowAnchor},
-    launcher::{self, AppEntry, EntryType},
-    log, search,
+    activation_log::{self, Activation},
+    app::APPLICATION_ID,
+    bookmarks,
+    categories::CATEGORY_QUERY_PREFIX,
+    clipboard,
+    config::{Config, ConfigChanges, FolderAction, SurfaceMode, WindowAnchor},
+    dmenu, exec, files,
+    history::{self, Recall},
+    hyprland, icons,
+    keymap::{self, Action, ChordStep, Keymap, PendingChord},
+    launcher::{self, AppEntry, DesktopAction, DisplayText, EntryType, LaunchPlan},
+    log, modes, open_with, profile, query,
+    result_row::ResultRow,
+    search::{self, SearchEvent},
 };
 use gtk4::{
-    gdk::Key,
-    gio,
+    gdk, gio,
     glib::{self},
     prelude::*,
     subclass::prelude::*,
     Application, ApplicationWindow, Box as GtkBox, CssProvider, Label, ListView, Orientation,
-    ScrolledWindow, SearchEntry, SignalListItemFactory, SingleSelection,
-    STYLE_PROVIDER_PRIORITY_USER,
+    Revealer, RevealerTransitionType, ScrolledWindow, SearchEntry, SignalListItemFactory,
+    SingleSelection, STYLE_PROVIDER_PRIORITY_USER,
 };
 use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
-use std::{cell::RefCell, process::Command, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::Duration,
+};
 use tokio::runtime::Handle;
 
+const COMMITTING_CLASS: &str = "committing";
+const CONFIG_WARNING_CLASS: &str = "config-warning";
+const LAUNCH_ERROR_CLASS: &str = "launch-error";
+const MODE_CHIP_CLASS: &str = "mode-chip";
+const MARK_MISSING_CLASS: &str = "mark-missing";
+const MARK_MISSING_FLASH: Duration = Duration::from_millis(200);
+const SEARCHING_CLASS: &str = "searching";
+const SEARCHING_DELAY: Duration = Duration::from_millis(150);
+
+thread_local! {
+    // The window rules last sent to Hyprland, so reloads that leave them
+    // unchanged don't send them again.
+    static APPLIED_WINDOW_RULES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // Set while the window is mapped again for new anchors, which isn't a
+    // hide and show for the hooks and the query.
+    static REMAPPING: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Clone)]
 pub struct LauncherWindow {
     window: ApplicationWindow,
     search_entry: SearchEntry,
     list_view: ListView,
     app_data_store: Rc<RefCell<Vec<AppEntry>>>,
+    icon_preload_cancelled: Rc<Cell<bool>>,
+    marks: Rc<RefCell<HashMap<char, MarkKey>>>,
+    pending_chord: Rc<RefCell<PendingChord>>,
     rt: Handle,
 }
 
+// Identifies a row across reordering: desktop actions share the ID of their
+// application, so the command is part of the key.
+type MarkKey = (String, String);
+
 impl LauncherWindow {
     pub fn new(app: &Application, rt: Handle) -> Self {
         let window_start = std::time::Instant::now();
@@ -44,21 +85,94 @@ impl LauncherWindow {
         let window = ApplicationWindow::builder()
             .application(app)
             .title("HyprLauncher")
-            .default_width(config.window.width)
-            .default_height(config.window.height)
+            .default_width(surface_size(&config).0)
+            .default_height(surface_size(&config).1)
             .build();
 
-        window.init_layer_shell();
-        window.set_layer(Layer::Top);
-        window.set_keyboard_mode(if config.debug.disable_auto_focus {
-            KeyboardMode::OnDemand
-        } else {
-            KeyboardMode::Exclusive
-        });
-        Self::setup_window_anchoring(&window, &config);
-        Self::apply_window_margins(&window, &config);
+        // Layer shell has to be set up before the window is realized, so the
+        // surface mode can't change while running.
+        match config.window.surface_mode {
+            SurfaceMode::layer => {
+                window.init_layer_shell();
+                window.set_namespace(Some(hyprland::NAMESPACE));
+                window.set_layer(Layer::Top);
+                window.set_keyboard_mode(if config.debug.disable_auto_focus {
+                    KeyboardMode::OnDemand
+                } else {
+                    KeyboardMode::Exclusive
+                });
+                Self::setup_window_anchoring(&window, &config);
+                Self::apply_window_margins(&window, &config);
+            }
+            SurfaceMode::window => {
+                window.set_decorated(false);
+                window.set_resizable(false);
+                Self::apply_window_rules(&config);
+            }
+        }
+        Self::apply_capture_protection(&window, &config);
+
+        let launcher = Self::build(window, &config, rt, Config::load);
+
+        let css_start = std::time::Instant::now();
+        let css_provider = CssProvider::new();
+        css_provider.load_from_data(&config.get_css());
+        if let Some(native) = launcher.window.native() {
+            gtk4::style_context_add_provider_for_display(
+                &native.display(),
+                &css_provider,
+                STYLE_PROVIDER_PRIORITY_USER,
+            );
+            icons::watch_theme(&native.display());
+        }
+        icons::set_cache_limit(config.theme.icon_cache_mb);
+        log!(
+            "CSS loading and application ({:.3}ms)",
+            css_start.elapsed().as_secs_f64() * 1000.0
+        );
+
+        let initial_results = initial_results.unwrap();
+        if config.window.show_icons {
+            icons::preload(
+                initial_results
+                    .matches
+                    .iter()
+                    .map(|result| result.app.icon_name.to_string())
+                    .collect(),
+                monitor_scale_factor(),
+                launcher.icon_preload_cancelled.clone(),
+            );
+        }
+
+        if initial_results.loading {
+            refresh_when_loaded(
+                &launcher.list_view,
+                &launcher.search_entry,
+                &launcher.app_data_store,
+                launcher.rt.clone(),
+            );
+        }
+        update_results_list(
+            &launcher.list_view,
+            initial_results,
+            &launcher.app_data_store,
+            &config,
+        );
+
+        launcher.setup_signals();
+        launcher
+    }
 
+    // Builds the widgets inside the window. Rows are bound with the config
+    // `load_config` returns, asked again for every row.
+    fn build(
+        window: ApplicationWindow,
+        config: &Config,
+        rt: Handle,
+        load_config: impl Fn() -> Config + 'static,
+    ) -> Self {
         let main_box = GtkBox::new(Orientation::Vertical, 0);
+        main_box.add_css_class("launcher-frame");
         let search_entry = SearchEntry::new();
         let scrolled = ScrolledWindow::new();
 
@@ -67,135 +181,88 @@ impl LauncherWindow {
         let factory = SignalListItemFactory::new();
         let list_view = ListView::new(Some(selection_model.clone()), Some(factory.clone()));
 
-        factory.connect_setup(move |_, list_item| {
-            let config = Config::load();
-            let box_row = GtkBox::builder()
-                .orientation(Orientation::Horizontal)
-                .spacing(12)
-                .margin_start(12)
-                .margin_end(12)
-                .margin_top(6)
-                .margin_bottom(6)
-                .build();
-
-            if config.window.show_icons {
-                let icon = gtk4::Image::builder()
-                    .icon_size(gtk4::IconSize::Large)
-                    .build();
-                box_row.append(&icon);
+        let path_footer = Label::builder()
+            .halign(gtk4::Align::Start)
+            .ellipsize(gtk4::pango::EllipsizeMode::Start)
+            .xalign(0.0)
+            .visible(false)
+            .build();
+        path_footer.add_css_class("path-footer");
+
+        let row_actions = gio::SimpleActionGroup::new();
+        let custom_action = gio::SimpleAction::new("custom-action", Some(glib::VariantTy::UINT32));
+        let list_view_for_action = list_view.clone();
+        let search_entry_for_action = search_entry.clone();
+        let window_for_action = window.clone();
+        let rt_for_action = rt.clone();
+        custom_action.connect_activate(move |_, index| {
+            if let Some(index) = index.and_then(|index| index.get::<u32>()) {
+                run_custom_action(
+                    index as usize,
+                    &list_view_for_action,
+                    &search_entry_for_action,
+                    &window_for_action,
+                    &rt_for_action,
+                );
             }
+        });
+        row_actions.add_action(&custom_action);
+        list_view.insert_action_group("row", Some(&row_actions));
 
-            let text_box = GtkBox::builder()
-                .orientation(Orientation::Vertical)
-                .spacing(3)
-                .build();
+        let path_footer_for_rows = path_footer.clone();
+        let selection_for_rows = selection_model.clone();
+        factory.connect_setup(move |_, list_item| {
+            let row = ResultRow::new();
+
+            let hover_controller = gtk4::EventControllerMotion::new();
+            let footer = path_footer_for_rows.clone();
+            let list_item_weak = list_item.downgrade();
+            hover_controller.connect_enter(move |_, _, _| {
+                let path = list_item_weak
+                    .upgrade()
+                    .and_then(|list_item| list_item.item())
+                    .and_downcast::<AppEntryObject>()
+                    .map(|app_entry| app_entry.imp().path().to_string())
+                    .unwrap_or_default();
+                show_path_footer(&footer, &path);
+            });
+            row.add_controller(hover_controller);
 
-            let name_label = Label::builder()
-                .halign(gtk4::Align::Start)
-                .ellipsize(gtk4::pango::EllipsizeMode::End)
+            let context_click = gtk4::GestureClick::builder()
+                .button(gdk::BUTTON_SECONDARY)
                 .build();
-            name_label.add_css_class("app-name");
-            text_box.append(&name_label);
-
-            if config.window.show_descriptions {
-                let desc_label = Label::builder()
-                    .halign(gtk4::Align::Start)
-                    .ellipsize(gtk4::pango::EllipsizeMode::End)
-                    .build();
-                desc_label.add_css_class("app-description");
-                text_box.append(&desc_label);
-            }
-
-            if config.window.show_paths {
-                let path_label = Label::builder()
-                    .halign(gtk4::Align::Start)
-                    .ellipsize(gtk4::pango::EllipsizeMode::End)
-                    .build();
-                path_label.add_css_class("app-path");
-                text_box.append(&path_label);
-            }
+            let selection = selection_for_rows.clone();
+            let list_item_weak = list_item.downgrade();
+            context_click.connect_pressed(move |gesture, _, x, y| {
+                let Some(list_item) = list_item_weak.upgrade() else {
+                    return;
+                };
+                let (Some(app_entry), Some(row)) = (
+                    list_item.item().and_downcast::<AppEntryObject>(),
+                    gesture.widget().and_downcast::<ResultRow>(),
+                ) else {
+                    return;
+                };
+                if app_entry.is_notice() {
+                    return;
+                }
+                selection.set_selected(list_item.position());
+                show_context_menu(&row, app_entry.imp().app_entry(), x, y);
+            });
+            row.add_controller(context_click);
 
-            box_row.append(&text_box);
-            list_item.set_child(Some(&box_row));
+            list_item.set_child(Some(&row));
         });
 
         factory.connect_bind(move |_, list_item| {
-            let config = Config::load();
+            let config = load_config();
             if let Some(app_entry) = list_item.item().and_downcast::<AppEntryObject>() {
-                if let Some(box_row) = list_item.child().and_downcast::<GtkBox>() {
-                    if config.window.show_icons {
-                        if let Some(icon) = box_row.first_child().and_downcast::<gtk4::Image>() {
-                            let icon_name = app_entry.imp().icon_name();
-                            if icon_name.starts_with('/') {
-                                icon.set_from_file(Some(icon_name));
-                            } else {
-                                icon.set_icon_name(Some(icon_name));
-                            }
-                        }
-                    }
-
-                    let text_box = box_row
-                        .last_child()
-                        .and_downcast::<GtkBox>()
-                        .expect("Last child must be a GtkBox");
-
-                    let name_label = text_box
-                        .first_child()
-                        .and_downcast::<Label>()
-                        .expect("First child must be a Label");
-                    name_label.set_text(app_entry.imp().name());
-
-                    if config.window.show_descriptions {
-                        let desc = app_entry.imp().description();
-                        if !desc.is_empty() {
-                            if let Some(desc_label) = text_box
-                                .first_child()
-                                .and_then(|w| w.next_sibling())
-                                .and_downcast::<Label>()
-                            {
-                                desc_label.set_text(desc);
-                                desc_label.set_visible(true);
-                            }
-                        } else if let Some(desc_label) = text_box
-                            .first_child()
-                            .and_then(|w| w.next_sibling())
-                            .and_downcast::<Label>()
-                        {
-                            desc_label.set_visible(false);
-                        }
-                    }
+                let is_divider = app_entry.is_divider();
+                list_item.set_selectable(!is_divider);
+                list_item.set_activatable(!is_divider);
 
-                    if config.window.show_paths {
-                        let path = app_entry.imp().path();
-                        if !path.is_empty() {
-                            let path_label = if config.window.show_descriptions {
-                                text_box
-                                    .first_child()
-                                    .and_then(|w| w.next_sibling())
-                                    .and_then(|w| w.next_sibling())
-                            } else {
-                                text_box.first_child().and_then(|w| w.next_sibling())
-                            };
-
-                            if let Some(path_label) = path_label.and_downcast::<Label>() {
-                                path_label.set_text(path);
-                                path_label.set_visible(true);
-                            }
-                        } else {
-                            let path_label = if config.window.show_descriptions {
-                                text_box
-                                    .first_child()
-                                    .and_then(|w| w.next_sibling())
-                                    .and_then(|w| w.next_sibling())
-                            } else {
-                                text_box.first_child().and_then(|w| w.next_sibling())
-                            };
-
-                            if let Some(path_label) = path_label.and_downcast::<Label>() {
-                                path_label.set_visible(false);
-                            }
-                        }
-                    }
+                if let Some(row) = list_item.child().and_downcast::<ResultRow>() {
+                    row.bind(&app_entry, &config);
                 }
             }
         });
@@ -205,40 +272,61 @@ impl LauncherWindow {
         list_view.set_single_click_activate(true);
 
         scrolled.set_child(Some(&list_view));
+        if let Some(reason) = crate::config::SAFE_MODE.get() {
+            let banner = Label::builder()
+                .label(format!("Safe mode: {}. Your theme is ignored.", reason))
+                .wrap(true)
+                .xalign(0.0)
+                .build();
+            banner.add_css_class("safe-mode-banner");
+            main_box.append(&banner);
+        }
+        let mode_chip = Label::builder()
+            .halign(gtk4::Align::Start)
+            .visible(false)
+            .build();
+        mode_chip.add_css_class(MODE_CHIP_CLASS);
+        main_box.append(&mode_chip);
         if config.window.show_search {
             main_box.append(&search_entry);
         }
+        let launch_error = Label::builder()
+            .halign(gtk4::Align::Start)
+            .wrap(true)
+            .xalign(0.0)
+            .visible(false)
+            .build();
+        launch_error.add_css_class(LAUNCH_ERROR_CLASS);
+        main_box.append(&launch_error);
+        let config_warning = Label::builder()
+            .halign(gtk4::Align::Start)
+            .wrap(true)
+            .xalign(0.0)
+            .build();
+        config_warning.add_css_class(CONFIG_WARNING_CLASS);
+        show_config_warnings(&config_warning, &keymap::reload(config));
+        main_box.append(&config_warning);
         main_box.append(&scrolled);
-        window.set_child(Some(&main_box));
+        main_box.append(&path_footer);
 
-        let css_start = std::time::Instant::now();
-        let css_provider = CssProvider::new();
-        css_provider.load_from_data(&config.get_css());
-        if let Some(native) = window.native() {
-            gtk4::style_context_add_provider_for_display(
-                &native.display(),
-                &css_provider,
-                STYLE_PROVIDER_PRIORITY_USER,
-            );
-        }
-        log!(
-            "CSS loading and application ({:.3}ms)",
-            css_start.elapsed().as_secs_f64() * 1000.0
-        );
+        let list_leave_controller = gtk4::EventControllerMotion::new();
+        list_leave_controller.connect_leave(move |_| path_footer.set_visible(false));
+        list_view.add_controller(list_leave_controller);
 
-        let app_data_store = Rc::new(RefCell::new(Vec::with_capacity(50)));
-        update_results_list(&list_view, initial_results.unwrap(), &app_data_store);
+        let revealer = Revealer::builder().child(&main_box).build();
+        Self::apply_animations(&revealer, config);
+        window.set_child(Some(&revealer));
 
-        let launcher = Self {
+        Self {
             window,
             search_entry,
             list_view,
-            app_data_store,
-            rt: rt.clone(),
-        };
-
-        launcher.setup_signals();
-        launcher
+            app_data_store: Rc::new(RefCell::new(Vec::with_capacity(50))),
+            icon_preload_cancelled: Rc::new(Cell::new(false)),
+            marks: Rc::new(RefCell::new(HashMap::new())),
+            pending_chord: Rc::new(RefCell::new(PendingChord::default())),
+            rt,
+        }
     }
 
     pub fn present(&self) {
@@ -248,6 +336,9 @@ impl LauncherWindow {
             present_start.elapsed().as_secs_f64() * 1000.0
         );
 
+        if modes::current().is_some() {
+            Self::apply_mode(&self.window);
+        }
         self.window.present();
 
         if Config::load().window.show_search {
@@ -256,25 +347,77 @@ impl LauncherWindow {
     }
 
     fn setup_window_anchoring(window: &ApplicationWindow, config: &Config) {
-        let anchors = match config.window.anchor {
-            WindowAnchor::center => [false; 4],
-            WindowAnchor::top => [true, false, false, false],
-            WindowAnchor::bottom => [false, false, true, false],
-            WindowAnchor::left => [false, false, false, true],
-            WindowAnchor::right => [false, true, false, false],
-            WindowAnchor::top_left => [true, false, false, true],
-            WindowAnchor::top_right => [true, true, false, false],
-            WindowAnchor::bottom_left => [false, false, true, true],
-            WindowAnchor::bottom_right => [false, true, true, true],
-        };
-        window.set_anchors(anchors);
+        window.set_anchors(window_anchors(config));
     }
 
     fn apply_window_margins(window: &ApplicationWindow, config: &Config) {
-        window.set_margin(Edge::Top, config.window.margin_top);
-        window.set_margin(Edge::Bottom, config.window.margin_bottom);
-        window.set_margin(Edge::Left, config.window.margin_left);
-        window.set_margin(Edge::Right, config.window.margin_right);
+        let monitor = primary_monitor()
+            .map(|monitor| monitor.geometry())
+            .map(|geometry| (geometry.width(), geometry.height()));
+        let [top, right, bottom, left] = window_margins(config, monitor);
+        window.set_margin(Edge::Top, top);
+        window.set_margin(Edge::Right, right);
+        window.set_margin(Edge::Bottom, bottom);
+        window.set_margin(Edge::Left, left);
+    }
+
+    fn apply_window_rules(config: &Config) {
+        let rules = window_rules(config);
+        if APPLIED_WINDOW_RULES.with_borrow(|applied| *applied == rules) {
+            return;
+        }
+        if hyprland::window_rules(APPLICATION_ID, &rules) {
+            APPLIED_WINDOW_RULES.set(rules);
+        } else {
+            log!("Warning: the compositor did not accept the window rules, the launcher may be tiled");
+        }
+    }
+
+    fn apply_animations(revealer: &Revealer, config: &Config) {
+        let animations = &config.theme.animations;
+        if !animations.enabled {
+            revealer.set_transition_type(RevealerTransitionType::None);
+            revealer.set_reveal_child(true);
+            return;
+        }
+
+        let anchor = match config.window.surface_mode {
+            SurfaceMode::layer => &config.window.anchor,
+            SurfaceMode::window => &WindowAnchor::center,
+        };
+        revealer.set_transition_type(match anchor {
+            WindowAnchor::top | WindowAnchor::top_left | WindowAnchor::top_right => {
+                RevealerTransitionType::SlideDown
+            }
+            WindowAnchor::bottom | WindowAnchor::bottom_left | WindowAnchor::bottom_right => {
+                RevealerTransitionType::SlideUp
+            }
+            WindowAnchor::left => RevealerTransitionType::SlideRight,
+            WindowAnchor::right => RevealerTransitionType::SlideLeft,
+            WindowAnchor::center => RevealerTransitionType::Crossfade,
+        });
+        revealer.set_transition_duration(animations.duration);
+    }
+
+    fn apply_capture_protection(window: &ApplicationWindow, config: &Config) {
+        if !config.window.hide_from_capture {
+            return;
+        }
+
+        let is_layer_window = window.is_layer_window();
+        std::thread::spawn(move || {
+            let applied = if is_layer_window {
+                let rule = format!("noscreenshare, {}", hyprland::NAMESPACE);
+                hyprland::keyword("layerrule", &rule)
+            } else {
+                hyprland::window_rules(APPLICATION_ID, &[String::from("noscreenshare")])
+            };
+            if applied {
+                log!("Hiding launcher from screen capture");
+            } else {
+                log!("Warning: hide_from_capture could not be honored, the compositor did not accept the noscreenshare rule");
+            }
+        });
     }
 
     fn setup_signals(&self) {
@@ -287,20 +430,64 @@ impl LauncherWindow {
             let search_entry_for_controller = search_entry.clone();
             let list_view_for_key = self.list_view.clone();
 
+            let window_for_key = self.window.clone();
+            let search_entry_for_key = search_entry.clone();
+            let recall: Rc<RefCell<Option<Recall>>> = Rc::new(RefCell::new(None));
+            let recall_for_key = recall.clone();
+
             let key_controller = gtk4::EventControllerKey::new();
-            key_controller.connect_key_pressed(move |_, key, _, _| match key {
-                Key::Up => {
-                    select_previous(&list_view_for_key);
-                    glib::Propagation::Stop
-                }
-                Key::Down => {
-                    select_next(&list_view_for_key);
-                    glib::Propagation::Stop
+            key_controller.connect_key_pressed(move |_, key, _, modifiers| {
+                match keymap::current().match_event(key, modifiers) {
+                    Some(Action::SelectPrevious) => {
+                        if key != gdk::Key::Up
+                            || !recall_older(
+                                &search_entry_for_key,
+                                &list_view_for_key,
+                                &recall_for_key,
+                            )
+                        {
+                            select_previous(&list_view_for_key)
+                        }
+                    }
+                    Some(Action::SelectNext) => {
+                        if key != gdk::Key::Down
+                            || !recall_newer(&search_entry_for_key, &recall_for_key)
+                        {
+                            select_next(&list_view_for_key)
+                        }
+                    }
+                    Some(Action::Close) => {
+                        search_entry_for_key.set_text("");
+                        hide_window(&window_for_key);
+                    }
+                    _ => return glib::Propagation::Proceed,
                 }
-                _ => glib::Propagation::Proceed,
+                glib::Propagation::Stop
             });
             search_entry_for_controller.add_controller(key_controller);
 
+            let search_entry_for_back = search_entry.clone();
+            let back_controller = gtk4::EventControllerKey::new();
+            back_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+            back_controller.connect_key_pressed(move |_, key, _, modifiers| {
+                let modifiers = modifiers & keymap::MODIFIER_MASK;
+                let back = match key {
+                    gdk::Key::BackSpace => modifiers.is_empty(),
+                    gdk::Key::Left => modifiers == gdk::ModifierType::ALT_MASK,
+                    _ => false,
+                };
+                if back
+                    && search_entry_for_back
+                        .text()
+                        .starts_with(CATEGORY_QUERY_PREFIX)
+                {
+                    search_entry_for_back.set_text("");
+                    return glib::Propagation::Stop;
+                }
+                glib::Propagation::Proceed
+            });
+            search_entry_for_controller.add_controller(back_controller);
+
             let focus_controller = gtk4::EventControllerFocus::new();
 
             focus_controller.connect_enter(move |_| {
@@ -315,96 +502,150 @@ impl LauncherWindow {
 
             let list_view_for_search = self.list_view.clone();
             let app_data_store_for_search = self.app_data_store.clone();
+            let pending_results = Rc::new(RefCell::new(PendingResults::default()));
+            let search_generation = Rc::new(Cell::new(0u64));
+            let icon_preload_cancelled = self.icon_preload_cancelled.clone();
+            let marks_for_search = self.marks.clone();
             let rt_handle = self.rt.clone();
 
             self.search_entry.connect_changed(move |entry| {
+                // Any change other than stepping through the history, such as
+                // typing, ends stepping.
+                let stepping = recall
+                    .borrow()
+                    .as_ref()
+                    .is_some_and(|recall| recall.current() == entry.text());
+                if !stepping {
+                    recall.replace(None);
+                }
+                if let Some(launch_error) = entry
+                    .parent()
+                    .and_then(|parent| launch_error_label(&parent))
+                {
+                    launch_error.set_visible(false);
+                }
+                icon_preload_cancelled.set(true);
+                marks_for_search.borrow_mut().clear();
                 let query = entry.text().to_string();
                 let list_view = list_view_for_search.clone();
                 let app_data_store = app_data_store_for_search.clone();
+                let pending_results = pending_results.clone();
+                let search_generation = search_generation.clone();
                 let rt_handle = rt_handle.clone();
 
-                glib::MainContext::default().spawn_local(async move {
-                    let config = Config::load();
-                    let results = rt_handle
-                        .spawn(async move { search::search_applications(&query, &config).await })
-                        .await
-                        .unwrap()
-                        .unwrap_or_default();
-                    update_results_list(&list_view, results, &app_data_store);
+                let generation = search_generation.get() + 1;
+                search_generation.set(generation);
+
+                let entry = entry.clone();
+                let finished = Rc::new(Cell::new(false));
+                let generation_for_progress = search_generation.clone();
+                let finished_for_progress = finished.clone();
+                let entry_for_progress = entry.clone();
+                glib::timeout_add_local_once(SEARCHING_DELAY, move || {
+                    if generation_for_progress.get() == generation && !finished_for_progress.get() {
+                        entry_for_progress.add_css_class(SEARCHING_CLASS);
+                    }
                 });
-            });
-
-            let window_for_search = self.window.clone();
-            let search_entry_for_search = self.search_entry.clone();
-            let search_controller = gtk4::EventControllerKey::new();
 
-            search_controller.connect_key_pressed(move |_, key, _, _| {
-                let window = window_for_search.clone();
-                let search_entry = search_entry_for_search.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    let mut batches =
+                        search::stream_search(&rt_handle, query, Config::load(), generation);
+                    let mut first_batch = true;
+
+                    while let Some(batch) = batches.recv().await {
+                        if search_generation.get() != batch.generation {
+                            log!("Discarding results of superseded search");
+                            return;
+                        }
 
-                match key {
-                    Key::Escape => {
-                        search_entry.set_text("");
-                        window.hide();
-                        glib::Propagation::Stop
+                        // Set here rather than by the search, so a superseded
+                        // one finishing last can't flip it.
+                        if let SearchEvent::Results(results) | SearchEvent::Complete(results) =
+                            &batch.event
+                        {
+                            files::set_browsing(results.browsing);
+                        }
+                        let update = match batch.event {
+                            SearchEvent::Results(results) if first_batch => {
+                                first_batch = false;
+                                ResultUpdate::Replace(results)
+                            }
+                            SearchEvent::Results(results) => ResultUpdate::Merge(results),
+                            SearchEvent::Complete(results) => {
+                                first_batch = false;
+                                ResultUpdate::Replace(results)
+                            }
+                            SearchEvent::Done => {
+                                finished.set(true);
+                                entry.remove_css_class(SEARCHING_CLASS);
+                                ResultUpdate::Done
+                            }
+                        };
+                        queue_results_update(&list_view, update, &app_data_store, &pending_results);
                     }
-                    _ => glib::Propagation::Proceed,
-                }
+                });
             });
-            self.search_entry.add_controller(search_controller);
         }
 
-        let list_view_for_window = self.list_view.clone();
-        let window_for_window = self.window.clone();
-        let search_entry_for_window = self.search_entry.clone();
+        // The key completing a chord is taken before the search entry can
+        // type it.
+        let launcher = self.clone();
+        let chord_controller = gtk4::EventControllerKey::new();
+        chord_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        chord_controller.connect_key_pressed(move |_, key, _, _| launcher.feed_chord(key));
+        self.window.add_controller(chord_controller);
+
+        // Delete would otherwise edit the search before the window sees it.
+        let launcher = self.clone();
+        let bookmark_controller = gtk4::EventControllerKey::new();
+        bookmark_controller.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        bookmark_controller.connect_key_pressed(
+            move |_, key, _, modifiers| match keymap::current().match_event(key, modifiers) {
+                Some(Action::RemoveBookmark) => launcher.run_action(Some(Action::RemoveBookmark)),
+                _ => glib::Propagation::Proceed,
+            },
+        );
+        self.window.add_controller(bookmark_controller);
 
+        let launcher = self.clone();
         let window_controller = gtk4::EventControllerKey::new();
-        window_controller.connect_key_pressed(move |_, key, _, _| {
-            let config = Config::load();
-            let list_view = list_view_for_window.clone();
-            let window = window_for_window.clone();
-            let search_entry = search_entry_for_window.clone();
-
-            match key.name().as_deref() {
-                Some(key_name) => {
-                    if key_name == config.window.custom_navigate_keys.up {
-                        select_previous(&list_view);
-                        glib::Propagation::Stop
-                    } else if key_name == config.window.custom_navigate_keys.down {
-                        select_next(&list_view);
-                        glib::Propagation::Stop
-                    } else if key_name == config.window.custom_navigate_keys.delete_word {
-                        let text = search_entry.text();
-                        let cursor_pos = search_entry.position() as usize;
-                        if let Some((new_text, new_pos)) = delete_word(&text, cursor_pos) {
-                            search_entry.set_text(&new_text);
-                            search_entry.set_position(new_pos as i32);
-                        }
-                        glib::Propagation::Stop
-                    } else {
-                        match key {
-                            Key::Escape => {
-                                window.hide();
-                                glib::Propagation::Stop
-                            }
-                            _ => glib::Propagation::Proceed,
-                        }
-                    }
-                }
-                None => glib::Propagation::Proceed,
-            }
+        window_controller.connect_key_pressed(move |_, key, _, modifiers| {
+            launcher.handle_key(&keymap::current(), key, modifiers)
         });
         self.window.add_controller(window_controller);
 
         let window_for_row = self.window.clone();
         let search_entry_for_row = self.search_entry.clone();
+        let rt_for_row = self.rt.clone();
 
         self.list_view.connect_activate(move |list_view, position| {
+            if window_for_row.has_css_class(COMMITTING_CLASS) {
+                return;
+            }
             if let Some(model) = list_view.model() {
                 if let Some(item) = model.item(position) {
                     if let Some(app_entry) = item.downcast_ref::<AppEntryObject>() {
-                        if launch_application(app_entry.imp().app_entry(), &search_entry_for_row) {
-                            window_for_row.hide();
+                        if app_entry.is_expander() {
+                            expand_hidden_matches(
+                                list_view,
+                                position,
+                                Config::load().window.max_entries,
+                            );
+                        } else if dmenu::is_active() {
+                            if !app_entry.is_notice() {
+                                accept_dmenu(
+                                    Some(app_entry.imp().app_entry()),
+                                    &search_entry_for_row,
+                                    &window_for_row,
+                                    None,
+                                );
+                            }
+                        } else if let Some(launch) = launch_application(
+                            app_entry.imp().app_entry(),
+                            &search_entry_for_row,
+                            Config::load().files.enter_action,
+                        ) {
+                            commit_launch(launch, &window_for_row, &rt_for_row);
                         }
                     }
                 }
@@ -414,143 +655,777 @@ impl LauncherWindow {
         let list_view_for_activate = self.list_view.clone();
         let window_for_activate = self.window.clone();
         let search_entry_for_activate = self.search_entry.clone();
+        let rt_for_activate = self.rt.clone();
 
         self.search_entry.connect_activate(move |_| {
+            if window_for_activate.has_css_class(COMMITTING_CLASS) {
+                return;
+            }
+            if dmenu::is_active() {
+                let selected =
+                    get_selected_item(&list_view_for_activate).filter(|item| !item.is_notice());
+                if !selected.as_ref().is_some_and(AppEntryObject::is_expander) {
+                    accept_dmenu(
+                        selected.as_ref().map(|item| item.imp().app_entry()),
+                        &search_entry_for_activate,
+                        &window_for_activate,
+                        None,
+                    );
+                    return;
+                }
+            }
             if let Some(selected) = get_selected_item(&list_view_for_activate) {
                 if let Some(app_entry) = selected.downcast_ref::<AppEntryObject>() {
-                    if launch_application(app_entry.imp().app_entry(), &search_entry_for_activate) {
-                        window_for_activate.hide();
+                    if app_entry.is_expander() {
+                        if let Some(selection_model) = list_view_for_activate
+                            .model()
+                            .and_downcast::<SingleSelection>()
+                        {
+                            expand_hidden_matches(
+                                &list_view_for_activate,
+                                selection_model.selected(),
+                                Config::load().window.max_entries,
+                            );
+                        }
+                    } else if let Some(launch) = launch_application(
+                        app_entry.imp().app_entry(),
+                        &search_entry_for_activate,
+                        Config::load().files.enter_action,
+                    ) {
+                        commit_launch(launch, &window_for_activate, &rt_for_activate);
                     }
                 }
             }
         });
 
+        self.window.connect_show(|window| {
+            if REMAPPING.get() {
+                return;
+            }
+            window.remove_css_class(COMMITTING_CLASS);
+            if let Some(launch_error) = window
+                .child()
+                .and_downcast::<Revealer>()
+                .and_then(|revealer| revealer.child())
+                .and_then(|main_box| launch_error_label(&main_box))
+            {
+                launch_error.set_visible(false);
+            }
+            if search::apply_score_changes() {
+                Self::refresh_results(window);
+            }
+            let config = Config::load();
+            launcher::run_hook(&config.hooks.on_show, &[]);
+            if config.window.restore_focus {
+                std::thread::spawn(hyprland::remember_active_window);
+            }
+        });
+
+        self.window.connect_map(|window| {
+            if let Some(revealer) = window.child().and_downcast::<Revealer>() {
+                revealer.set_reveal_child(true);
+            }
+        });
+
         let search_entry_for_hide = self.search_entry.clone();
-        self.window.connect_hide(move |_| {
+        self.window.connect_hide(move |window| {
+            if REMAPPING.get() {
+                return;
+            }
+            open_with::clear();
+            files::set_browsing(false);
+            if modes::clear().is_some() {
+                Self::apply_mode(window);
+            }
             search_entry_for_hide.set_text("");
             search_entry_for_hide.grab_focus();
+            icons::report_usage();
+            files::report_usage();
+            let config = Config::load();
+            if let Some(revealer) = window.child().and_downcast::<Revealer>() {
+                if config.theme.animations.enabled {
+                    revealer.set_reveal_child(false);
+                }
+            }
+            launcher::run_hook(&config.hooks.on_hide, &[]);
+            if config.window.restore_focus {
+                std::thread::spawn(hyprland::restore_focus);
+            }
+            // A dmenu instance ends with its window, once a value it copied
+            // has been read.
+            if dmenu::is_active() {
+                if let Some(app) = window.application() {
+                    clipboard::when_settled(move || app.quit());
+                }
+            }
         });
     }
 
-    pub fn update_window_config(window: &ApplicationWindow, config: &Config) {
-        window.set_default_width(config.window.width);
-        window.set_default_height(config.window.height);
-        window.set_keyboard_mode(if config.debug.disable_auto_focus {
-            KeyboardMode::OnDemand
-        } else {
-            KeyboardMode::Exclusive
-        });
-
-        Self::setup_window_anchoring(window, config);
-        Self::apply_window_margins(window, config);
-
-        if let Some(native) = window.native() {
-            let css_provider = CssProvider::new();
-            css_provider.load_from_data(&config.get_css());
-            gtk4::style_context_add_provider_for_display(
-                &native.display(),
-                &css_provider,
-                STYLE_PROVIDER_PRIORITY_USER,
-            );
+    fn feed_chord(&self, key: gdk::Key) -> glib::Propagation {
+        match self.pending_chord.borrow_mut().feed(key) {
+            ChordStep::Idle => return glib::Propagation::Proceed,
+            ChordStep::Completed(Action::SetMark, letter) => {
+                set_mark(&self.list_view, &self.marks, letter)
+            }
+            ChordStep::Completed(Action::JumpToMark, letter) => {
+                jump_to_mark(&self.list_view, &self.marks, letter)
+            }
+            ChordStep::Completed(action, _) | ChordStep::Cancelled(action) => {
+                log!("Cancelled {}", action.name());
+            }
+            ChordStep::Pending => {}
         }
+        glib::Propagation::Stop
+    }
 
-        if let Some(main_box) = window.first_child() {
-            if let Some(main_box) = main_box.downcast_ref::<gtk4::Box>() {
-                let search_entry = main_box
-                    .first_child()
-                    .and_then(|child| child.downcast::<gtk4::SearchEntry>().ok());
+    // The list has extra bindings on top of the window's, for keys the search
+    // entry would otherwise type.
+    fn handle_key(
+        &self,
+        keymap: &Keymap,
+        key: gdk::Key,
+        modifiers: gdk::ModifierType,
+    ) -> glib::Propagation {
+        let list_focused = self.list_view.has_focus() || self.list_view.focus_child().is_some();
+        let action = if list_focused {
+            keymap.match_list_event(key, modifiers)
+        } else {
+            keymap.match_event(key, modifiers)
+        };
+        self.run_action(action)
+    }
 
-                if let Some(search_entry) = search_entry {
-                    if config.window.show_search {
-                        search_entry.set_visible(true);
-                        search_entry.set_text("__config_reload__");
-                        search_entry.set_text("");
-                    } else {
-                        search_entry.set_visible(false);
-                    }
+    fn run_action(&self, action: Option<Action>) -> glib::Propagation {
+        let list_view = &self.list_view;
+        let search_entry = &self.search_entry;
+        let window = &self.window;
+        let rt = &self.rt;
+
+        match action {
+            Some(Action::SelectPrevious) => select_previous(list_view),
+            Some(Action::SelectNext) => select_next(list_view),
+            Some(Action::DeleteWord) => {
+                let text = search_entry.text();
+                let cursor_pos = search_entry.position() as usize;
+                if let Some((new_text, new_pos)) = query::delete_word(&text, cursor_pos) {
+                    search_entry.set_text(&new_text);
+                    search_entry.set_position(new_pos as i32);
                 }
-
-                if let Some(scrolled) = main_box.last_child().and_downcast::<ScrolledWindow>() {
-                    if let Some(list_view) = scrolled.child().and_downcast::<ListView>() {
-                        if let Some(selection_model) =
-                            list_view.model().and_downcast::<SingleSelection>()
-                        {
-                            if let Some(model) =
-                                selection_model.model().and_downcast::<gio::ListStore>()
-                            {
-                                let items: Vec<_> =
-                                    (0..model.n_items()).filter_map(|i| model.item(i)).collect();
-                                model.remove_all();
-                                for item in items {
-                                    model.append(&item);
-                                }
-                            }
-                        }
-                    }
+            }
+            Some(Action::EditDesktopFile) => edit_selected_desktop_file(list_view, window),
+            Some(Action::TogglePin) => toggle_selected_pin(list_view, search_entry),
+            Some(Action::ToggleBookmark) => {
+                if !toggle_selected_bookmark(list_view, search_entry) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(Action::DeleteHistoryEntry) => {
+                if !delete_selected_history_entry(list_view, search_entry) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(Action::RemoveBookmark) => {
+                if !remove_selected_bookmark(list_view, search_entry) {
+                    return glib::Propagation::Proceed;
                 }
             }
+            Some(Action::ToggleActions) => {
+                if !toggle_desktop_actions(list_view) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(Action::AcceptCustom(index)) if dmenu::is_active() => {
+                let selected = get_selected_item(list_view).filter(|item| !item.is_notice());
+                accept_dmenu(
+                    selected.as_ref().map(|item| item.imp().app_entry()),
+                    search_entry,
+                    window,
+                    Some(index),
+                );
+            }
+            Some(Action::AcceptCustom(index)) => {
+                if !run_custom_action(index, list_view, search_entry, window, rt) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(action @ (Action::DescendFolder | Action::OpenFolder)) => {
+                let folder_action = match action {
+                    Action::OpenFolder => FolderAction::open,
+                    _ => FolderAction::descend,
+                };
+                if !activate_selected_folder(list_view, search_entry, window, rt, folder_action) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(Action::OpenTerminal) => {
+                if !open_terminal_in_selected(list_view, search_entry, window, rt) {
+                    return glib::Propagation::Proceed;
+                }
+            }
+            Some(action @ (Action::SetMark | Action::JumpToMark)) => {
+                self.pending_chord.borrow_mut().begin(action);
+            }
+            Some(Action::Close) => hide_window(window),
+            None => return glib::Propagation::Proceed,
         }
+        glib::Propagation::Stop
     }
-}
-
-fn update_results_list(
-    list_view: &ListView,
-    results: Vec<search::SearchResult>,
-    store: &Rc<RefCell<Vec<AppEntry>>>,
-) {
-    if let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() {
-        if let Some(model) = selection_model.model().and_downcast::<gio::ListStore>() {
-            let config = Config::load();
-            let max_entries = config.window.max_entries;
-            let mut store = store.borrow_mut();
 
-            model.remove_all();
-            store.clear();
-            store.reserve(max_entries);
+    // Hides the window without returning focus, for when the user has moved
+    // on, e.g. to another workspace.
+    pub fn dismiss(window: &impl IsA<gtk4::Window>) {
+        let Some(window) = window.dynamic_cast_ref::<ApplicationWindow>() else {
+            return;
+        };
+        if window.is_visible() {
+            hyprland::forget_active_window();
+            hide_window(window);
+        }
+    }
 
-            let results = if results.len() > max_entries {
-                &results[..max_entries]
-            } else {
-                &results
-            };
+    pub fn refresh_results(window: &impl IsA<gtk4::Window>) {
+        let main_box = window
+            .child()
+            .and_downcast::<Revealer>()
+            .and_then(|revealer| revealer.child());
+        let search_entry = main_box.and_then(|main_box| {
+            std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+                .find_map(|child| child.downcast::<SearchEntry>().ok())
+        });
 
-            store.extend(results.iter().map(|r| r.app.clone()));
-            model.extend_from_slice(
-                &results
-                    .iter()
-                    .map(|r| AppEntryObject::new(r.app.clone()))
-                    .collect::<Vec<_>>(),
-            );
+        if let Some(search_entry) = search_entry {
+            search_entry.set_text("");
+            search_entry.emit_by_name::<()>("changed", &[]);
         }
     }
-}
 
-fn select_next(list_view: &ListView) {
-    if let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() {
-        let n_items = selection_model.n_items();
-        if n_items == 0 {
+    // Shows the chip and window overrides of the current mode, or restores the
+    // configured window when no mode is set.
+    pub fn apply_mode(window: &impl IsA<gtk4::Window>) {
+        let Some(window) = window.dynamic_cast_ref::<ApplicationWindow>() else {
             return;
+        };
+        let mode = modes::current();
+        let mut config = Config::load();
+        if let Some(mode) = mode {
+            config
+                .window
+                .apply_mode_overrides(mode.window(&config.modes));
         }
-        let current_pos = selection_model.selected();
-        if current_pos < n_items - 1 {
-            let next_pos = current_pos + 1;
-            selection_model.set_selected(next_pos);
-            list_view
-                .activate_action("list.scroll-to-item", Some(&next_pos.to_variant()))
-                .unwrap_or_default();
+        Self::apply_geometry(window, &config);
+
+        let mode_chip = window
+            .child()
+            .and_downcast::<Revealer>()
+            .and_then(|revealer| revealer.child())
+            .and_then(|main_box| {
+                std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+                    .find(|child| child.has_css_class(MODE_CHIP_CLASS))
+            })
+            .and_downcast::<Label>();
+        if let Some(mode_chip) = mode_chip {
+            mode_chip.set_label(mode.map_or("", |mode| mode.label()));
+            mode_chip.set_visible(mode.is_some());
         }
+
+        Self::refresh_results(window);
     }
-}
 
-fn select_previous(list_view: &ListView) {
+    fn apply_geometry(window: &ApplicationWindow, config: &Config) {
+        let (width, height) = surface_size(config);
+        window.set_default_width(width);
+        window.set_default_height(height);
+        if window.is_layer_window() {
+            Self::setup_window_anchoring(window, config);
+            Self::apply_window_margins(window, config);
+        } else {
+            Self::apply_window_rules(config);
+        }
+    }
+
+    pub fn update_window_config(
+        window: &ApplicationWindow,
+        config: &Config,
+        changes: ConfigChanges,
+    ) {
+        log!("Applying config changes: {:?}", changes);
+
+        if window.is_layer_window() != (config.window.surface_mode == SurfaceMode::layer) {
+            log!("window.surface_mode takes effect after a restart");
+        }
+
+        if changes.geometry {
+            Self::apply_geometry(window, config);
+        }
+        if changes.anchors && window.is_layer_window() {
+            // Hyprland moves a mapped layer surface for new anchors only once
+            // it is mapped again.
+            Self::setup_window_anchoring(window, config);
+            Self::apply_window_margins(window, config);
+            // The slide direction follows the anchor.
+            if let Some(revealer) = window.child().and_downcast::<Revealer>() {
+                Self::apply_animations(&revealer, config);
+            }
+            if window.is_visible() {
+                REMAPPING.set(true);
+                window.set_visible(false);
+                window.present();
+                REMAPPING.set(false);
+            }
+        } else if changes.margins && window.is_layer_window() {
+            // Margins are layer surface state that applies on the next
+            // commit, so a mapped window moves without being remapped.
+            Self::apply_window_margins(window, config);
+        }
+        if changes.surface_only() {
+            return;
+        }
+
+        if changes.behavior {
+            if window.is_layer_window() {
+                window.set_keyboard_mode(if config.debug.disable_auto_focus {
+                    KeyboardMode::OnDemand
+                } else {
+                    KeyboardMode::Exclusive
+                });
+            }
+            Self::apply_capture_protection(window, config);
+            icons::set_cache_limit(config.theme.icon_cache_mb);
+        }
+        // Key bindings are part of the behavior changes.
+        let keymap = changes.behavior.then(|| keymap::reload(config));
+
+        if changes.theme {
+            if let Some(native) = window.native() {
+                let css_provider = CssProvider::new();
+                css_provider.load_from_data(&config.get_css());
+                gtk4::style_context_add_provider_for_display(
+                    &native.display(),
+                    &css_provider,
+                    STYLE_PROVIDER_PRIORITY_USER,
+                );
+            }
+        }
+
+        let revealer = window.child().and_downcast::<Revealer>();
+        if let Some(revealer) = revealer
+            .as_ref()
+            .filter(|_| changes.geometry || changes.behavior)
+        {
+            Self::apply_animations(revealer, config);
+        }
+
+        let Some(main_box) = revealer
+            .and_then(|revealer| revealer.child())
+            .and_downcast::<gtk4::Box>()
+        else {
+            return;
+        };
+
+        if let Some(keymap) = keymap {
+            if let Some(config_warning) =
+                std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+                    .filter(|child| child.has_css_class(CONFIG_WARNING_CLASS))
+                    .find_map(|child| child.downcast::<Label>().ok())
+            {
+                show_config_warnings(&config_warning, &keymap);
+            }
+        }
+
+        let search_entry =
+            std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+                .find_map(|child| child.downcast::<gtk4::SearchEntry>().ok());
+
+        if let Some(search_entry) = search_entry {
+            if !config.window.show_search {
+                search_entry.set_visible(false);
+            } else if changes.search {
+                search_entry.set_visible(true);
+                search::apply_score_changes();
+                search_entry.set_text("__config_reload__");
+                search_entry.set_text("");
+            } else if changes.favorites {
+                search_entry.set_visible(true);
+                search::apply_score_changes();
+                search_entry.emit_by_name::<()>("changed", &[]);
+            } else {
+                search_entry.set_visible(true);
+            }
+        }
+
+        if !config.window.hover_path_footer {
+            if let Some(path_footer) = main_box.last_child().and_downcast::<Label>() {
+                path_footer.set_visible(false);
+            }
+        }
+
+        if !changes.search {
+            return;
+        }
+
+        let scrolled = std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+            .find_map(|child| child.downcast::<ScrolledWindow>().ok());
+        if let Some(model) = scrolled
+            .and_then(|scrolled| scrolled.child())
+            .and_downcast::<ListView>()
+            .and_then(|list_view| list_view.model())
+            .and_downcast::<SingleSelection>()
+            .and_then(|selection_model| selection_model.model())
+            .and_downcast::<gio::ListStore>()
+        {
+            let items: Vec<_> = (0..model.n_items()).filter_map(|i| model.item(i)).collect();
+            model.remove_all();
+            for item in items {
+                model.append(&item);
+            }
+        }
+    }
+}
+
+fn primary_monitor() -> Option<gtk4::gdk::Monitor> {
+    gtk4::gdk::Display::default()
+        .and_then(|display| display.monitors().item(0))
+        .and_downcast::<gtk4::gdk::Monitor>()
+}
+
+fn monitor_scale_factor() -> i32 {
+    primary_monitor()
+        .map(|monitor| monitor.scale_factor())
+        .unwrap_or(1)
+}
+
+fn hide_window(window: &ApplicationWindow) {
+    let Some(revealer) = window.child().and_downcast::<Revealer>() else {
+        window.hide();
+        return;
+    };
+
+    if !Config::load().theme.animations.enabled || !revealer.is_child_revealed() {
+        window.hide();
+        return;
+    }
+
+    let handler = Rc::new(RefCell::new(None));
+    let handler_for_notify = handler.clone();
+    let window = window.clone();
+    let id = revealer.connect_child_revealed_notify(move |revealer| {
+        if !revealer.is_child_revealed() {
+            window.hide();
+            if let Some(id) = handler_for_notify.borrow_mut().take() {
+                revealer.disconnect(id);
+            }
+        }
+    });
+    handler.replace(Some(id));
+    revealer.set_reveal_child(false);
+}
+
+fn refresh_when_loaded(
+    list_view: &ListView,
+    search_entry: &SearchEntry,
+    store: &Rc<RefCell<Vec<AppEntry>>>,
+    rt: Handle,
+) {
+    let list_view = list_view.clone();
+    let search_entry = search_entry.clone();
+    let store = store.clone();
+
+    glib::MainContext::default().spawn_local(async move {
+        let _ = rt.spawn(launcher::wait_until_loaded()).await;
+        log!("Applications loaded, refreshing results");
+
+        let query = search_entry.text().to_string();
+        let config = Config::load();
+        let results = rt
+            .spawn(async move { search::search_applications(&query, &config).await })
+            .await
+            .unwrap()
+            .unwrap_or_default();
+        update_results_list(&list_view, results, &store, &Config::load());
+    });
+}
+
+enum ResultUpdate {
+    Replace(search::SearchResults),
+    Merge(search::SearchResults),
+    Done,
+}
+
+// The updates to apply at the next frame.
+#[derive(Default)]
+struct QueuedResults {
+    replace: Option<search::SearchResults>,
+    merges: Vec<search::SearchResults>,
+    done: bool,
+}
+
+// Updates arriving within one frame are coalesced: a replacement drops the
+// replacement and merges queued before it, so only the newest results are
+// applied. `dropped` and `frames` count over the window's lifetime.
+#[derive(Default)]
+struct PendingResults {
+    queued: QueuedResults,
+    scheduled: bool,
+    dropped: u64,
+    frames: u64,
+}
+
+impl PendingResults {
+    // Returns whether a frame has to be asked for to apply the update.
+    fn push(&mut self, update: ResultUpdate) -> bool {
+        match update {
+            ResultUpdate::Replace(results) => {
+                let superseded = usize::from(self.queued.replace.replace(results).is_some())
+                    + self.queued.merges.len();
+                if superseded > 0 {
+                    self.dropped += superseded as u64;
+                    log!(
+                        "Dropping {} result updates superseded within the same frame, {} so far",
+                        superseded,
+                        self.dropped
+                    );
+                }
+                self.queued.merges.clear();
+                self.queued.done = false;
+            }
+            ResultUpdate::Merge(results) => self.queued.merges.push(results),
+            ResultUpdate::Done => self.queued.done = true,
+        }
+        !std::mem::replace(&mut self.scheduled, true)
+    }
+
+    fn take(&mut self) -> QueuedResults {
+        self.scheduled = false;
+        self.frames += 1;
+        std::mem::take(&mut self.queued)
+    }
+}
+
+fn queue_results_update(
+    list_view: &ListView,
+    update: ResultUpdate,
+    store: &Rc<RefCell<Vec<AppEntry>>>,
+    pending: &Rc<RefCell<PendingResults>>,
+) {
+    if !pending.borrow_mut().push(update) {
+        return;
+    }
+
+    let store = store.clone();
+    let pending = pending.clone();
+    list_view.add_tick_callback(move |list_view, _| {
+        let queued = pending.borrow_mut().take();
+        if let Some(results) = queued.replace {
+            update_results_list(list_view, results, &store, &Config::load());
+        }
+        for results in queued.merges {
+            merge_results(list_view, results, &store);
+        }
+        if queued.done {
+            show_empty_placeholder(list_view);
+            let pending = pending.borrow();
+            profile::report(
+                "result updates",
+                format_args!("{} frames, {} dropped", pending.frames, pending.dropped),
+            );
+        }
+        glib::ControlFlow::Break
+    });
+}
+
+fn update_results_list(
+    list_view: &ListView,
+    results: search::SearchResults,
+    store: &Rc<RefCell<Vec<AppEntry>>>,
+    config: &Config,
+) {
+    if let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() {
+        if let Some(model) = selection_model.model().and_downcast::<gio::ListStore>() {
+            let max_entries = config.window.max_entries;
+            let mut store = store.borrow_mut();
+
+            model.remove_all();
+            store.clear();
+            // Browsing a large directory can leave room for many more rows
+            // than a regular search needs.
+            if !files::is_browsing() && store.capacity() > max_entries {
+                log!(
+                    "Shrinking the result store from {} to {} entries",
+                    store.capacity(),
+                    max_entries
+                );
+                store.shrink_to(max_entries);
+            }
+            store.reserve(max_entries);
+
+            let matches: Vec<_> = results
+                .matches
+                .into_iter()
+                .filter(|r| search::is_allowed(&r.app, config))
+                .take(max_entries)
+                .collect();
+            let hidden: Vec<_> = results
+                .hidden
+                .into_iter()
+                .filter(|r| search::is_allowed(&r.app, config))
+                .map(|r| r.app)
+                .collect();
+
+            store.extend(matches.iter().map(|r| r.app.clone()));
+            let mut items: Vec<_> = matches
+                .into_iter()
+                .map(|r| AppEntryObject::scored(r.app, r.score))
+                .collect();
+            if !hidden.is_empty() {
+                items.push(AppEntryObject::expander(hidden));
+            }
+            if results.loading {
+                items.push(AppEntryObject::loading());
+            }
+            model.extend_from_slice(&items);
+        }
+    }
+}
+
+fn merge_results(
+    list_view: &ListView,
+    results: search::SearchResults,
+    store: &Rc<RefCell<Vec<AppEntry>>>,
+) {
+    let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() else {
+        return;
+    };
+    let Some(model) = selection_model.model().and_downcast::<gio::ListStore>() else {
+        return;
+    };
+
+    let user_selection = (selection_model.selected() > 0)
+        .then(|| selection_model.selected_item())
+        .flatten();
+    let mut store = store.borrow_mut();
+
+    // Batches were filtered by search::stream_search already.
+    for result in results.matches {
+        let rows = (0..model.n_items()).map(|i| {
+            model
+                .item(i)
+                .and_downcast::<AppEntryObject>()
+                .filter(|item| !item.is_notice())
+                .map(|item| item.score())
+        });
+        let position = search::merge_position(rows, result.score) as u32;
+        store.push(result.app.clone());
+        model.insert(position, &AppEntryObject::scored(result.app, result.score));
+    }
+
+    let hidden: Vec<_> = results.hidden.into_iter().map(|r| r.app).collect();
+    if !hidden.is_empty() {
+        model.append(&AppEntryObject::expander(hidden));
+    }
+    if results.loading {
+        model.append(&AppEntryObject::loading());
+    }
+
+    match user_selection.and_then(|item| model.find(&item)) {
+        Some(position) => selection_model.set_selected(position),
+        None if model.n_items() > 0 => selection_model.set_selected(0),
+        None => {}
+    }
+}
+
+fn show_empty_placeholder(list_view: &ListView) {
+    let model = list_view
+        .model()
+        .and_downcast::<SingleSelection>()
+        .and_then(|selection_model| selection_model.model())
+        .and_downcast::<gio::ListStore>();
+
+    if let Some(model) = model.filter(|model| model.n_items() == 0) {
+        model.append(&AppEntryObject::notice("No results", "edit-find-symbolic"));
+    }
+}
+
+fn expand_hidden_matches(list_view: &ListView, position: u32, page_size: usize) {
+    let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() else {
+        return;
+    };
+    let Some(model) = selection_model.model().and_downcast::<gio::ListStore>() else {
+        return;
+    };
+    let Some(expander) = model.item(position).and_downcast::<AppEntryObject>() else {
+        return;
+    };
+
+    let mut hidden = expander.imp().hidden.take();
+    let remaining = hidden.split_off(hidden.len().min(page_size));
+
+    let expanded_before = (0..position)
+        .filter_map(|i| model.item(i).and_downcast::<AppEntryObject>())
+        .any(|item| item.is_divider());
+    let mut items = Vec::with_capacity(hidden.len() + 2);
+    if !expanded_before {
+        items.push(AppEntryObject::divider());
+    }
+    let first_page_item = position + items.len() as u32;
+    items.extend(hidden.into_iter().map(AppEntryObject::new));
+    if !remaining.is_empty() {
+        items.push(AppEntryObject::expander(remaining));
+    }
+
+    model.splice(position, 1, &items);
+    selection_model.set_selected(first_page_item);
+    list_view
+        .activate_action("list.scroll-to-item", Some(&first_page_item.to_variant()))
+        .unwrap_or_default();
+}
+
+// Problems with the key bindings are shown until the config is fixed, since a
+// binding that silently does nothing is hard to track down.
+fn show_config_warnings(label: &Label, keymap: &Keymap) {
+    let problems = keymap.problems();
+    label.set_label(&problems.join("\n"));
+    label.set_visible(!problems.is_empty());
+}
+
+fn show_path_footer(footer: &Label, path: &str) {
+    if !Config::load().window.hover_path_footer || path.is_empty() {
+        footer.set_visible(false);
+        return;
+    }
+
+    footer.set_text(path);
+    footer.set_visible(true);
+}
+
+fn select_next(list_view: &ListView) {
     if let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() {
         let n_items = selection_model.n_items();
         if n_items == 0 {
             return;
         }
         let current_pos = selection_model.selected();
-        if current_pos > 0 {
-            let prev_pos = current_pos - 1;
+        if let Some(next_pos) =
+            (current_pos + 1..n_items).find(|&pos| is_selectable(&selection_model, pos))
+        {
+            selection_model.set_selected(next_pos);
+            list_view
+                .activate_action("list.scroll-to-item", Some(&next_pos.to_variant()))
+                .unwrap_or_default();
+        }
+    }
+}
+
+fn select_previous(list_view: &ListView) {
+    if let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() {
+        let n_items = selection_model.n_items();
+        if n_items == 0 {
+            return;
+        }
+        let current_pos = selection_model.selected();
+        if let Some(prev_pos) = (0..current_pos)
+            .rev()
+            .find(|&pos| is_selectable(&selection_model, pos))
+        {
             selection_model.set_selected(prev_pos);
             list_view
                 .activate_action("list.scroll-to-item", Some(&prev_pos.to_variant()))
@@ -559,28 +1434,501 @@ fn select_previous(list_view: &ListView) {
     }
 }
 
-fn launch_application(app: &AppEntry, search_entry: &SearchEntry) -> bool {
+// Up on an empty search bar, with nothing above the selected row, fills in
+// the last query of the current mode, and further presses older ones.
+fn recall_older(
+    search_entry: &SearchEntry,
+    list_view: &ListView,
+    recall: &RefCell<Option<Recall>>,
+) -> bool {
+    let mut stepping = recall.borrow_mut();
+    let query = match stepping.as_mut() {
+        Some(stepping) => stepping.older().to_string(),
+        None => {
+            let at_top = list_view
+                .model()
+                .and_downcast::<SingleSelection>()
+                .is_none_or(|selection_model| {
+                    let current_pos = selection_model.selected().min(selection_model.n_items());
+                    !(0..current_pos).any(|pos| is_selectable(&selection_model, pos))
+                });
+            if Config::load().search.history_size == 0 || !search_entry.text().is_empty() || !at_top
+            {
+                return false;
+            }
+            let Some(started) = Recall::start(history::mode_for("")) else {
+                return false;
+            };
+            stepping.insert(started).current().to_string()
+        }
+    };
+    // Setting the text runs the search, which checks the recalled query.
+    drop(stepping);
+    search_entry.set_text(&query);
+    search_entry.set_position(-1);
+    true
+}
+
+// Down while stepping through the history comes forward again, back to the
+// empty search bar past the newest query.
+fn recall_newer(search_entry: &SearchEntry, recall: &RefCell<Option<Recall>>) -> bool {
+    let mut stepping = recall.borrow_mut();
+    let Some(current) = stepping.as_mut() else {
+        return false;
+    };
+    let query = current.newer().map(str::to_string);
+    if query.is_none() {
+        *stepping = None;
+    }
+    drop(stepping);
+    search_entry.set_text(query.as_deref().unwrap_or(""));
+    search_entry.set_position(-1);
+    true
+}
+
+fn mark_key(app_entry: &AppEntryObject) -> Option<MarkKey> {
+    if app_entry.is_notice() || app_entry.is_expander() || app_entry.is_divider() {
+        return None;
+    }
+    let app = app_entry.imp().app_entry();
+    Some((app.id.clone(), app.exec.clone()))
+}
+
+fn set_mark(list_view: &ListView, marks: &RefCell<HashMap<char, MarkKey>>, letter: char) {
+    let Some(key) = get_selected_item(list_view).and_then(|selected| mark_key(&selected)) else {
+        return;
+    };
+    log!("Setting mark {} on {}", letter, key.0);
+    marks.borrow_mut().insert(letter, key);
+}
+
+fn jump_to_mark(list_view: &ListView, marks: &RefCell<HashMap<char, MarkKey>>, letter: char) {
+    let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() else {
+        return;
+    };
+    let position = marks.borrow().get(&letter).and_then(|key| {
+        (0..selection_model.n_items()).find(|&position| {
+            selection_model
+                .item(position)
+                .and_downcast::<AppEntryObject>()
+                .and_then(|app_entry| mark_key(&app_entry))
+                .is_some_and(|candidate| candidate == *key)
+        })
+    });
+
+    match position {
+        Some(position) => {
+            selection_model.set_selected(position);
+            list_view
+                .activate_action("list.scroll-to-item", Some(&position.to_variant()))
+                .unwrap_or_default();
+        }
+        None => {
+            log!("Mark {} is not in the list", letter);
+            list_view.add_css_class(MARK_MISSING_CLASS);
+            let list_view = list_view.clone();
+            glib::timeout_add_local_once(MARK_MISSING_FLASH, move || {
+                list_view.remove_css_class(MARK_MISSING_CLASS);
+            });
+        }
+    }
+}
+
+fn edit_selected_desktop_file(list_view: &ListView, window: &ApplicationWindow) {
+    let Some(selected) = get_selected_item(list_view) else {
+        return;
+    };
+    let app = selected.imp().app_entry();
+    if !matches!(app.entry_type, EntryType::Application) || !app.path.ends_with(".desktop") {
+        return;
+    }
+
+    hide_window(window);
+    if !launcher::edit_desktop_file(&app.path) {
+        log!("Failed to open desktop file for editing: {}", app.path);
+    }
+}
+
+fn toggle_selected_pin(list_view: &ListView, search_entry: &SearchEntry) {
+    let Some(selected) = get_selected_item(list_view) else {
+        return;
+    };
+    let app = selected.imp().app_entry();
+    if !matches!(app.entry_type, EntryType::Application) || selected.is_action() {
+        return;
+    }
+
+    match launcher::toggle_favorite(app) {
+        Ok(true) => log!("Pinned {}", app.name),
+        Ok(false) => log!("Unpinned {}", app.name),
+        Err(e) => {
+            log!("Failed to update favorites: {}", e);
+            return;
+        }
+    }
+    // Searches load the config themselves, so this already sees the change.
+    search_entry.emit_by_name::<()>("changed", &[]);
+}
+
+fn toggle_selected_bookmark(list_view: &ListView, search_entry: &SearchEntry) -> bool {
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    if !is_folder(app) {
+        return false;
+    }
+
+    match bookmarks::toggle(&app.path) {
+        Ok(true) => log!("Bookmarked {}", app.path),
+        Ok(false) => log!("Removed bookmark {}", app.path),
+        Err(e) => log!("Failed to update bookmarks: {}", e),
+    }
+    search_entry.emit_by_name::<()>("changed", &[]);
+    true
+}
+
+// Only the bookmarks: listing takes the key; elsewhere it edits the search.
+fn remove_selected_bookmark(list_view: &ListView, search_entry: &SearchEntry) -> bool {
+    if !bookmarks::is_listing(&search_entry.text()) {
+        return false;
+    }
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    if !is_folder(app) {
+        return false;
+    }
+
+    match bookmarks::remove(&app.path) {
+        Ok(()) => log!("Removed bookmark {}", app.path),
+        Err(e) => log!("Failed to update bookmarks: {}", e),
+    }
+    search_entry.emit_by_name::<()>("changed", &[]);
+    true
+}
+
+fn delete_selected_history_entry(list_view: &ListView, search_entry: &SearchEntry) -> bool {
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    if !history::is_history_entry(app) {
+        return false;
+    }
+
+    match history::remove(app) {
+        Ok(()) => log!("Removed {:?} from the query history", app.exec),
+        Err(e) => log!("Failed to update the query history: {}", e),
+    }
+    search_entry.emit_by_name::<()>("changed", &[]);
+    true
+}
+
+// Prints the selected line, or the typed text when nothing matches, and
+// closes the window so App::run exits with the code of the accepting key.
+fn accept_dmenu(
+    selected: Option<&AppEntry>,
+    search_entry: &SearchEntry,
+    window: &ApplicationWindow,
+    custom: Option<usize>,
+) {
+    let line = selected.map_or_else(|| search_entry.text().to_string(), |app| app.exec.clone());
+    if line.is_empty() {
+        return;
+    }
+    dmenu::accept(&line, custom);
+    window.add_css_class(COMMITTING_CLASS);
+    hide_window(window);
+}
+
+// Runs the `index`th alternate action configured for the selected entry's
+// kind in `custom_actions`.
+fn custom_actions<'a>(app: &AppEntry, config: &'a Config) -> Option<&'a [String]> {
     match app.entry_type {
-        EntryType::Application => {
-            log!("Launching application: {}", app.name);
-            let exec = app
-                .exec
-                .replace("%f", "")
-                .replace("%F", "")
-                .replace("%u", "")
-                .replace("%U", "")
-                .replace("%i", "")
-                .replace("%c", &app.name)
-                .trim()
-                .to_string();
+        EntryType::Application => Some(&config.custom_actions.application),
+        EntryType::File if is_folder(app) => Some(&config.custom_actions.folder),
+        EntryType::File => Some(&config.custom_actions.file),
+        EntryType::Link => Some(&config.custom_actions.link),
+        EntryType::Query | EntryType::Value | EntryType::Window | EntryType::Divider => None,
+    }
+}
+
+// The name of a custom action in the context menu.
+fn custom_action_label(action: &str) -> Option<&'static str> {
+    Some(match action {
+        "launch" => "Launch",
+        "open" => "Open",
+        "descend" => "Show Contents",
+        "open_with" => "Open With…",
+        "open_terminal" => "Open Terminal Here",
+        "copy_path" => "Copy Path",
+        "copy_command" => "Copy Command",
+        "edit_desktop_file" => "Edit Desktop File",
+        _ => return None,
+    })
+}
+
+// Right-clicking a row selects it and lists its custom actions, e.g. opening
+// a terminal in a folder.
+fn show_context_menu(row: &ResultRow, app: &AppEntry, x: f64, y: f64) {
+    let config = Config::load();
+    let Some(actions) = custom_actions(app, &config) else {
+        return;
+    };
+    let menu = gio::Menu::new();
+    for (index, action) in actions.iter().enumerate() {
+        if let Some(label) = custom_action_label(action) {
+            menu.append(
+                Some(label),
+                Some(&format!("row.custom-action({})", index as u32)),
+            );
+        }
+    }
+    if menu.n_items() == 0 {
+        return;
+    }
+
+    let popover = gtk4::PopoverMenu::from_model(Some(&menu));
+    popover.set_parent(row);
+    popover.set_has_arrow(false);
+    popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+    popover.connect_closed(|popover| {
+        // The menu item is activated after the menu closes.
+        let popover = popover.clone();
+        glib::idle_add_local_once(move || popover.unparent());
+    });
+    popover.popup();
+}
+
+fn run_custom_action(
+    index: usize,
+    list_view: &ListView,
+    search_entry: &SearchEntry,
+    window: &ApplicationWindow,
+    rt: &Handle,
+) -> bool {
+    if window.has_css_class(COMMITTING_CLASS) {
+        return false;
+    }
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    let config = Config::load();
+    let Some(action) = custom_actions(app, &config).and_then(|actions| actions.get(index)) else {
+        return false;
+    };
+
+    log!("Running custom action {:?} on {}", action, app.name);
+    let copy = |text: &str| {
+        launcher::copy_to_clipboard(text);
+        hide_window(window);
+    };
+
+    let folder_action = match action.as_str() {
+        "launch" => config.files.enter_action,
+        "open" => FolderAction::open,
+        "descend" => FolderAction::descend,
+        "copy_path" => {
+            copy(&app.path);
+            return true;
+        }
+        "copy_command" => {
+            copy(&app.exec);
+            return true;
+        }
+        "edit_desktop_file" => {
+            edit_selected_desktop_file(list_view, window);
+            return true;
+        }
+        "open_terminal" => {
+            return open_terminal_in_selected(list_view, search_entry, window, rt);
+        }
+        "open_with" => {
+            open_with::set_targets(&[gio::File::for_path(&app.path)]);
+            LauncherWindow::refresh_results(window);
+            return true;
+        }
+        other => {
+            log!("Unknown custom action: {}", other);
+            return false;
+        }
+    };
+
+    if let Some(launch) = launch_application(app, search_entry, folder_action) {
+        commit_launch(launch, window, rt);
+    }
+    true
+}
+
+// Shows the desktop actions of the selected application as rows below it,
+// or hides them again if they are already shown.
+fn toggle_desktop_actions(list_view: &ListView) -> bool {
+    let Some(selection_model) = list_view.model().and_downcast::<SingleSelection>() else {
+        return false;
+    };
+    let Some(model) = selection_model.model().and_downcast::<gio::ListStore>() else {
+        return false;
+    };
+    let position = selection_model.selected();
+    let Some(selected) = model.item(position).and_downcast::<AppEntryObject>() else {
+        return false;
+    };
+    if selected.is_action() || selected.imp().app_entry().actions.is_empty() {
+        return false;
+    }
+
+    let shown = (position + 1..model.n_items())
+        .take_while(|&i| {
+            model
+                .item(i)
+                .and_downcast::<AppEntryObject>()
+                .is_some_and(|item| item.is_action())
+        })
+        .count() as u32;
+
+    if shown > 0 {
+        model.splice(position + 1, shown, &[] as &[glib::Object]);
+    } else {
+        let parent = selected.imp().app_entry();
+        let rows: Vec<_> = parent
+            .actions
+            .iter()
+            .map(|action| AppEntryObject::action(parent, action, selected.score()))
+            .collect();
+        model.splice(position + 1, 0, &rows);
+    }
+    true
+}
+
+fn is_folder(app: &AppEntry) -> bool {
+    matches!(app.entry_type, EntryType::File) && &*app.icon_name == "folder"
+}
+
+fn activate_selected_folder(
+    list_view: &ListView,
+    search_entry: &SearchEntry,
+    window: &ApplicationWindow,
+    rt: &Handle,
+    folder_action: FolderAction,
+) -> bool {
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    if !is_folder(app) || window.has_css_class(COMMITTING_CLASS) {
+        return false;
+    }
+
+    if let Some(launch) = launch_application(app, search_entry, folder_action) {
+        commit_launch(launch, window, rt);
+    }
+    true
+}
+
+// Opens a terminal in the selected folder. The launch counts towards the
+// folder's launch history.
+fn open_terminal_in_selected(
+    list_view: &ListView,
+    search_entry: &SearchEntry,
+    window: &ApplicationWindow,
+    rt: &Handle,
+) -> bool {
+    let Some(selected) = get_selected_item(list_view) else {
+        return false;
+    };
+    let app = selected.imp().app_entry();
+    if !is_folder(app) || window.has_css_class(COMMITTING_CLASS) {
+        return false;
+    }
+
+    log!("Opening terminal in: {}", app.path);
+    if let Some(plan) = launcher::terminal_plan(app, &app.path) {
+        let launch = PendingLaunch {
+            target: LaunchTarget::Plan(plan),
+            counted: Some(app.clone()),
+            activation: Activation::new(app, &search_entry.text()),
+        };
+        commit_launch(launch, window, rt);
+    }
+    true
+}
+
+fn is_selectable(selection_model: &SingleSelection, position: u32) -> bool {
+    !selection_model
+        .item(position)
+        .and_downcast::<AppEntryObject>()
+        .is_some_and(|item| item.is_divider())
+}
+
+enum LaunchTarget {
+    Plan(LaunchPlan),
+    Uri(String),
+    Copy(String),
+    // The address of a Hyprland window.
+    Focus(String),
+}
 
-            launcher::increment_launch_count(app).unwrap();
+struct PendingLaunch {
+    target: LaunchTarget,
+    counted: Option<AppEntry>,
+    activation: Activation,
+}
 
-            Command::new("sh").arg("-c").arg(&exec).spawn().is_ok()
+fn launch_application(
+    app: &AppEntry,
+    search_entry: &SearchEntry,
+    folder_action: FolderAction,
+) -> Option<PendingLaunch> {
+    let activation = Activation::new(app, &search_entry.text());
+    match app.entry_type {
+        EntryType::Application => {
+            log!("Launching application: {}", app.name);
+            let fields = exec::Fields {
+                name: &app.name,
+                icon: &app.icon_name,
+                desktop_file: &app.path,
+                ..Default::default()
+            };
+            let argv = exec::command(&app.exec, &fields)
+                .map_err(|e| {
+                    log!(
+                        "Not launching {}, malformed Exec {:?}: {}",
+                        app.name,
+                        app.exec,
+                        e
+                    )
+                })
+                .ok()?;
+
+            Some(PendingLaunch {
+                target: LaunchTarget::Plan(LaunchPlan::command(app, argv)),
+                counted: Some(app.clone()),
+                activation,
+            })
         }
         EntryType::File => {
-            if app.icon_name == "folder" {
+            if is_folder(app) && folder_action == FolderAction::open {
+                log!("Opening folder in file manager: {}", app.path);
+                Some(PendingLaunch {
+                    target: LaunchTarget::Uri(app.path.clone()),
+                    counted: bookmarks::contains(&app.path).then(|| app.clone()),
+                    activation,
+                })
+            } else if is_folder(app) {
                 log!("Opening folder: {}", app.path);
+                // Descending stays in the launcher, so a bookmark's launch is
+                // counted here rather than in commit_launch.
+                if bookmarks::contains(&app.path) {
+                    let app = app.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = launcher::increment_launch_count(&app) {
+                            log!("Failed to update launch count: {}", e);
+                        }
+                    });
+                }
                 let path = if app.path.ends_with('/') {
                     app.path.clone()
                 } else {
@@ -589,15 +1937,191 @@ fn launch_application(app: &AppEntry, search_entry: &SearchEntry) -> bool {
                 search_entry.set_text(&path);
                 search_entry.set_position(-1);
 
-                false
+                None
             } else {
                 log!("Opening file: {}", app.path);
-                Command::new("sh").arg("-c").arg(&app.exec).spawn().is_ok()
+                Some(PendingLaunch {
+                    target: LaunchTarget::Plan(LaunchPlan::new(app, app.exec.clone())),
+                    counted: search::is_binary_fallback(app).then(|| app.clone()),
+                    activation,
+                })
             }
         }
+        EntryType::Link => {
+            log!("Opening link: {}", app.exec);
+            Some(PendingLaunch {
+                target: LaunchTarget::Uri(app.exec.clone()),
+                counted: Some(app.clone()),
+                activation,
+            })
+        }
+        EntryType::Value => {
+            log!("Copying value: {}", app.exec);
+            Some(PendingLaunch {
+                target: LaunchTarget::Copy(app.exec.clone()),
+                counted: None,
+                activation,
+            })
+        }
+        EntryType::Window => {
+            log!("Focusing window: {}", app.name);
+            Some(PendingLaunch {
+                target: LaunchTarget::Focus(app.exec.clone()),
+                counted: None,
+                activation,
+            })
+        }
+        EntryType::Divider => None,
+        EntryType::Query => {
+            search_entry.set_text(&app.exec);
+            search_entry.grab_focus();
+            search_entry.set_position(-1);
+
+            None
+        }
     }
 }
 
+// The label below the search bar that shows why the last launch failed.
+fn launch_error_label(main_box: &gtk4::Widget) -> Option<Label> {
+    std::iter::successors(main_box.first_child(), |child| child.next_sibling())
+        .filter(|child| child.has_css_class(LAUNCH_ERROR_CLASS))
+        .find_map(|child| child.downcast::<Label>().ok())
+}
+
+// With `window.show_launch_errors` the window stays open until the program
+// got past its first moments, and shows the error if it failed. Otherwise it
+// hides right away and only a failure to start is reported, as a
+// notification.
+fn commit_launch(launch: PendingLaunch, window: &ApplicationWindow, rt: &Handle) {
+    let watch = Config::load().window.show_launch_errors;
+    // Taken before hiding the window returns to the default mode.
+    let history_mode = history::mode_for(&launch.activation.query);
+    if let LaunchTarget::Copy(text) = &launch.target {
+        launcher::copy_to_clipboard(text);
+    }
+    window.add_css_class(COMMITTING_CLASS);
+    if !watch {
+        hyprland::forget_active_window();
+        hide_window(window);
+    }
+
+    let window = window.clone();
+    let rt = rt.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let PendingLaunch {
+            target,
+            counted,
+            activation,
+        } = launch;
+        let result = rt
+            .spawn_blocking(move || {
+                let launch = || match &target {
+                    LaunchTarget::Plan(plan) => plan.launch(watch).map_err(|e| e.to_string()),
+                    LaunchTarget::Uri(uri) => launcher::open_uri(uri)
+                        .then_some(())
+                        .ok_or_else(|| String::from("failed to open")),
+                    // Already copied before the window was hidden.
+                    LaunchTarget::Copy(_) => Ok(()),
+                    LaunchTarget::Focus(address) => hyprland::focus_window(address)
+                        .then_some(())
+                        .ok_or_else(|| String::from("failed to focus")),
+                };
+                let launched = launcher::count_after(launch, counted.as_ref(), |app| {
+                    if let Err(e) = launcher::increment_launch_count(app) {
+                        log!("Failed to update launch count: {}", e);
+                    }
+                });
+                if launched.is_ok() {
+                    activation_log::record(&activation);
+                    history::record(&activation.query, history_mode);
+                }
+                (target, launched)
+            })
+            .await;
+
+        let plan_failed = matches!(result, Ok((LaunchTarget::Plan(_), Err(_))));
+        match result {
+            Ok((LaunchTarget::Plan(plan), Ok(()))) => launcher::run_hook(
+                &Config::load().hooks.on_launch,
+                &[("HL_ENTRY_NAME", &plan.name), ("HL_ENTRY_EXEC", &plan.exec)],
+            ),
+            Ok((LaunchTarget::Plan(plan), Err(e))) => {
+                log!("Failed to launch {}: {}", plan.exec, e);
+                if watch {
+                    show_launch_error(&window, &plan, &e);
+                } else {
+                    notify_launch_failure(&plan, &window);
+                }
+            }
+            Ok((LaunchTarget::Copy(_), _)) => {}
+            Ok((LaunchTarget::Focus(address), launched)) => {
+                if launched.is_err() {
+                    log!("Failed to focus window {}", address);
+                }
+            }
+            Ok((LaunchTarget::Uri(uri), launched)) => {
+                if launched.is_err() {
+                    log!("Failed to open link: {}", uri);
+                }
+            }
+            Err(e) => log!("Launch task failed: {}", e),
+        }
+
+        if watch && plan_failed {
+            window.remove_css_class(COMMITTING_CLASS);
+        } else if watch {
+            hyprland::forget_active_window();
+            hide_window(&window);
+        }
+    });
+}
+
+fn show_launch_error(window: &ApplicationWindow, plan: &LaunchPlan, error: &str) {
+    let label = window
+        .child()
+        .and_downcast::<Revealer>()
+        .and_then(|revealer| revealer.child())
+        .and_then(|main_box| launch_error_label(&main_box));
+    let Some(label) = label else {
+        notify_launch_failure(plan, window);
+        return;
+    };
+    label.set_text(&format!(
+        "Failed to launch {}: {}\n{}",
+        plan.name, error, plan.exec
+    ));
+    label.set_visible(true);
+}
+
+fn notify_launch_failure(plan: &LaunchPlan, window: &ApplicationWindow) {
+    let Some(app) = window.application() else {
+        return;
+    };
+
+    let notification = gio::Notification::new(&format!("Failed to launch {}", plan.name));
+    notification.set_body(Some(&plan.exec));
+
+    if !app.is_remote() {
+        let target = plan.to_variant();
+        notification.add_button_with_target_value("Retry", "app.retry", Some(&target));
+        notification.add_button_with_target_value(
+            "Copy command",
+            "app.copy-command",
+            Some(&target),
+        );
+        if plan.path.ends_with(".desktop") {
+            notification.add_button_with_target_value(
+                "Open .desktop file",
+                "app.open-desktop-file",
+                Some(&target),
+            );
+        }
+    }
+
+    app.send_notification(Some("launch-failure"), &notification);
+}
+
 trait WindowAnchoring {
     fn set_anchors(&self, anchors: [bool; 4]);
 }
@@ -621,19 +2145,22 @@ mod imp {
 
     #[derive(Default)]
     pub struct AppEntryObject {
-        pub(crate) name: OnceCell<String>,
-        pub(crate) description: OnceCell<String>,
+        pub(crate) name: OnceCell<DisplayText>,
+        pub(crate) description: OnceCell<DisplayText>,
         pub(crate) path: OnceCell<String>,
         pub(crate) icon_name: OnceCell<String>,
         pub(crate) app_entry: OnceCell<AppEntry>,
+        pub(crate) hidden: RefCell<Vec<AppEntry>>,
+        pub(crate) score: Cell<i64>,
+        pub(crate) is_action: Cell<bool>,
     }
 
     impl AppEntryObject {
-        pub fn name(&self) -> &str {
+        pub fn name(&self) -> &DisplayText {
             self.name.get().unwrap()
         }
 
-        pub fn description(&self) -> &str {
+        pub fn description(&self) -> &DisplayText {
             self.description.get().unwrap()
         }
 
@@ -641,8 +2168,11 @@ mod imp {
             self.path.get().unwrap()
         }
 
+        // File icons depend on the content type, which is only detected the
+        // first time the row is shown.
         pub fn icon_name(&self) -> &str {
-            self.icon_name.get().unwrap()
+            self.icon_name
+                .get_or_init(|| launcher::icon_name(self.app_entry()).to_string())
         }
 
         pub fn app_entry(&self) -> &AppEntry {
@@ -664,13 +2194,103 @@ impl AppEntryObject {
     pub fn new(app_entry: AppEntry) -> Self {
         let obj: Self = glib::Object::new();
         let imp = obj.imp();
-        imp.name.set(app_entry.name.clone()).unwrap();
-        imp.description.set(app_entry.description.clone()).unwrap();
+        imp.name
+            .set(DisplayText::Plain(app_entry.name.clone()))
+            .unwrap();
+        imp.description
+            .set(match &app_entry.description_markup {
+                Some(markup) => DisplayText::Markup(markup.clone()),
+                None => DisplayText::Plain(app_entry.description.clone()),
+            })
+            .unwrap();
         imp.path.set(app_entry.path.clone()).unwrap();
-        imp.icon_name.set(app_entry.icon_name.clone()).unwrap();
+        if !app_entry.icon_name.is_empty() {
+            imp.icon_name.set(app_entry.icon_name.to_string()).unwrap();
+        }
         imp.app_entry.set(app_entry).unwrap();
         obj
     }
+
+    pub fn expander(hidden: Vec<AppEntry>) -> Self {
+        let obj = Self::new(AppEntry {
+            name: format!("… {} more matches", hidden.len()),
+            icon_name: "view-more-symbolic".into(),
+            entry_type: EntryType::Divider,
+            ..Default::default()
+        });
+        obj.imp().hidden.replace(hidden);
+        obj
+    }
+
+    pub fn scored(app_entry: AppEntry, score: i64) -> Self {
+        let obj = Self::new(app_entry);
+        obj.imp().score.set(score);
+        obj
+    }
+
+    pub fn action(parent: &AppEntry, action: &DesktopAction, score: i64) -> Self {
+        let obj = Self::scored(
+            AppEntry {
+                name: action.name.clone(),
+                description: parent.name.clone(),
+                exec: action.exec.clone(),
+                icon_name: action.icon_name.clone(),
+                // Actions run their own Exec rather than activating the app.
+                dbus_activatable: false,
+                actions: Vec::new(),
+                description_markup: None,
+                ..parent.clone()
+            },
+            score,
+        );
+        obj.imp().is_action.set(true);
+        obj
+    }
+
+    pub fn notice(name: &str, icon_name: &str) -> Self {
+        Self::new(AppEntry {
+            name: String::from(name),
+            icon_name: icon_name.into(),
+            entry_type: EntryType::Divider,
+            ..Default::default()
+        })
+    }
+
+    // Stands in for the results until the applications are loaded, or tells
+    // why they couldn't be.
+    pub fn loading() -> Self {
+        match launcher::load_error() {
+            Some(error) => Self::notice(
+                &format!("Failed to load applications: {}", error),
+                "dialog-error-symbolic",
+            ),
+            None => Self::notice("Loading applications…", "process-working-symbolic"),
+        }
+    }
+
+    pub fn divider() -> Self {
+        Self::notice("Weaker matches", "")
+    }
+
+    pub fn score(&self) -> i64 {
+        self.imp().score.get()
+    }
+
+    pub fn is_notice(&self) -> bool {
+        matches!(self.imp().app_entry().entry_type, EntryType::Divider)
+    }
+
+    pub fn is_expander(&self) -> bool {
+        self.is_notice() && !self.imp().hidden.borrow().is_empty()
+    }
+
+    pub fn is_divider(&self) -> bool {
+        self.is_notice() && self.imp().hidden.borrow().is_empty()
+    }
+
+    pub fn is_action(&self) -> bool {
+        self.imp().is_action.get()
+    }
 }
 
 fn get_selected_item(list_view: &ListView) -> Option<AppEntryObject> {
@@ -686,26 +2306,660 @@ fn get_selected_item(list_view: &ListView) -> Option<AppEntryObject> {
         })
 }
 
-fn delete_word(text: &str, cursor_pos: usize) -> Option<(String, usize)> {
-    if text.is_empty() || cursor_pos == 0 {
-        return None;
+// The shadow is drawn inside the surface, which grows by its extent on every
+// side.
+fn surface_size(config: &Config) -> (i32, i32) {
+    let extent = config.theme.shadow.extent();
+    (
+        config.window.width + 2 * extent,
+        config.window.height + 2 * extent,
+    )
+}
+
+// The edges of the monitor the layer surface sticks to, as top, right,
+// bottom and left.
+fn window_anchors(config: &Config) -> [bool; 4] {
+    if let Some(anchors) = config.window.edge_anchors() {
+        return anchors;
+    }
+    match config.window.anchor {
+        WindowAnchor::center => [false; 4],
+        WindowAnchor::top => [true, false, false, false],
+        WindowAnchor::bottom => [false, false, true, false],
+        WindowAnchor::left => [false, false, false, true],
+        WindowAnchor::right => [false, true, false, false],
+        WindowAnchor::top_left => [true, false, false, true],
+        WindowAnchor::top_right => [true, true, false, false],
+        WindowAnchor::bottom_left => [false, false, true, true],
+        WindowAnchor::bottom_right => [false, true, true, true],
     }
+}
+
+// The layer surface margins as top, right, bottom and left. Each keeps the
+// window on a monitor of `monitor` (width, height) when it is known, and is
+// pulled out by the shadow's extent so the visible window stays where the
+// margin puts it. Margins only move the surface on anchored edges.
+fn window_margins(config: &Config, monitor: Option<(i32, i32)>) -> [i32; 4] {
+    let window = &config.window;
+    let (max_horizontal, max_vertical) = monitor
+        .map(|(width, height)| {
+            (
+                (width - window.width).max(0),
+                (height - window.height).max(0),
+            )
+        })
+        .unwrap_or((i32::MAX, i32::MAX));
+    let extent = config.theme.shadow.extent();
+    [
+        window.margin_top.min(max_vertical) - extent,
+        window.margin_right.min(max_horizontal) - extent,
+        window.margin_bottom.min(max_vertical) - extent,
+        window.margin_left.min(max_horizontal) - extent,
+    ]
+}
+
+// A regular window is floated, centered by Hyprland at the configured size
+// and pinned to every workspace, or opened on its own special workspace.
+fn window_rules(config: &Config) -> Vec<String> {
+    let (width, height) = surface_size(config);
+    let placement = match config.window.special_workspace.as_str() {
+        "" => String::from("pin"),
+        name => format!("workspace special:{}", name),
+    };
+    vec![
+        String::from("float"),
+        placement,
+        format!("size {} {}", width, height),
+        String::from("center"),
+    ]
+}
 
-    let mut chars: Vec<char> = text.chars().collect();
-    let mut new_pos = cursor_pos;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    while new_pos > 0 && chars[new_pos - 1].is_whitespace() {
-        new_pos -= 1;
+    fn placed(margins: [i32; 4], shadow: Option<(i32, i32, i32)>) -> Config {
+        let mut config = Config::default();
+        let [top, right, bottom, left] = margins;
+        config.window.margin_top = top;
+        config.window.margin_right = right;
+        config.window.margin_bottom = bottom;
+        config.window.margin_left = left;
+        if let Some((size, offset_x, offset_y)) = shadow {
+            config.theme.shadow.enabled = true;
+            config.theme.shadow.size = size;
+            config.theme.shadow.offset_x = offset_x;
+            config.theme.shadow.offset_y = offset_y;
+        }
+        config
     }
 
-    while new_pos > 0 && !chars[new_pos - 1].is_whitespace() {
-        new_pos -= 1;
+    #[test]
+    fn each_anchor_sticks_to_its_edges() {
+        let cases = [
+            (WindowAnchor::center, [false, false, false, false]),
+            (WindowAnchor::top, [true, false, false, false]),
+            (WindowAnchor::bottom, [false, false, true, false]),
+            (WindowAnchor::left, [false, false, false, true]),
+            (WindowAnchor::right, [false, true, false, false]),
+            (WindowAnchor::top_left, [true, false, false, true]),
+            (WindowAnchor::top_right, [true, true, false, false]),
+            (WindowAnchor::bottom_left, [false, false, true, true]),
+            (WindowAnchor::bottom_right, [false, true, true, true]),
+        ];
+        for (anchor, anchors) in cases {
+            let mut config = placed([10, 20, 30, 40], Some((8, 0, 0)));
+            config.window.anchor = anchor;
+            assert_eq!(
+                window_anchors(&config),
+                anchors,
+                "{:?}",
+                config.window.anchor
+            );
+            // Margins don't depend on the anchor, the compositor ignores
+            // those of unanchored edges.
+            assert_eq!(window_margins(&config, Some((1920, 1080))), [2, 12, 22, 32]);
+        }
+
+        let mut config = placed([0; 4], None);
+        config.window.anchor_edges = vec![String::from("left"), String::from("right")];
+        assert_eq!(window_anchors(&config), [false, true, false, true]);
     }
 
-    chars.drain(new_pos..cursor_pos);
-    let result: String = chars.into_iter().collect();
-    let trimmed = result.trim_end().to_string();
-    let new_pos = new_pos.min(trimmed.len());
+    #[test]
+    fn margins_keep_the_window_on_the_monitor() {
+        // A 600x600 window on a 1280x720 monitor leaves 120 pixels
+        // vertically and 680 horizontally.
+        let config = placed([100, 700, 5000, 680], None);
+        assert_eq!(
+            window_margins(&config, Some((1280, 720))),
+            [100, 680, 120, 680]
+        );
+        assert_eq!(window_margins(&config, None), [100, 700, 5000, 680]);
 
-    Some((trimmed, new_pos))
+        // A window larger than the monitor gets no margin at all.
+        assert_eq!(window_margins(&config, Some((500, 500))), [0; 4]);
+
+        let shadowed = placed([100, 700, 5000, 680], Some((16, 0, 0)));
+        assert_eq!(
+            window_margins(&shadowed, Some((1280, 720))),
+            [84, 664, 104, 664]
+        );
+    }
+
+    #[test]
+    fn shadow_offsets_grow_the_surface_by_the_largest_one() {
+        for (offset_x, offset_y) in [(-6, 2), (2, -6), (6, 6), (-6, -6)] {
+            let config = placed([30, 30, 30, 30], Some((10, offset_x, offset_y)));
+            assert_eq!(surface_size(&config), (632, 632));
+            assert_eq!(
+                window_margins(&config, Some((1920, 1080))),
+                [14, 14, 14, 14]
+            );
+        }
+
+        // Margins smaller than the extent move the surface past the edge, so
+        // the window itself still sits at the margin.
+        let config = placed([0, 4, 0, 0], Some((10, 0, 0)));
+        assert_eq!(
+            window_margins(&config, Some((1920, 1080))),
+            [-10, -6, -10, -10]
+        );
+    }
+
+    #[test]
+    fn disabled_shadows_leave_the_geometry_as_configured() {
+        let mut config = placed([10, 20, 30, 40], Some((24, -8, 12)));
+        config.theme.shadow.enabled = false;
+        assert_eq!(surface_size(&config), (600, 600));
+        assert_eq!(
+            window_margins(&config, Some((1920, 1080))),
+            [10, 20, 30, 40]
+        );
+        assert_eq!(window_rules(&config)[2], "size 600 600");
+    }
+
+    #[test]
+    fn windows_are_pinned_or_sent_to_a_special_workspace() {
+        let mut config = Config::default();
+        config.theme.shadow.enabled = false;
+        assert_eq!(
+            window_rules(&config),
+            ["float", "pin", "size 600 600", "center"]
+        );
+
+        config.window.special_workspace = String::from("launcher");
+        config.window.width = 800;
+        assert_eq!(
+            window_rules(&config),
+            [
+                "float",
+                "workspace special:launcher",
+                "size 800 600",
+                "center"
+            ]
+        );
+    }
+
+    #[test]
+    fn default_custom_actions_have_menu_labels() {
+        let config = Config::default();
+        let actions = &config.custom_actions;
+        for action in [
+            &actions.application,
+            &actions.file,
+            &actions.folder,
+            &actions.link,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            assert!(custom_action_label(action).is_some(), "{}", action);
+        }
+        assert_eq!(
+            custom_action_label("open_terminal"),
+            Some("Open Terminal Here")
+        );
+        assert_eq!(custom_action_label("reboot"), None);
+    }
+
+    fn results(count: usize) -> search::SearchResults {
+        search::SearchResults {
+            deferred: count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replacements_drop_the_updates_queued_before_them() {
+        let mut pending = PendingResults::default();
+        assert!(pending.push(ResultUpdate::Replace(results(1))));
+        assert!(!pending.push(ResultUpdate::Merge(results(2))));
+        assert!(!pending.push(ResultUpdate::Done));
+        assert!(!pending.push(ResultUpdate::Replace(results(3))));
+        assert!(!pending.push(ResultUpdate::Merge(results(4))));
+        assert_eq!(pending.dropped, 2);
+
+        let queued = pending.take();
+        assert_eq!(queued.replace.map(|results| results.deferred), Some(3));
+        let merges: Vec<_> = queued
+            .merges
+            .iter()
+            .map(|results| results.deferred)
+            .collect();
+        assert_eq!(merges, [4]);
+        assert!(!queued.done, "the earlier search's end was kept");
+
+        assert!(pending.push(ResultUpdate::Done));
+        assert!(pending.take().done);
+        assert_eq!(pending.frames, 2);
+    }
+
+    // Typing at 200 keys a second, each search sending its results, a late
+    // merge and its end, against a 60 Hz frame clock.
+    #[test]
+    fn fast_typing_applies_at_most_one_replacement_a_frame() {
+        const EVENTS: u64 = 400;
+        const EVENT_MS: f64 = 1000.0 / 200.0;
+        const FRAME_MS: f64 = 1000.0 / 60.0;
+
+        let mut pending = PendingResults::default();
+        let (mut replaced, mut merged, mut next_frame) = (0, 0, FRAME_MS);
+        for event in 0..EVENTS {
+            let at = event as f64 * EVENT_MS;
+            while at >= next_frame {
+                let queued = pending.take();
+                replaced += usize::from(queued.replace.is_some());
+                merged += queued.merges.len();
+                next_frame += FRAME_MS;
+            }
+            pending.push(ResultUpdate::Replace(results(0)));
+            pending.push(ResultUpdate::Merge(results(0)));
+            pending.push(ResultUpdate::Done);
+        }
+        let queued = pending.take();
+        replaced += usize::from(queued.replace.is_some());
+        merged += queued.merges.len();
+
+        // Without coalescing, every one of the 800 updates touched the model.
+        assert_eq!(
+            replaced + merged + pending.dropped as usize,
+            2 * EVENTS as usize
+        );
+        assert!(replaced as u64 <= pending.frames);
+        assert_eq!(replaced, merged);
+        assert!(
+            pending.dropped >= EVENTS,
+            "only {} updates dropped",
+            pending.dropped
+        );
+        assert!(queued.done);
+    }
+}
+
+// The window over an injected entry set and config, without the application
+// cache, the config file or the compositor. Signals aren't connected; tests
+// call the update and key functions the signals would.
+#[cfg(all(test, feature = "gui-tests"))]
+mod gui_tests {
+    use super::*;
+    use gdk::{Key, ModifierType};
+    use std::sync::LazyLock;
+
+    static RUNTIME: LazyLock<tokio::runtime::Runtime> =
+        LazyLock::new(|| tokio::runtime::Runtime::new().unwrap());
+
+    impl LauncherWindow {
+        // Lists `entries` ranked in the order given.
+        fn with_entries(entries: &[&str], config: impl Fn() -> Config + 'static) -> Self {
+            let current = config();
+            let launcher = Self::build(
+                ApplicationWindow::builder().build(),
+                &current,
+                RUNTIME.handle().clone(),
+                config,
+            );
+            launcher.show(ranked(entries), &current);
+            launcher
+        }
+
+        fn show(&self, results: search::SearchResults, config: &Config) {
+            update_results_list(&self.list_view, results, &self.app_data_store, config);
+        }
+
+        fn merge(&self, results: search::SearchResults) {
+            merge_results(&self.list_view, results, &self.app_data_store);
+        }
+
+        fn selection(&self) -> SingleSelection {
+            self.list_view.model().and_downcast().unwrap()
+        }
+
+        fn row(&self, position: u32) -> AppEntryObject {
+            self.selection().item(position).and_downcast().unwrap()
+        }
+
+        fn names(&self) -> Vec<String> {
+            (0..self.selection().n_items())
+                .map(|position| self.row(position).imp().app_entry().name.clone())
+                .collect()
+        }
+
+        fn selected_name(&self) -> String {
+            self.row(self.selection().selected())
+                .imp()
+                .app_entry()
+                .name
+                .clone()
+        }
+    }
+
+    fn settings(max_entries: usize) -> impl Fn() -> Config + Copy {
+        move || {
+            let mut config = Config::default();
+            config.window.max_entries = max_entries;
+            config.window.show_icons = false;
+            config
+        }
+    }
+
+    fn app(name: &str) -> AppEntry {
+        AppEntry {
+            id: format!("{}.desktop", name),
+            desktop_id: format!("{}.desktop", name),
+            name: String::from(name),
+            exec: name.to_lowercase(),
+            ..Default::default()
+        }
+    }
+
+    fn ranked(names: &[&str]) -> search::SearchResults {
+        search::SearchResults {
+            matches: scored(names, 1000),
+            ..Default::default()
+        }
+    }
+
+    // Scores step down by ten from `top`.
+    fn scored(names: &[&str], top: i64) -> Vec<search::SearchResult> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(rank, name)| search::SearchResult {
+                app: app(name),
+                score: top - 10 * rank as i64,
+            })
+            .collect()
+    }
+
+    fn with_hidden(shown: &[&str], hidden: &[&str]) -> search::SearchResults {
+        search::SearchResults {
+            hidden: scored(hidden, 500),
+            ..ranked(shown)
+        }
+    }
+
+    #[gtk4::test]
+    fn results_replace_the_rows_and_the_store() {
+        let config = settings(10);
+        let launcher = LauncherWindow::with_entries(&["Firefox", "Files"], config);
+        assert_eq!(launcher.names(), ["Firefox", "Files"]);
+
+        launcher.show(ranked(&["Kitty"]), &config());
+        assert_eq!(launcher.names(), ["Kitty"]);
+        let store = launcher.app_data_store.borrow();
+        assert_eq!(
+            store
+                .iter()
+                .map(|app| app.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Kitty"]
+        );
+    }
+
+    #[gtk4::test]
+    fn hidden_matches_wait_behind_an_expander() {
+        let config = settings(2);
+        let launcher = LauncherWindow::with_entries(&[], config);
+        launcher.show(with_hidden(&["A", "B", "C"], &["D", "E"]), &config());
+
+        assert_eq!(launcher.names(), ["A", "B", "… 2 more matches"]);
+        assert!(launcher.row(2).is_expander());
+        assert_eq!(launcher.app_data_store.borrow().len(), 2);
+    }
+
+    #[gtk4::test]
+    fn disallowed_results_are_left_out() {
+        let config = move || {
+            let mut config = settings(10)();
+            config.search.enable_file_browsing = false;
+            config
+        };
+        let launcher = LauncherWindow::with_entries(&[], config);
+        let mut results = ranked(&["Firefox"]);
+        results.matches.push(search::SearchResult {
+            app: AppEntry {
+                entry_type: EntryType::File,
+                ..app("notes.txt")
+            },
+            score: 1,
+        });
+        launcher.show(results, &config());
+        assert_eq!(launcher.names(), ["Firefox"]);
+    }
+
+    #[gtk4::test]
+    fn loading_results_end_with_an_unselectable_notice() {
+        let config = settings(10);
+        let launcher = LauncherWindow::with_entries(&[], config);
+        launcher.show(
+            search::SearchResults {
+                loading: true,
+                ..ranked(&["Firefox"])
+            },
+            &config(),
+        );
+
+        assert_eq!(launcher.selection().n_items(), 2);
+        assert!(launcher.row(1).is_notice());
+        assert!(!is_selectable(&launcher.selection(), 1));
+    }
+
+    #[gtk4::test]
+    fn merged_results_are_placed_by_score() {
+        let launcher = LauncherWindow::with_entries(&["A", "B", "C"], settings(10));
+        // A, B and C score 1000, 990 and 980.
+        launcher.merge(search::SearchResults {
+            matches: scored(&["X"], 985),
+            ..Default::default()
+        });
+        assert_eq!(launcher.names(), ["A", "B", "X", "C"]);
+        assert_eq!(launcher.app_data_store.borrow().len(), 4);
+    }
+
+    #[gtk4::test]
+    fn merging_keeps_a_row_the_user_selected() {
+        let launcher = LauncherWindow::with_entries(&["A", "B", "C"], settings(10));
+        launcher.selection().set_selected(2);
+        launcher.merge(search::SearchResults {
+            matches: scored(&["X"], 2000),
+            ..Default::default()
+        });
+        assert_eq!(launcher.names(), ["X", "A", "B", "C"]);
+        assert_eq!(launcher.selected_name(), "C");
+    }
+
+    #[gtk4::test]
+    fn merging_keeps_the_best_match_selected() {
+        let launcher = LauncherWindow::with_entries(&["A", "B"], settings(10));
+        launcher.merge(search::SearchResults {
+            matches: scored(&["X"], 2000),
+            ..Default::default()
+        });
+        assert_eq!(launcher.selection().selected(), 0);
+        assert_eq!(launcher.selected_name(), "X");
+    }
+
+    #[gtk4::test]
+    fn only_empty_lists_get_the_placeholder() {
+        let launcher = LauncherWindow::with_entries(&["A"], settings(10));
+        show_empty_placeholder(&launcher.list_view);
+        assert_eq!(launcher.names(), ["A"]);
+
+        launcher.show(ranked(&[]), &settings(10)());
+        show_empty_placeholder(&launcher.list_view);
+        assert_eq!(launcher.names(), ["No results"]);
+        assert!(!is_selectable(&launcher.selection(), 0));
+    }
+
+    #[gtk4::test]
+    fn hidden_matches_expand_a_page_at_a_time() {
+        let config = settings(2);
+        let launcher = LauncherWindow::with_entries(&[], config);
+        launcher.show(with_hidden(&["A"], &["B", "C", "D"]), &config());
+
+        expand_hidden_matches(&launcher.list_view, 1, 2);
+        assert_eq!(
+            launcher.names(),
+            ["A", "Weaker matches", "B", "C", "… 1 more matches"]
+        );
+        assert_eq!(launcher.selected_name(), "B");
+
+        expand_hidden_matches(&launcher.list_view, 4, 2);
+        assert_eq!(launcher.names(), ["A", "Weaker matches", "B", "C", "D"]);
+        assert_eq!(launcher.selected_name(), "D");
+    }
+
+    #[gtk4::test]
+    fn selection_skips_dividers() {
+        let config = settings(5);
+        let launcher = LauncherWindow::with_entries(&[], config);
+        launcher.show(with_hidden(&["A"], &["B"]), &config());
+        expand_hidden_matches(&launcher.list_view, 1, 5);
+        assert_eq!(launcher.names(), ["A", "Weaker matches", "B"]);
+
+        select_previous(&launcher.list_view);
+        assert_eq!(launcher.selection().selected(), 0);
+        select_next(&launcher.list_view);
+        assert_eq!(launcher.selection().selected(), 2);
+    }
+
+    #[gtk4::test]
+    fn selection_stops_at_either_end() {
+        let launcher = LauncherWindow::with_entries(&["A", "B"], settings(10));
+        select_previous(&launcher.list_view);
+        assert_eq!(launcher.selection().selected(), 0);
+        select_next(&launcher.list_view);
+        select_next(&launcher.list_view);
+        assert_eq!(launcher.selection().selected(), 1);
+    }
+
+    #[gtk4::test]
+    fn bound_keys_are_dispatched_through_the_keymap() {
+        let config = settings(10);
+        let launcher = LauncherWindow::with_entries(&["A", "B"], config);
+        let keymap = Keymap::from_config(&config());
+
+        let propagation = launcher.handle_key(&keymap, Key::j, ModifierType::CONTROL_MASK);
+        assert_eq!(propagation, glib::Propagation::Stop);
+        assert_eq!(launcher.selected_name(), "B");
+        launcher.handle_key(&keymap, Key::k, ModifierType::CONTROL_MASK);
+        assert_eq!(launcher.selected_name(), "A");
+    }
+
+    #[gtk4::test]
+    fn other_keys_reach_the_search_entry() {
+        let config = settings(10);
+        let launcher = LauncherWindow::with_entries(&["A", "B"], config);
+        let keymap = Keymap::from_config(&config());
+
+        // `m` sets a mark only while the list has the focus.
+        for key in [Key::j, Key::m] {
+            assert_eq!(
+                launcher.handle_key(&keymap, key, ModifierType::empty()),
+                glib::Propagation::Proceed
+            );
+        }
+        assert_eq!(launcher.feed_chord(Key::a), glib::Propagation::Proceed);
+        assert_eq!(launcher.selected_name(), "A");
+    }
+
+    #[gtk4::test]
+    fn delete_word_edits_the_search_entry() {
+        let config = settings(10);
+        let launcher = LauncherWindow::with_entries(&[], config);
+        launcher.search_entry.set_text("open fire");
+        launcher.search_entry.set_position(-1);
+
+        let keymap = Keymap::from_config(&config());
+        launcher.handle_key(&keymap, Key::h, ModifierType::CONTROL_MASK);
+        assert_eq!(launcher.search_entry.text(), "open");
+        assert_eq!(launcher.search_entry.position(), 4);
+    }
+
+    #[gtk4::test]
+    fn marks_jump_back_to_their_row() {
+        let launcher = LauncherWindow::with_entries(&["A", "B", "C"], settings(10));
+        launcher.selection().set_selected(1);
+        launcher.run_action(Some(Action::SetMark));
+        assert_eq!(launcher.feed_chord(Key::a), glib::Propagation::Stop);
+
+        launcher.selection().set_selected(2);
+        launcher.run_action(Some(Action::JumpToMark));
+        launcher.feed_chord(Key::a);
+        assert_eq!(launcher.selected_name(), "B");
+
+        launcher.run_action(Some(Action::JumpToMark));
+        launcher.feed_chord(Key::b);
+        assert!(launcher.list_view.has_css_class(MARK_MISSING_CLASS));
+        assert_eq!(launcher.selected_name(), "B");
+    }
+
+    #[gtk4::test]
+    fn recycled_rows_drop_the_previous_entry() {
+        let config = {
+            let mut config = settings(10)();
+            config.launcher.favorites = vec![String::from("Firefox.desktop")];
+            config.window.show_descriptions = true;
+            config
+        };
+        let row = ResultRow::new();
+        row.bind(
+            &AppEntryObject::new(AppEntry {
+                description: String::from("Web Browser"),
+                ..app("Firefox")
+            }),
+            &config,
+        );
+        assert!(row.has_css_class("app-pinned"));
+        assert!(row.imp().description.is_visible());
+
+        row.bind(&AppEntryObject::divider(), &config);
+        assert!(!row.has_css_class("app-pinned"));
+        assert!(row.has_css_class("result-divider"));
+        assert!(!row.imp().description.is_visible());
+        assert_eq!(row.imp().name.text(), "Weaker matches");
+
+        row.bind(&AppEntryObject::new(app("Kitty")), &config);
+        assert!(!row.has_css_class("result-divider"));
+    }
+
+    #[gtk4::test]
+    fn rows_follow_the_display_settings() {
+        let mut config = settings(10)();
+        config.window.show_descriptions = false;
+        config.window.show_paths = true;
+        let row = ResultRow::new();
+        row.bind(
+            &AppEntryObject::new(AppEntry {
+                description: String::from("Web Browser"),
+                path: String::from("/opt/firefox/firefox.desktop"),
+                ..app("Firefox")
+            }),
+            &config,
+        );
+        assert!(!row.imp().description.is_visible());
+        assert!(row.imp().path.is_visible());
+        assert_eq!(row.imp().path.text(), "/opt/firefox/firefox.desktop");
+    }
 }
@@ -0,0 +1,300 @@
+use crate::{config::Launcher, files::glob_matches, launcher::SkipReason};
+
+// What decides whether an entry is listed: its desktop ID, name and file for
+// the blacklist and favorites, what its desktop file says and the `hidden`
+// value of its overrides in entries.d.
+pub struct Candidate<'a> {
+    pub desktop_id: &'a str,
+    pub name: &'a str,
+    pub path: &'a str,
+    // Why NoDisplay, Hidden, OnlyShowIn or NotShowIn hide the entry.
+    pub desktop_file: Option<&'a SkipReason>,
+    pub forced_hidden: Option<bool>,
+}
+
+impl Candidate<'_> {
+    // Returns the first `launcher.blacklist` pattern naming the entry, either
+    // its exact desktop ID or a glob matching its name or file path.
+    pub fn blacklisted_by<'b>(&self, blacklist: &'b [String]) -> Option<&'b str> {
+        blacklist
+            .iter()
+            .find(|pattern| {
+                (!self.desktop_id.is_empty() && **pattern == self.desktop_id)
+                    || glob_matches(pattern, self.name)
+                    || glob_matches(pattern, self.path)
+            })
+            .map(String::as_str)
+    }
+
+    fn is_favorite(&self, favorites: &[String]) -> bool {
+        favorites.iter().any(|favorite| {
+            (!self.desktop_id.is_empty() && *favorite == self.desktop_id)
+                || favorite.eq_ignore_ascii_case(self.name)
+        })
+    }
+}
+
+// The rules deciding an entry's visibility, strongest first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VisibilityRule {
+    // An override sets `hidden = true`.
+    ForceHide,
+    Blacklist(String),
+    // An override sets `hidden = false`.
+    ForceShow,
+    DesktopFile(SkipReason),
+    Default,
+}
+
+impl std::fmt::Display for VisibilityRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VisibilityRule::ForceHide => write!(f, "override hidden = true"),
+            VisibilityRule::Blacklist(pattern) => write!(f, "blacklisted by {:?}", pattern),
+            VisibilityRule::ForceShow => write!(f, "override hidden = false"),
+            VisibilityRule::DesktopFile(reason) => write!(f, "desktop file {}", reason),
+            VisibilityRule::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryVisibility {
+    pub shown: bool,
+    pub rule: VisibilityRule,
+}
+
+impl EntryVisibility {
+    // force_hide > blacklist > force_show > desktop file > default. Hidden=true
+    // means the entry was deleted, so an override doesn't bring it back.
+    pub fn evaluate(entry: &Candidate, config: &Launcher) -> Self {
+        let rule = if entry.forced_hidden == Some(true) {
+            VisibilityRule::ForceHide
+        } else if let Some(pattern) = entry.blacklisted_by(&config.blacklist) {
+            VisibilityRule::Blacklist(pattern.to_string())
+        } else if let Some(SkipReason::Hidden) = entry.desktop_file {
+            VisibilityRule::DesktopFile(SkipReason::Hidden)
+        } else if entry.forced_hidden == Some(false) {
+            VisibilityRule::ForceShow
+        } else if let Some(reason) = entry.desktop_file {
+            VisibilityRule::DesktopFile(reason.clone())
+        } else {
+            VisibilityRule::Default
+        };
+        Self {
+            shown: matches!(rule, VisibilityRule::ForceShow | VisibilityRule::Default),
+            rule,
+        }
+    }
+
+    // Whether an override or the blacklist decided, rather than the desktop
+    // file.
+    pub fn is_configured(&self) -> bool {
+        matches!(
+            self.rule,
+            VisibilityRule::ForceHide | VisibilityRule::Blacklist(_) | VisibilityRule::ForceShow
+        )
+    }
+
+    // Why the entry was skipped, for hidden entries.
+    pub fn skip_reason(&self) -> Option<SkipReason> {
+        match &self.rule {
+            VisibilityRule::ForceHide => Some(SkipReason::HiddenByOverride),
+            VisibilityRule::Blacklist(pattern) => Some(SkipReason::Blacklisted(pattern.clone())),
+            VisibilityRule::DesktopFile(reason) => Some(reason.clone()),
+            VisibilityRule::ForceShow | VisibilityRule::Default => None,
+        }
+    }
+
+    // Describes the contradiction when the entry is a favorite that an
+    // override or the blacklist hides.
+    pub fn contradiction(&self, entry: &Candidate, config: &Launcher) -> Option<String> {
+        if !entry.is_favorite(&config.favorites) {
+            return None;
+        }
+        let id = if entry.desktop_id.is_empty() {
+            entry.name
+        } else {
+            entry.desktop_id
+        };
+        match self.rule {
+            VisibilityRule::ForceHide => Some(format!(
+                "{} is both in favorites and hidden by an override; the override wins",
+                id
+            )),
+            VisibilityRule::Blacklist(_) => Some(format!(
+                "{} is both in favorites and blacklist; blacklist wins",
+                id
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(blacklisted: bool, favorite: bool) -> Launcher {
+        Launcher {
+            blacklist: if blacklisted {
+                vec![String::from("firefox.desktop")]
+            } else {
+                Vec::new()
+            },
+            favorites: if favorite {
+                vec![String::from("firefox.desktop")]
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn candidate(desktop_file: Option<&SkipReason>, forced_hidden: Option<bool>) -> Candidate<'_> {
+        Candidate {
+            desktop_id: "firefox.desktop",
+            name: "Firefox",
+            path: "/usr/share/applications/firefox.desktop",
+            desktop_file,
+            forced_hidden,
+        }
+    }
+
+    #[test]
+    fn every_combination_follows_the_precedence() {
+        let desktop_files = [
+            None,
+            Some(SkipReason::NoDisplay),
+            Some(SkipReason::NotShownIn(String::from("Hyprland"))),
+            Some(SkipReason::Hidden),
+        ];
+        for forced_hidden in [None, Some(false), Some(true)] {
+            for blacklisted in [false, true] {
+                for desktop_file in &desktop_files {
+                    let expected = match (forced_hidden, blacklisted, desktop_file) {
+                        (Some(true), _, _) => VisibilityRule::ForceHide,
+                        (_, true, _) => VisibilityRule::Blacklist(String::from("firefox.desktop")),
+                        (_, _, Some(SkipReason::Hidden)) => {
+                            VisibilityRule::DesktopFile(SkipReason::Hidden)
+                        }
+                        (Some(false), _, _) => VisibilityRule::ForceShow,
+                        (None, false, Some(reason)) => VisibilityRule::DesktopFile(reason.clone()),
+                        (None, false, None) => VisibilityRule::Default,
+                    };
+                    let shown = matches!(
+                        expected,
+                        VisibilityRule::ForceShow | VisibilityRule::Default
+                    );
+                    let visibility = EntryVisibility::evaluate(
+                        &candidate(desktop_file.as_ref(), forced_hidden),
+                        &config(blacklisted, false),
+                    );
+                    assert_eq!(
+                        visibility,
+                        EntryVisibility {
+                            shown,
+                            rule: expected
+                        },
+                        "forced_hidden={:?} blacklisted={} desktop_file={:?}",
+                        forced_hidden,
+                        blacklisted,
+                        desktop_file
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blacklist_globs_match_names_and_paths() {
+        let entry = candidate(None, None);
+        for (pattern, matches) in [
+            ("firefox.desktop", true),
+            ("Fire*", true),
+            ("/usr/share/applications/*", true),
+            ("firefox", false),
+            ("fire*", false),
+        ] {
+            let config = Launcher {
+                blacklist: vec![String::from(pattern)],
+                ..Default::default()
+            };
+            assert_eq!(
+                !EntryVisibility::evaluate(&entry, &config).shown,
+                matches,
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn empty_desktop_ids_never_match_exactly() {
+        let entry = Candidate {
+            desktop_id: "",
+            ..candidate(None, None)
+        };
+        let config = Launcher {
+            blacklist: vec![String::new()],
+            ..Default::default()
+        };
+        assert!(EntryVisibility::evaluate(&entry, &config).shown);
+    }
+
+    #[test]
+    fn hidden_favorites_are_contradictions() {
+        let cases = [
+            (None, false, None),
+            (
+                None,
+                true,
+                Some("firefox.desktop is both in favorites and blacklist; blacklist wins"),
+            ),
+            (
+                Some(true),
+                false,
+                Some(
+                    "firefox.desktop is both in favorites and hidden by an override; the override wins",
+                ),
+            ),
+            (
+                Some(true),
+                true,
+                Some(
+                    "firefox.desktop is both in favorites and hidden by an override; the override wins",
+                ),
+            ),
+            (
+                Some(false),
+                true,
+                Some("firefox.desktop is both in favorites and blacklist; blacklist wins"),
+            ),
+            (Some(false), false, None),
+        ];
+        for (forced_hidden, blacklisted, expected) in cases {
+            let entry = candidate(None, forced_hidden);
+            let config = config(blacklisted, true);
+            let visibility = EntryVisibility::evaluate(&entry, &config);
+            assert_eq!(
+                visibility.contradiction(&entry, &config).as_deref(),
+                expected
+            );
+            let config = Launcher {
+                favorites: Vec::new(),
+                ..config
+            };
+            assert_eq!(visibility.contradiction(&entry, &config), None);
+        }
+    }
+
+    #[test]
+    fn desktop_files_hiding_favorites_are_not_contradictions() {
+        let reason = SkipReason::NoDisplay;
+        let entry = candidate(Some(&reason), None);
+        let config = config(false, true);
+        let visibility = EntryVisibility::evaluate(&entry, &config);
+        assert!(!visibility.shown);
+        assert_eq!(visibility.contradiction(&entry, &config), None);
+    }
+}
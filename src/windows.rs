@@ -0,0 +1,202 @@
+use crate::{
+    hyprland::{self, Client},
+    launcher::{AppEntry, EntryType},
+    log,
+    search::SearchResult,
+};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use std::{cmp::Reverse, collections::HashMap};
+
+const WINDOW_ID_PREFIX: &str = "window:";
+
+// Rows for the windows mode, one per open Hyprland window. Activating a row
+// focuses its window.
+pub fn candidates(
+    apps: &HashMap<String, AppEntry>,
+    query: &str,
+    max_results: usize,
+) -> Vec<SearchResult> {
+    if !hyprland::is_running() {
+        return vec![SearchResult {
+            app: AppEntry {
+                name: String::from("Switching windows needs Hyprland"),
+                icon_name: "dialog-information-symbolic".into(),
+                entry_type: EntryType::Divider,
+                ..Default::default()
+            },
+            score: 0,
+        }];
+    }
+    match hyprland::clients() {
+        Ok(clients) => matching_windows(apps, clients, query, max_results),
+        Err(e) => {
+            log!("Listing windows failed: {}", e);
+            vec![error_row(&e)]
+        }
+    }
+}
+
+// Shown in place of the windows when hyprctl fails or is killed, so an empty
+// list isn't mistaken for there being no windows.
+fn error_row(error: &str) -> SearchResult {
+    SearchResult {
+        app: AppEntry {
+            name: format!("Windows: {}", error),
+            icon_name: "dialog-error-symbolic".into(),
+            entry_type: EntryType::Divider,
+            ..Default::default()
+        },
+        score: 0,
+    }
+}
+
+// Without a query, windows are listed from the most recently focused.
+fn matching_windows(
+    apps: &HashMap<String, AppEntry>,
+    clients: Vec<Client>,
+    query: &str,
+    max_results: usize,
+) -> Vec<SearchResult> {
+    let query = query.trim();
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut results: Vec<_> = clients
+        .into_iter()
+        .filter_map(|client| {
+            let score = if query.is_empty() {
+                0
+            } else {
+                matcher
+                    .fuzzy_match(&client.title, query)
+                    .max(matcher.fuzzy_match(&client.class, query))?
+            };
+            Some(SearchResult {
+                app: entry(apps, client),
+                score,
+            })
+        })
+        .collect();
+    results.sort_by_key(|result| Reverse(result.score));
+    results.truncate(max_results);
+    results
+}
+
+fn entry(apps: &HashMap<String, AppEntry>, client: Client) -> AppEntry {
+    AppEntry {
+        id: format!("{}{}", WINDOW_ID_PREFIX, client.address),
+        name: if client.title.is_empty() {
+            client.class.clone()
+        } else {
+            client.title
+        },
+        description: format!("{} on workspace {}", client.class, client.workspace),
+        icon_name: icon_for(apps, &client.class).into(),
+        exec: client.address,
+        startup_wm_class: client.class.into(),
+        entry_type: EntryType::Window,
+        ..Default::default()
+    }
+}
+
+// The icon of the application the window belongs to, found by its
+// StartupWMClass or desktop ID, or else the class itself, which most icon
+// themes name icons after.
+fn icon_for(apps: &HashMap<String, AppEntry>, class: &str) -> String {
+    let desktop_id = format!("{}.desktop", class);
+    apps.values()
+        .find(|app| {
+            !app.icon_name.is_empty()
+                && (app.startup_wm_class.eq_ignore_ascii_case(class)
+                    || app.desktop_id.eq_ignore_ascii_case(&desktop_id))
+        })
+        .map_or_else(|| class.to_lowercase(), |app| app.icon_name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(address: &str, class: &str, title: &str) -> Client {
+        Client {
+            address: address.to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+            workspace: String::from("1"),
+        }
+    }
+
+    fn clients() -> Vec<Client> {
+        vec![
+            client("0x1", "kitty", "~/src/hyprlauncher"),
+            client("0x2", "firefox", "Rust documentation — Mozilla Firefox"),
+            client("0x3", "org.gnome.Nautilus", "Downloads"),
+        ]
+    }
+
+    fn addresses(results: &[SearchResult]) -> Vec<&str> {
+        results
+            .iter()
+            .map(|result| result.app.exec.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn an_empty_query_keeps_the_focus_order() {
+        let results = matching_windows(&HashMap::new(), clients(), "", 10);
+        assert_eq!(addresses(&results), ["0x1", "0x2", "0x3"]);
+        assert_eq!(
+            addresses(&matching_windows(&HashMap::new(), clients(), "", 2)),
+            ["0x1", "0x2"]
+        );
+    }
+
+    #[test]
+    fn queries_match_titles_and_classes() {
+        let apps = HashMap::new();
+        assert_eq!(
+            addresses(&matching_windows(&apps, clients(), "rust doc", 10)),
+            ["0x2"]
+        );
+        assert_eq!(
+            addresses(&matching_windows(&apps, clients(), "nautilus", 10)),
+            ["0x3"]
+        );
+        assert!(matching_windows(&apps, clients(), "gimp", 10).is_empty());
+    }
+
+    #[test]
+    fn rows_name_the_window_and_its_workspace() {
+        let results = matching_windows(&HashMap::new(), clients(), "kitty", 10);
+        let app = &results[0].app;
+        assert_eq!(app.name, "~/src/hyprlauncher");
+        assert_eq!(app.description, "kitty on workspace 1");
+        assert_eq!(app.id, "window:0x1");
+        assert!(matches!(app.entry_type, EntryType::Window));
+    }
+
+    #[test]
+    fn untitled_windows_are_named_after_their_class() {
+        let results = matching_windows(&HashMap::new(), vec![client("0x4", "mpv", "")], "", 10);
+        assert_eq!(results[0].app.name, "mpv");
+    }
+
+    #[test]
+    fn failures_name_the_windows_provider() {
+        let row = error_row("hyprctl killed after 2000ms");
+        assert_eq!(row.app.name, "Windows: hyprctl killed after 2000ms");
+        assert!(matches!(row.app.entry_type, EntryType::Divider));
+    }
+
+    #[test]
+    fn icons_come_from_the_matching_application() {
+        let apps = HashMap::from([(
+            String::from("org.gnome.Nautilus.desktop"),
+            AppEntry {
+                desktop_id: String::from("org.gnome.Nautilus.desktop"),
+                icon_name: "system-file-manager".into(),
+                ..Default::default()
+            },
+        )]);
+        assert_eq!(icon_for(&apps, "org.gnome.Nautilus"), "system-file-manager");
+        assert_eq!(icon_for(&apps, "Firefox"), "firefox");
+    }
+}